@@ -0,0 +1,1087 @@
+use bevy::{
+	asset::{Assets, Handle},
+	ecs::{prelude::*, system::SystemParam},
+	math::{Mat3, Mat4, Quat, Vec3, Vec4},
+	reflect::Reflect,
+	render::mesh::{
+		morph::MorphAttributes,
+		skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+		Mesh, VertexAttributeValues,
+	},
+	transform::components::GlobalTransform,
+};
+
+use crate::{DualQuat, ScaledDualQuat, ATTRIBUTE_DQS_BLEND};
+
+/// How the joint transforms influencing a vertex are blended together.
+///
+/// As a component on an entity with a [DqsMaterial], this overrides the
+/// material's [DqsSkinningMode], so individual entities can switch between dual
+/// quaternion and linear blend skinning without swapping materials.
+/// [SkinningMethod::Blended] only has an effect with
+/// [DqsSkinningMode::Blended], and entities without the component follow the
+/// material.
+///
+/// [DqsMaterial]: crate::DqsMaterial
+/// [DqsSkinningMode]: crate::DqsSkinningMode
+/// [DqsSkinningMode::Blended]: crate::DqsSkinningMode::Blended
+#[derive(Component, Reflect, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[reflect(Component)]
+pub enum SkinningMethod {
+	/// Linear blend skinning, as used by Bevy's built-in materials.
+	Linear,
+	/// Dual quaternion skinning, as used by [DqsSkinningMode::DualQuaternion].
+	///
+	/// [DqsSkinningMode::DualQuaternion]: crate::DqsSkinningMode::DualQuaternion
+	#[default]
+	DualQuaternion,
+	/// Per-vertex mix of the two, as used by [DqsSkinningMode::Blended].
+	///
+	/// [DqsSkinningMode::Blended]: crate::DqsSkinningMode::Blended
+	Blended,
+}
+
+/// A CPU implementation of the `dq_skinning` shader, for deforming meshes
+/// without a GPU -- e.g. for raycasting against posed characters, drawing
+/// gizmos, or baking.
+///
+/// Joints are stored exactly as they're uploaded to the GPU, and each step
+/// mirrors the shader's math, so the results match the rendered mesh. Morph
+/// targets are only applied by [CpuSkin::deform_morphed_mesh], since a [Mesh]
+/// only holds them as a GPU texture.
+#[derive(Clone, Debug, Default)]
+pub struct CpuSkin {
+	joints: Vec<ScaledDualQuat>,
+}
+
+/// Skinned vertex data produced by [CpuSkin::deform_mesh], in world space.
+#[derive(Clone, Debug, Default)]
+pub struct DeformedMesh {
+	pub positions: Vec<Vec3>,
+	/// Empty if the source mesh has no normals.
+	pub normals: Vec<Vec3>,
+	/// Empty if the source mesh has no tangents.
+	pub tangents: Vec<Vec4>,
+}
+
+/// A vertex's blended joint transform, along with the parts needed to skin its
+/// normal and tangent consistently with it. Equivalent to the shader's
+/// `SkinnedTransform`.
+#[derive(Clone, Copy, Debug)]
+pub struct SkinnedTransform {
+	pub model: Mat4,
+	/// The blended dual quaternion rotation.
+	pub rotation: Quat,
+	/// The blended scale/shear, applied before `rotation`.
+	pub scale: Mat3,
+	/// From `0.0` (fully linear) to `1.0` (fully dual quaternion).
+	pub dq_weight: f32,
+}
+
+impl SkinnedTransform {
+	pub const IDENTITY: Self = Self {
+		model: Mat4::IDENTITY,
+		rotation: Quat::IDENTITY,
+		scale: Mat3::IDENTITY,
+		dq_weight: 1.,
+	};
+
+	/// Skins a normal vector. Fully dual-quaternion-skinned normals are rotated
+	/// by the blended rotation after the inverse transpose of the blended scale;
+	/// any linear blending falls back to the inverse transpose of the model
+	/// matrix.
+	///
+	/// Equivalent to the shader's `skin_normal`.
+	pub fn skin_normal(&self, normal: Vec3) -> Vec3 {
+		if self.dq_weight < 1. {
+			return skin_normal(self.model, normal);
+		}
+
+		(self.rotation * (self.scale.inverse().transpose() * normal)).normalize()
+	}
+
+	/// Skins a tangent vector, flipping its handedness (`w`) if the transform
+	/// is mirrored.
+	///
+	/// Equivalent to the shader's `skin_tangent`.
+	pub fn skin_tangent(&self, tangent: Vec4) -> Vec4 {
+		let (direction, linear) = if self.dq_weight < 1. {
+			let linear = Mat3::from_mat4(self.model);
+			(linear * tangent.truncate(), linear)
+		} else {
+			(
+				self.rotation * (self.scale * tangent.truncate()),
+				self.scale,
+			)
+		};
+		let handedness = if linear.determinant() >= 0. { 1. } else { -1. };
+
+		direction.normalize().extend(tangent.w * handedness)
+	}
+}
+
+impl CpuSkin {
+	/// Creates a skin from each joint's skinning matrix, i.e. its world
+	/// transform multiplied by its inverse bindpose.
+	pub fn new(joint_matrices: impl IntoIterator<Item = Mat4>) -> Self {
+		Self {
+			joints: joint_matrices
+				.into_iter()
+				.map(ScaledDualQuat::from)
+				.collect(),
+		}
+	}
+
+	/// Creates a skin from the current pose of a [SkinnedMesh]'s joints.
+	///
+	/// Returns `None` if any of the joints are missing a [GlobalTransform].
+	pub fn from_skinned_mesh(
+		skinned_mesh: &SkinnedMesh,
+		inverse_bindposes: &SkinnedMeshInverseBindposes,
+		q_joints: &Query<&GlobalTransform>,
+	) -> Option<Self> {
+		let joint_matrices = skinned_mesh
+			.joints
+			.iter()
+			.zip(inverse_bindposes.iter())
+			.map(|(&joint, bindpose)| {
+				q_joints
+					.get(joint)
+					.ok()
+					.map(|xform| xform.affine() * *bindpose)
+			})
+			.collect::<Option<Vec<_>>>()?;
+
+		Some(Self::new(joint_matrices))
+	}
+
+	#[inline]
+	pub fn joints(&self) -> &[ScaledDualQuat] {
+		&self.joints
+	}
+
+	/// Computes the skinning matrix for a single vertex. `dqs_blend` mixes
+	/// between linear blend skinning (`0.0`) and dual quaternion skinning
+	/// (`1.0`).
+	///
+	/// Equivalent to the shader's `skin_model_blended`. Joint indices that are
+	/// out of range are treated as identity transforms.
+	#[inline]
+	pub fn skin_model(&self, indices: [u16; 4], weights: [f32; 4], dqs_blend: f32) -> Mat4 {
+		self.skin_transform(indices, weights, dqs_blend).model
+	}
+
+	/// Like [CpuSkin::skin_model], but also returns the blended rotation and
+	/// scale for skinning normals and tangents.
+	///
+	/// Equivalent to the shader's `skin_transform`.
+	pub fn skin_transform(
+		&self,
+		indices: [u16; 4],
+		weights: [f32; 4],
+		dqs_blend: f32,
+	) -> SkinnedTransform {
+		if weights.iter().sum::<f32>() <= 0.001 {
+			return SkinnedTransform::IDENTITY;
+		}
+
+		let joints = indices.map(|idx| {
+			self.joints
+				.get(idx as usize)
+				.copied()
+				.unwrap_or(ScaledDualQuat::IDENTITY)
+		});
+
+		let t = dqs_blend.clamp(0., 1.);
+		let mut lbs = Mat4::ZERO;
+		if t < 1. {
+			for (joint, &weight) in joints.iter().zip(weights.iter()) {
+				lbs += mat4_from_dq(joint.rigid) * mat4_from_scale(joint.scale) * weight;
+			}
+			if t <= 0. {
+				return SkinnedTransform {
+					model: lbs,
+					dq_weight: 0.,
+					..SkinnedTransform::IDENTITY
+				};
+			}
+		}
+
+		let dq = blend_dqs(&joints, weights);
+		let scale = blend_scales(&joints, weights);
+		let dqs = mat4_from_dq(dq) * scale;
+
+		SkinnedTransform {
+			model: lbs * (1. - t) + dqs * t,
+			rotation: dq.real(),
+			scale: Mat3::from_mat4(scale),
+			dq_weight: t,
+		}
+	}
+
+	/// Deforms the positions, normals and tangents of `mesh`, which must have
+	/// joint index and weight attributes.
+	///
+	/// [SkinningMethod::Blended] reads each vertex's blend factor from the
+	/// mesh's [ATTRIBUTE_DQS_BLEND], falling back to dual quaternion skinning
+	/// if the attribute is missing.
+	pub fn deform_mesh(&self, mesh: &Mesh, method: SkinningMethod) -> Option<DeformedMesh> {
+		self.deform_morphed_mesh(mesh, &[], &[], method)
+	}
+
+	/// Like [CpuSkin::deform_mesh], but first applies each of `morph_targets`,
+	/// with the matching weight from `morph_weights`, as the `dqs_pre_skinning`
+	/// shader does. Each target has one [MorphAttributes] per vertex, as passed
+	/// to [MorphTargetImage::new].
+	///
+	/// [MorphTargetImage::new]: bevy::render::mesh::morph::MorphTargetImage::new
+	pub fn deform_morphed_mesh(
+		&self,
+		mesh: &Mesh,
+		morph_targets: &[Vec<MorphAttributes>],
+		morph_weights: &[f32],
+		method: SkinningMethod,
+	) -> Option<DeformedMesh> {
+		use VertexAttributeValues::*;
+
+		let Some(Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
+			return None;
+		};
+		let Some(Uint16x4(joint_indices)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_INDEX) else {
+			return None;
+		};
+		let Some(Float32x4(joint_weights)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT) else {
+			return None;
+		};
+		let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+			Some(Float32x3(normals)) => &normals[..],
+			_ => &[],
+		};
+		let tangents = match mesh.attribute(Mesh::ATTRIBUTE_TANGENT) {
+			Some(Float32x4(tangents)) => &tangents[..],
+			_ => &[],
+		};
+		let blends = match (method, mesh.attribute(ATTRIBUTE_DQS_BLEND)) {
+			(SkinningMethod::Blended, Some(Float32(blends))) => &blends[..],
+			_ => &[],
+		};
+
+		let mut result = DeformedMesh {
+			positions: Vec::with_capacity(positions.len()),
+			normals: Vec::with_capacity(normals.len()),
+			tangents: Vec::with_capacity(tangents.len()),
+		};
+
+		for (idx, &position) in positions.iter().enumerate() {
+			let mut position = Vec3::from(position);
+			let mut normal = normals.get(idx).copied().map(Vec3::from);
+			let mut tangent = tangents.get(idx).copied().map(Vec4::from);
+			for (target, &weight) in morph_targets.iter().zip(morph_weights.iter()) {
+				let Some(delta) = target.get(idx).filter(|_| weight != 0.) else {
+					continue;
+				};
+				position += weight * delta.position;
+				normal = normal.map(|normal| normal + weight * delta.normal);
+				tangent = tangent.map(|tangent| tangent + (weight * delta.tangent).extend(0.));
+			}
+
+			let dqs_blend = match method {
+				SkinningMethod::Linear => 0.,
+				SkinningMethod::DualQuaternion => 1.,
+				SkinningMethod::Blended => blends.get(idx).copied().unwrap_or(1.),
+			};
+			let skinned = self.skin_transform(
+				joint_indices.get(idx).copied().unwrap_or_default(),
+				joint_weights.get(idx).copied().unwrap_or_default(),
+				dqs_blend,
+			);
+
+			result
+				.positions
+				.push(skinned.model.transform_point3(position));
+
+			if let Some(normal) = normal {
+				result.normals.push(skinned.skin_normal(normal));
+			}
+			if let Some(tangent) = tangent {
+				result.tangents.push(skinned.skin_tangent(tangent));
+			}
+		}
+
+		Some(result)
+	}
+}
+
+/// Transforms a normal by the inverse transpose of a skinning matrix.
+/// Equivalent to the shader's `skin_normals`.
+pub fn skin_normal(model: Mat4, normal: Vec3) -> Vec3 {
+	let m = Mat3::from_mat4(model);
+	let x = m.y_axis.cross(m.z_axis);
+	let y = m.z_axis.cross(m.x_axis);
+	let z = m.x_axis.cross(m.y_axis);
+	let det = m.z_axis.dot(z);
+
+	(Mat3::from_cols(x / det, y / det, z / det) * normal).normalize()
+}
+
+/// The full-detail mesh of a skinned mesh entity whose [`Handle<Mesh>`] is
+/// swapped for simplified versions, e.g. by LODs. [CpuSkinning] deforms this
+/// one instead, so the results don't depend on the distance to the camera.
+#[derive(Component, Reflect, Clone, Debug, Default)]
+#[reflect(Component)]
+pub struct FullDetailMesh(pub Handle<Mesh>);
+
+/// Deforms skinned mesh entities on the CPU.
+#[derive(SystemParam)]
+pub struct CpuSkinning<'w, 's> {
+	ra_meshes: Res<'w, Assets<Mesh>>,
+	ra_inverse_bindposes: Res<'w, Assets<SkinnedMeshInverseBindposes>>,
+	q_skinned_meshes: Query<
+		'w,
+		's,
+		(
+			&'static Handle<Mesh>,
+			&'static SkinnedMesh,
+			Option<&'static FullDetailMesh>,
+		),
+	>,
+	q_joints: Query<'w, 's, &'static GlobalTransform>,
+}
+
+impl CpuSkinning<'_, '_> {
+	/// Creates a [CpuSkin] from the current pose of `entity`'s joints.
+	pub fn skin(&self, entity: Entity) -> Option<CpuSkin> {
+		let (_, skinned_mesh, _) = self.q_skinned_meshes.get(entity).ok()?;
+		let inverse_bindposes = self
+			.ra_inverse_bindposes
+			.get(&skinned_mesh.inverse_bindposes)?;
+
+		CpuSkin::from_skinned_mesh(skinned_mesh, inverse_bindposes, &self.q_joints)
+	}
+
+	/// Deforms the mesh of a skinned mesh entity with its current pose, or its
+	/// [FullDetailMesh] if it has one.
+	///
+	/// Joint transforms are read from [GlobalTransform]s, so changes made this
+	/// frame won't be reflected until after transform propagation.
+	pub fn deform(&self, entity: Entity, method: SkinningMethod) -> Option<DeformedMesh> {
+		let (mesh_handle, _, full_detail) = self.q_skinned_meshes.get(entity).ok()?;
+		let mesh_handle = full_detail.map_or(mesh_handle, |FullDetailMesh(mesh)| mesh);
+		let mesh = self.ra_meshes.get(mesh_handle)?;
+
+		self.skin(entity)?.deform_mesh(mesh, method)
+	}
+}
+
+/// Equivalent to the shader's `dq_math::mat4x4_from_dq`, which doesn't
+/// normalize the rotation.
+fn mat4_from_dq(dq: DualQuat) -> Mat4 {
+	let DualQuat(real, dual) = dq;
+	let translation = (dual * 2.) * real.conjugate();
+
+	let rotation = Mat3::from_quat(real);
+	Mat4::from_cols(
+		rotation.x_axis.extend(0.),
+		rotation.y_axis.extend(0.),
+		rotation.z_axis.extend(0.),
+		translation.xyz().extend(1.),
+	)
+}
+
+fn mat4_from_scale(scale: Mat3) -> Mat4 {
+	Mat4::from_mat3(scale)
+}
+
+/// Equivalent to the shader's `blend_dqs`.
+fn blend_dqs(joints: &[ScaledDualQuat; 4], weights: [f32; 4]) -> DualQuat {
+	let dq0 = joints[0].rigid;
+	let q0 = dq0.real();
+
+	let mut result = dq0 * weights[0];
+	for (joint, &weight) in joints.iter().zip(weights.iter()).skip(1) {
+		let dq = joint.rigid;
+		let weight = if dq.real().dot(q0) < 0. {
+			-weight
+		} else {
+			weight
+		};
+
+		result = result + dq * weight;
+	}
+
+	let mag = result.magnitude();
+	if mag <= 0.001 {
+		return DualQuat::IDENTITY;
+	}
+
+	DualQuat(result.real() / mag, result.dual() / mag)
+}
+
+/// Equivalent to the shader's `blend_scales`.
+fn blend_scales(joints: &[ScaledDualQuat; 4], weights: [f32; 4]) -> Mat4 {
+	let mut result = Mat4::from_cols(Vec4::ZERO, Vec4::ZERO, Vec4::ZERO, Vec4::W);
+	for (joint, &weight) in joints.iter().zip(weights.iter()) {
+		let scale = mat4_from_scale(joint.scale);
+		result.x_axis += scale.x_axis * weight;
+		result.y_axis += scale.y_axis * weight;
+		result.z_axis += scale.z_axis * weight;
+	}
+
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use bevy::{
+		math::{vec3, vec4, Affine3A, EulerRot, Mat3, Mat4, Quat, Vec3},
+		render::{
+			mesh::{
+				morph::{MorphAttributes, MorphTargetImage},
+				Mesh, PrimitiveTopology, VertexAttributeValues,
+			},
+			render_asset::RenderAssetUsages,
+		},
+	};
+
+	use super::{skin_normal, CpuSkin, SkinningMethod};
+	use crate::{pre_skinning::source_vertices, ScaledDualQuat, ATTRIBUTE_DQS_BLEND};
+
+	fn test_skin() -> CpuSkin {
+		CpuSkin::new([
+			Mat4::IDENTITY,
+			Mat4::from_rotation_translation(Quat::from_rotation_z(1.2), vec3(0., 1., 0.)),
+			Mat4::from_scale_rotation_translation(
+				vec3(1.5, 1., 0.5),
+				Quat::from_rotation_x(-0.7),
+				vec3(2., 0., -1.),
+			),
+		])
+	}
+
+	#[test]
+	fn single_joint_matches_joint_matrix() {
+		let skin = test_skin();
+		let expected = Mat4::from_scale_rotation_translation(
+			vec3(1.5, 1., 0.5),
+			Quat::from_rotation_x(-0.7),
+			vec3(2., 0., -1.),
+		);
+
+		for blend in [0., 0.5, 1.] {
+			let model = skin.skin_model([2, 0, 0, 0], [1., 0., 0., 0.], blend);
+			assert!(model.abs_diff_eq(expected, 1.0e-4));
+		}
+	}
+
+	#[test]
+	fn linear_is_weighted_sum_of_matrices() {
+		let skin = test_skin();
+		let weights = [0.5, 0.3, 0.2, 0.];
+		let model = skin.skin_model([0, 1, 2, 0], weights, 0.);
+
+		let expected = Mat4::IDENTITY * 0.5
+			+ Mat4::from_rotation_translation(Quat::from_rotation_z(1.2), vec3(0., 1., 0.)) * 0.3
+			+ Mat4::from_scale_rotation_translation(
+				vec3(1.5, 1., 0.5),
+				Quat::from_rotation_x(-0.7),
+				vec3(2., 0., -1.),
+			) * 0.2;
+
+		assert!(model.abs_diff_eq(expected, 1.0e-4));
+	}
+
+	#[test]
+	fn dual_quaternion_preserves_volume() {
+		// Halfway between two opposing twists, LBS collapses toward the axis
+		// while DQS keeps the point at its original distance
+		let skin = CpuSkin::new([Mat4::from_rotation_y(1.5), Mat4::from_rotation_y(-1.5)]);
+
+		let mut mesh = Mesh::new(PrimitiveTopology::PointList, RenderAssetUsages::default());
+		mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[1., 0., 0.]]);
+		mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[1., 0., 0.]]);
+		mesh.insert_attribute(
+			Mesh::ATTRIBUTE_JOINT_INDEX,
+			VertexAttributeValues::Uint16x4(vec![[0, 1, 0, 0]]),
+		);
+		mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, vec![[0.5, 0.5, 0., 0.]]);
+
+		let lbs = skin.deform_mesh(&mesh, SkinningMethod::Linear).unwrap();
+		let dqs = skin
+			.deform_mesh(&mesh, SkinningMethod::DualQuaternion)
+			.unwrap();
+
+		assert!(lbs.positions[0].length() < 0.1);
+		assert!((dqs.positions[0].length() - 1.).abs() < 1.0e-4);
+		assert!(dqs.normals[0].abs_diff_eq(Vec3::X, 1.0e-4));
+	}
+
+	#[test]
+	fn normals_and_tangents_follow_blended_rotation() {
+		#[rustfmt::skip]
+		let shear = Mat3::from_cols_array(&[
+			1.3, 0.0, 0.0,
+			0.5, 0.9, 0.0,
+			0.0, 0.2, 1.1,
+		]);
+		let skin = CpuSkin::new([
+			Mat4::from_rotation_translation(Quat::from_rotation_z(0.4), vec3(0., 1., 0.))
+				* Mat4::from_mat3(shear),
+			Mat4::from_rotation_translation(Quat::from_rotation_x(-0.9), vec3(1., 0., 2.)),
+		]);
+		let skinned = skin.skin_transform([0, 1, 0, 0], [0.7, 0.3, 0., 0.], 1.);
+
+		let normal = vec3(0., 1., 0.);
+		let tangent = vec4(1., 0., 0., 1.);
+		let skinned_normal = skinned.skin_normal(normal);
+		let skinned_tangent = skinned.skin_tangent(tangent);
+
+		// Rotating by the blended rotation matches the inverse transpose of the
+		// blended matrix, and keeps tangents perpendicular to normals
+		assert!(skinned_normal.abs_diff_eq(skin_normal(skinned.model, normal), 1.0e-4));
+		assert!(skinned_normal.dot(skinned_tangent.truncate()).abs() < 1.0e-4);
+		assert_eq!(skinned_tangent.w, 1.);
+	}
+
+	#[test]
+	fn mirrored_joints_flip_tangent_handedness() {
+		let skin = CpuSkin::new([Mat4::from_scale(vec3(-1., 1., 1.))]);
+		let tangent = vec4(0., 0., 1., 1.);
+
+		for blend in [0., 1.] {
+			let skinned = skin.skin_transform([0; 4], [1., 0., 0., 0.], blend);
+			assert_eq!(skinned.skin_tangent(tangent).w, -1.);
+		}
+	}
+
+	/// Joints and vertices shared by the CPU and the shader port below:
+	/// unweighted, rigid, blended, sheared and mirrored vertices, and a vertex
+	/// moved by two morph targets.
+	fn fixture() -> (CpuSkin, Mesh, Vec<Vec<MorphAttributes>>, Vec<f32>) {
+		#[rustfmt::skip]
+		let shear = Mat3::from_cols_array(&[
+			1.3, 0.0, 0.0,
+			0.5, 0.9, 0.0,
+			0.0, 0.2, 1.1,
+		]);
+		let skin = CpuSkin::new([
+			Mat4::IDENTITY,
+			Mat4::from_rotation_translation(Quat::from_rotation_z(1.2), vec3(0., 1., 0.)),
+			Mat4::from_scale_rotation_translation(
+				vec3(1.5, 1., 0.5),
+				Quat::from_rotation_x(-0.7),
+				vec3(2., 0., -1.),
+			),
+			Mat4::from_rotation_translation(Quat::from_rotation_y(2.9), vec3(-1., 0.5, 0.))
+				* Mat4::from_mat3(shear),
+			Mat4::from_scale(vec3(-1., 1., 1.)),
+		]);
+
+		let mut mesh = Mesh::new(PrimitiveTopology::PointList, RenderAssetUsages::default());
+		mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![
+			[0.5, 0.5, 0.5],
+			[1., 0., 0.],
+			[0., 2., -1.],
+			[-0.5, 1., 0.25],
+			[0.3, -0.2, 1.],
+			[1., 1., 0.],
+		]);
+		mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![
+			[0., 1., 0.],
+			[1., 0., 0.],
+			[0., 0.6, 0.8],
+			[0., 0., 1.],
+			[0.6, 0., 0.8],
+			[0., 0., 1.],
+		]);
+		mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, vec![
+			[1., 0., 0., 1.],
+			[0., 1., 0., -1.],
+			[1., 0., 0., 1.],
+			[0., 1., 0., 1.],
+			[0., 1., 0., 1.],
+			[1., 0., 0., -1.],
+		]);
+		mesh.insert_attribute(
+			Mesh::ATTRIBUTE_JOINT_INDEX,
+			VertexAttributeValues::Uint16x4(vec![
+				[0, 0, 0, 0],
+				[2, 0, 0, 0],
+				[1, 2, 0, 0],
+				[3, 1, 2, 0],
+				[4, 0, 0, 0],
+				[1, 3, 2, 0],
+			]),
+		);
+		mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, vec![
+			[0., 0., 0., 0.],
+			[1., 0., 0., 0.],
+			[0.6, 0.4, 0., 0.],
+			[0.5, 0.3, 0.2, 0.],
+			[1., 0., 0., 0.],
+			[0.4, 0.4, 0.1, 0.1],
+		]);
+		mesh.insert_attribute(ATTRIBUTE_DQS_BLEND, vec![1., 1., 0.25, 0.5, 0., 0.75]);
+
+		// Only the last vertex is morphed, by both targets
+		let morph = |position: Vec3, normal: Vec3, tangent: Vec3| {
+			let mut target = vec![MorphAttributes::default(); 6];
+			target[5] = MorphAttributes {
+				position,
+				normal,
+				tangent,
+			};
+			target
+		};
+		let morph_targets = vec![
+			morph(vec3(0., 0.5, -0.25), vec3(0.3, 0., -0.2), vec3(0., 0.4, 0.)),
+			morph(vec3(-0.4, 0., 0.1), vec3(0., 0.2, 0.), vec3(0., 0., 0.3)),
+		];
+
+		(skin, mesh, morph_targets, vec![0.8, 0.35])
+	}
+
+	#[test]
+	fn matches_shader_on_fixtures() {
+		let (skin, mesh, morph_targets, morph_weights) = fixture();
+		let joints = skin
+			.joints()
+			.iter()
+			.map(|joint| joint.to_packed())
+			.collect::<Vec<_>>();
+		let sources = source_vertices(&mesh).unwrap();
+		let morph_image = MorphTargetImage::new(
+			morph_targets
+				.iter()
+				.map(|target| target.iter().copied())
+				.collect::<Vec<_>>()
+				.into_iter(),
+			6,
+			RenderAssetUsages::default(),
+		)
+		.unwrap()
+		.0;
+
+		// The material's mode, and the entity's method override packed by `DqSkin`
+		for (method, skinning_mode, method_override) in [
+			(SkinningMethod::DualQuaternion, 0, 0),
+			(SkinningMethod::Blended, 1, 0),
+			(SkinningMethod::Linear, 1, 1),
+			(SkinningMethod::DualQuaternion, 1, 2),
+		] {
+			let cpu = skin
+				.deform_morphed_mesh(&mesh, &morph_targets, &morph_weights, method)
+				.unwrap();
+			let gpu = (0..6)
+				.map(|idx| {
+					shader::pre_skin(
+						&joints,
+						&sources,
+						&morph_image,
+						&morph_weights,
+						skinning_mode,
+						method_override,
+						idx,
+					)
+				})
+				.collect::<Vec<_>>();
+
+			for (idx, (position, normal, tangent)) in gpu.into_iter().enumerate() {
+				let context = format!("{method:?}, vertex {idx}");
+				assert!(
+					cpu.positions[idx].abs_diff_eq(position, 1.0e-4),
+					"{context}: {} != {position}",
+					cpu.positions[idx],
+				);
+				assert!(
+					cpu.normals[idx].abs_diff_eq(normal, 1.0e-4),
+					"{context}: {} != {normal}",
+					cpu.normals[idx],
+				);
+				assert!(
+					cpu.tangents[idx].abs_diff_eq(tangent, 1.0e-4),
+					"{context}: {} != {tangent}",
+					cpu.tangents[idx],
+				);
+			}
+		}
+
+		// The morphed vertex actually moved
+		let unmorphed = skin
+			.deform_mesh(&mesh, SkinningMethod::DualQuaternion)
+			.unwrap();
+		let morphed = skin
+			.deform_morphed_mesh(
+				&mesh,
+				&morph_targets,
+				&morph_weights,
+				SkinningMethod::DualQuaternion,
+			)
+			.unwrap();
+		assert_eq!(unmorphed.positions[..5], morphed.positions[..5]);
+		assert!(!unmorphed.positions[5].abs_diff_eq(morphed.positions[5], 0.1));
+	}
+
+	#[test]
+	fn shader_decomposes_bevy_joint_matrices() {
+		for expected in [
+			Affine3A::from_scale_rotation_translation(
+				vec3(1.5, 0.5, 1.2),
+				Quat::from_euler(EulerRot::XYZ, 0.4, -1.1, 2.3),
+				vec3(3., -2., 5.),
+			),
+			Affine3A::from_scale_rotation_translation(
+				vec3(-1., 2., 1.),
+				Quat::from_rotation_z(0.7),
+				vec3(0., 1., 0.),
+			),
+		] {
+			let packed = shader::joint_from_mat4x4(Mat4::from(expected));
+			let decoded = Affine3A::from(ScaledDualQuat::from_packed(packed));
+			assert!(
+				decoded.abs_diff_eq(expected, 1.0e-4),
+				"{decoded:?} != {expected:?}"
+			);
+		}
+	}
+
+	/// A line-by-line port of `dqs_pre_skinning::pre_skin` and the
+	/// `dq_skinning` and `dq_math` functions it calls, reading the same packed
+	/// joints, source vertices and morph target texture as the GPU. The
+	/// entity's `DqSkin` is reduced to its method override. Quaternions
+	/// are `Vec4`s and matrices are column-major, as in WGSL.
+	mod shader {
+		use bevy::{
+			math::{Mat3, Mat4, Quat, Vec3, Vec4, Vec4Swizzles},
+			render::texture::Image,
+		};
+
+		use crate::pre_skinning::SOURCE_VERTEX_WORDS;
+
+		const MORPH_COMPONENT_COUNT: u32 = 9;
+
+		struct SkinnedTransform {
+			model: Mat4,
+			rotation: Vec4,
+			scale: Mat3,
+			dq_weight: f32,
+		}
+
+		/// Returns the skinned position, normal and tangent of vertex `index`.
+		pub(super) fn pre_skin(
+			joints: &[Mat4],
+			sources: &[u32],
+			morph_targets: &Image,
+			morph_weights: &[f32],
+			skinning_mode: u32,
+			method: u32,
+			index: usize,
+		) -> (Vec3, Vec3, Vec4) {
+			let source = &sources[index * SOURCE_VERTEX_WORDS..][..SOURCE_VERTEX_WORDS];
+			let float = |word: usize| f32::from_bits(source[word]);
+			let vec4 = |word: usize| {
+				Vec4::new(
+					float(word),
+					float(word + 1),
+					float(word + 2),
+					float(word + 3),
+				)
+			};
+
+			let mut position = vec4(0).xyz();
+			let dqs_blend = float(3);
+			let mut normal = vec4(4).xyz();
+			let mut tangent = vec4(8);
+			let joint_indices = [12, 13, 14, 15].map(|word| source[word]);
+			let joint_weights = vec4(16);
+
+			for (i, &weight) in morph_weights.iter().enumerate() {
+				if weight == 0. {
+					continue;
+				}
+
+				position += weight * morph(morph_targets, index as u32, 0, i as u32);
+				normal += weight * morph(morph_targets, index as u32, 3, i as u32);
+				tangent += (weight * morph(morph_targets, index as u32, 6, i as u32)).extend(0.);
+			}
+
+			let mut blend = 1.;
+			if skinning_mode == 1 {
+				blend = dqs_blend;
+			}
+			let skinned = skin_transform(joints, method, joint_indices, joint_weights, blend);
+
+			(
+				(skinned.model * position.extend(1.)).xyz(),
+				skin_normal(&skinned, normal),
+				skin_tangent(&skinned, tangent),
+			)
+		}
+
+		fn morph_pixel(image: &Image, vertex: u32, component: u32, morph_index: u32) -> f32 {
+			let size = image.texture_descriptor.size;
+			let component_index = MORPH_COMPONENT_COUNT * vertex + component;
+			let (x, y) = (component_index % size.width, component_index / size.width);
+
+			let texel = (morph_index * size.width * size.height + y * size.width + x) as usize;
+			let bytes = &image.data[texel * 4..][..4];
+			f32::from_le_bytes(bytes.try_into().unwrap())
+		}
+
+		fn morph(image: &Image, vertex: u32, component_offset: u32, morph_index: u32) -> Vec3 {
+			Vec3::new(
+				morph_pixel(image, vertex, component_offset, morph_index),
+				morph_pixel(image, vertex, component_offset + 1, morph_index),
+				morph_pixel(image, vertex, component_offset + 2, morph_index),
+			)
+		}
+
+		fn skin_transform(
+			joints: &[Mat4],
+			method: u32,
+			indices: [u32; 4],
+			weights: Vec4,
+			blend: f32,
+		) -> SkinnedTransform {
+			if weights.x + weights.y + weights.z + weights.w <= 0.001 {
+				return identity_transform();
+			}
+
+			let joints = indices.map(|idx| joints[idx as usize]);
+			let blend = match method {
+				1 => 0.,
+				2 => 1.,
+				_ => blend,
+			};
+			blend_joints(&joints, weights, blend)
+		}
+
+		fn blend_joints(joints: &[Mat4; 4], weights: Vec4, blend: f32) -> SkinnedTransform {
+			let t = blend.clamp(0., 1.);
+			let mut lbs = Mat4::ZERO;
+			if t < 1. {
+				for i in 0..4 {
+					let joint = joints[i];
+					lbs += mat4x4_from_dq(joint_dq(joint)) * joint_scale(joint) * weights[i];
+				}
+				if t <= 0. {
+					let mut result = identity_transform();
+					result.model = lbs;
+					result.dq_weight = 0.;
+					return result;
+				}
+			}
+
+			let dq = blend_joint_dqs(joints, weights);
+			let scale = blend_joint_scales(joints, weights);
+			let dqs = mat4x4_from_dq(dq) * scale;
+
+			SkinnedTransform {
+				model: lbs * (1. - t) + dqs * t,
+				rotation: dq[0],
+				scale: Mat3::from_mat4(scale),
+				dq_weight: t,
+			}
+		}
+
+		fn identity_transform() -> SkinnedTransform {
+			SkinnedTransform {
+				model: Mat4::IDENTITY,
+				rotation: Vec4::W,
+				scale: Mat3::IDENTITY,
+				dq_weight: 1.,
+			}
+		}
+
+		fn skin_normal(skinned: &SkinnedTransform, normal: Vec3) -> Vec3 {
+			if skinned.dq_weight < 1. {
+				return (inverse_transpose_3x3m(Mat3::from_mat4(skinned.model)) * normal)
+					.normalize();
+			}
+
+			q_rotate(
+				skinned.rotation,
+				inverse_transpose_3x3m(skinned.scale) * normal,
+			)
+			.normalize()
+		}
+
+		fn skin_tangent(skinned: &SkinnedTransform, tangent: Vec4) -> Vec4 {
+			if skinned.dq_weight < 1. {
+				let model = Mat3::from_mat4(skinned.model);
+				return (model * tangent.xyz())
+					.normalize()
+					.extend(tangent.w * handedness(model));
+			}
+
+			q_rotate(skinned.rotation, skinned.scale * tangent.xyz())
+				.normalize()
+				.extend(tangent.w * handedness(skinned.scale))
+		}
+
+		fn handedness(m: Mat3) -> f32 {
+			if m.determinant() >= 0. {
+				1.
+			} else {
+				-1.
+			}
+		}
+
+		fn blend_joint_dqs(joints: &[Mat4; 4], weights: Vec4) -> [Vec4; 2] {
+			let dq0 = joint_dq(joints[0]);
+			let q0 = dq0[0];
+
+			let mut result = dq_scale(dq0, weights.x);
+			for i in 1..4 {
+				let mut w = weights[i];
+
+				let dq = joint_dq(joints[i]);
+				if dq[0].dot(q0) < 0. {
+					w *= -1.;
+				}
+
+				result = dq_add(result, dq_scale(dq, w));
+			}
+
+			dq_normalize(result)
+		}
+
+		fn blend_joint_scales(joints: &[Mat4; 4], weights: Vec4) -> Mat4 {
+			let mut result = Mat4::from_cols(Vec4::ZERO, Vec4::ZERO, Vec4::ZERO, Vec4::W);
+			for i in 0..4 {
+				let scale = joint_scale(joints[i]);
+				result.x_axis += scale.x_axis * weights[i];
+				result.y_axis += scale.y_axis * weights[i];
+				result.z_axis += scale.z_axis * weights[i];
+			}
+
+			result
+		}
+
+		/// Returns the joint in the layout of [ScaledDualQuat::to_packed].
+		/// `q_from_mat3x3` is adapted from [Quat::from_mat3], which stands in
+		/// for it.
+		///
+		/// [ScaledDualQuat::to_packed]: crate::ScaledDualQuat::to_packed
+		pub(super) fn joint_from_mat4x4(m: Mat4) -> Mat4 {
+			let m3 = Mat3::from_mat4(m);
+			let det = m3.determinant();
+			if det.abs() <= 1.0e-8 {
+				return identity_joint();
+			}
+
+			let x = m3.x_axis.normalize() * det.signum();
+			let y = (m3.y_axis - m3.y_axis.dot(x) * x).normalize();
+			let rotation = Mat3::from_cols(x, y, x.cross(y));
+
+			let mut stretch = rotation.transpose() * m3;
+			stretch = (stretch + stretch.transpose()) * 0.5;
+
+			let real = Vec4::from(Quat::from_mat3(&rotation));
+			let dual = q_mul(m.w_axis.xyz().extend(0.), real) * 0.5;
+
+			Mat4::from_cols(
+				real,
+				dual,
+				Vec4::new(
+					stretch.x_axis.x,
+					stretch.y_axis.y,
+					stretch.z_axis.z,
+					stretch.y_axis.x,
+				),
+				Vec4::new(stretch.z_axis.x, stretch.z_axis.y, 0., 0.),
+			)
+		}
+
+		fn identity_joint() -> Mat4 {
+			Mat4::from_cols(Vec4::W, Vec4::ZERO, Vec4::new(1., 1., 1., 0.), Vec4::ZERO)
+		}
+
+		fn joint_dq(joint: Mat4) -> [Vec4; 2] {
+			[joint.x_axis, joint.y_axis]
+		}
+
+		fn joint_scale(joint: Mat4) -> Mat4 {
+			let d = joint.z_axis;
+			let o = joint.w_axis;
+
+			#[rustfmt::skip]
+			let result = Mat4::from_cols_array(&[
+				d.x, d.w, o.x, 0.,
+				d.w, d.y, o.y, 0.,
+				o.x, o.y, d.z, 0.,
+				0., 0., 0., 1.,
+			]);
+			result
+		}
+
+		fn inverse_transpose_3x3m(m: Mat3) -> Mat3 {
+			let x = m.y_axis.cross(m.z_axis);
+			let y = m.z_axis.cross(m.x_axis);
+			let z = m.x_axis.cross(m.y_axis);
+			let det = m.z_axis.dot(z);
+
+			Mat3::from_cols(x / det, y / det, z / det)
+		}
+
+		fn dq_scale(dq: [Vec4; 2], scale: f32) -> [Vec4; 2] {
+			[dq[0] * scale, dq[1] * scale]
+		}
+
+		fn dq_add(lhs: [Vec4; 2], rhs: [Vec4; 2]) -> [Vec4; 2] {
+			[lhs[0] + rhs[0], lhs[1] + rhs[1]]
+		}
+
+		fn dq_normalize(dq: [Vec4; 2]) -> [Vec4; 2] {
+			let mag = dq[0].length();
+			if mag <= 0.001 {
+				return [Vec4::W, Vec4::ZERO];
+			}
+
+			[dq[0] / mag, dq[1] / mag]
+		}
+
+		fn q_mul(lhs: Vec4, rhs: Vec4) -> Vec4 {
+			let w = lhs.w * rhs.w - lhs.xyz().dot(rhs.xyz());
+			let xyz = lhs.w * rhs.xyz() + rhs.w * lhs.xyz() + lhs.xyz().cross(rhs.xyz());
+
+			xyz.extend(w)
+		}
+
+		fn q_rotate(q: Vec4, v: Vec3) -> Vec3 {
+			let t = 2. * q.xyz().cross(v);
+			v + q.w * t + q.xyz().cross(t)
+		}
+
+		fn mat4x4_from_dq(dq: [Vec4; 2]) -> Mat4 {
+			let rotation = dq[0];
+
+			let x2 = rotation.x + rotation.x;
+			let y2 = rotation.y + rotation.y;
+			let z2 = rotation.z + rotation.z;
+			let xx = rotation.x * x2;
+			let xy = rotation.x * y2;
+			let xz = rotation.x * z2;
+			let yy = rotation.y * y2;
+			let yz = rotation.y * z2;
+			let zz = rotation.z * z2;
+			let wx = rotation.w * x2;
+			let wy = rotation.w * y2;
+			let wz = rotation.w * z2;
+
+			let m11_m12_m13 = Vec3::new(1. - (yy + zz), xy + wz, xz - wy);
+			let m21_m22_m23 = Vec3::new(xy - wz, 1. - (xx + zz), yz + wx);
+			let m31_m32_m33 = Vec3::new(xz + wy, yz - wx, 1. - (xx + yy));
+
+			let lhs = dq[1] * 2.;
+			let rhs = (-dq[0].xyz()).extend(dq[0].w);
+			let product = q_mul(lhs, rhs);
+
+			Mat4::from_cols(
+				m11_m12_m13.extend(0.),
+				m21_m22_m23.extend(0.),
+				m31_m32_m33.extend(0.),
+				product.xyz().extend(1.),
+			)
+		}
+	}
+}
@@ -37,6 +37,12 @@ fn q_mul(lhs: vec4<f32>, rhs: vec4<f32>) -> vec4<f32> {
 	return vec4<f32>(xyz, w);
 }
 
+/// Rotates a vector by a unit quaternion
+fn q_rotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+	let t = 2.0 * cross(q.xyz, v);
+	return v + (q.w * t) + cross(q.xyz, t);
+}
+
 /// Dual-quaternion to 4x4 transform matrix
 fn mat4x4_from_dq(dq: mat2x4<f32>) -> mat4x4<f32> {
 	// Convert the "real" quaternion to a 3x3 rotation matrix
@@ -1,47 +1,505 @@
 #define_import_path bevy_dqskinning::dq_skinning
 
-#import bevy_pbr::mesh_types::SkinnedMesh
 #import bevy_dqskinning::dq_math
 
+// World-space vertex data written by the `dqs_pre_skinning` compute shader
+struct PreSkinnedVertex {
+	position: vec4<f32>,
+	normal: vec4<f32>,
+	tangent: vec4<f32>,
+	// Last frame's position, for motion vectors
+	previous_position: vec4<f32>,
+};
+
+#ifdef DQS_PRE_SKINNED
+@group(2) @binding(100)
+var<storage, read> pre_skinned_vertices: array<PreSkinnedVertex>;
+#endif
+
 #ifdef SKINNED
 
+#ifndef DQS_PRE_SKINNING
+#import bevy_pbr::{mesh_bindings::mesh, mesh_functions}
+#endif
+
+// Joint poses are uploaded as dual quaternions plus a symmetric scale/shear
+// matrix, one per mat4x4 slot of the `DqSkinBuffer` (see
+// `ScaledDualQuat::to_packed`).
+struct DqsJoint {
+	real: vec4<f32>,
+	dual: vec4<f32>,
+	// xx, yy, zz, xy
+	scale_diag: vec4<f32>,
+	// xz, yz, unused
+	scale_off_diag: vec4<f32>,
+};
+
+/// An entity's block in the `DqSkinBuffer` (0 if it has none), its
+/// `SkinningMethod` override, and whether its joints are relative to a
+/// `DqsSharedPalette` root rather than in world space.
+struct DqSkin {
+	block: u32,
+	method: u32,
+	shared_palette: bool,
+};
+
+// Per-entity `SkinningMethod` overrides
+const SKINNING_METHOD_LINEAR: u32 = 1u;
+const SKINNING_METHOD_DUAL_QUATERNION: u32 = 2u;
+
+// Bits of the mesh uniform's `flags` holding the entity's `DqSkin`, packed by
+// `DqSkin::flags`
+const DQ_SKIN_METHOD_SHIFT: u32 = 2u;
+const DQ_SKIN_METHOD_MASK: u32 = 3u;
+const DQ_SKIN_SHARED_BIT: u32 = 16u;
+const DQ_SKIN_BLOCK_SHIFT: u32 = 5u;
+const DQ_SKIN_BLOCK_MASK: u32 = 0x03ffffffu;
+
+fn unpack_dq_skin(flags: u32) -> DqSkin {
+	return DqSkin(
+		(flags >> DQ_SKIN_BLOCK_SHIFT) & DQ_SKIN_BLOCK_MASK,
+		(flags >> DQ_SKIN_METHOD_SHIFT) & DQ_SKIN_METHOD_MASK,
+		(flags & DQ_SKIN_SHARED_BIT) != 0u
+	);
+}
+
+#ifndef DQS_PRE_SKINNING
+/// The `DqSkin` of the mesh drawn as `instance_index`.
+fn mesh_dq_skin(instance_index: u32) -> DqSkin {
+	return unpack_dq_skin(mesh[instance_index].flags);
+}
+
+/// Places a skinned model matrix in the world. Shared palettes are relative to
+/// their root, so they're placed with the mesh's own transform.
+fn dq_skin_model(skin: DqSkin, instance_index: u32, model: mat4x4<f32>) -> mat4x4<f32> {
+	if (skin.shared_palette) {
+		return mesh_functions::get_model_matrix(instance_index) * model;
+	}
+	return model;
+}
+
+/// Like `dq_skin_model`, with last frame's transform of the mesh.
+fn dq_skin_previous_model(
+	skin: DqSkin,
+	instance_index: u32,
+	model: mat4x4<f32>,
+) -> mat4x4<f32> {
+	if (skin.shared_palette) {
+		return mesh_functions::get_previous_model_matrix(instance_index) * model;
+	}
+	return model;
+}
+
+/// Places a normal from `skin_normal` in the world, like `dq_skin_model`.
+fn dq_skin_normal(skin: DqSkin, instance_index: u32, normal: vec3<f32>) -> vec3<f32> {
+	if (skin.shared_palette) {
+		return mesh_functions::mesh_normal_local_to_world(normal, instance_index);
+	}
+	return normal;
+}
+
+/// Places a tangent from `skin_tangent` in the world, like `dq_skin_model`.
+fn dq_skin_tangent(skin: DqSkin, instance_index: u32, tangent: vec4<f32>) -> vec4<f32> {
+	if (skin.shared_palette) {
+		return mesh_functions::mesh_tangent_local_to_world(
+			mesh_functions::get_model_matrix(instance_index),
+			tangent,
+			instance_index
+		);
+	}
+	return tangent;
+}
+
+struct SkinnedMesh {
+	data: array<mat4x4<f32>, 256u>,
+};
+
+// Bevy's joint matrices, for entities without a block in the `DqSkinBuffer`
 @group(1) @binding(1)
-var<uniform> joint_xforms: SkinnedMesh;
+var<uniform> joint_matrices: SkinnedMesh;
+#endif
+
+#ifdef DQS_SKIN_BUFFER
+// The joints of every dual-quaternion-skinned entity, along with last frame's
+// joints and morph weights for motion vectors. Each entity's block starts with
+// a header holding the indices of its joints and last frame's joints, followed
+// by `PREVIOUS_MORPH_WEIGHT_SLOTS` slots of last frame's morph weights.
+#ifdef DQS_PRE_SKINNING
+@group(0) @binding(0)
+#else
+@group(2) @binding(101)
+#endif
+var<storage, read> dq_skins: array<vec4<f32>>;
+
+const PREVIOUS_MORPH_WEIGHT_SLOTS: u32 = 4u;
+
+fn load_buffer_joint(slot: u32) -> DqsJoint {
+	let offset = slot * 4u;
+	return DqsJoint(
+		dq_skins[offset],
+		dq_skins[offset + 1u],
+		dq_skins[offset + 2u],
+		dq_skins[offset + 3u]
+	);
+}
+
+/// Like `skin_transform`, but with the joints of the previous frame. Falls back
+/// to the current joints for entities that weren't drawn last frame.
+fn previous_skin_transform(
+	skin: DqSkin,
+	indices: vec4<u32>,
+	weights: vec4<f32>,
+	blend: f32,
+) -> SkinnedTransform {
+	if (skin.block == 0u) {
+		return skin_transform(skin, indices, weights, blend);
+	}
+	if ((weights.x + weights.y + weights.z + weights.w) <= 0.001) {
+		return identity_transform();
+	}
+
+	let base = u32(dq_skins[skin.block * 4u].y);
+	let joints = array<DqsJoint, 4>(
+		load_buffer_joint(base + indices.x),
+		load_buffer_joint(base + indices.y),
+		load_buffer_joint(base + indices.z),
+		load_buffer_joint(base + indices.w)
+	);
+	return blend_joints(joints, weights, skinning_blend(skin, blend));
+}
+
+/// The previous frame's weight of morph target `morph_index`, or `current` if
+/// there's no previous data.
+fn previous_morph_weight(skin: DqSkin, morph_index: u32, current: f32) -> f32 {
+	if (skin.block == 0u) {
+		return current;
+	}
+
+	let offset = (skin.block + 1u) * 4u + morph_index / 4u;
+	return dq_skins[offset][morph_index % 4u];
+}
+#else
+// Without the buffer (e.g. on WebGL2), skinning and morphing don't contribute
+// to motion vectors
+fn previous_skin_transform(
+	skin: DqSkin,
+	indices: vec4<u32>,
+	weights: vec4<f32>,
+	blend: f32,
+) -> SkinnedTransform {
+	return skin_transform(skin, indices, weights, blend);
+}
+
+fn previous_morph_weight(skin: DqSkin, morph_index: u32, current: f32) -> f32 {
+	return current;
+}
+#endif
+
+/// Loads one of the entity's current joints, from the `DqSkinBuffer` if it has
+/// a block there, or else by decomposing Bevy's joint matrix.
+fn load_joint(skin: DqSkin, joint_index: u32) -> DqsJoint {
+#ifdef DQS_SKIN_BUFFER
+	if (skin.block != 0u) {
+		return load_buffer_joint(u32(dq_skins[skin.block * 4u].x) + joint_index);
+	}
+#endif
+#ifdef DQS_PRE_SKINNING
+	// Only entities with a block are pre-skinned
+	return identity_joint();
+#else
+	return joint_from_mat4x4(joint_matrices.data[joint_index]);
+#endif
+}
+
+/// Splits an affine joint matrix into a rotation and a symmetric scale/shear
+/// matrix. This approximates the polar decomposition done on the CPU by
+/// orthonormalizing the matrix's columns, which is exact for rotations with
+/// non-uniform scale along the joint's own axes.
+fn joint_from_mat4x4(m: mat4x4<f32>) -> DqsJoint {
+	let m3 = mat3x3<f32>(m[0].xyz, m[1].xyz, m[2].xyz);
+	let det = determinant(m3);
+	if (abs(det) <= 1.0e-8) {
+		return identity_joint();
+	}
+
+	// Mirroring is kept in the scale, so the rotation is always proper
+	let x = normalize(m3[0]) * sign(det);
+	let y = normalize(m3[1] - dot(m3[1], x) * x);
+	let rotation = mat3x3<f32>(x, y, cross(x, y));
+
+	var stretch = transpose(rotation) * m3;
+	stretch = (stretch + transpose(stretch)) * 0.5;
+
+	let real = dq_math::q_from_mat3x3(rotation);
+	let dual = dq_math::q_mul(vec4<f32>(m[3].xyz, 0.0), real) * 0.5;
+
+	return DqsJoint(
+		real,
+		dual,
+		vec4<f32>(stretch[0].x, stretch[1].y, stretch[2].z, stretch[1].x),
+		vec4<f32>(stretch[2].x, stretch[2].y, 0.0, 0.0)
+	);
+}
+
+fn identity_joint() -> DqsJoint {
+	return DqsJoint(
+		vec4<f32>(0.0, 0.0, 0.0, 1.0),
+		vec4<f32>(0.0),
+		vec4<f32>(1.0, 1.0, 1.0, 0.0),
+		vec4<f32>(0.0)
+	);
+}
 
 fn skin_model(
+	skin: DqSkin,
 	indices: vec4<u32>,
 	weights: vec4<f32>
 ) -> mat4x4<f32> {
+	return skin_model_blended(skin, indices, weights, 1.0);
+}
+
+/// Mixes the results of linear blend skinning and dual quaternion skinning, by
+/// `blend` from 0.0 (fully linear) to 1.0 (fully dual quaternion), unless the
+/// entity overrides its skinning method.
+fn skin_model_blended(
+	skin: DqSkin,
+	indices: vec4<u32>,
+	weights: vec4<f32>,
+	blend: f32,
+) -> mat4x4<f32> {
+	return skin_transform(skin, indices, weights, blend).model;
+}
+
+/// A vertex's blended joint transform, along with the parts needed to skin its
+/// normal and tangent consistently with it.
+struct SkinnedTransform {
+	model: mat4x4<f32>,
+	// Blended dual quaternion rotation
+	rotation: vec4<f32>,
+	// Blended scale/shear, applied before `rotation`
+	scale: mat3x3<f32>,
+	// From 0.0 (fully linear) to 1.0 (fully dual quaternion)
+	dq_weight: f32,
+};
+
+/// Like `skin_model_blended`, but also returns the blended rotation and scale
+/// for `skin_normal` and `skin_tangent`.
+fn skin_transform(
+	skin: DqSkin,
+	indices: vec4<u32>,
+	weights: vec4<f32>,
+	blend: f32,
+) -> SkinnedTransform {
 	if ((weights.x + weights.y + weights.z + weights.w) <= 0.001) {
-		return mat4x4<f32>(
-			1.0, 0.0, 0.0, 0.0,
-			0.0, 1.0, 0.0, 0.0,
-			0.0, 0.0, 1.0, 0.0,
-			0.0, 0.0, 0.0, 1.0
+		return identity_transform();
+	}
+
+	return blend_joints(load_joints(skin, indices), weights, skinning_blend(skin, blend));
+}
+
+/// Blends the four joints influencing a vertex, mixing linear blend skinning
+/// and dual quaternion skinning by `blend`.
+fn blend_joints(
+	joints: array<DqsJoint, 4>,
+	weights: vec4<f32>,
+	blend: f32,
+) -> SkinnedTransform {
+	let t = clamp(blend, 0.0, 1.0);
+	var lbs = mat4x4<f32>(
+		vec4<f32>(0.0),
+		vec4<f32>(0.0),
+		vec4<f32>(0.0),
+		vec4<f32>(0.0)
+	);
+	var local_joints = joints;
+	if (t < 1.0) {
+		for (var i: u32 = 0u; i < 4; i = i + 1) {
+			let joint = local_joints[i];
+			lbs += dq_math::mat4x4_from_dq(joint_dq(joint)) * joint_scale(joint) * weights[i];
+		}
+		if (t <= 0.0) {
+			var result = identity_transform();
+			result.model = lbs;
+			result.dq_weight = 0.0;
+			return result;
+		}
+	}
+
+	let dq = blend_joint_dqs(joints, weights);
+	let scale = blend_joint_scales(joints, weights);
+	let dqs = dq_math::mat4x4_from_dq(dq) * scale;
+
+	return SkinnedTransform(
+		lbs * (1.0 - t) + dqs * t,
+		dq[0],
+		mat3x3<f32>(scale[0].xyz, scale[1].xyz, scale[2].xyz),
+		t
+	);
+}
+
+fn load_joints(skin: DqSkin, indices: vec4<u32>) -> array<DqsJoint, 4> {
+	return array<DqsJoint, 4>(
+		load_joint(skin, indices.x),
+		load_joint(skin, indices.y),
+		load_joint(skin, indices.z),
+		load_joint(skin, indices.w)
+	);
+}
+
+fn identity_transform() -> SkinnedTransform {
+	return SkinnedTransform(
+		identity_mat4x4(),
+		vec4<f32>(0.0, 0.0, 0.0, 1.0),
+		mat3x3<f32>(
+			1.0, 0.0, 0.0,
+			0.0, 1.0, 0.0,
+			0.0, 0.0, 1.0
+		),
+		1.0
+	);
+}
+
+/// Skins a normal vector. Fully dual-quaternion-skinned normals are rotated by
+/// the blended rotation after the inverse transpose of the blended scale; any
+/// linear blending falls back to the inverse transpose of the model matrix.
+fn skin_normal(skinned: SkinnedTransform, normal: vec3<f32>) -> vec3<f32> {
+	if (skinned.dq_weight < 1.0) {
+		return skin_normals(skinned.model, normal);
+	}
+
+	return normalize(
+		dq_math::q_rotate(skinned.rotation, inverse_transpose_3x3m(skinned.scale) * normal)
+	);
+}
+
+/// Skins a tangent vector, flipping its handedness (`w`) if the transform is
+/// mirrored.
+fn skin_tangent(skinned: SkinnedTransform, tangent: vec4<f32>) -> vec4<f32> {
+	if (skinned.dq_weight < 1.0) {
+		let model = mat3x3<f32>(
+			skinned.model[0].xyz,
+			skinned.model[1].xyz,
+			skinned.model[2].xyz
 		);
+
+		return vec4<f32>(
+			normalize(model * tangent.xyz),
+			tangent.w * handedness(model)
+		);
+	}
+
+	return vec4<f32>(
+		normalize(dq_math::q_rotate(skinned.rotation, skinned.scale * tangent.xyz)),
+		tangent.w * handedness(skinned.scale)
+	);
+}
+
+fn handedness(m: mat3x3<f32>) -> f32 {
+	return select(-1.0, 1.0, determinant(m) >= 0.0);
+}
+
+/// Applies the entity's skinning method override to a vertex's blend factor.
+fn skinning_blend(skin: DqSkin, blend: f32) -> f32 {
+	switch skin.method {
+		case SKINNING_METHOD_LINEAR: {
+			return 0.0;
+		}
+		case SKINNING_METHOD_DUAL_QUATERNION: {
+			return 1.0;
+		}
+		default: {
+			return blend;
+		}
 	}
+}
+
+fn blend_dqs(
+	skin: DqSkin,
+	indices: vec4<u32>,
+	weights: vec4<f32>
+) -> mat2x4<f32> {
+	return blend_joint_dqs(load_joints(skin, indices), weights);
+}
 
-	let dq0 = dq_math::dq_from_mat4x4(joint_xforms.data[indices.x]);
-	let q0 = normalize(dq0[0]);
+fn blend_joint_dqs(
+	joints: array<DqsJoint, 4>,
+	weights: vec4<f32>
+) -> mat2x4<f32> {
+	var local_joints = joints;
+	let dq0 = joint_dq(local_joints[0]);
+	let q0 = dq0[0];
 
 	var result: mat2x4<f32> = dq_math::dq_scale(dq0, weights.x);
 
 	for (var i: u32 = 1u; i < 4; i = i + 1) {
-		let k = indices[i];
 		var w: f32 = weights[i];
 
-		let m = joint_xforms.data[k];
-		let dq = dq_math::dq_from_mat4x4(m);
-
-		let rotation = normalize(dq[0]);
-		if (dot(rotation, q0) < 0.0) {
+		let dq = joint_dq(local_joints[i]);
+		if (dot(dq[0], q0) < 0.0) {
 			w = w * -1.0;
 		}
 
 		result = dq_math::dq_add(result, dq_math::dq_scale(dq, w));
 	}
 
-	return dq_math::mat4x4_from_dq(dq_math::dq_normalize(result));
+	return dq_math::dq_normalize(result);
+}
+
+/// Linearly blends the joints' scale/shear matrices, which are applied before
+/// the blended rigid transform.
+fn blend_scales(
+	skin: DqSkin,
+	indices: vec4<u32>,
+	weights: vec4<f32>
+) -> mat4x4<f32> {
+	return blend_joint_scales(load_joints(skin, indices), weights);
+}
+
+fn blend_joint_scales(
+	joints: array<DqsJoint, 4>,
+	weights: vec4<f32>
+) -> mat4x4<f32> {
+	var result = mat4x4<f32>(
+		vec4<f32>(0.0),
+		vec4<f32>(0.0),
+		vec4<f32>(0.0),
+		vec4<f32>(0.0, 0.0, 0.0, 1.0)
+	);
+
+	var local_joints = joints;
+	for (var i: u32 = 0u; i < 4; i = i + 1) {
+		let scale = joint_scale(local_joints[i]);
+		result[0] += scale[0] * weights[i];
+		result[1] += scale[1] * weights[i];
+		result[2] += scale[2] * weights[i];
+	}
+
+	return result;
+}
+
+fn joint_dq(joint: DqsJoint) -> mat2x4<f32> {
+	return mat2x4<f32>(joint.real, joint.dual);
+}
+
+fn joint_scale(joint: DqsJoint) -> mat4x4<f32> {
+	let d = joint.scale_diag;
+	let o = joint.scale_off_diag;
+
+	return mat4x4<f32>(
+		d.x, d.w, o.x, 0.0,
+		d.w, d.y, o.y, 0.0,
+		o.x, o.y, d.z, 0.0,
+		0.0, 0.0, 0.0, 1.0
+	);
+}
+
+fn identity_mat4x4() -> mat4x4<f32> {
+	return mat4x4<f32>(
+		1.0, 0.0, 0.0, 0.0,
+		0.0, 1.0, 0.0, 0.0,
+		0.0, 0.0, 1.0, 0.0,
+		0.0, 0.0, 0.0, 1.0
+	);
 }
 
 fn inverse_transpose_3x3m(in: mat3x3<f32>) -> mat3x3<f32> {
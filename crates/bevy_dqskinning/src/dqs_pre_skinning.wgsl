@@ -0,0 +1,111 @@
+#import bevy_dqskinning::dq_skinning
+
+// Packed on the CPU by `pre_skinning::source_vertices`
+struct SourceVertex {
+	position: vec3<f32>,
+	dqs_blend: f32,
+	normal: vec4<f32>,
+	tangent: vec4<f32>,
+	joint_indices: vec4<u32>,
+	joint_weights: vec4<f32>,
+};
+
+struct PreSkinningParams {
+	vertex_count: u32,
+	morph_count: u32,
+	// 0: dual quaternion, 1: blended
+	skinning_mode: u32,
+	// The entity's `DqSkin`, packed like the mesh uniform's flags
+	dq_skin_flags: u32,
+	morph_weights: array<vec4<f32>, 16u>,
+};
+
+@group(0) @binding(1)
+var<storage, read> source_vertices: array<SourceVertex>;
+
+@group(0) @binding(2)
+var<storage, read_write> skinned_vertices: array<dq_skinning::PreSkinnedVertex>;
+
+@group(0) @binding(3)
+var<uniform> params: PreSkinningParams;
+
+@group(0) @binding(4)
+var morph_targets: texture_3d<f32>;
+
+// Matches the layout of Bevy's `MorphAttributes`: position, normal, tangent
+const MORPH_COMPONENT_COUNT: u32 = 9u;
+
+fn morph_pixel(vertex: u32, component: u32, morph_index: u32) -> f32 {
+	let width = textureDimensions(morph_targets).x;
+	let component_index = MORPH_COMPONENT_COUNT * vertex + component;
+	let coord = vec2<u32>(component_index % width, component_index / width);
+
+	return textureLoad(morph_targets, vec3<u32>(coord, morph_index), 0).r;
+}
+
+fn morph(vertex: u32, component_offset: u32, morph_index: u32) -> vec3<f32> {
+	return vec3<f32>(
+		morph_pixel(vertex, component_offset, morph_index),
+		morph_pixel(vertex, component_offset + 1u, morph_index),
+		morph_pixel(vertex, component_offset + 2u, morph_index),
+	);
+}
+
+@compute @workgroup_size(64)
+fn pre_skin(@builtin(global_invocation_id) id: vec3<u32>) {
+	let index = id.x;
+	if (index >= params.vertex_count) {
+		return;
+	}
+
+	let source = source_vertices[index];
+	var position = source.position;
+	var normal = source.normal.xyz;
+	var tangent = source.tangent;
+	var previous_position = source.position;
+	let dq_skin = dq_skinning::unpack_dq_skin(params.dq_skin_flags);
+
+	for (var i: u32 = 0u; i < params.morph_count; i = i + 1u) {
+		let weight = params.morph_weights[i / 4u][i % 4u];
+		let previous_weight = dq_skinning::previous_morph_weight(dq_skin, i, weight);
+		if (previous_weight != 0.0) {
+			previous_position += previous_weight * morph(index, 0u, i);
+		}
+		if (weight == 0.0) {
+			continue;
+		}
+
+		position += weight * morph(index, 0u, i);
+		normal += weight * morph(index, 3u, i);
+		tangent += vec4<f32>(weight * morph(index, 6u, i), 0.0);
+	}
+
+	var blend = 1.0;
+	if (params.skinning_mode == 1u) {
+		blend = source.dqs_blend;
+	}
+	let skinned = dq_skinning::skin_transform(
+		dq_skin,
+		source.joint_indices,
+		source.joint_weights,
+		blend
+	);
+	let previous_skinned = dq_skinning::previous_skin_transform(
+		dq_skin,
+		source.joint_indices,
+		source.joint_weights,
+		blend
+	);
+
+	var out: dq_skinning::PreSkinnedVertex;
+	out.position = skinned.model * vec4<f32>(position, 1.0);
+	out.previous_position = previous_skinned.model * vec4<f32>(previous_position, 1.0);
+	if (any(normal != vec3<f32>(0.0))) {
+		out.normal = vec4<f32>(dq_skinning::skin_normal(skinned, normal), 0.0);
+	}
+	if (any(tangent.xyz != vec3<f32>(0.0))) {
+		out.tangent = dq_skinning::skin_tangent(skinned, tangent);
+	}
+
+	skinned_vertices[index] = out;
+}
@@ -34,17 +34,61 @@ fn morph_vertex(vertex_in: Vertex) -> Vertex {
 #endif
 
 @vertex
-fn vertex(vertex_no_morph: Vertex) -> VertexOutput {
+fn vertex(
+	vertex_no_morph: Vertex,
+#ifdef DQS_BLEND
+	@location(8) dqs_blend: f32,
+#endif
+#ifdef DQS_PRE_SKINNED
+#ifndef MORPH_TARGETS
+	@builtin(vertex_index) vertex_index: u32,
+#endif
+#endif
+) -> VertexOutput {
 	var out: VertexOutput;
 
+#ifdef DQS_PRE_SKINNED
+	// Morphing and skinning were already done by the `dqs_pre_skinning` compute
+	// shader, which outputs world-space data
+	var vertex = vertex_no_morph;
 #ifdef MORPH_TARGETS
+	let pre_skinned = dq_skinning::pre_skinned_vertices[vertex_no_morph.index];
+#else
+	let pre_skinned = dq_skinning::pre_skinned_vertices[vertex_index];
+#endif
+	vertex.position = pre_skinned.position.xyz;
+#ifdef NORMAL_PREPASS_OR_DEFERRED_PREPASS
+	vertex.normal = pre_skinned.normal.xyz;
+#ifdef VERTEX_TANGENTS
+	vertex.tangent = pre_skinned.tangent;
+#endif
+#endif
+#else ifdef MORPH_TARGETS
 	var vertex = morph_vertex(vertex_no_morph);
 #else
 	var vertex = vertex_no_morph;
 #endif
 
 #ifdef SKINNED
-	var model = dq_skinning::skin_model(vertex.joint_indices, vertex.joint_weights);
+	let dq_skin = dq_skinning::mesh_dq_skin(vertex_no_morph.instance_index);
+#ifdef DQS_PRE_SKINNED
+	let skinned = dq_skinning::identity_transform();
+#else ifdef DQS_BLEND
+	let skinned = dq_skinning::skin_transform(
+		dq_skin,
+		vertex.joint_indices,
+		vertex.joint_weights,
+		dqs_blend
+	);
+#else
+	let skinned = dq_skinning::skin_transform(
+		dq_skin,
+		vertex.joint_indices,
+		vertex.joint_weights,
+		1.0
+	);
+#endif
+	var model = dq_skinning::dq_skin_model(dq_skin, vertex_no_morph.instance_index, skinned.model);
 #else // SKINNED
 	// Use vertex_no_morph.instance_index instead of vertex.instance_index to work around a wgpu dx12 bug.
 	// See https://github.com/gfx-rs/naga/issues/2416
@@ -67,7 +111,11 @@ fn vertex(vertex_no_morph: Vertex) -> VertexOutput {
 
 #ifdef NORMAL_PREPASS_OR_DEFERRED_PREPASS
 #ifdef SKINNED
-	out.world_normal = dq_skinning::skin_normals(model, vertex.normal);
+	out.world_normal = dq_skinning::dq_skin_normal(
+		dq_skin,
+		vertex_no_morph.instance_index,
+		dq_skinning::skin_normal(skinned, vertex.normal)
+	);
 #else // SKINNED
 	out.world_normal = mesh_functions::mesh_normal_local_to_world(
 		vertex.normal,
@@ -78,6 +126,13 @@ fn vertex(vertex_no_morph: Vertex) -> VertexOutput {
 #endif // SKINNED
 
 #ifdef VERTEX_TANGENTS
+#ifdef SKINNED
+	out.world_tangent = dq_skinning::dq_skin_tangent(
+		dq_skin,
+		vertex_no_morph.instance_index,
+		dq_skinning::skin_tangent(skinned, vertex.tangent)
+	);
+#else // SKINNED
 	out.world_tangent = mesh_functions::mesh_tangent_local_to_world(
 		model,
 		vertex.tangent,
@@ -85,6 +140,7 @@ fn vertex(vertex_no_morph: Vertex) -> VertexOutput {
 		// See https://github.com/gfx-rs/naga/issues/2416
 		vertex_no_morph.instance_index
 	);
+#endif // SKINNED
 #endif // VERTEX_TANGENTS
 #endif // NORMAL_PREPASS_OR_DEFERRED_PREPASS
 
@@ -95,12 +151,62 @@ fn vertex(vertex_no_morph: Vertex) -> VertexOutput {
 	out.world_position = mesh_functions::mesh_position_local_to_world(model, vec4<f32>(vertex.position, 1.0));
 
 #ifdef MOTION_VECTOR_PREPASS
+#ifdef DQS_PRE_SKINNED
+	out.previous_world_position = mesh_functions::mesh_position_local_to_world(
+		dq_skinning::dq_skin_previous_model(
+			dq_skin,
+			vertex_no_morph.instance_index,
+			dq_skinning::identity_mat4x4()
+		),
+		vec4<f32>(pre_skinned.previous_position.xyz, 1.0)
+	);
+#else ifdef SKINNED
+	// Repeat morphing and skinning with last frame's morph weights and joints
+	var previous_position = vertex_no_morph.position;
+#ifdef MORPH_TARGETS
+	for (var i: u32 = 0u; i < morph::layer_count(); i = i + 1) {
+		let weight = dq_skinning::previous_morph_weight(
+			dq_skin,
+			i,
+			morph::weight_at(i)
+		);
+		if (weight == 0.0) {
+			continue;
+		}
+		previous_position += weight * morph::morph(vertex_no_morph.index, morph::position_offset, i);
+	}
+#endif
+#ifdef DQS_BLEND
+	let previous_skinned = dq_skinning::previous_skin_transform(
+		dq_skin,
+		vertex_no_morph.joint_indices,
+		vertex_no_morph.joint_weights,
+		dqs_blend
+	);
+#else
+	let previous_skinned = dq_skinning::previous_skin_transform(
+		dq_skin,
+		vertex_no_morph.joint_indices,
+		vertex_no_morph.joint_weights,
+		1.0
+	);
+#endif
+	out.previous_world_position = mesh_functions::mesh_position_local_to_world(
+		dq_skinning::dq_skin_previous_model(
+			dq_skin,
+			vertex_no_morph.instance_index,
+			previous_skinned.model
+		),
+		vec4<f32>(previous_position, 1.0)
+	);
+#else
 	// Use vertex_no_morph.instance_index instead of vertex.instance_index to work around a wgpu dx12 bug.
 	// See https://github.com/gfx-rs/naga/issues/2416
 	out.previous_world_position = mesh_functions::mesh_position_local_to_world(
 		mesh_functions::get_previous_model_matrix(vertex_no_morph.instance_index),
 		vec4<f32>(vertex.position, 1.0)
 	);
+#endif // DQS_PRE_SKINNED
 #endif // MOTION_VECTOR_PREPASS
 
 #ifdef VERTEX_OUTPUT_INSTANCE_INDEX
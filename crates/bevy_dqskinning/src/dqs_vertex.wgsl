@@ -32,17 +32,61 @@ fn morph_vertex(in: Vertex) -> Vertex {
 #endif
 
 @vertex
-fn vertex(in: Vertex) -> VertexOutput {
+fn vertex(
+	in: Vertex,
+#ifdef DQS_BLEND
+	@location(8) dqs_blend: f32,
+#endif
+#ifdef DQS_PRE_SKINNED
+#ifndef MORPH_TARGETS
+	@builtin(vertex_index) vertex_index: u32,
+#endif
+#endif
+) -> VertexOutput {
 	var out: VertexOutput;
 
+#ifdef DQS_PRE_SKINNED
+	// Morphing and skinning were already done by the `dqs_pre_skinning` compute
+	// shader, which outputs world-space data
+	var vertex = in;
 #ifdef MORPH_TARGETS
+	let pre_skinned = dq_skinning::pre_skinned_vertices[in.index];
+#else
+	let pre_skinned = dq_skinning::pre_skinned_vertices[vertex_index];
+#endif
+	vertex.position = pre_skinned.position.xyz;
+#ifdef VERTEX_NORMALS
+	vertex.normal = pre_skinned.normal.xyz;
+#endif
+#ifdef VERTEX_TANGENTS
+	vertex.tangent = pre_skinned.tangent;
+#endif
+#else ifdef MORPH_TARGETS
 	var vertex = morph_vertex(in);
 #else
 	var vertex = in;
 #endif
 
 #ifdef SKINNED
-	var model = dq_skinning::skin_model(vertex.joint_indices, vertex.joint_weights);
+	let dq_skin = dq_skinning::mesh_dq_skin(in.instance_index);
+#ifdef DQS_PRE_SKINNED
+	let skinned = dq_skinning::identity_transform();
+#else ifdef DQS_BLEND
+	let skinned = dq_skinning::skin_transform(
+		dq_skin,
+		vertex.joint_indices,
+		vertex.joint_weights,
+		dqs_blend
+	);
+#else
+	let skinned = dq_skinning::skin_transform(
+		dq_skin,
+		vertex.joint_indices,
+		vertex.joint_weights,
+		1.0
+	);
+#endif
+	var model = dq_skinning::dq_skin_model(dq_skin, in.instance_index, skinned.model);
 #else
 	// TODO: See https://github.com/gfx-rs/naga/issues/2416
 	var model = mesh_functions::get_model_matrix(in.instance_index);
@@ -50,7 +94,11 @@ fn vertex(in: Vertex) -> VertexOutput {
 
 #ifdef VERTEX_NORMALS
 #ifdef SKINNED
-	out.world_normal = dq_skinning::skin_normals(model, vertex.normal);
+	out.world_normal = dq_skinning::dq_skin_normal(
+		dq_skin,
+		in.instance_index,
+		dq_skinning::skin_normal(skinned, vertex.normal)
+	);
 #else
 	out.world_normal = mesh_functions::mesh_normal_local_to_world(
 		vertex.normal,
@@ -76,6 +124,13 @@ fn vertex(in: Vertex) -> VertexOutput {
 #endif
 
 #ifdef VERTEX_TANGENTS
+#ifdef SKINNED
+	out.world_tangent = dq_skinning::dq_skin_tangent(
+		dq_skin,
+		in.instance_index,
+		dq_skinning::skin_tangent(skinned, vertex.tangent)
+	);
+#else
 	out.world_tangent = mesh_functions::mesh_tangent_local_to_world(
 		model,
 		vertex.tangent,
@@ -83,6 +138,7 @@ fn vertex(in: Vertex) -> VertexOutput {
 		in.instance_index
 	);
 #endif
+#endif
 
 #ifdef VERTEX_COLORS
 	out.color = vertex.color;
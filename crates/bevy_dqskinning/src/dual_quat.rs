@@ -2,15 +2,26 @@
 use core::fmt;
 use core::ops;
 
+#[cfg(feature = "bevy")]
 use bevy::{
-	core::{Pod, Zeroable},
-	math::{Affine3A, Mat3A, Mat4, Quat, Vec3, Vec3A},
 	reflect::Reflect,
-	transform::components::GlobalTransform,
+	transform::components::{GlobalTransform, Transform},
 };
+use bytemuck::{Pod, Zeroable};
+use glam::{Affine3A, Mat3A, Mat4, Quat, Vec3, Vec3A};
 
-#[derive(Clone, Copy)]
-#[cfg_attr(not(target_arch = "spirv"), derive(Reflect))]
+/// Below this, the rotation part of a [DualQuat] is treated as the identity
+/// when extracting its screw parameters.
+const SCREW_ANGLE_EPSILON: f32 = 1.0e-6;
+
+/// Dual quaternions with a smaller magnitude than this can't be normalized.
+const NORMALIZE_EPSILON: f32 = f32::EPSILON * 2.;
+
+/// How far from `1.0` the magnitude of a normalized [DualQuat] may be.
+const NORMALIZED_TOLERANCE: f32 = 1.0e-5;
+
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(all(feature = "bevy", not(target_arch = "spirv")), derive(Reflect))]
 #[repr(C)]
 pub struct DualQuat(pub Quat, pub Quat);
 
@@ -64,11 +75,18 @@ impl DualQuat {
 		self.magnitude()
 	}
 
+	/// Returns `self` with a magnitude of `1.0`.
+	///
+	/// # Panics
+	///
+	/// Panics in debug builds if `self` can't be normalized (see
+	/// [DualQuat::try_normalize]). Release builds return non-finite values
+	/// instead.
 	#[inline]
 	pub fn normalize(self) -> Self {
 		let mag = self.magnitude();
-		assert!(
-			mag > f32::EPSILON * 2.0,
+		debug_assert!(
+			mag.is_finite() && mag > NORMALIZE_EPSILON,
 			"Attempted to normalize a DualQuat with magnitude {mag}; \
 				(({:.3} [{:.3} {:.3} {:.3}]), ({:.3} [{:.3} {:.3} {:.3}]))",
 			self.0.w,
@@ -84,11 +102,208 @@ impl DualQuat {
 		Self(self.real() / mag, self.dual() / mag)
 	}
 
+	/// Returns `self` with a magnitude of `1.0`, or `None` if its magnitude is
+	/// zero, close to zero, or non-finite.
+	#[inline]
+	pub fn try_normalize(self) -> Option<Self> {
+		let mag = self.magnitude();
+		if mag.is_finite() && mag > NORMALIZE_EPSILON {
+			Some(Self(self.real() / mag, self.dual() / mag))
+		} else {
+			None
+		}
+	}
+
+	/// Returns `self` with a magnitude of `1.0`, or [DualQuat::IDENTITY] if it
+	/// can't be normalized.
+	#[inline]
+	pub fn normalize_or_identity(self) -> Self {
+		self.try_normalize().unwrap_or(Self::IDENTITY)
+	}
+
+	/// Returns `true` if the magnitude of `self` is `1.0`, within a small
+	/// tolerance.
+	#[inline]
+	pub fn is_normalized(self) -> bool {
+		(self.magnitude() - 1.).abs() <= NORMALIZED_TOLERANCE
+	}
+
+	/// Returns `true` if all elements of `self` are finite.
+	#[inline]
+	pub fn is_finite(self) -> bool {
+		self.real().is_finite() && self.dual().is_finite()
+	}
+
 	#[inline]
 	pub fn conjugate(self) -> Self {
 		Self(self.real().conjugate(), self.dual().conjugate())
 	}
 
+	/// Returns the inverse of `self`, such that `self * self.inverse()` is the
+	/// identity. For unit dual quaternions this is the same as
+	/// [DualQuat::conjugate].
+	#[inline]
+	pub fn inverse(self) -> Self {
+		let real = self.real().conjugate() / self.real().length_squared();
+		let dual = -(real * self.dual() * real);
+
+		Self(real, dual)
+	}
+
+	/// Returns `true` if each element of `self` is within `max_abs_diff` of the
+	/// same element of `rhs`.
+	///
+	/// Note that `q` and `q * -1.0` represent the same transform but are not
+	/// considered equal.
+	#[inline]
+	pub fn abs_diff_eq(self, rhs: Self, max_abs_diff: f32) -> bool {
+		self.real().abs_diff_eq(rhs.real(), max_abs_diff)
+			&& self.dual().abs_diff_eq(rhs.dual(), max_abs_diff)
+	}
+
+	/// Creates a unit dual quaternion from its [ScrewParameters].
+	pub fn from_screw(screw: ScrewParameters) -> Self {
+		let ScrewParameters {
+			direction,
+			moment,
+			angle,
+			pitch,
+		} = screw;
+		let (sin, cos) = (angle * 0.5).sin_cos();
+
+		let real = Quat::from_vec4((direction * sin).extend(cos));
+		let dual = Quat::from_vec4(
+			(moment * sin + direction * (pitch * 0.5 * cos)).extend(-pitch * 0.5 * sin),
+		);
+
+		Self(real, dual)
+	}
+
+	/// Decomposes a unit dual quaternion into a rotation about and a
+	/// translation along a single axis.
+	///
+	/// For pure translations (including the identity), the axis passes through
+	/// the origin in the direction of the translation.
+	pub fn to_screw(self) -> ScrewParameters {
+		let real = self.real();
+		let dual = self.dual();
+		let real_xyz = real.xyz();
+		let dual_xyz = dual.xyz();
+
+		let sin = real_xyz.length();
+		if sin <= SCREW_ANGLE_EPSILON {
+			let translation = self.translation();
+			return ScrewParameters {
+				direction: translation.try_normalize().unwrap_or(Vec3::X),
+				moment: Vec3::ZERO,
+				angle: 0.,
+				pitch: translation.length(),
+			};
+		}
+
+		let direction = real_xyz / sin;
+		let angle = 2. * sin.atan2(real.w);
+		let pitch = -2. * dual.w / sin;
+		let moment = (dual_xyz - direction * (pitch * 0.5 * real.w)) / sin;
+
+		ScrewParameters {
+			direction,
+			moment,
+			angle,
+			pitch,
+		}
+	}
+
+	/// The logarithm of a unit dual quaternion: a pure dual quaternion (with
+	/// zero scalar parts) holding half of its screw motion.
+	pub fn ln(self) -> Self {
+		let ScrewParameters {
+			direction,
+			moment,
+			angle,
+			pitch,
+		} = self.to_screw();
+
+		let real = direction * (angle * 0.5);
+		let dual = direction * (pitch * 0.5) + moment * (angle * 0.5);
+
+		Self(
+			Quat::from_vec4(real.extend(0.)),
+			Quat::from_vec4(dual.extend(0.)),
+		)
+	}
+
+	/// The exponential of a pure dual quaternion, the inverse of
+	/// [DualQuat::ln].
+	pub fn exp(self) -> Self {
+		let real_xyz = self.real().xyz();
+		let dual_xyz = self.dual().xyz();
+
+		let half_angle = real_xyz.length();
+		if half_angle <= SCREW_ANGLE_EPSILON {
+			return Self(Quat::IDENTITY, Quat::from_vec4(dual_xyz.extend(0.)));
+		}
+
+		let direction = real_xyz / half_angle;
+		let half_pitch = dual_xyz.dot(direction);
+
+		Self::from_screw(ScrewParameters {
+			direction,
+			moment: (dual_xyz - direction * half_pitch) / half_angle,
+			angle: half_angle * 2.,
+			pitch: half_pitch * 2.,
+		})
+	}
+
+	/// Raises a unit dual quaternion to the power `exponent`, scaling both the
+	/// rotation angle and the translation along its screw axis.
+	pub fn pow(self, exponent: f32) -> Self {
+		let mut screw = self.to_screw();
+		screw.angle *= exponent;
+		screw.pitch *= exponent;
+
+		Self::from_screw(screw)
+	}
+
+	/// Screw linear interpolation between two unit dual quaternions, along the
+	/// shortest path. This is the dual quaternion equivalent of [Quat::slerp]:
+	/// the result moves with constant rotational and translational speed.
+	pub fn sclerp(self, rhs: Self, t: f32) -> Self {
+		let rhs = if self.dot(rhs) < 0. { -rhs } else { rhs };
+
+		self * (self.conjugate() * rhs).pow(t)
+	}
+
+	/// Dual quaternion linear blending (DLB): the normalized weighted sum of
+	/// unit dual quaternions, flipping any whose rotation is in the opposite
+	/// hemisphere from the first one. This is what skinning uses -- it's
+	/// cheaper than [DualQuat::sclerp] and works for any number of inputs.
+	///
+	/// Returns [DualQuat::IDENTITY] if the weights sum to zero.
+	pub fn dlb(weighted: impl IntoIterator<Item = (DualQuat, f32)>) -> Self {
+		let mut weighted = weighted.into_iter();
+		let Some((first, first_weight)) = weighted.next() else {
+			return Self::IDENTITY;
+		};
+
+		let mut result = first * first_weight;
+		for (dq, weight) in weighted {
+			if dq.dot(first) < 0. {
+				result = result - dq * weight;
+			} else {
+				result = result + dq * weight;
+			}
+		}
+
+		result.normalize_or_identity()
+	}
+
+	/// [DualQuat::dlb] of two unit dual quaternions.
+	#[inline]
+	pub fn lerp(self, rhs: Self, t: f32) -> Self {
+		Self::dlb([(self, 1. - t), (rhs, t)])
+	}
+
 	#[inline]
 	pub fn rotation(self) -> Quat {
 		self.real().normalize()
@@ -104,10 +319,15 @@ impl DualQuat {
 		self.transform_point3a(point.into()).into()
 	}
 
+	/// Transforms `point` by `self`, which must be normalized.
+	///
+	/// # Panics
+	///
+	/// Panics in debug builds if `self` isn't normalized.
 	#[inline]
 	pub fn transform_point3a(&self, point: Vec3A) -> Vec3A {
-		assert!(
-			(self.length() - 1.0).abs() <= 1.0e-5,
+		debug_assert!(
+			self.is_normalized(),
 			"DualQuat must be normalized before being used as a transform! \
 			Attempted to transform point with a DualQuat with magnitude {}",
 			self.length()
@@ -129,10 +349,15 @@ impl DualQuat {
 		self.transform_vector3a(vector.into()).into()
 	}
 
+	/// Rotates `vector` by `self`, which must be normalized.
+	///
+	/// # Panics
+	///
+	/// Panics in debug builds if `self` isn't normalized.
 	#[inline]
 	pub fn transform_vector3a(&self, vector: Vec3A) -> Vec3A {
-		assert!(
-			(self.length() - 1.0).abs() <= 1.0e-5,
+		debug_assert!(
+			self.is_normalized(),
 			"DualQuat must be normalized before being used as a transform! \
 			Attempted to rotate vector with a DualQuat with magnitude {}",
 			self.length()
@@ -141,6 +366,20 @@ impl DualQuat {
 	}
 }
 
+/// The screw motion represented by a unit [DualQuat]: a rotation by `angle`
+/// around a line, combined with a translation of `pitch` along it.
+///
+/// The line is given in Plücker coordinates: its unit `direction`, and its
+/// `moment` about the origin (`p.cross(direction)` for any point `p` on the
+/// line).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScrewParameters {
+	pub direction: Vec3,
+	pub moment: Vec3,
+	pub angle: f32,
+	pub pitch: f32,
+}
+
 impl Default for DualQuat {
 	#[inline(always)]
 	fn default() -> Self {
@@ -175,6 +414,24 @@ impl ops::Add for DualQuat {
 	}
 }
 
+impl ops::Sub for DualQuat {
+	type Output = DualQuat;
+
+	#[inline]
+	fn sub(self, rhs: DualQuat) -> DualQuat {
+		DualQuat(self.real() - rhs.real(), self.dual() - rhs.dual())
+	}
+}
+
+impl ops::Neg for DualQuat {
+	type Output = DualQuat;
+
+	#[inline]
+	fn neg(self) -> DualQuat {
+		self * -1.
+	}
+}
+
 impl ops::Mul for DualQuat {
 	type Output = DualQuat;
 
@@ -201,6 +458,7 @@ impl From<DualQuat> for Mat4 {
 	}
 }
 
+#[cfg(feature = "bevy")]
 impl From<DualQuat> for GlobalTransform {
 	#[inline(always)]
 	fn from(value: DualQuat) -> Self {
@@ -208,6 +466,23 @@ impl From<DualQuat> for GlobalTransform {
 	}
 }
 
+#[cfg(feature = "bevy")]
+impl From<DualQuat> for Transform {
+	#[inline]
+	fn from(value: DualQuat) -> Self {
+		Transform::from_translation(value.translation()).with_rotation(value.rotation())
+	}
+}
+
+/// Discards the transform's scale, which can't be represented by a [DualQuat].
+#[cfg(feature = "bevy")]
+impl From<Transform> for DualQuat {
+	#[inline]
+	fn from(value: Transform) -> Self {
+		Self::from_rotation_translation(value.rotation, value.translation)
+	}
+}
+
 impl From<Affine3A> for DualQuat {
 	#[inline]
 	fn from(value: Affine3A) -> Self {
@@ -241,6 +516,7 @@ impl From<Mat4> for DualQuat {
 	}
 }
 
+#[cfg(feature = "bevy")]
 impl From<GlobalTransform> for DualQuat {
 	#[inline(always)]
 	fn from(value: GlobalTransform) -> Self {
@@ -293,7 +569,10 @@ unsafe impl Zeroable for DualQuat {}
 #[cfg(test)]
 mod tests {
 	use super::DualQuat;
-	use bevy::math::{vec3, Affine3A, EulerRot, Quat, Vec3, Vec3A};
+	#[cfg(feature = "bevy")]
+	use bevy::transform::components::Transform;
+	use glam::{vec3, Affine3A, EulerRot, Quat, Vec3, Vec3A};
+	use proptest::prelude::*;
 
 	trait NearlyEq {
 		fn nearly_eq(self, rhs: Self) -> bool;
@@ -383,4 +662,99 @@ mod tests {
 
 		assert_mats_nearly_eq(dq_to_mat, m);
 	}
+
+	fn unit_dual_quat() -> impl Strategy<Value = DualQuat> {
+		let axis = (-1f32..1., -1f32..1., -1f32..1.)
+			.prop_filter_map("degenerate axis", |(x, y, z)| vec3(x, y, z).try_normalize());
+		let translation = (-10f32..10., -10f32..10., -10f32..10.);
+
+		(axis, 0.05f32..3., translation).prop_map(|(axis, angle, (x, y, z))| {
+			DualQuat::from_rotation_translation(Quat::from_axis_angle(axis, angle), vec3(x, y, z))
+		})
+	}
+
+	const EPSILON: f32 = 1.0e-3;
+
+	proptest! {
+		#[test]
+		fn inverse_matches_affine(dq in unit_dual_quat()) {
+			let expected = Affine3A::from(dq).inverse();
+
+			prop_assert!(Affine3A::from(dq.inverse()).abs_diff_eq(expected, EPSILON));
+			prop_assert!((dq * dq.inverse()).abs_diff_eq(DualQuat::IDENTITY, EPSILON));
+		}
+
+		#[test]
+		fn screw_parameters_round_trip(dq in unit_dual_quat()) {
+			let screw = dq.to_screw();
+
+			prop_assert!((screw.direction.length() - 1.).abs() <= EPSILON);
+			prop_assert!(screw.direction.dot(screw.moment).abs() <= EPSILON);
+			prop_assert!(DualQuat::from_screw(screw).abs_diff_eq(dq, EPSILON));
+		}
+
+		#[test]
+		fn exp_inverts_ln(dq in unit_dual_quat()) {
+			prop_assert!(dq.ln().exp().abs_diff_eq(dq, EPSILON));
+		}
+
+		#[test]
+		fn pow_matches_repeated_mul(dq in unit_dual_quat()) {
+			let squared = Affine3A::from(dq) * Affine3A::from(dq);
+			let half = dq.pow(0.5);
+
+			prop_assert!(Affine3A::from(dq.pow(2.)).abs_diff_eq(squared, EPSILON));
+			prop_assert!(Affine3A::from(half * half).abs_diff_eq(Affine3A::from(dq), EPSILON));
+		}
+
+		#[test]
+		fn sclerp_moves_at_constant_speed(a in unit_dual_quat(), b in unit_dual_quat()) {
+			let start = Affine3A::from(a.sclerp(b, 0.));
+			let end = Affine3A::from(a.sclerp(b, 1.));
+
+			prop_assert!(start.abs_diff_eq(Affine3A::from(a), EPSILON));
+			prop_assert!(end.abs_diff_eq(Affine3A::from(b), EPSILON * 10.));
+
+			// Each half of the path is the same relative motion
+			let mid = a.sclerp(b, 0.5);
+			let first_half = Affine3A::from(a).inverse() * Affine3A::from(mid);
+			let second_half = Affine3A::from(mid).inverse() * Affine3A::from(a.sclerp(b, 1.));
+
+			prop_assert!(first_half.abs_diff_eq(second_half, EPSILON * 10.));
+		}
+
+		#[cfg(feature = "bevy")]
+		#[test]
+		fn transform_round_trip(dq in unit_dual_quat()) {
+			let xform = Transform::from(dq);
+
+			prop_assert!(xform.compute_affine().abs_diff_eq(Affine3A::from(dq), EPSILON));
+			prop_assert!(DualQuat::from(xform).abs_diff_eq(dq, EPSILON));
+		}
+	}
+
+	#[test]
+	fn degenerate_normalization() {
+		let zero = DualQuat::IDENTITY * 0.;
+		let nan = DualQuat(Quat::from_xyzw(f32::NAN, 0., 0., 1.), Quat::IDENTITY);
+
+		assert!(zero.try_normalize().is_none());
+		assert!(nan.try_normalize().is_none());
+		assert_eq!(zero.normalize_or_identity(), DualQuat::IDENTITY);
+		assert!((DualQuat::IDENTITY * 3.)
+			.normalize_or_identity()
+			.is_normalized());
+	}
+
+	#[test]
+	fn screw_of_pure_translation() {
+		let dq = DualQuat::from_rotation_translation(Quat::IDENTITY, vec3(0., 3., 4.));
+		let screw = dq.to_screw();
+
+		assert!(screw.angle.nearly_eq(0.));
+		assert!(screw.pitch.nearly_eq(5.));
+		assert!(screw.direction.nearly_eq(vec3(0., 0.6, 0.8)));
+		assert!(dq.pow(0.5).translation().nearly_eq(vec3(0., 1.5, 2.)));
+		assert!(DualQuat::IDENTITY.to_screw().pitch.nearly_eq(0.));
+	}
 }
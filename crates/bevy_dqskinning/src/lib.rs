@@ -1,35 +1,41 @@
+//! Dual quaternion skinning for Bevy.
+//!
+//! With the default `bevy` feature disabled, only the [glam]-based math types
+//! ([DualQuat] and [ScaledDualQuat]) are available, for use in tooling that
+//! doesn't depend on Bevy.
+
+#[cfg(feature = "bevy")]
+mod cpu;
 mod dual_quat;
+#[cfg(feature = "bevy")]
 mod material;
+#[cfg(feature = "bevy")]
+mod plugin;
+#[cfg(feature = "bevy")]
+mod pre_skinning;
+mod scaled;
+#[cfg(feature = "bevy")]
+mod skin;
 
-use bevy::{
-	app::{App, Plugin},
-	asset::{load_internal_asset, Handle},
-	pbr::MaterialPlugin,
-	render::render_resource::Shader,
+pub use crate::{
+	dual_quat::{DualQuat, ScrewParameters},
+	scaled::ScaledDualQuat,
 };
 
+#[cfg(feature = "bevy")]
 pub use crate::{
-	dual_quat::DualQuat,
-	material::{DqsMaterialExt, DqsStandardMaterial},
+	cpu::{
+		skin_normal, CpuSkin, CpuSkinning, DeformedMesh, FullDetailMesh, SkinnedTransform,
+		SkinningMethod,
+	},
+	material::{
+		DqsMaterial, DqsMaterialExt, DqsMaterialKey, DqsSkinningMode, DqsStandardMaterial,
+		ATTRIBUTE_DQS_BLEND,
+	},
+	plugin::{
+		DqSkinningPlugin, DqsMaterialPlugin, DQS_PREPASS_HANDLE, DQS_PRE_SKINNING_HANDLE,
+		DQS_VERTEX_HANDLE, DQ_MATH_HANDLE, DQ_SKINNING_HANDLE,
+	},
+	pre_skinning::{prepare_pre_skinned_meshes, DqsPreSkinning, PreSkinnedMesh},
+	skin::{extract_dq_skins, DqSkinBuffer, DqsSharedPalette, DQ_SKIN_BUFFER_CAPACITY},
 };
-
-pub const DQ_MATH_HANDLE: Handle<Shader> = Handle::weak_from_u128(13324415035412822000);
-pub const DQ_SKINNING_HANDLE: Handle<Shader> = Handle::weak_from_u128(7187723715191461000);
-
-pub struct DqSkinningPlugin;
-
-impl Plugin for DqSkinningPlugin {
-	fn build(&self, app: &mut App) {
-		app.register_type::<DualQuat>();
-
-		load_internal_asset!(app, DQ_MATH_HANDLE, "dq_math.wgsl", Shader::from_wgsl);
-		load_internal_asset!(
-			app,
-			DQ_SKINNING_HANDLE,
-			"dq_skinning.wgsl",
-			Shader::from_wgsl
-		);
-
-		app.add_plugins(MaterialPlugin::<DqsStandardMaterial>::default());
-	}
-}
@@ -1,26 +1,181 @@
 use bevy::{
 	asset::Asset,
-	pbr::{ExtendedMaterial, MaterialExtension, StandardMaterial},
+	pbr::{
+		ExtendedMaterial, MaterialExtension, MaterialExtensionKey, MaterialExtensionPipeline,
+		StandardMaterial,
+	},
 	reflect::Reflect,
-	render::render_resource::{AsBindGroup, ShaderRef},
+	render::{
+		mesh::{MeshVertexAttribute, MeshVertexBufferLayout},
+		render_asset::RenderAssets,
+		render_resource::{
+			binding_types::storage_buffer_read_only_sized, AsBindGroup, AsBindGroupError,
+			BindGroupLayout, BindGroupLayoutEntry, Buffer, OwnedBindingResource,
+			RenderPipelineDescriptor, ShaderRef, ShaderStages, SpecializedMeshPipelineError,
+			UnpreparedBindGroup, VertexFormat,
+		},
+		renderer::RenderDevice,
+		texture::{FallbackImage, Image},
+	},
 };
 
-pub type DqsStandardMaterial = ExtendedMaterial<StandardMaterial, DqsMaterialExt>;
+use crate::{DQS_PREPASS_HANDLE, DQS_VERTEX_HANDLE};
 
-#[derive(Asset, AsBindGroup, Reflect, Clone, Debug, Default)]
-pub struct DqsMaterialExt {}
+/// Adds dual quaternion skinning to the material `M`, replacing its vertex
+/// shaders. Register each base material type with a [DqsMaterialPlugin].
+///
+/// `M`'s fragment shaders must accept Bevy's standard `VertexOutput`, and its
+/// bind group must leave bindings 100 and 101 free. Those are only bound where
+/// vertex shaders can read storage buffers, so not on WebGL2. Materials with their own vertex
+/// shaders should import `bevy_dqskinning::dq_skinning` instead.
+///
+/// [DqsMaterialPlugin]: crate::DqsMaterialPlugin
+pub type DqsMaterial<M> = ExtendedMaterial<M, DqsMaterialExt>;
+
+pub type DqsStandardMaterial = DqsMaterial<StandardMaterial>;
+
+/// Per-vertex blend factor between linear blend skinning (`0.0`) and dual
+/// quaternion skinning (`1.0`), used by [DqsSkinningMode::Blended].
+pub const ATTRIBUTE_DQS_BLEND: MeshVertexAttribute = MeshVertexAttribute::new(
+	"Vertex_DqsBlend",
+	1438902617295740011,
+	VertexFormat::Float32,
+);
+
+/// Shader location of [ATTRIBUTE_DQS_BLEND]. Bevy's own vertex inputs occupy
+/// locations 0 through 7 in both the main and prepass pipelines.
+const DQS_BLEND_SHADER_LOCATION: u32 = 8;
+
+/// Binding index of the pre-skinned vertex buffer in the material bind group.
+/// Chosen to stay clear of the base material's bindings.
+const PRE_SKINNED_VERTICES_BINDING: u32 = 100;
+
+/// Binding index of the [DqSkinBuffer] in the material bind group.
+///
+/// [DqSkinBuffer]: crate::DqSkinBuffer
+const DQ_SKINS_BINDING: u32 = 101;
+
+/// Size of a single `PreSkinnedVertex` in the `dq_skinning` shader.
+pub(crate) const PRE_SKINNED_VERTEX_SIZE: u64 = 64;
+
+#[derive(Asset, Reflect, Clone, Debug, Default)]
+pub struct DqsMaterialExt {
+	pub mode: DqsSkinningMode,
+	/// World-space vertex data written by the pre-skinning compute pass. This is
+	/// managed by [DqsPreSkinning] and shouldn't be set by hand.
+	///
+	/// [DqsPreSkinning]: crate::DqsPreSkinning
+	#[reflect(ignore)]
+	pub pre_skinned: Option<Buffer>,
+	/// The dual quaternion joints of all dual-quaternion-skinned meshes. This
+	/// is set by [DqSkinningPlugin] when the material is added.
+	///
+	/// [DqSkinningPlugin]: crate::DqSkinningPlugin
+	#[reflect(ignore)]
+	pub dq_skins: Option<Buffer>,
+}
+
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DqsSkinningMode {
+	/// Every vertex is skinned with dual quaternions.
+	#[default]
+	DualQuaternion,
+	/// Each vertex mixes the results of linear blend skinning and dual
+	/// quaternion skinning by the mesh's [ATTRIBUTE_DQS_BLEND] values. Meshes
+	/// without that attribute fall back to [DqsSkinningMode::DualQuaternion].
+	Blended,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DqsMaterialKey {
+	mode: DqsSkinningMode,
+	pre_skinned: bool,
+	/// Whether the storage buffer bindings are part of the layout.
+	storage_buffers: bool,
+}
+
+/// Whether vertex and compute shaders can read storage buffers, which the
+/// pre-skinned vertices and [DqSkinBuffer] are bound as. WebGL2 can't.
+///
+/// [DqSkinBuffer]: crate::DqSkinBuffer
+pub(crate) fn storage_buffers_supported(render_device: &RenderDevice) -> bool {
+	render_device.limits().max_storage_buffers_per_shader_stage > 0
+}
+
+impl AsBindGroup for DqsMaterialExt {
+	type Data = DqsMaterialKey;
+
+	fn label() -> Option<&'static str> {
+		Some("dqs_material_ext")
+	}
+
+	fn unprepared_bind_group(
+		&self,
+		_: &BindGroupLayout,
+		render_device: &RenderDevice,
+		_: &RenderAssets<Image>,
+		_: &FallbackImage,
+	) -> Result<UnpreparedBindGroup<Self::Data>, AsBindGroupError> {
+		if !storage_buffers_supported(render_device) {
+			return Ok(UnpreparedBindGroup {
+				bindings: vec![],
+				data: DqsMaterialKey {
+					mode: self.mode,
+					pre_skinned: false,
+					storage_buffers: false,
+				},
+			});
+		}
+
+		// Set by `attach_dq_skin_buffer` once the material has been added
+		let Some(dq_skins) = self.dq_skins.clone() else {
+			return Err(AsBindGroupError::RetryNextUpdate);
+		};
+		// The binding is always part of the layout, so materials that aren't
+		// pre-skinned bind a buffer the shader never reads in its place
+		let pre_skinned = self.pre_skinned.clone().unwrap_or_else(|| dq_skins.clone());
+
+		Ok(UnpreparedBindGroup {
+			bindings: vec![
+				(
+					PRE_SKINNED_VERTICES_BINDING,
+					OwnedBindingResource::Buffer(pre_skinned),
+				),
+				(DQ_SKINS_BINDING, OwnedBindingResource::Buffer(dq_skins)),
+			],
+			data: DqsMaterialKey {
+				mode: self.mode,
+				pre_skinned: self.pre_skinned.is_some(),
+				storage_buffers: true,
+			},
+		})
+	}
+
+	fn bind_group_layout_entries(render_device: &RenderDevice) -> Vec<BindGroupLayoutEntry> {
+		if !storage_buffers_supported(render_device) {
+			return vec![];
+		}
+
+		vec![
+			storage_buffer_read_only_sized(false, None)
+				.build(PRE_SKINNED_VERTICES_BINDING, ShaderStages::VERTEX),
+			storage_buffer_read_only_sized(false, None)
+				.build(DQ_SKINS_BINDING, ShaderStages::VERTEX),
+		]
+	}
+}
 
 impl MaterialExtension for DqsMaterialExt {
 	fn prepass_vertex_shader() -> ShaderRef {
-		"shaders/dqs_prepass.wgsl".into()
+		DQS_PREPASS_HANDLE.into()
 	}
 
 	fn deferred_vertex_shader() -> ShaderRef {
-		"shaders/dqs_vertex.wgsl".into()
+		DQS_VERTEX_HANDLE.into()
 	}
 
 	fn vertex_shader() -> ShaderRef {
-		"shaders/dqs_vertex.wgsl".into()
+		DQS_VERTEX_HANDLE.into()
 	}
 
 	fn fragment_shader() -> ShaderRef {
@@ -30,4 +185,36 @@ impl MaterialExtension for DqsMaterialExt {
 	fn deferred_fragment_shader() -> ShaderRef {
 		ShaderRef::Default
 	}
+
+	fn specialize(
+		_: &MaterialExtensionPipeline,
+		descriptor: &mut RenderPipelineDescriptor,
+		layout: &MeshVertexBufferLayout,
+		key: MaterialExtensionKey<Self>,
+	) -> Result<(), SpecializedMeshPipelineError> {
+		// Without the buffer, joints are decomposed from Bevy's joint matrices
+		// for every vertex, and motion vectors only come from the mesh's own
+		// transform
+		if key.bind_group_data.storage_buffers {
+			descriptor.vertex.shader_defs.push("DQS_SKIN_BUFFER".into());
+		}
+
+		if key.bind_group_data.pre_skinned {
+			descriptor.vertex.shader_defs.push("DQS_PRE_SKINNED".into());
+		} else if key.bind_group_data.mode == DqsSkinningMode::Blended
+			&& layout.contains(ATTRIBUTE_DQS_BLEND)
+		{
+			let blend_layout = layout
+				.get_layout(&[ATTRIBUTE_DQS_BLEND.at_shader_location(DQS_BLEND_SHADER_LOCATION)])?;
+
+			// The offsets and stride are relative to the mesh's full vertex
+			// buffer, so the attribute can be appended to the existing layout
+			if let Some(buffer) = descriptor.vertex.buffers.first_mut() {
+				buffer.attributes.extend(blend_layout.attributes);
+				descriptor.vertex.shader_defs.push("DQS_BLEND".into());
+			}
+		}
+
+		Ok(())
+	}
 }
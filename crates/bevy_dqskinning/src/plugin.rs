@@ -0,0 +1,169 @@
+use std::{hash::Hash, marker::PhantomData};
+
+use bevy::{
+	app::{App, Plugin, PostUpdate},
+	asset::{load_internal_asset, Handle},
+	ecs::{
+		schedule::{IntoSystemConfigs, SystemSet},
+		world::FromWorld,
+	},
+	pbr::{extract_meshes, Material, MaterialPlugin, StandardMaterial},
+	render::{
+		graph::CameraDriverLabel, render_graph::RenderGraph, render_resource::Shader,
+		renderer::RenderDevice, ExtractSchedule, Render, RenderApp, RenderSet,
+	},
+};
+
+use crate::{
+	extract_dq_skins,
+	material::storage_buffers_supported,
+	pre_skinning::{
+		evict_pre_skinning_sources, extract_pre_skins, prepare_pre_skin_bind_groups,
+		ExtractedPreSkins, PreSkinningLabel, PreSkinningNode, PreSkinningPipeline,
+		PreSkinningSources, PreparedPreSkins,
+	},
+	prepare_pre_skinned_meshes,
+	skin::{
+		attach_dq_skin_buffer, extract_dq_skin_flags, extract_dq_skinned, write_dq_skins,
+		DqSkinnedEntities, DqSkins,
+	},
+	DqSkinBuffer, DqsMaterial, DqsPreSkinning, DqsSharedPalette, DqsSkinningMode, DualQuat,
+	FullDetailMesh, SkinningMethod,
+};
+
+pub const DQ_MATH_HANDLE: Handle<Shader> = Handle::weak_from_u128(13324415035412822000);
+pub const DQ_SKINNING_HANDLE: Handle<Shader> = Handle::weak_from_u128(7187723715191461000);
+pub const DQS_PRE_SKINNING_HANDLE: Handle<Shader> = Handle::weak_from_u128(2650978410326155000);
+pub const DQS_VERTEX_HANDLE: Handle<Shader> = Handle::weak_from_u128(9105372716640532000);
+pub const DQS_PREPASS_HANDLE: Handle<Shader> = Handle::weak_from_u128(4478180913526347000);
+
+pub struct DqSkinningPlugin;
+
+impl Plugin for DqSkinningPlugin {
+	fn build(&self, app: &mut App) {
+		app.register_type::<DualQuat>()
+			.register_type::<DqsSkinningMode>()
+			.register_type::<SkinningMethod>()
+			.register_type::<DqsPreSkinning>()
+			.register_type::<DqsSharedPalette>()
+			.register_type::<FullDetailMesh>();
+
+		load_internal_asset!(app, DQ_MATH_HANDLE, "dq_math.wgsl", Shader::from_wgsl);
+		load_internal_asset!(
+			app,
+			DQ_SKINNING_HANDLE,
+			"dq_skinning.wgsl",
+			Shader::from_wgsl
+		);
+		load_internal_asset!(
+			app,
+			DQS_PRE_SKINNING_HANDLE,
+			"dqs_pre_skinning.wgsl",
+			Shader::from_wgsl
+		);
+		load_internal_asset!(app, DQS_VERTEX_HANDLE, "dqs_vertex.wgsl", Shader::from_wgsl);
+		load_internal_asset!(
+			app,
+			DQS_PREPASS_HANDLE,
+			"dqs_prepass.wgsl",
+			Shader::from_wgsl
+		);
+
+		app.init_resource::<PreSkinningSources>().add_systems(
+			PostUpdate,
+			evict_pre_skinning_sources.before(PrepareDqsMaterialsSet),
+		);
+
+		app.add_plugins(DqsMaterialPlugin::<StandardMaterial>::default());
+
+		let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+			return;
+		};
+
+		render_app
+			.init_resource::<DqSkins>()
+			.init_resource::<DqSkinnedEntities>()
+			.init_resource::<ExtractedPreSkins>()
+			.init_resource::<PreparedPreSkins>()
+			.add_systems(
+				ExtractSchedule,
+				(
+					extract_dq_skins.after(ExtractDqSkinnedSet),
+					extract_dq_skin_flags
+						.after(extract_dq_skins)
+						.after(extract_meshes),
+					extract_pre_skins.after(extract_dq_skins),
+				),
+			)
+			.add_systems(
+				Render,
+				(
+					write_dq_skins.in_set(RenderSet::PrepareResources),
+					prepare_pre_skin_bind_groups.in_set(RenderSet::PrepareBindGroups),
+				),
+			);
+
+		let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
+		render_graph.add_node(PreSkinningLabel, PreSkinningNode);
+		render_graph.add_node_edge(PreSkinningLabel, CameraDriverLabel);
+	}
+
+	fn finish(&self, app: &mut App) {
+		let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+			return;
+		};
+
+		// Both need storage buffers, so neither is available on WebGL2
+		let render_device = render_app.world.resource::<RenderDevice>();
+		if !storage_buffers_supported(render_device) {
+			return;
+		}
+
+		// Shared by the materials in the main world and the pre-skinning pass
+		let dq_skin_buffer = DqSkinBuffer::from_world(&mut render_app.world);
+		render_app
+			.insert_resource(dq_skin_buffer.clone())
+			.init_resource::<PreSkinningPipeline>();
+		app.insert_resource(dq_skin_buffer);
+	}
+}
+
+/// Adds dual quaternion skinning for [DqsMaterial]s with the base material
+/// `M`. [DqSkinningPlugin] adds this for [StandardMaterial]; add it for any
+/// other base materials.
+pub struct DqsMaterialPlugin<M: Material>(PhantomData<M>);
+
+impl<M: Material> Default for DqsMaterialPlugin<M> {
+	fn default() -> Self {
+		Self(PhantomData)
+	}
+}
+
+impl<M: Material> Plugin for DqsMaterialPlugin<M>
+where M::Data: PartialEq + Eq + Hash + Clone
+{
+	fn build(&self, app: &mut App) {
+		app.add_plugins(MaterialPlugin::<DqsMaterial<M>>::default())
+			// For spawning material handles from scenes
+			.register_type::<Handle<DqsMaterial<M>>>()
+			.add_systems(
+				PostUpdate,
+				(attach_dq_skin_buffer::<M>, prepare_pre_skinned_meshes::<M>)
+					.chain()
+					.in_set(PrepareDqsMaterialsSet),
+			);
+
+		if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+			render_app.add_systems(
+				ExtractSchedule,
+				extract_dq_skinned::<M>.in_set(ExtractDqSkinnedSet),
+			);
+		}
+	}
+}
+
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct ExtractDqSkinnedSet;
+
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct PrepareDqsMaterialsSet;
@@ -0,0 +1,557 @@
+use std::num::NonZeroU64;
+
+use bevy::{
+	asset::{AssetEvent, AssetId, Assets, Handle, UntypedHandle},
+	ecs::{entity::EntityHashMap, prelude::*},
+	log::warn,
+	pbr::Material,
+	reflect::Reflect,
+	render::{
+		mesh::{morph::MeshMorphWeights, skinning::SkinnedMesh, Mesh, VertexAttributeValues},
+		render_asset::RenderAssets,
+		render_graph::{self, RenderLabel},
+		render_resource::{
+			binding_types::{
+				storage_buffer_read_only_sized, storage_buffer_sized, texture_3d,
+				uniform_buffer_sized,
+			},
+			BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+			BufferDescriptor, BufferId, BufferInitDescriptor, BufferUsages,
+			CachedComputePipelineId, ComputePassDescriptor, ComputePipelineDescriptor,
+			PipelineCache, ShaderStages, TextureSampleType, TextureViewId,
+		},
+		renderer::{RenderContext, RenderDevice, RenderQueue},
+		texture::FallbackImage,
+		view::ViewVisibility,
+		Extract,
+	},
+	utils::HashMap,
+};
+
+use crate::{
+	material::{storage_buffers_supported, PRE_SKINNED_VERTEX_SIZE},
+	skin::DqSkins,
+	DqSkinBuffer, DqsMaterial, DqsSkinningMode, ATTRIBUTE_DQS_BLEND, DQS_PRE_SKINNING_HANDLE,
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Matches `MAX_MORPH_WEIGHTS` in Bevy, which is packed into 16 `vec4`s.
+const MAX_MORPH_WEIGHTS: usize = 64;
+
+/// Size of a `SourceVertex` in the `dqs_pre_skinning` shader.
+pub(crate) const SOURCE_VERTEX_WORDS: usize = 20;
+
+/// Size of `PreSkinningParams` in the `dqs_pre_skinning` shader: four `u32`s,
+/// followed by the morph weights.
+const PARAMS_WORDS: usize = 4 + MAX_MORPH_WEIGHTS;
+
+/// Opts a dual-quaternion-skinned mesh entity into compute-shader pre-skinning.
+///
+/// By default, morphing and skinning run in the vertex shader of every pass
+/// that draws the mesh -- the main pass, the prepass, and once per shadow
+/// cascade or cube face for each shadow-casting light. With this component, a
+/// compute pass morphs and skins the mesh once per frame into a vertex buffer,
+/// and each of those passes just reads the results.
+///
+/// Insert it on the entity with the [SkinnedMesh] and the
+/// [`Handle<DqsMaterial>`]. The entity's material is replaced with a private
+/// copy that binds the pre-skinned vertex buffer; removing this component
+/// restores the original. Changes to the original material aren't picked up
+/// until then.
+///
+/// Requires compute shaders and vertex-stage storage buffers, so it isn't
+/// available on WebGL2.
+#[derive(Component, Reflect, Clone, Copy, Debug, Default)]
+#[reflect(Component)]
+pub struct DqsPreSkinning;
+
+/// GPU resources allocated for a [DqsPreSkinning] mesh entity.
+///
+/// They're kept when the entity's [`Handle<Mesh>`] changes, e.g. between LODs:
+/// only the packed source vertices, which are shared with every other entity
+/// using the same mesh, are swapped. The output buffer and private material
+/// are only reallocated for meshes with more vertices than the largest one so
+/// far.
+#[derive(Component, Clone, Debug)]
+pub struct PreSkinnedMesh {
+	mesh: AssetId<Mesh>,
+	vertex_count: u32,
+	/// The number of vertices `output` has room for.
+	capacity: u32,
+	source: Buffer,
+	output: Buffer,
+	/// Rewritten by the render world whenever the morph weights change.
+	params: Buffer,
+	skinning_mode: DqsSkinningMode,
+	original_material: UntypedHandle,
+}
+
+/// The packed source vertices of each mesh used by a [DqsPreSkinning] entity.
+#[derive(Resource, Default)]
+pub struct PreSkinningSources(HashMap<AssetId<Mesh>, PreSkinningSource>);
+
+#[derive(Clone)]
+struct PreSkinningSource {
+	buffer: Buffer,
+	vertex_count: u32,
+}
+
+/// Drops the source vertices of modified and removed meshes, so entities using
+/// them are updated by [prepare_pre_skinned_meshes].
+pub(crate) fn evict_pre_skinning_sources(
+	mut r_sources: ResMut<PreSkinningSources>,
+	mut r_mesh_events: EventReader<AssetEvent<Mesh>>,
+) {
+	for event in r_mesh_events.read() {
+		if let AssetEvent::Modified { id } | AssetEvent::Removed { id } = event {
+			r_sources.0.remove(id);
+		}
+	}
+}
+
+/// Allocates buffers and per-entity materials for [DqsPreSkinning] entities,
+/// and restores the original materials of entities that opt back out.
+#[allow(clippy::type_complexity)]
+pub fn prepare_pre_skinned_meshes<M: Material>(
+	mut cmd: Commands,
+	render_device: Option<Res<RenderDevice>>,
+	ra_meshes: Res<Assets<Mesh>>,
+	mut ra_materials: ResMut<Assets<DqsMaterial<M>>>,
+	mut r_sources: ResMut<PreSkinningSources>,
+	mut q_opted_in: Query<
+		(
+			Entity,
+			&Handle<Mesh>,
+			&mut Handle<DqsMaterial<M>>,
+			Option<&mut PreSkinnedMesh>,
+		),
+		(With<DqsPreSkinning>, With<SkinnedMesh>),
+	>,
+	mut q_opted_out: Query<
+		(Entity, &PreSkinnedMesh, &mut Handle<DqsMaterial<M>>),
+		Without<DqsPreSkinning>,
+	>,
+) {
+	let Some(render_device) = render_device else {
+		return;
+	};
+
+	for (entity, pre_skinned, mut material) in q_opted_out.iter_mut() {
+		*material = pre_skinned.original_material.clone().typed();
+		cmd.entity(entity).remove::<PreSkinnedMesh>();
+	}
+
+	if !storage_buffers_supported(&render_device) {
+		for (entity, ..) in q_opted_in.iter() {
+			warn!("Pre-skinning isn't supported without storage buffers");
+			cmd.entity(entity).remove::<DqsPreSkinning>();
+		}
+		return;
+	}
+
+	for (entity, mesh_handle, mut material, mut pre_skinned) in q_opted_in.iter_mut() {
+		let source = match r_sources.0.get(&mesh_handle.id()) {
+			Some(source) => source.clone(),
+			None => {
+				let Some(mesh) = ra_meshes.get(mesh_handle) else {
+					continue;
+				};
+				let Some(source_data) = source_vertices(mesh) else {
+					warn!(
+						"Mesh {:?} can't be pre-skinned: missing skinning attributes",
+						mesh_handle.id()
+					);
+					cmd.entity(entity).remove::<DqsPreSkinning>();
+					continue;
+				};
+
+				let source = PreSkinningSource {
+					buffer: render_device.create_buffer_with_data(&BufferInitDescriptor {
+						label: Some("dqs_pre_skinning_source"),
+						contents: bevy::core::cast_slice(&source_data),
+						usage: BufferUsages::STORAGE,
+					}),
+					vertex_count: mesh.count_vertices() as u32,
+				};
+				r_sources.0.insert(mesh_handle.id(), source.clone());
+				source
+			}
+		};
+
+		let up_to_date = pre_skinned
+			.as_ref()
+			.is_some_and(|pre_skinned| pre_skinned.source.id() == source.buffer.id());
+		if up_to_date {
+			continue;
+		}
+
+		if let Some(pre_skinned) = pre_skinned.as_deref_mut() {
+			if pre_skinned.capacity >= source.vertex_count {
+				pre_skinned.mesh = mesh_handle.id();
+				pre_skinned.vertex_count = source.vertex_count;
+				pre_skinned.source = source.buffer;
+				continue;
+			}
+		}
+
+		let output = render_device.create_buffer(&BufferDescriptor {
+			label: Some("dqs_pre_skinned_vertices"),
+			size: PRE_SKINNED_VERTEX_SIZE * source.vertex_count.max(1) as u64,
+			usage: BufferUsages::STORAGE,
+			mapped_at_creation: false,
+		});
+
+		if let Some(pre_skinned) = pre_skinned {
+			// Outgrown by the new mesh, so its private material is rebound
+			let Some(material_data) = ra_materials.get_mut(&*material) else {
+				continue;
+			};
+			material_data.extension.pre_skinned = Some(output.clone());
+
+			let pre_skinned = pre_skinned.into_inner();
+			pre_skinned.mesh = mesh_handle.id();
+			pre_skinned.vertex_count = source.vertex_count;
+			pre_skinned.capacity = source.vertex_count;
+			pre_skinned.source = source.buffer;
+			pre_skinned.output = output;
+			continue;
+		}
+
+		let Some(mut material_data) = ra_materials.get(&*material).cloned() else {
+			continue;
+		};
+		let params = render_device.create_buffer(&BufferDescriptor {
+			label: Some("dqs_pre_skinning_params"),
+			size: PARAMS_WORDS as u64 * 4,
+			usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let skinning_mode = material_data.extension.mode;
+		material_data.extension.pre_skinned = Some(output.clone());
+		let original_material = std::mem::replace(&mut *material, ra_materials.add(material_data));
+
+		cmd.entity(entity).insert(PreSkinnedMesh {
+			mesh: mesh_handle.id(),
+			vertex_count: source.vertex_count,
+			capacity: source.vertex_count,
+			source: source.buffer,
+			output,
+			params,
+			skinning_mode,
+			original_material: original_material.untyped(),
+		});
+	}
+}
+
+/// Packs the mesh's vertex attributes into the `SourceVertex` layout expected
+/// by the compute shader. Returns `None` if the mesh isn't skinned.
+pub(crate) fn source_vertices(mesh: &Mesh) -> Option<Vec<u32>> {
+	use VertexAttributeValues::*;
+
+	let Some(Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
+		return None;
+	};
+	let Some(Uint16x4(joint_indices)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_INDEX) else {
+		return None;
+	};
+	let Some(Float32x4(joint_weights)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT) else {
+		return None;
+	};
+	let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+		Some(Float32x3(normals)) => &normals[..],
+		_ => &[],
+	};
+	let tangents = match mesh.attribute(Mesh::ATTRIBUTE_TANGENT) {
+		Some(Float32x4(tangents)) => &tangents[..],
+		_ => &[],
+	};
+	let blends = match mesh.attribute(ATTRIBUTE_DQS_BLEND) {
+		Some(Float32(blends)) => &blends[..],
+		_ => &[],
+	};
+
+	let mut result = Vec::with_capacity(positions.len() * SOURCE_VERTEX_WORDS);
+	for (idx, position) in positions.iter().enumerate() {
+		let [nx, ny, nz] = normals.get(idx).copied().unwrap_or_default();
+		let tangent = tangents.get(idx).copied().unwrap_or_default();
+		let blend = blends.get(idx).copied().unwrap_or(1.);
+		let indices = joint_indices.get(idx).copied().unwrap_or_default();
+		let weights = joint_weights.get(idx).copied().unwrap_or_default();
+
+		result.extend(position.map(f32::to_bits));
+		result.push(blend.to_bits());
+		result.extend([nx, ny, nz, 0.].map(f32::to_bits));
+		result.extend(tangent.map(f32::to_bits));
+		result.extend(indices.map(u32::from));
+		result.extend(weights.map(f32::to_bits));
+	}
+
+	Some(result)
+}
+
+/// Render-world copy of a visible [PreSkinnedMesh].
+struct ExtractedPreSkin {
+	entity: Entity,
+	source: Buffer,
+	output: Buffer,
+	params: Buffer,
+	vertex_count: u32,
+	dq_skin_flags: u32,
+	skinning_mode: DqsSkinningMode,
+	mesh: AssetId<Mesh>,
+	morph_weights: Vec<f32>,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct ExtractedPreSkins(Vec<ExtractedPreSkin>);
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn extract_pre_skins(
+	mut extracted: ResMut<ExtractedPreSkins>,
+	skins: Res<DqSkins>,
+	query: Extract<
+		Query<(
+			Entity,
+			&ViewVisibility,
+			&PreSkinnedMesh,
+			Option<&MeshMorphWeights>,
+		)>,
+	>,
+) {
+	extracted.0.clear();
+
+	for (entity, view_visibility, pre_skinned, morph_weights) in &query {
+		if !view_visibility.get() {
+			continue;
+		}
+		// Without a block in the `DqSkinBuffer`, there are no joints to read
+		let Some(dq_skin) = skins
+			.entities
+			.get(&entity)
+			.filter(|dq_skin| dq_skin.block != 0)
+		else {
+			continue;
+		};
+
+		extracted.0.push(ExtractedPreSkin {
+			entity,
+			source: pre_skinned.source.clone(),
+			output: pre_skinned.output.clone(),
+			params: pre_skinned.params.clone(),
+			vertex_count: pre_skinned.vertex_count,
+			dq_skin_flags: dq_skin.flags(),
+			skinning_mode: pre_skinned.skinning_mode,
+			mesh: pre_skinned.mesh,
+			morph_weights: morph_weights
+				.map(|weights| weights.weights().to_vec())
+				.unwrap_or_default(),
+		});
+	}
+}
+
+#[derive(Resource)]
+pub(crate) struct PreSkinningPipeline {
+	layout: BindGroupLayout,
+	pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for PreSkinningPipeline {
+	fn from_world(world: &mut World) -> Self {
+		let render_device = world.resource::<RenderDevice>();
+		let layout = render_device.create_bind_group_layout(
+			"dqs_pre_skinning_layout",
+			&BindGroupLayoutEntries::sequential(
+				ShaderStages::COMPUTE,
+				(
+					storage_buffer_read_only_sized(false, None),
+					storage_buffer_read_only_sized(false, None),
+					storage_buffer_sized(false, None),
+					uniform_buffer_sized(false, NonZeroU64::new(PARAMS_WORDS as u64 * 4)),
+					texture_3d(TextureSampleType::Float { filterable: false }),
+				),
+			),
+		);
+
+		let pipeline =
+			world
+				.resource::<PipelineCache>()
+				.queue_compute_pipeline(ComputePipelineDescriptor {
+					label: Some("dqs_pre_skinning_pipeline".into()),
+					layout: vec![layout.clone()],
+					push_constant_ranges: vec![],
+					shader: DQS_PRE_SKINNING_HANDLE,
+					shader_defs: vec![
+						"SKINNED".into(),
+						"DQS_PRE_SKINNING".into(),
+						"DQS_SKIN_BUFFER".into(),
+					],
+					entry_point: "pre_skin".into(),
+				});
+
+		Self { layout, pipeline }
+	}
+}
+
+struct PreparedPreSkin {
+	bind_group: BindGroup,
+	bindings: PreSkinBindings,
+	params: [u32; PARAMS_WORDS],
+	workgroups: u32,
+}
+
+/// Everything bound by a [PreparedPreSkin]'s bind group, which is only
+/// recreated when one of them changes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct PreSkinBindings {
+	dq_skins: BufferId,
+	source: BufferId,
+	output: BufferId,
+	params: BufferId,
+	morph_targets: TextureViewId,
+}
+
+/// Bind groups of the entities extracted this frame.
+#[derive(Resource, Default)]
+pub(crate) struct PreparedPreSkins(EntityHashMap<PreparedPreSkin>);
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn prepare_pre_skin_bind_groups(
+	render_device: Res<RenderDevice>,
+	render_queue: Res<RenderQueue>,
+	pipeline: Option<Res<PreSkinningPipeline>>,
+	meshes: Res<RenderAssets<Mesh>>,
+	fallback_image: Res<FallbackImage>,
+	dq_skin_buffer: Option<Res<DqSkinBuffer>>,
+	extracted: Res<ExtractedPreSkins>,
+	mut prepared: ResMut<PreparedPreSkins>,
+) {
+	let (Some(pipeline), Some(dq_skin_buffer)) = (pipeline, dq_skin_buffer) else {
+		prepared.0.clear();
+		return;
+	};
+
+	prepared.0.retain(|entity, _| {
+		extracted
+			.0
+			.iter()
+			.any(|pre_skin| pre_skin.entity == *entity)
+	});
+
+	for pre_skin in extracted.0.iter() {
+		// Bevy only binds morph targets when the entity has morph weights, and
+		// the weights always match the number of targets
+		let morph_view = meshes
+			.get(pre_skin.mesh)
+			.and_then(|mesh| mesh.morph_targets.as_ref())
+			.filter(|_| !pre_skin.morph_weights.is_empty());
+		let morph_count = if morph_view.is_some() {
+			pre_skin.morph_weights.len().min(MAX_MORPH_WEIGHTS) as u32
+		} else {
+			0
+		};
+
+		let mut params = [0_u32; PARAMS_WORDS];
+		params[0] = pre_skin.vertex_count;
+		params[1] = morph_count;
+		params[2] = match pre_skin.skinning_mode {
+			DqsSkinningMode::DualQuaternion => 0,
+			DqsSkinningMode::Blended => 1,
+		};
+		params[3] = pre_skin.dq_skin_flags;
+		for (idx, &weight) in pre_skin
+			.morph_weights
+			.iter()
+			.take(MAX_MORPH_WEIGHTS)
+			.enumerate()
+		{
+			params[4 + idx] = weight.to_bits();
+		}
+
+		let morph_view = morph_view.unwrap_or(&fallback_image.d3.texture_view);
+		let bindings = PreSkinBindings {
+			dq_skins: dq_skin_buffer.0.id(),
+			source: pre_skin.source.id(),
+			output: pre_skin.output.id(),
+			params: pre_skin.params.id(),
+			morph_targets: morph_view.id(),
+		};
+
+		let cached = prepared.0.get_mut(&pre_skin.entity);
+		if cached.as_ref().is_none_or(|cached| {
+			cached.params != params || cached.bindings.params != bindings.params
+		}) {
+			render_queue.write_buffer(&pre_skin.params, 0, bevy::core::cast_slice(&params));
+		}
+		if let Some(cached) = cached.filter(|cached| cached.bindings == bindings) {
+			cached.params = params;
+			continue;
+		}
+
+		let bind_group = render_device.create_bind_group(
+			"dqs_pre_skinning_bind_group",
+			&pipeline.layout,
+			&BindGroupEntries::sequential((
+				dq_skin_buffer.0.as_entire_binding(),
+				pre_skin.source.as_entire_binding(),
+				pre_skin.output.as_entire_binding(),
+				pre_skin.params.as_entire_binding(),
+				morph_view,
+			)),
+		);
+
+		prepared.0.insert(pre_skin.entity, PreparedPreSkin {
+			bind_group,
+			bindings,
+			params,
+			workgroups: pre_skin.vertex_count.div_ceil(WORKGROUP_SIZE),
+		});
+	}
+}
+
+#[derive(RenderLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct PreSkinningLabel;
+
+/// Runs the pre-skinning compute pass once per frame, before any cameras are
+/// rendered.
+#[derive(Default)]
+pub(crate) struct PreSkinningNode;
+
+impl render_graph::Node for PreSkinningNode {
+	fn run(
+		&self,
+		_: &mut render_graph::RenderGraphContext,
+		render_context: &mut RenderContext,
+		world: &World,
+	) -> Result<(), render_graph::NodeRunError> {
+		let prepared = world.resource::<PreparedPreSkins>();
+		if prepared.0.is_empty() {
+			return Ok(());
+		}
+
+		let pipeline_cache = world.resource::<PipelineCache>();
+		let Some(compute_pipeline) = world
+			.get_resource::<PreSkinningPipeline>()
+			.and_then(|pipeline| pipeline_cache.get_compute_pipeline(pipeline.pipeline))
+		else {
+			return Ok(());
+		};
+
+		let mut pass =
+			render_context
+				.command_encoder()
+				.begin_compute_pass(&ComputePassDescriptor {
+					label: Some("dqs_pre_skinning"),
+					timestamp_writes: None,
+				});
+		pass.set_pipeline(compute_pipeline);
+
+		for pre_skin in prepared.0.values() {
+			pass.set_bind_group(0, &pre_skin.bind_group, &[]);
+			pass.dispatch_workgroups(pre_skin.workgroups, 1, 1);
+		}
+
+		Ok(())
+	}
+}
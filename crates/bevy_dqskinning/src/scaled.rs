@@ -0,0 +1,247 @@
+use glam::{Affine3A, Mat3, Mat4, Quat, Vec3, Vec4};
+
+use crate::DualQuat;
+
+const POLAR_DECOMPOSITION_MAX_ITERATIONS: usize = 16;
+const POLAR_DECOMPOSITION_TOLERANCE: f32 = 1.0e-6;
+
+/// An affine transform split into a rigid [DualQuat] and a symmetric
+/// scale/shear matrix, such that `M = rigid * scale`.
+///
+/// Dual quaternions can only represent rotation and translation, so skinning a
+/// vertex with scaled joints blends each part separately: the scale matrices
+/// are blended linearly and applied to the vertex first, then the blended
+/// [DualQuat] is applied to the result.
+///
+/// * [Reference](https://users.cs.utah.edu/~ladislav/kavan08geometric/kavan08geometric.pdf)
+///   (Kavan et al., "Geometric Skinning with Approximate Dual Quaternion
+///   Blending", section 3.3)
+#[derive(Clone, Copy, Debug)]
+pub struct ScaledDualQuat {
+	pub rigid: DualQuat,
+	pub scale: Mat3,
+}
+
+impl ScaledDualQuat {
+	pub const IDENTITY: Self = Self {
+		rigid: DualQuat::IDENTITY,
+		scale: Mat3::IDENTITY,
+	};
+
+	#[inline]
+	pub fn from_affine3a(value: Affine3A) -> Self {
+		let (rotation, scale) = polar_decomposition(value.matrix3.into());
+
+		Self {
+			rigid: DualQuat::from_rotation_translation(rotation, value.translation.into()),
+			scale,
+		}
+	}
+
+	/// Blends a set of weighted transforms, flipping the sign of any [DualQuat]
+	/// whose rotation is in the opposite hemisphere from the first one.
+	///
+	/// Returns [ScaledDualQuat::IDENTITY] if the weights sum to zero.
+	pub fn blend(weighted: impl IntoIterator<Item = (ScaledDualQuat, f32)>) -> Self {
+		let mut weighted = weighted.into_iter();
+		let Some((first, first_weight)) = weighted.next() else {
+			return Self::IDENTITY;
+		};
+
+		let mut rigid = first.rigid * first_weight;
+		let mut scale = first.scale * first_weight;
+
+		for (xform, weight) in weighted {
+			let signed_weight = if xform.rigid.dot(first.rigid) < 0. {
+				-weight
+			} else {
+				weight
+			};
+
+			rigid = rigid + xform.rigid * signed_weight;
+			scale += xform.scale * weight;
+		}
+
+		let Some(rigid) = rigid.try_normalize() else {
+			return Self::IDENTITY;
+		};
+
+		Self { rigid, scale }
+	}
+
+	#[inline]
+	pub fn transform_point3(&self, point: Vec3) -> Vec3 {
+		self.rigid.transform_point3(self.scale * point)
+	}
+
+	#[inline]
+	pub fn transform_vector3(&self, vector: Vec3) -> Vec3 {
+		self.rigid.transform_vector3(self.scale * vector)
+	}
+
+	/// Packs this transform into the layout read by the `dq_skinning` shader:
+	/// the real and dual parts of the [DualQuat], followed by the six unique
+	/// elements of the symmetric scale matrix.
+	#[inline]
+	pub fn to_packed(&self) -> Mat4 {
+		let DualQuat(real, dual) = self.rigid;
+		let s = self.scale;
+
+		Mat4::from_cols(
+			real.into(),
+			dual.into(),
+			Vec4::new(s.x_axis.x, s.y_axis.y, s.z_axis.z, s.y_axis.x),
+			Vec4::new(s.z_axis.x, s.z_axis.y, 0., 0.),
+		)
+	}
+
+	/// The inverse of [ScaledDualQuat::to_packed].
+	#[inline]
+	pub fn from_packed(packed: Mat4) -> Self {
+		let [xx, yy, zz, xy] = packed.z_axis.to_array();
+		let [xz, yz, ..] = packed.w_axis.to_array();
+
+		#[rustfmt::skip]
+		let scale = Mat3::from_cols_array(&[
+			xx, xy, xz,
+			xy, yy, yz,
+			xz, yz, zz,
+		]);
+
+		Self {
+			rigid: DualQuat(
+				Quat::from_vec4(packed.x_axis),
+				Quat::from_vec4(packed.y_axis),
+			),
+			scale,
+		}
+	}
+}
+
+impl Default for ScaledDualQuat {
+	#[inline(always)]
+	fn default() -> Self {
+		Self::IDENTITY
+	}
+}
+
+impl From<Affine3A> for ScaledDualQuat {
+	#[inline(always)]
+	fn from(value: Affine3A) -> Self {
+		Self::from_affine3a(value)
+	}
+}
+
+impl From<Mat4> for ScaledDualQuat {
+	#[inline(always)]
+	fn from(value: Mat4) -> Self {
+		Self::from_affine3a(Affine3A::from_mat4(value))
+	}
+}
+
+impl From<ScaledDualQuat> for Affine3A {
+	#[inline]
+	fn from(value: ScaledDualQuat) -> Self {
+		Affine3A::from(value.rigid) * Affine3A::from_mat3(value.scale)
+	}
+}
+
+impl From<ScaledDualQuat> for Mat4 {
+	#[inline(always)]
+	fn from(value: ScaledDualQuat) -> Self {
+		Mat4::from(Affine3A::from(value))
+	}
+}
+
+/// Splits `matrix` into a rotation and a symmetric matrix such that
+/// `matrix = rotation * stretch`.
+///
+/// Negative determinants (mirroring) are kept in the stretch matrix, so the
+/// rotation is always proper. Degenerate matrices are returned unchanged with
+/// an identity rotation.
+fn polar_decomposition(matrix: Mat3) -> (Quat, Mat3) {
+	let det = matrix.determinant();
+	if det.abs() <= f32::EPSILON {
+		return (Quat::IDENTITY, matrix);
+	}
+
+	// Newton iteration: R' = (R + R^-T) / 2
+	let mut rotation = matrix * det.signum();
+	for _ in 0..POLAR_DECOMPOSITION_MAX_ITERATIONS {
+		let next = (rotation + rotation.inverse().transpose()) * 0.5;
+		let delta = (next - rotation).to_cols_array();
+		rotation = next;
+
+		if delta
+			.iter()
+			.all(|d| d.abs() <= POLAR_DECOMPOSITION_TOLERANCE)
+		{
+			break;
+		}
+	}
+
+	let rotation = Quat::from_mat3(&rotation).normalize();
+	let stretch = Mat3::from_quat(rotation.conjugate()) * matrix;
+
+	// Remove any asymmetry left over from floating-point error
+	let stretch = (stretch + stretch.transpose()) * 0.5;
+
+	(rotation, stretch)
+}
+
+#[cfg(test)]
+mod tests {
+	use glam::{vec3, Affine3A, Mat3, Quat, Vec3};
+
+	use super::ScaledDualQuat;
+
+	fn sheared_xform() -> Affine3A {
+		#[rustfmt::skip]
+		let shear = Mat3::from_cols_array(&[
+			1.5, 0.0, 0.0,
+			0.4, 0.8, 0.0,
+			0.0, 0.0, 1.2,
+		]);
+
+		Affine3A::from_rotation_translation(Quat::from_rotation_z(1.1), vec3(3., -2., 5.))
+			* Affine3A::from_mat3(shear)
+	}
+
+	#[test]
+	fn round_trips_scale_and_shear() {
+		let xform = sheared_xform();
+		let sdq = ScaledDualQuat::from(xform);
+
+		assert!(sdq.scale.abs_diff_eq(sdq.scale.transpose(), 1.0e-5));
+
+		for point in [Vec3::ZERO, Vec3::X, vec3(-2., 7., 0.5)] {
+			let expected = xform.transform_point3(point);
+			assert!(sdq.transform_point3(point).abs_diff_eq(expected, 1.0e-4));
+		}
+
+		let packed = ScaledDualQuat::from_packed(sdq.to_packed());
+		assert!(Affine3A::from(packed).abs_diff_eq(xform, 1.0e-4));
+	}
+
+	#[test]
+	fn blend_of_identical_joints_is_exact() {
+		let sdq = ScaledDualQuat::from(sheared_xform());
+		let blended = ScaledDualQuat::blend([(sdq, 0.25), (sdq, 0.75)]);
+
+		let point = vec3(1., 2., 3.);
+		assert!(blended
+			.transform_point3(point)
+			.abs_diff_eq(sheared_xform().transform_point3(point), 1.0e-4));
+	}
+
+	#[test]
+	fn keeps_mirroring_in_scale() {
+		let xform = Affine3A::from_scale(vec3(-1., 2., 1.));
+		let sdq = ScaledDualQuat::from(xform);
+
+		assert!(sdq.scale.determinant() < 0.);
+		assert!(sdq
+			.transform_point3(Vec3::ONE)
+			.abs_diff_eq(vec3(-1., 2., 1.), 1.0e-5));
+	}
+}
@@ -0,0 +1,586 @@
+use bevy::{
+	asset::{AssetEvent, AssetId, Assets, Handle},
+	ecs::{
+		entity::{EntityHashMap, EntityHashSet, EntityMapper, MapEntities},
+		prelude::*,
+		reflect::{ReflectComponent, ReflectMapEntities},
+	},
+	math::{Mat4, Vec4},
+	pbr::{Material, RenderMeshInstances, MAX_JOINTS},
+	prelude::{Deref, DerefMut},
+	reflect::Reflect,
+	render::{
+		mesh::{
+			morph::MeshMorphWeights,
+			skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+		},
+		render_resource::{Buffer, BufferDescriptor, BufferUsages},
+		renderer::{RenderDevice, RenderQueue},
+		view::ViewVisibility,
+		Extract,
+	},
+	transform::components::GlobalTransform,
+	utils::HashMap,
+};
+
+use crate::{DqsMaterial, ScaledDualQuat, SkinningMethod};
+
+/// Number of joint-sized slots in the [DqSkinBuffer], shared by all
+/// dual-quaternion-skinned entities. Entities that don't fit are skinned from
+/// Bevy's joint matrices instead, and get no motion from skinning or morphing.
+pub const DQ_SKIN_BUFFER_CAPACITY: usize = 1 << 17;
+
+/// Joint-sized slots of morph weights in each entity's block of the
+/// [DqSkinBuffer]. Matches `PREVIOUS_MORPH_WEIGHT_SLOTS` in the `dq_skinning`
+/// shader, and Bevy's limit of 64 morph weights.
+const MORPH_WEIGHT_SLOTS: usize = 4;
+
+/// Slots in each entity's block: a header pointing at its joints, then last
+/// frame's morph weights.
+const HEADER_SLOTS: usize = 1 + MORPH_WEIGHT_SLOTS;
+
+/// Bits of Bevy's per-mesh `flags` holding the entity's [DqSkin]. Bevy only
+/// uses bits 0, 1 and 31. Matches the `DQ_SKIN_*` constants in the
+/// `dq_skinning` shader.
+const DQ_SKIN_METHOD_SHIFT: u32 = 2;
+const DQ_SKIN_SHARED_BIT: u32 = 1 << 4;
+const DQ_SKIN_BLOCK_SHIFT: u32 = 5;
+const DQ_SKIN_BLOCK_MASK: u32 = (1 << 26) - 1;
+const DQ_SKIN_FLAGS_MASK: u32 = (0b11 << DQ_SKIN_METHOD_SHIFT)
+	| DQ_SKIN_SHARED_BIT
+	| (DQ_SKIN_BLOCK_MASK << DQ_SKIN_BLOCK_SHIFT);
+
+/// Storage buffer holding the joints of every dual-quaternion-skinned entity
+/// as packed [ScaledDualQuat]s, along with last frame's joints and morph
+/// weights for motion vectors. Bound to every [DqsMaterial] by
+/// [DqsMaterialPlugin](crate::DqsMaterialPlugin).
+///
+/// Each entity has a block of a header slot, holding the indices of its joints
+/// and of last frame's joints, followed by last frame's morph weights. The
+/// joints themselves may be shared with other entities (see
+/// [DqsSharedPalette]). Slot 0 is left empty, so a block index of 0 means the
+/// entity has no block.
+#[derive(Resource, Clone, Debug)]
+pub struct DqSkinBuffer(pub(crate) Buffer);
+
+impl FromWorld for DqSkinBuffer {
+	fn from_world(world: &mut World) -> Self {
+		let render_device = world.resource::<RenderDevice>();
+		Self(render_device.create_buffer(&BufferDescriptor {
+			label: Some("dqs_skins"),
+			size: (DQ_SKIN_BUFFER_CAPACITY * std::mem::size_of::<Mat4>()) as u64,
+			usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		}))
+	}
+}
+
+/// Binds the [DqSkinBuffer] to [DqsMaterial]s that are added, or replaced with
+/// ones that don't have it.
+pub(crate) fn attach_dq_skin_buffer<M: Material>(
+	dq_skin_buffer: Option<Res<DqSkinBuffer>>,
+	mut ra_materials: ResMut<Assets<DqsMaterial<M>>>,
+	mut r_material_events: EventReader<AssetEvent<DqsMaterial<M>>>,
+) {
+	let Some(dq_skin_buffer) = dq_skin_buffer else {
+		return;
+	};
+
+	for event in r_material_events.read() {
+		let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = event else {
+			continue;
+		};
+		// Checked first, since `get_mut` marks the material as modified
+		let Some(material) = ra_materials.get(*id) else {
+			continue;
+		};
+		if material.extension.dq_skins.is_some() {
+			continue;
+		}
+		if let Some(material) = ra_materials.get_mut(*id) {
+			material.extension.dq_skins = Some(dq_skin_buffer.0.clone());
+		}
+	}
+}
+
+/// Skins a mesh entity with its joints relative to `root`, then places the
+/// result with the mesh's own transform, instead of skinning it in world space.
+///
+/// Meshes with the same joints, inverse bindposes and `root` share one joint
+/// palette on the GPU, so a crowd of meshes can be posed by a single skeleton:
+/// give each of them the skeleton's joints and the same `root`, e.g. the
+/// skeleton's own mesh. Those without morph weights are drawn instanced when
+/// they also share a mesh and material.
+///
+/// Without storage buffers (e.g. on WebGL2), or once the [DqSkinBuffer] is
+/// full, meshes are drawn where their joints are instead.
+#[derive(Component, Reflect, Clone, Copy, Debug, PartialEq, Eq)]
+#[reflect(Component, MapEntities)]
+pub struct DqsSharedPalette {
+	pub root: Entity,
+}
+
+impl FromWorld for DqsSharedPalette {
+	fn from_world(_: &mut World) -> Self {
+		Self {
+			root: Entity::PLACEHOLDER,
+		}
+	}
+}
+
+impl MapEntities for DqsSharedPalette {
+	fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+		self.root = entity_mapper.map_entity(self.root);
+	}
+}
+
+/// A dual-quaternion-skinned entity's block in the [DqSkinBuffer], if it has
+/// one, and its [SkinningMethod] override.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct DqSkin {
+	pub block: u32,
+	pub method: Option<SkinningMethod>,
+	/// Whether the joints are relative to a [DqsSharedPalette] root.
+	pub shared_palette: bool,
+	/// Whether the entity can be drawn instanced with others sharing its
+	/// palette, mesh and material.
+	pub instanced: bool,
+}
+
+impl DqSkin {
+	/// Packs this into the bits of Bevy's per-mesh `flags` read by the
+	/// `dq_skinning` shader's `unpack_dq_skin`.
+	pub fn flags(self) -> u32 {
+		// Matches the `SKINNING_METHOD_*` constants in the `dq_skinning` shader
+		let method = match self.method {
+			None | Some(SkinningMethod::Blended) => 0,
+			Some(SkinningMethod::Linear) => 1,
+			Some(SkinningMethod::DualQuaternion) => 2,
+		};
+		let shared = if self.shared_palette {
+			DQ_SKIN_SHARED_BIT
+		} else {
+			0
+		};
+
+		(method << DQ_SKIN_METHOD_SHIFT)
+			| shared | ((self.block & DQ_SKIN_BLOCK_MASK) << DQ_SKIN_BLOCK_SHIFT)
+	}
+}
+
+/// Identifies a joint palette in the [DqSkinBuffer].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum PaletteKey {
+	/// An entity's own palette, in world space.
+	Entity(Entity),
+	/// A palette shared by every [DqsSharedPalette] entity with the same
+	/// joints, inverse bindposes and root.
+	Shared {
+		root: Entity,
+		joints: Vec<Entity>,
+		inverse_bindposes: AssetId<SkinnedMeshInverseBindposes>,
+	},
+}
+
+/// Each visible dual-quaternion-skinned entity's [DqSkin], along with the
+/// data kept from one frame to the next for motion vectors.
+#[derive(Resource, Default)]
+pub struct DqSkins {
+	pub(crate) entities: EntityHashMap<DqSkin>,
+	/// Where each palette was written this frame: its joints, then last
+	/// frame's joints.
+	palettes: HashMap<PaletteKey, Option<[u32; 2]>>,
+	/// Each palette's joints, and each entity's morph weights, from the last
+	/// frame they were extracted.
+	last_joints: HashMap<PaletteKey, Vec<Mat4>>,
+	current_joints: HashMap<PaletteKey, Vec<Mat4>>,
+	last_morph_weights: EntityHashMap<[Mat4; MORPH_WEIGHT_SLOTS]>,
+	current_morph_weights: EntityHashMap<[Mat4; MORPH_WEIGHT_SLOTS]>,
+	/// Contents of the [DqSkinBuffer] for this frame.
+	staging: Vec<Mat4>,
+}
+
+impl DqSkins {
+	fn begin_frame(&mut self) {
+		self.entities.clear();
+		self.palettes.clear();
+		self.current_joints.clear();
+		self.current_morph_weights.clear();
+		self.staging.clear();
+		self.staging.push(Mat4::ZERO);
+	}
+
+	/// Lays out an entity's block, and the palette of `key` unless another
+	/// entity already did. `joints` is only called for new palettes, and
+	/// returns `None` if they can't be computed. Entities without previous data
+	/// reuse their current data, so only the mesh's own transform contributes
+	/// to their motion.
+	///
+	/// Returns the block's index, or 0 if it doesn't fit.
+	fn push(
+		&mut self,
+		entity: Entity,
+		key: PaletteKey,
+		morph_weights: &[f32],
+		joints: impl FnOnce() -> Option<Vec<Mat4>>,
+	) -> u32 {
+		let palette = match self.palettes.get(&key) {
+			Some(&palette) => palette,
+			None => {
+				let Some(joints) = joints() else {
+					return 0;
+				};
+				let palette = push_palette(&mut self.staging, self.last_joints.get(&key), &joints);
+				self.current_joints.insert(key.clone(), joints);
+				self.palettes.insert(key, palette);
+				palette
+			}
+		};
+
+		let morph_weights = pack_morph_weights(morph_weights);
+		let previous_morph_weights = self
+			.last_morph_weights
+			.get(&entity)
+			.unwrap_or(&morph_weights);
+		let block = palette
+			.and_then(|palette| push_header(&mut self.staging, palette, previous_morph_weights));
+		self.current_morph_weights.insert(entity, morph_weights);
+
+		block.unwrap_or(0)
+	}
+
+	fn end_frame(&mut self) {
+		std::mem::swap(&mut self.last_joints, &mut self.current_joints);
+		std::mem::swap(
+			&mut self.last_morph_weights,
+			&mut self.current_morph_weights,
+		);
+	}
+}
+
+/// Encodes the joints of every visible entity using a [DqsMaterial] as
+/// [ScaledDualQuat]s, and lays them out in the [DqSkinBuffer] next to last
+/// frame's joints and morph weights.
+///
+/// Without a [DqSkinBuffer] (e.g. on WebGL2), only the entities'
+/// [SkinningMethod] overrides are recorded.
+#[allow(clippy::type_complexity)]
+pub fn extract_dq_skins(
+	mut skins: ResMut<DqSkins>,
+	mut dq_skinned: ResMut<DqSkinnedEntities>,
+	dq_skin_buffer: Option<Res<DqSkinBuffer>>,
+	query: Extract<
+		Query<(
+			&ViewVisibility,
+			&SkinnedMesh,
+			Option<&SkinningMethod>,
+			Option<&MeshMorphWeights>,
+			Option<&DqsSharedPalette>,
+		)>,
+	>,
+	inverse_bindposes: Extract<Res<Assets<SkinnedMeshInverseBindposes>>>,
+	joints: Extract<Query<&GlobalTransform>>,
+) {
+	skins.begin_frame();
+
+	for &entity in dq_skinned.iter() {
+		let Ok((view_visibility, skin, method, morph_weights, shared)) = query.get(entity) else {
+			continue;
+		};
+		if !view_visibility.get() {
+			continue;
+		}
+
+		let mut dq_skin = DqSkin {
+			method: method.copied(),
+			..Default::default()
+		};
+
+		if dq_skin_buffer.is_some() {
+			// Shared palettes are relative to the root, and placed by the mesh's
+			// own transform
+			let root = shared.and_then(|shared| {
+				let root = joints.get(shared.root).ok()?;
+				Some((shared.root, root.affine().inverse()))
+			});
+			let key = match root {
+				Some((root, _)) => PaletteKey::Shared {
+					root,
+					joints: skin.joints.clone(),
+					inverse_bindposes: skin.inverse_bindposes.id(),
+				},
+				None => PaletteKey::Entity(entity),
+			};
+			let morph_weights = morph_weights.map_or(&[][..], |weights| weights.weights());
+
+			dq_skin.block = skins.push(entity, key, morph_weights, || {
+				let inverse_bindposes = inverse_bindposes.get(&skin.inverse_bindposes)?;
+				let to_root = root.map_or(Mat4::IDENTITY, |(_, to_root)| to_root.into());
+				let palette = joints
+					.iter_many(&skin.joints)
+					.zip(inverse_bindposes.iter())
+					.take(MAX_JOINTS)
+					.map(|(joint, bindpose)| {
+						ScaledDualQuat::from(to_root * joint.compute_matrix() * *bindpose)
+							.to_packed()
+					})
+					.collect::<Vec<_>>();
+
+				(palette.len() == skin.joints.len().min(MAX_JOINTS)).then_some(palette)
+			});
+			dq_skin.shared_palette = root.is_some() && dq_skin.block != 0;
+			dq_skin.instanced = dq_skin.shared_palette && morph_weights.is_empty();
+		}
+
+		skins.entities.insert(entity, dq_skin);
+	}
+
+	skins.end_frame();
+	// Refilled by `extract_dq_skinned` for each material type next frame
+	dq_skinned.clear();
+}
+
+/// Appends a palette's joints to `staging`, followed by last frame's joints,
+/// and returns the index of each. Returns `None` if it doesn't fit in the
+/// [DqSkinBuffer].
+fn push_palette(
+	staging: &mut Vec<Mat4>,
+	previous: Option<&Vec<Mat4>>,
+	joints: &[Mat4],
+) -> Option<[u32; 2]> {
+	let previous = previous
+		.map(Vec::as_slice)
+		.filter(|previous| previous.len() == joints.len())
+		.unwrap_or(joints);
+
+	let start = staging.len();
+	if start + 2 * joints.len() + HEADER_SLOTS > DQ_SKIN_BUFFER_CAPACITY {
+		return None;
+	}
+
+	staging.extend_from_slice(joints);
+	staging.extend_from_slice(previous);
+
+	Some([start as u32, (start + joints.len()) as u32])
+}
+
+/// Appends an entity's block to `staging`, pointing at its palette, and
+/// returns its index. Returns `None` if it doesn't fit in the [DqSkinBuffer].
+fn push_header(
+	staging: &mut Vec<Mat4>,
+	[joints, previous_joints]: [u32; 2],
+	previous_morph_weights: &[Mat4; MORPH_WEIGHT_SLOTS],
+) -> Option<u32> {
+	let block = staging.len();
+	if block + HEADER_SLOTS > DQ_SKIN_BUFFER_CAPACITY {
+		return None;
+	}
+
+	// Stored as floats, which are exact for every index below the capacity
+	staging.push(Mat4::from_cols(
+		Vec4::new(joints as f32, previous_joints as f32, 0., 0.),
+		Vec4::ZERO,
+		Vec4::ZERO,
+		Vec4::ZERO,
+	));
+	staging.extend_from_slice(previous_morph_weights);
+
+	Some(block as u32)
+}
+
+/// Packs up to 64 morph weights into joint-sized slots.
+fn pack_morph_weights(weights: &[f32]) -> [Mat4; MORPH_WEIGHT_SLOTS] {
+	let mut packed = [0.; 16 * MORPH_WEIGHT_SLOTS];
+	for (dst, &weight) in packed.iter_mut().zip(weights) {
+		*dst = weight;
+	}
+
+	std::array::from_fn(|idx| Mat4::from_cols_slice(&packed[idx * 16..]))
+}
+
+/// Stores each entity's [DqSkin] in spare bits of its mesh uniform's `flags`,
+/// where the shaders look it up by instance index, and lets entities that
+/// share a palette be batched. Must run after Bevy's `extract_meshes`, which
+/// rebuilds the flags every frame.
+pub(crate) fn extract_dq_skin_flags(
+	mut render_mesh_instances: ResMut<RenderMeshInstances>,
+	skins: Res<DqSkins>,
+) {
+	for (entity, dq_skin) in skins.entities.iter() {
+		if let Some(instance) = render_mesh_instances.get_mut(entity) {
+			let flags = &mut instance.transforms.flags;
+			*flags = (*flags & !DQ_SKIN_FLAGS_MASK) | dq_skin.flags();
+			// Bevy doesn't batch skinned meshes, since each one binds its own
+			// joints, but these read them from the `DqSkinBuffer` instead
+			if dq_skin.instanced {
+				instance.automatic_batching = true;
+			}
+		}
+	}
+}
+
+pub(crate) fn write_dq_skins(
+	render_queue: Res<RenderQueue>,
+	buffer: Option<Res<DqSkinBuffer>>,
+	skins: Res<DqSkins>,
+) {
+	let Some(buffer) = buffer else {
+		return;
+	};
+	render_queue.write_buffer(&buffer.0, 0, bevy::core::cast_slice(&skins.staging));
+}
+
+/// Collects the entities using a [DqsMaterial] with the base material `M`, to
+/// be picked up by [extract_dq_skins].
+#[allow(clippy::type_complexity)]
+pub(crate) fn extract_dq_skinned<M: Material>(
+	mut dq_skinned: ResMut<DqSkinnedEntities>,
+	query: Extract<Query<Entity, (With<SkinnedMesh>, With<Handle<DqsMaterial<M>>>)>>,
+) {
+	dq_skinned.extend(query.iter());
+}
+
+/// Entities whose joints are encoded by [extract_dq_skins].
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct DqSkinnedEntities(EntityHashSet);
+
+#[cfg(test)]
+mod tests {
+	use bevy::{
+		asset::AssetId,
+		ecs::entity::Entity,
+		math::{vec3, Affine3A, Mat4, Quat},
+	};
+
+	use super::{
+		pack_morph_weights, push_palette, DqSkin, DqSkins, PaletteKey, DQ_SKIN_BUFFER_CAPACITY,
+		HEADER_SLOTS,
+	};
+	use crate::{ScaledDualQuat, SkinningMethod};
+
+	fn joints(offset: f32) -> Vec<Mat4> {
+		(0..3)
+			.map(|idx| {
+				let idx = idx as f32 + offset;
+				ScaledDualQuat::from(Affine3A::from_scale_rotation_translation(
+					vec3(1. + idx * 0.1, 1., 1. - idx * 0.1),
+					Quat::from_rotation_y(idx * 0.3),
+					vec3(idx, idx * 2., idx * -3.),
+				))
+				.to_packed()
+			})
+			.collect()
+	}
+
+	/// The joints and last frame's joints of `block`, read like `load_joint`
+	/// and `previous_skin_transform` do.
+	fn palette(skins: &DqSkins, block: u32, count: usize) -> [&[Mat4]; 2] {
+		let header = skins.staging[block as usize].x_axis;
+		[header.x, header.y].map(|start| &skins.staging[start as usize..][..count])
+	}
+
+	#[test]
+	fn lays_out_blocks_like_the_shader_reads_them() {
+		let entity = Entity::from_raw(1);
+		let key = PaletteKey::Entity(entity);
+		let mut skins = DqSkins::default();
+
+		skins.begin_frame();
+		let block = skins.push(entity, key.clone(), &[0.25], || Some(joints(0.)));
+		skins.end_frame();
+		assert_ne!(block, 0);
+		// Without previous data, the current data stands in for it
+		assert_eq!(palette(&skins, block, 3), [
+			&joints(0.)[..],
+			&joints(0.)[..]
+		]);
+
+		skins.begin_frame();
+		let block = skins.push(entity, key, &[0.5, 0.75], || Some(joints(1.)));
+		skins.end_frame();
+		assert_eq!(palette(&skins, block, 3), [
+			&joints(1.)[..],
+			&joints(0.)[..]
+		]);
+
+		// `previous_morph_weight` reads the slots after the header
+		let block = block as usize;
+		assert_eq!(
+			skins.staging[block + 1..block + HEADER_SLOTS],
+			pack_morph_weights(&[0.25])
+		);
+	}
+
+	#[test]
+	fn shares_palettes_between_entities() {
+		let key = PaletteKey::Shared {
+			root: Entity::from_raw(10),
+			joints: vec![Entity::from_raw(11), Entity::from_raw(12)],
+			inverse_bindposes: AssetId::default(),
+		};
+		let mut skins = DqSkins::default();
+
+		skins.begin_frame();
+		let first = skins.push(Entity::from_raw(1), key.clone(), &[], || Some(joints(0.)));
+		let second = skins.push(Entity::from_raw(2), key, &[], || {
+			panic!("shared palettes are only computed once")
+		});
+		skins.end_frame();
+
+		assert_ne!(first, second);
+		assert_eq!(
+			skins.staging[first as usize],
+			skins.staging[second as usize]
+		);
+		// One palette and its previous joints, plus a block for each entity
+		assert_eq!(skins.staging.len(), 1 + 6 + 2 * HEADER_SLOTS);
+	}
+
+	#[test]
+	fn skips_palettes_that_dont_fit() {
+		let mut staging = vec![Mat4::ZERO; DQ_SKIN_BUFFER_CAPACITY - HEADER_SLOTS - 5];
+		assert_eq!(push_palette(&mut staging, None, &joints(0.)), None);
+		assert_eq!(staging.len(), DQ_SKIN_BUFFER_CAPACITY - HEADER_SLOTS - 5);
+	}
+
+	#[test]
+	fn packs_flags_clear_of_bevys() {
+		let flags = DqSkin {
+			block: super::DQ_SKIN_BLOCK_MASK,
+			method: Some(SkinningMethod::DualQuaternion),
+			shared_palette: true,
+			instanced: true,
+		}
+		.flags();
+
+		// Bevy's shadow receiver and sign determinant bits
+		assert_eq!(flags & 0b11, 0);
+		assert_eq!(flags & (1 << 31), 0);
+		assert_eq!(flags & super::DQ_SKIN_FLAGS_MASK, flags);
+		assert_eq!(
+			DqSkin {
+				block: 5,
+				method: Some(SkinningMethod::Linear),
+				..Default::default()
+			}
+			.flags(),
+			(5 << 5) | (1 << 2)
+		);
+	}
+
+	#[test]
+	fn packs_morph_weights_like_the_shader_reads_them() {
+		let weights = (0..70).map(|idx| idx as f32).collect::<Vec<_>>();
+		let packed = pack_morph_weights(&weights);
+
+		// `previous_morph_weight` reads weight `i` from vec4 `i / 4`, component
+		// `i % 4`, counting from the first slot
+		let vec4s = packed
+			.iter()
+			.flat_map(|slot| slot.to_cols_array_2d())
+			.collect::<Vec<_>>();
+		for idx in 0..64 {
+			assert_eq!(vec4s[idx / 4][idx % 4], weights[idx]);
+		}
+	}
+}
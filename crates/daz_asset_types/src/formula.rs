@@ -0,0 +1,62 @@
+use serde::Deserialize;
+use serde_json as json;
+
+use super::util::strenum;
+
+/// A formula defines a set of operations which compute the value of an output
+/// channel from the values of other channels, e.g. moving a joint's center
+/// point as a shaping morph is dialed in.
+///
+/// ## Details
+///
+/// Operations are evaluated on a stack. The result of the final operation is
+/// combined with the output channel's value according to `stage`.
+///
+/// * [Reference](http://docs.daz3d.com/doku.php/public/dson_spec/object_definitions/formula/start)
+#[derive(Clone, Debug, Deserialize)]
+pub struct Formula {
+	/// A string representing the URI of the output channel, e.g.
+	/// `l_thigh:/data/Daz%203D/Genesis%209/Base/Genesis9.dsf#l_thigh?center_point/x`.
+	pub output: String,
+
+	/// A string representing how the formula's result is combined with the
+	/// output channel's value.
+	#[serde(default)]
+	pub stage: FormulaStage,
+
+	/// An array of operation objects, evaluated in order.
+	pub operations: Vec<Operation>,
+}
+
+strenum! { FormulaStage
+	Sum = "sum",
+	Mult = "mult",
+}
+
+/// A single step of a [Formula].
+///
+/// * [Reference](http://docs.daz3d.com/doku.php/public/dson_spec/object_definitions/operation/start)
+#[derive(Clone, Debug, Deserialize)]
+pub struct Operation {
+	/// A string representing the operator.
+	pub op: OperationType,
+
+	/// The value to push onto the stack, for `push` operations without a `url`.
+	/// A number, or an array of spline knots for spline operations.
+	pub val: Option<json::Value>,
+
+	/// A string representing the URI of the channel whose value to push onto
+	/// the stack, for `push` operations without a `val`.
+	pub url: Option<String>,
+}
+
+strenum! { OperationType
+	Push = "push",
+	Add = "add",
+	Sub = "sub",
+	Mult = "mult",
+	Div = "div",
+	SplineConstant = "spline_constant",
+	SplineLinear = "spline_linear",
+	SplineTcb = "spline_tcb",
+}
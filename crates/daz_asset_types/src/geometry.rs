@@ -138,65 +138,3 @@ impl<'a> Deserialize<'a> for Polygon {
 		de.deserialize_seq(PolygonVisitor)
 	}
 }
-
-#[cfg(feature = "bevy")]
-impl From<Geometry> for bevy_render::mesh::Mesh {
-	fn from(geo: Geometry) -> Self {
-		use bevy_math::Vec3;
-		use bevy_render::{
-			mesh::{Indices, Mesh, PrimitiveTopology},
-			render_asset::RenderAssetUsages,
-		};
-
-		let positions: Vec<_> = geo.vertices.values.into_iter().map(|v| v * 0.01).collect();
-		let mut normals = vec![Vec3::ZERO; positions.len()];
-		let mut indices = Vec::new();
-
-		for polygon in &geo.polylist.values {
-			let (i0, i1, i2, i3) = polygon.vertex_indices;
-
-			let v0 = positions[i0 as usize];
-			let v1 = positions[i1 as usize];
-			let v2 = positions[i2 as usize];
-
-			let a = (v1 - v0).normalize();
-			let b = (v2 - v0).normalize();
-			let n1 = a.cross(b);
-
-			normals[i0 as usize] += n1;
-			normals[i1 as usize] += n1;
-			normals[i2 as usize] += n1;
-
-			if let Some(i3) = i3 {
-				let v3 = positions[i3 as usize];
-				let c = (v3 - v0).normalize();
-				let n2 = b.cross(c);
-
-				normals[i0 as usize] += n2;
-				normals[i2 as usize] += n2;
-				normals[i3 as usize] += n2;
-
-				indices.extend([i0, i1, i2, i0, i2, i3]);
-			} else {
-				indices.extend([i0, i1, i2]);
-			}
-
-			// TODO: Polygon groups
-			// TODO: Material groups
-		}
-
-		for normal in normals.iter_mut() {
-			*normal = normal.normalize();
-		}
-
-		let mut mesh = Mesh::new(
-			PrimitiveTopology::TriangleList,
-			RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-		);
-		mesh.insert_indices(Indices::U32(indices));
-		mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-		mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-
-		mesh
-	}
-}
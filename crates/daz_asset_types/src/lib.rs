@@ -3,7 +3,11 @@ use serde_json as json;
 
 mod asset_info;
 mod channel;
+mod formula;
 mod geometry;
+mod library;
+#[cfg(any(feature = "bevy", feature = "glam"))]
+mod mesh;
 mod modifier;
 mod node;
 mod util;
@@ -11,13 +15,17 @@ mod uv_set;
 
 pub use asset_info::{AssetInfo, Contributor};
 pub use channel::{ChannelFloat, ChannelType};
+pub use formula::{Formula, FormulaStage, Operation, OperationType};
 pub use geometry::{EdgeInterpolationMode, Geometry, GeometryType, Polygon};
-pub use modifier::{Modifier, SkinBinding, WeightedJoint};
+pub use library::{percent_decode, LIBRARY_PATHS_VAR};
+pub use modifier::{Modifier, Morph, SkinBinding, WeightedJoint};
 pub use node::{Node, NodeType, RotationOrder};
 pub use uv_set::UvSet;
 
 #[cfg(any(feature = "bevy", feature = "glam"))]
 pub use channel::ChannelsAsVec3;
+#[cfg(any(feature = "bevy", feature = "glam"))]
+pub use mesh::TriangleMesh;
 
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct Array<T> {
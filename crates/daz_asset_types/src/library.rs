@@ -0,0 +1,32 @@
+//! Conventions for locating assets in Daz content libraries, shared by the
+//! tools that read them.
+
+/// Environment variable containing a list of Daz library root directories,
+/// separated by the platform's path separator (`;` on Windows, `:` elsewhere).
+pub const LIBRARY_PATHS_VAR: &str = "DAZ_LIBRARY_PATHS";
+
+/// Decodes the `%XX` escapes of an asset URI, e.g. `Daz%203D` -> `Daz 3D`.
+pub fn percent_decode(s: &str) -> String {
+	let bytes = s.as_bytes();
+	let mut result = Vec::with_capacity(bytes.len());
+	let mut idx = 0;
+	while idx < bytes.len() {
+		let decoded = (bytes[idx] == b'%')
+			.then(|| s.get(idx + 1..idx + 3))
+			.flatten()
+			.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+		match decoded {
+			Some(byte) => {
+				result.push(byte);
+				idx += 3;
+			}
+			None => {
+				result.push(bytes[idx]);
+				idx += 1;
+			}
+		}
+	}
+
+	String::from_utf8_lossy(&result).into_owned()
+}
@@ -0,0 +1,348 @@
+#[cfg(feature = "bevy")]
+use bevy_math::{Vec2, Vec3};
+#[cfg(all(feature = "glam", not(feature = "bevy")))]
+use glam::{Vec2, Vec3};
+
+use std::collections::HashMap;
+
+use crate::{Geometry, Morph, SkinBinding, UvSet};
+
+/// A [Geometry] triangulated for rendering or export, with positions in meters.
+///
+/// UV seams are stored by DSON as per-polygon overrides of a vertex's UV, so
+/// vertices along them are split into a copy for each side. The first vertices
+/// are the geometry's own, in order, followed by those copies.
+#[derive(Clone, Debug, Default)]
+pub struct TriangleMesh {
+	pub positions: Vec<Vec3>,
+	/// Area-weighted smooth normals, which are shared by the copies of a vertex
+	/// so that UV seams don't show.
+	pub normals: Vec<Vec3>,
+	/// UVs with their origin at the top left, like glTF's and Bevy's, where
+	/// DSON's is at the bottom left.
+	pub uvs: Option<Vec<Vec2>>,
+	/// For each vertex, the index of the geometry vertex it's a copy of, which
+	/// skin weights and morph deltas are indexed by.
+	pub source_vertices: Vec<u32>,
+	/// Triangle indices for each of the geometry's polygon material groups.
+	pub surfaces: Vec<Vec<u32>>,
+	vertex_count: usize,
+}
+
+impl TriangleMesh {
+	pub fn new(geometry: &Geometry, uv_set: Option<&UvSet>) -> Self {
+		match uv_set {
+			Some(uv_set) => Self::with_uvs(
+				geometry,
+				&uv_set.uvs.values,
+				uv_set.polygon_vertex_indices.as_deref().unwrap_or_default(),
+			),
+			None => Self::triangulate(geometry, &[]).0,
+		}
+	}
+
+	/// Like [TriangleMesh::new], with the `uvs` and `polygon_vertex_indices` of
+	/// a [UvSet] that's been unpacked.
+	pub fn with_uvs(
+		geometry: &Geometry,
+		uvs: &[Vec2],
+		polygon_vertex_indices: &[[usize; 3]],
+	) -> Self {
+		let (mut result, uv_indices) = Self::triangulate(geometry, polygon_vertex_indices);
+		result.uvs = Some(
+			uv_indices
+				.into_iter()
+				.map(|uv_idx| {
+					let uv = uvs.get(uv_idx).copied().unwrap_or_default();
+					Vec2::new(uv.x, 1. - uv.y)
+				})
+				.collect(),
+		);
+
+		result
+	}
+
+	/// Returns the mesh, and the UV index of each of its vertices.
+	fn triangulate(
+		geometry: &Geometry,
+		polygon_vertex_indices: &[[usize; 3]],
+	) -> (Self, Vec<usize>) {
+		let vertex_count = geometry.vertices.values.len();
+		let positions = geometry
+			.vertices
+			.values
+			.iter()
+			.map(|&position| position * 0.01)
+			.collect::<Vec<_>>();
+
+		// (polygon index, vertex index) -> UV index
+		let uv_overrides = polygon_vertex_indices
+			.iter()
+			.map(|&[poly_idx, vert_idx, uv_idx]| ((poly_idx, vert_idx), uv_idx))
+			.collect::<HashMap<_, _>>();
+		// (vertex index, UV index) -> mesh vertex index, for the copies
+		let mut copies = HashMap::<(usize, usize), u32>::new();
+		let mut source_vertices = (0..vertex_count as u32).collect::<Vec<_>>();
+		let mut uv_indices = (0..vertex_count).collect::<Vec<_>>();
+		let mut surfaces = vec![vec![]; geometry.polygon_material_groups.values.len()];
+		let mut normals = vec![Vec3::ZERO; vertex_count];
+
+		for (poly_idx, polygon) in geometry.polylist.values.iter().enumerate() {
+			let (i0, i1, i2, i3) = polygon.vertex_indices;
+			let corners = [Some(i0), Some(i1), Some(i2), i3]
+				.into_iter()
+				.flatten()
+				.map(|vert_idx| vert_idx as usize)
+				.collect::<Vec<_>>();
+			if corners.iter().any(|&vert_idx| vert_idx >= vertex_count) {
+				continue;
+			}
+
+			let triangles = [Some([0, 1, 2]), (corners.len() == 4).then_some([0, 2, 3])];
+			for [a, b, c] in triangles.into_iter().flatten() {
+				let [a, b, c] = [corners[a], corners[b], corners[c]];
+				let normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+				normals[a] += normal;
+				normals[b] += normal;
+				normals[c] += normal;
+			}
+
+			let Some(indices) = surfaces.get_mut(polygon.material_groups_index) else {
+				continue;
+			};
+			let corners = corners
+				.into_iter()
+				.map(|vert_idx| match uv_overrides.get(&(poly_idx, vert_idx)) {
+					Some(&uv_idx) if uv_idx != vert_idx => {
+						*copies.entry((vert_idx, uv_idx)).or_insert_with(|| {
+							source_vertices.push(vert_idx as u32);
+							uv_indices.push(uv_idx);
+							source_vertices.len() as u32 - 1
+						})
+					}
+					_ => vert_idx as u32,
+				})
+				.collect::<Vec<_>>();
+
+			indices.extend([corners[0], corners[1], corners[2]]);
+			if let Some(&c3) = corners.get(3) {
+				indices.extend([corners[0], corners[2], c3]);
+			}
+		}
+
+		let mut result = Self {
+			positions: vec![],
+			normals: vec![],
+			uvs: None,
+			source_vertices,
+			surfaces,
+			vertex_count,
+		};
+		result.positions = result.map_vertices(&positions);
+		result.normals = result
+			.map_vertices(&normals)
+			.into_iter()
+			.map(Vec3::normalize_or_zero)
+			.collect();
+
+		(result, uv_indices)
+	}
+
+	/// Copies per-vertex values of the geometry to the mesh's vertices.
+	pub fn map_vertices<T: Copy>(&self, values: &[T]) -> Vec<T> {
+		self.source_vertices
+			.iter()
+			.map(|&idx| values[idx as usize])
+			.collect()
+	}
+
+	/// The triangle indices of every surface, in order.
+	pub fn indices(&self) -> Vec<u32> {
+		self.surfaces.concat()
+	}
+
+	/// The 4 most influential joints of each vertex, as indices into the skin's
+	/// `joints`, and their weights, which are normalized. Vertices with no
+	/// weights get zeroes.
+	pub fn joint_weights(&self, skin: &SkinBinding) -> (Vec<[u16; 4]>, Vec<[f32; 4]>) {
+		let vertex_count = self.geometry_vertex_count();
+		let mut influences = vec![vec![]; vertex_count];
+		for (joint_idx, joint) in skin.joints.iter().flatten().enumerate() {
+			let weights = joint.node_weights.as_ref();
+			for &(vert_idx, weight) in weights.iter().flat_map(|weights| &weights.values) {
+				if let Some(influences) = influences.get_mut(vert_idx) {
+					influences.push((joint_idx as u16, weight));
+				}
+			}
+		}
+
+		let (joints, weights) = influences
+			.iter_mut()
+			.map(|influences| {
+				influences.sort_by(|a: &(u16, f32), b| b.1.total_cmp(&a.1));
+				influences.truncate(4);
+
+				let sum = influences.iter().map(|(_, weight)| weight).sum::<f32>();
+				let mut joints = [0; 4];
+				let mut weights = [0.; 4];
+				for (slot, &(joint, weight)) in influences.iter().enumerate() {
+					joints[slot] = joint;
+					weights[slot] = if sum > f32::EPSILON { weight / sum } else { 0. };
+				}
+				(joints, weights)
+			})
+			.unzip::<_, _, Vec<_>, Vec<_>>();
+
+		(self.map_vertices(&joints), self.map_vertices(&weights))
+	}
+
+	/// The skin's dual quaternion blend weight for each vertex, if it has any.
+	pub fn blend_weights(&self, skin: &SkinBinding) -> Option<Vec<f32>> {
+		let blend_weights = skin.blend_weights.as_ref()?;
+		let mut blends = vec![0.; self.geometry_vertex_count()];
+		for &(vert_idx, blend) in blend_weights.values.iter() {
+			if let Some(dst) = blends.get_mut(vert_idx) {
+				*dst = blend;
+			}
+		}
+
+		Some(self.map_vertices(&blends))
+	}
+
+	/// The morph's offset of each vertex, in meters.
+	pub fn morph_deltas(&self, morph: &Morph) -> Vec<Vec3> {
+		let mut deltas = vec![Vec3::ZERO; self.geometry_vertex_count()];
+		for &(vert_idx, x, y, z) in morph.deltas.values.iter() {
+			if let Some(delta) = deltas.get_mut(vert_idx) {
+				*delta = Vec3::new(x, y, z) * 0.01;
+			}
+		}
+
+		self.map_vertices(&deltas)
+	}
+
+	/// The number of vertices in the geometry, which may be fewer than the
+	/// mesh's.
+	pub fn geometry_vertex_count(&self) -> usize {
+		self.vertex_count
+	}
+}
+
+#[cfg(feature = "bevy")]
+impl From<&TriangleMesh> for bevy_render::mesh::Mesh {
+	fn from(mesh: &TriangleMesh) -> Self {
+		use bevy_render::{
+			mesh::{Indices, Mesh, PrimitiveTopology},
+			render_asset::RenderAssetUsages,
+		};
+
+		let mut result = Mesh::new(
+			PrimitiveTopology::TriangleList,
+			RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+		);
+		result.insert_indices(Indices::U32(mesh.indices()));
+		result.insert_attribute(Mesh::ATTRIBUTE_POSITION, mesh.positions.clone());
+		result.insert_attribute(Mesh::ATTRIBUTE_NORMAL, mesh.normals.clone());
+		if let Some(uvs) = mesh.uvs.clone() {
+			result.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+		}
+
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json as json;
+
+	use super::{TriangleMesh, Vec2, Vec3};
+	use crate::{Geometry, SkinBinding};
+
+	/// A quad made of two triangles, whose second uses UV 4 for vertex 0.
+	fn seamed_quad() -> TriangleMesh {
+		let geometry = json::from_value::<Geometry>(json::json!({
+			"id": "Quad",
+			"vertices": { "count": 4, "values": [[0, 0, 0], [100, 0, 0], [100, 100, 0], [0, 100, 0]] },
+			"polygon_groups": { "count": 1, "values": ["Default"] },
+			"polygon_material_groups": { "count": 1, "values": ["Skin"] },
+			"polylist": { "count": 2, "values": [[0, 0, 0, 1, 2], [0, 0, 0, 2, 3]] }
+		}))
+		.unwrap();
+		let uvs = [
+			Vec2::new(0., 0.),
+			Vec2::new(1., 0.),
+			Vec2::new(1., 1.),
+			Vec2::new(0., 1.),
+			Vec2::new(0.5, 0.5),
+		];
+
+		TriangleMesh::with_uvs(&geometry, &uvs, &[[1, 0, 4]])
+	}
+
+	#[test]
+	fn splits_vertices_along_uv_seams() {
+		let triangles = seamed_quad();
+
+		assert_eq!(triangles.source_vertices, [0, 1, 2, 3, 0]);
+		assert_eq!(triangles.surfaces, [vec![0, 1, 2, 4, 2, 3]]);
+		assert_eq!(triangles.geometry_vertex_count(), 4);
+		assert_eq!(triangles.positions[1], Vec3::X);
+		assert_eq!(triangles.positions[4], triangles.positions[0]);
+		assert_eq!(triangles.normals[4], Vec3::Z);
+		assert_eq!(triangles.normals[4], triangles.normals[0]);
+	}
+
+	#[test]
+	fn flips_uvs_to_a_top_left_origin() {
+		let triangles = seamed_quad();
+
+		assert_eq!(triangles.uvs.unwrap()[..], [
+			Vec2::new(0., 1.),
+			Vec2::new(1., 1.),
+			Vec2::new(1., 0.),
+			Vec2::new(0., 0.),
+			Vec2::new(0.5, 0.5),
+		]);
+	}
+
+	#[test]
+	fn keeps_the_four_most_influential_joints() {
+		let geometry = json::from_value::<Geometry>(json::json!({
+			"id": "Triangle",
+			"vertices": { "count": 3, "values": [[0, 0, 0], [100, 0, 0], [0, 100, 0]] },
+			"polygon_groups": { "count": 1, "values": ["Default"] },
+			"polygon_material_groups": { "count": 1, "values": ["Skin"] },
+			"polylist": { "count": 1, "values": [[0, 0, 0, 1, 2]] }
+		}))
+		.unwrap();
+		let joint = |id: &str, weight: f32| {
+			json::json!({
+				"id": id,
+				"node": format!("#{id}"),
+				"node_weights": { "count": 1, "values": [[0, weight]] }
+			})
+		};
+		let skin = json::from_value::<SkinBinding>(json::json!({
+			"node": "#Figure",
+			"geometry": "#Triangle",
+			"vertex_count": 3,
+			"joints": [
+				joint("a", 1.),
+				joint("b", 4.),
+				joint("c", 2.),
+				joint("d", 3.),
+				joint("e", 0.5),
+			]
+		}))
+		.unwrap();
+
+		let (joints, weights) = TriangleMesh::new(&geometry, None).joint_weights(&skin);
+
+		// "e" is dropped, and the rest are renormalized to sum to 1
+		assert_eq!(joints[0], [1, 3, 2, 0]);
+		assert_eq!(weights[0], [0.4, 0.3, 0.2, 0.1]);
+		// Unweighted
+		assert_eq!(joints[1], [0; 4]);
+		assert_eq!(weights[1], [0.; 4]);
+	}
+}
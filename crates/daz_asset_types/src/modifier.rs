@@ -1,7 +1,7 @@
 use serde::Deserialize;
 use serde_json as json;
 
-use crate::Array;
+use crate::{util::lenient_vec, Array, Formula};
 
 /// This element defines an individual modifier asset for a morph, a skin
 /// binding, a channel, or an application-defined modifier type.
@@ -63,11 +63,13 @@ pub struct Modifier {
 	#[serde(default = "group_default")]
 	pub group: String,
 
-	/// An array of formula objects owned by this modifier.
-	pub formulas: Option<Vec<json::Value>>, // TODO
+	/// An array of formula objects owned by this modifier. Formulas that fail
+	/// to parse are skipped.
+	#[serde(default, deserialize_with = "lenient_vec")]
+	pub formulas: Option<Vec<Formula>>,
 
 	/// Any morph attached to this modifier.
-	pub morph: Option<json::Value>, // TODO
+	pub morph: Option<Morph>,
 
 	/// Any skin_binding attached to this modifier
 	pub skin: Option<SkinBinding>,
@@ -81,6 +83,25 @@ fn group_default() -> String {
 	"/".into()
 }
 
+/// A morph defines vertex offsets for a geometry, scaled by its modifier's
+/// value.
+///
+/// * [Reference](http://docs.daz3d.com/doku.php/public/dson_spec/object_definitions/morph/start)
+#[derive(Deserialize, Debug, Clone)]
+pub struct Morph {
+	/// An int representing the number of vertices expected in the target
+	/// geometry, or -1 if the morph may be applied to any geometry.
+	#[serde(default = "morph_vertex_count_default")]
+	pub vertex_count: i64,
+
+	/// A float3_indexed_array representing the offset of each affected vertex.
+	pub deltas: Array<(usize, f32, f32, f32)>,
+}
+
+fn morph_vertex_count_default() -> i64 {
+	-1
+}
+
 /// A skin_binding defines the offsets and weights that relate a skin (geometry)
 /// to a skeleton (a collection of nodes).
 ///
@@ -103,6 +124,13 @@ pub struct SkinBinding {
 	/// A named_string_map that provides a one to one mapping from face groups to
 	/// nodes.
 	pub selection_sets: Option<Vec<json::Value>>, // TODO
+
+	/// A float_indexed_array representing the "Blend Dual Quaternion/Linear"
+	/// weight map, where 0 is fully linear and 1 is fully dual quaternion
+	/// skinning. This is a Daz Studio extension and isn't part of the DSON
+	/// spec.
+	#[serde(alias = "dual_quaternion_blend_weights")]
+	pub blend_weights: Option<Array<(usize, f32)>>,
 }
 
 /// Defines one of the joints in a skin binding. For now, the binding matrix for
@@ -110,7 +138,7 @@ pub struct SkinBinding {
 ///
 /// ## Details
 /// * At least one of `node_weights`, `scale_weights`, and/or `local_weights`
-/// must be present.
+///   must be present.
 ///
 /// * [Reference](http://docs.daz3d.com/doku.php/public/dson_spec/object_definitions/weighted_joint/start)
 #[derive(Deserialize, Debug, Clone)]
@@ -39,3 +39,21 @@ macro_rules! strenum {
 }
 
 pub(crate) use strenum;
+
+/// Deserializes an optional array, skipping elements that fail to deserialize
+/// instead of failing the whole array, e.g. formulas using operations that
+/// aren't supported yet.
+pub(crate) fn lenient_vec<'de, D, T>(de: D) -> Result<Option<Vec<T>>, D::Error>
+where
+	D: serde::Deserializer<'de>,
+	T: serde::de::DeserializeOwned,
+{
+	let values = <Option<Vec<serde_json::Value>> as serde::Deserialize>::deserialize(de)?;
+
+	Ok(values.map(|values| {
+		values
+			.into_iter()
+			.filter_map(|value| serde_json::from_value(value).ok())
+			.collect()
+	}))
+}
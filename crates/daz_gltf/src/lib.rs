@@ -0,0 +1,261 @@
+//! A minimal glTF 2.0 writer, shared by `bevy_daz`'s exporter and `daz-tool`.
+//!
+//! [GltfBuilder] packs accessor data into a single buffer and collects the
+//! document's arrays as JSON, leaving their contents up to the caller.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use serde_json::{self as json, json};
+
+pub const FLOAT: u32 = 5126;
+pub const UNSIGNED_SHORT: u32 = 5123;
+pub const UNSIGNED_INT: u32 = 5125;
+pub const ARRAY_BUFFER: u32 = 34962;
+pub const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// A glTF document and its binary buffer.
+#[derive(Clone, Debug)]
+pub struct Gltf {
+	pub json: json::Value,
+	pub buffer: Vec<u8>,
+}
+
+impl Gltf {
+	/// Packs the document and its buffer into a single binary glTF (`.glb`).
+	/// The binary chunk is left out if the buffer is empty.
+	pub fn to_glb(&self) -> Vec<u8> {
+		let mut json = json::to_vec(&self.json).expect("glTF JSON is always serializable");
+		json.resize(json.len().next_multiple_of(4), b' ');
+		let mut bin = self.buffer.clone();
+		bin.resize(bin.len().next_multiple_of(4), 0);
+
+		let bin_chunk_len = if bin.is_empty() { 0 } else { 8 + bin.len() };
+		let total_len = 12 + 8 + json.len() + bin_chunk_len;
+		let mut glb = Vec::with_capacity(total_len);
+		glb.extend_from_slice(b"glTF");
+		glb.extend_from_slice(&2_u32.to_le_bytes());
+		glb.extend_from_slice(&(total_len as u32).to_le_bytes());
+		glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+		glb.extend_from_slice(b"JSON");
+		glb.extend_from_slice(&json);
+		if !bin.is_empty() {
+			glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+			glb.extend_from_slice(b"BIN\0");
+			glb.extend_from_slice(&bin);
+		}
+
+		glb
+	}
+
+	/// Writes a `.glb` file, or for any other extension, a `.gltf` file with
+	/// the buffer in a `.bin` file next to it.
+	pub fn write(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+		let path = path.as_ref();
+		if path.extension().is_some_and(|ext| ext == "glb") {
+			std::fs::write(path, self.to_glb())?;
+			return Ok(());
+		}
+		if self.buffer.is_empty() {
+			std::fs::write(path, json::to_vec_pretty(&self.json)?)?;
+			return Ok(());
+		}
+
+		let bin_path = path.with_extension("bin");
+		let bin_name = bin_path
+			.file_name()
+			.ok_or_else(|| anyhow!("Invalid glTF path: {}", path.display()))?
+			.to_string_lossy();
+
+		let mut json = self.json.clone();
+		json["buffers"][0]["uri"] = json!(bin_name);
+
+		std::fs::write(&bin_path, &self.buffer)?;
+		std::fs::write(path, json::to_vec_pretty(&json)?)?;
+
+		Ok(())
+	}
+}
+
+/// Builds a [Gltf] with a single buffer.
+#[derive(Default)]
+pub struct GltfBuilder {
+	pub buffer: Vec<u8>,
+	pub buffer_views: Vec<json::Value>,
+	pub accessors: Vec<json::Value>,
+	pub nodes: Vec<json::Value>,
+	pub meshes: Vec<json::Value>,
+	pub skins: Vec<json::Value>,
+	pub materials: Vec<json::Value>,
+	pub textures: Vec<json::Value>,
+	pub images: Vec<json::Value>,
+}
+
+impl GltfBuilder {
+	/// Appends `bytes` to the buffer, 4-byte aligned, and returns the index of
+	/// their buffer view.
+	pub fn buffer_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+		self.buffer.resize(self.buffer.len().next_multiple_of(4), 0);
+
+		let mut view = json!({
+			"buffer": 0,
+			"byteOffset": self.buffer.len(),
+			"byteLength": bytes.len(),
+		});
+		if let Some(target) = target {
+			view["target"] = json!(target);
+		}
+		self.buffer.extend_from_slice(bytes);
+		self.buffer_views.push(view);
+
+		self.buffer_views.len() - 1
+	}
+
+	/// Appends `bytes` to the buffer, with an accessor of `count` elements of
+	/// `type_`, e.g. `VEC3`, and returns the accessor's index.
+	pub fn accessor(
+		&mut self,
+		bytes: &[u8],
+		component_type: u32,
+		count: usize,
+		type_: &str,
+		target: Option<u32>,
+		bounds: Option<([f32; 3], [f32; 3])>,
+	) -> usize {
+		let mut accessor = json!({
+			"bufferView": self.buffer_view(bytes, target),
+			"componentType": component_type,
+			"count": count,
+			"type": type_,
+		});
+		if let Some((min, max)) = bounds {
+			accessor["min"] = json!(min);
+			accessor["max"] = json!(max);
+		}
+		self.accessors.push(accessor);
+
+		self.accessors.len() - 1
+	}
+
+	/// A `VEC3` accessor with the bounds glTF requires for positions.
+	pub fn vec3_accessor(&mut self, values: &[[f32; 3]], target: Option<u32>) -> usize {
+		let mut min = [f32::MAX; 3];
+		let mut max = [f32::MIN; 3];
+		for value in values.iter() {
+			for axis in 0..3 {
+				min[axis] = min[axis].min(value[axis]);
+				max[axis] = max[axis].max(value[axis]);
+			}
+		}
+
+		self.accessor(
+			&f32_bytes(values.as_flattened()),
+			FLOAT,
+			values.len(),
+			"VEC3",
+			target,
+			Some((min, max)),
+		)
+	}
+
+	/// A scalar accessor for triangle indices.
+	pub fn index_accessor(&mut self, indices: &[u32]) -> usize {
+		let bytes = indices
+			.iter()
+			.flat_map(|idx| idx.to_le_bytes())
+			.collect::<Vec<_>>();
+
+		self.accessor(
+			&bytes,
+			UNSIGNED_INT,
+			indices.len(),
+			"SCALAR",
+			Some(ELEMENT_ARRAY_BUFFER),
+			None,
+		)
+	}
+
+	/// Finishes the document, with `roots` as its scene's nodes. Empty arrays
+	/// and buffers are left out, since glTF doesn't allow them.
+	pub fn finish(self, generator: &str, roots: Vec<usize>) -> Gltf {
+		let mut json = json!({
+			"asset": {
+				"version": "2.0",
+				"generator": generator,
+			},
+		});
+		if !roots.is_empty() {
+			json["scene"] = json!(0);
+			json["scenes"] = json!([{ "nodes": roots }]);
+		}
+		if !self.buffer.is_empty() {
+			json["buffers"] = json!([{ "byteLength": self.buffer.len() }]);
+		}
+		for (key, values) in [
+			("nodes", self.nodes),
+			("bufferViews", self.buffer_views),
+			("accessors", self.accessors),
+			("meshes", self.meshes),
+			("skins", self.skins),
+			("materials", self.materials),
+			("textures", self.textures),
+			("images", self.images),
+		] {
+			if !values.is_empty() {
+				json[key] = json!(values);
+			}
+		}
+
+		Gltf {
+			json,
+			buffer: self.buffer,
+		}
+	}
+}
+
+/// Adds `child` to a node's `children`.
+pub fn push_child(node: &mut json::Value, child: usize) {
+	match node["children"].as_array_mut() {
+		Some(children) => children.push(json!(child)),
+		None => node["children"] = json!([child]),
+	}
+}
+
+pub fn f32_bytes(values: &[f32]) -> Vec<u8> {
+	values
+		.iter()
+		.flat_map(|value| value.to_le_bytes())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::Gltf;
+
+	#[test]
+	fn packs_glb_chunks_aligned() {
+		let gltf = Gltf {
+			json: json!({ "asset": { "version": "2.0" } }),
+			buffer: vec![1, 2, 3],
+		};
+		let glb = gltf.to_glb();
+
+		assert_eq!(&glb[0..4], b"glTF");
+		assert_eq!(
+			u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+			glb.len()
+		);
+
+		let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+		assert_eq!(json_len % 4, 0);
+		assert_eq!(&glb[16..20], b"JSON");
+
+		let bin_start = 20 + json_len;
+		let bin_len = u32::from_le_bytes(glb[bin_start..bin_start + 4].try_into().unwrap());
+		assert_eq!(bin_len, 4);
+		assert_eq!(&glb[bin_start + 4..bin_start + 8], b"BIN\0");
+		assert_eq!(&glb[bin_start + 8..], &[1, 2, 3, 0]);
+	}
+}
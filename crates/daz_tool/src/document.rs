@@ -0,0 +1,321 @@
+//! Reading DSON files and resolving the references between them.
+
+use std::{
+	collections::{HashMap, HashSet},
+	path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use daz_asset_types::{percent_decode, Daz};
+use flate2::read::GzDecoder;
+use serde_json as json;
+
+/// Object keys whose string values are asset URIs.
+const REFERENCE_KEYS: [&str; 8] = [
+	"url",
+	"parent",
+	"node",
+	"geometry",
+	"default_uv_set",
+	"output",
+	"conform_target",
+	"image_file",
+];
+
+/// A parsed `.dsf` or `.duf` file.
+pub struct Document {
+	pub path: PathBuf,
+	pub daz: Daz,
+	raw: json::Value,
+	ids: HashSet<String>,
+}
+
+impl Document {
+	/// Reads a DSON file, which may be gzip-compressed as Daz Studio saves
+	/// them by default.
+	pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+		let path = path.as_ref();
+		Self::from_value(path, read_json(path)?)
+	}
+
+	pub fn from_value(path: impl AsRef<Path>, raw: json::Value) -> anyhow::Result<Self> {
+		let path = path.as_ref();
+		let daz = json::from_value::<Daz>(raw.clone())
+			.map_err(|err| anyhow!("Failed to parse \"{}\": {err}", path.display()))?;
+
+		Ok(Self {
+			path: path.to_owned(),
+			daz,
+			ids: ids(&raw),
+			raw,
+		})
+	}
+
+	/// The file's own path within its library, e.g.
+	/// `/data/Daz 3D/Genesis 9/Base/Genesis9.dsf`.
+	pub fn library_path(&self) -> String {
+		percent_decode(&self.daz.asset_info.id)
+	}
+
+	/// The library root containing this file, inferred from its library path.
+	pub fn library_root(&self) -> Option<PathBuf> {
+		let path = self.path.canonicalize().ok()?;
+		let library_path = self.library_path();
+		let mut root = path.as_path();
+		for _ in Path::new(library_path.trim_start_matches('/')).components() {
+			root = root.parent()?;
+		}
+
+		(root.join(library_path.trim_start_matches('/')) == path).then(|| root.to_owned())
+	}
+
+	/// IDs of every asset defined in the file, including scene instances.
+	pub fn ids(&self) -> &HashSet<String> {
+		&self.ids
+	}
+
+	/// Every asset URI the file refers to, in document order.
+	pub fn references(&self) -> Vec<Reference> {
+		let mut result = vec![];
+		collect_references(&self.raw, None, &mut result);
+		result
+	}
+
+	/// The decoded asset ID in `uri`, if it refers to an asset in this file.
+	pub fn local_id(&self, uri: &str) -> Option<String> {
+		let uri = Uri::parse(uri);
+		if self.is_local(&uri) {
+			uri.id
+		} else {
+			None
+		}
+	}
+
+	fn is_local(&self, uri: &Uri) -> bool {
+		uri.path.is_empty() || uri.path == self.library_path()
+	}
+}
+
+/// An asset URI found in a [Document].
+#[derive(Clone, Debug)]
+pub struct Reference {
+	/// The key of the URI's value, e.g. `parent`.
+	pub key: String,
+	/// The ID of the closest enclosing object that has one.
+	pub context: Option<String>,
+	pub uri: String,
+}
+
+/// A DSON asset URI, e.g.
+/// `l_thigh:/data/Daz%203D/Genesis%209/Base/Genesis9.dsf#l_thigh?center_point/x`.
+///
+/// * [Reference](http://docs.daz3d.com/doku.php/public/dson_spec/format_description/asset_addressing/start)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Uri {
+	/// The scene node the URI is relative to, e.g. `l_thigh`.
+	pub node: Option<String>,
+	/// The decoded file path, or an empty string for the current file.
+	pub path: String,
+	/// The decoded asset ID.
+	pub id: Option<String>,
+	/// The property path, e.g. `center_point/x`.
+	pub property: Option<String>,
+}
+
+impl Uri {
+	pub fn parse(uri: &str) -> Self {
+		let (uri, property) = match uri.find('#') {
+			Some(fragment_idx) => match uri[fragment_idx..].split_once('?') {
+				Some((fragment, property)) => {
+					(&uri[..fragment_idx + fragment.len()], Some(property))
+				}
+				None => (uri, None),
+			},
+			None => match uri.split_once('?') {
+				Some((uri, property)) => (uri, Some(property)),
+				None => (uri, None),
+			},
+		};
+		let (uri, id) = match uri.split_once('#') {
+			Some((uri, id)) => (uri, Some(id)),
+			None => (uri, None),
+		};
+		let (node, path) = match uri.split_once(':') {
+			Some((node, path)) if !node.contains('/') => (Some(node), path),
+			_ => (None, uri),
+		};
+
+		Self {
+			node: node.map(percent_decode),
+			path: percent_decode(path),
+			id: id.map(percent_decode),
+			property: property.map(str::to_owned),
+		}
+	}
+}
+
+/// Resolves references against the library root directories.
+pub struct Library {
+	roots: Vec<PathBuf>,
+	/// File path -> IDs defined in the file, or `None` if it couldn't be read
+	ids: HashMap<PathBuf, Option<HashSet<String>>>,
+}
+
+impl Library {
+	pub fn new(roots: Vec<PathBuf>) -> Self {
+		Self {
+			roots,
+			ids: HashMap::default(),
+		}
+	}
+
+	pub fn roots(&self) -> &[PathBuf] {
+		&self.roots
+	}
+
+	/// Finds a library file, e.g. `/data/Daz 3D/Genesis 9/Base/Genesis9.dsf`,
+	/// in the first root that contains it.
+	pub fn find(&self, library_path: &str) -> Option<PathBuf> {
+		let relative = library_path.trim_start_matches('/');
+		self.roots
+			.iter()
+			.map(|root| root.join(relative))
+			.find(|path| path.is_file())
+	}
+
+	/// Returns the reason `reference` can't be resolved, or `None` if it can.
+	/// External references are only checked if the library has any roots.
+	pub fn check(&mut self, doc: &Document, reference: &Reference) -> Option<String> {
+		let uri = Uri::parse(&reference.uri);
+		if doc.is_local(&uri) {
+			let id = uri.id?;
+			return (!doc.ids().contains(&id)).then(|| format!("no asset '{id}' in this file"));
+		}
+		if self.roots.is_empty() {
+			return None;
+		}
+
+		let Some(path) = self.find(&uri.path) else {
+			return Some("file not found in library roots".into());
+		};
+		let id = uri.id?;
+		let ids = self
+			.ids
+			.entry(path.clone())
+			.or_insert_with(|| read_json(&path).ok().map(|raw| ids(&raw)));
+
+		match ids {
+			Some(ids) if ids.contains(&id) => None,
+			Some(_) => Some(format!("no asset '{id}' in {}", uri.path)),
+			None => Some(format!("failed to read {}", path.display())),
+		}
+	}
+
+	/// Every reference in `doc` that can't be resolved, with the reason why.
+	/// Repeated URIs are only reported once.
+	pub fn unresolved_references(&mut self, doc: &Document) -> Vec<(Reference, String)> {
+		let mut seen = HashSet::new();
+		doc.references()
+			.into_iter()
+			.filter(|reference| seen.insert(reference.uri.clone()))
+			.filter_map(|reference| {
+				let reason = self.check(doc, &reference)?;
+				Some((reference, reason))
+			})
+			.collect()
+	}
+}
+
+fn read_json(path: &Path) -> anyhow::Result<json::Value> {
+	let bytes = std::fs::read(path)
+		.map_err(|err| anyhow!("Failed to read \"{}\": {err}", path.display()))?;
+
+	let value = if bytes.starts_with(&[0x1f, 0x8b]) {
+		json::from_reader(GzDecoder::new(bytes.as_slice()))
+	} else {
+		json::from_slice(&bytes)
+	};
+
+	value.map_err(|err| anyhow!("Failed to parse \"{}\": {err}", path.display()))
+}
+
+/// IDs of the objects in each `*_library` array and in each of the scene's
+/// arrays.
+fn ids(raw: &json::Value) -> HashSet<String> {
+	let Some(root) = raw.as_object() else {
+		return HashSet::new();
+	};
+
+	let libraries = root
+		.iter()
+		.filter(|(key, _)| key.ends_with("_library"))
+		.map(|(_, value)| value);
+	let scene = root
+		.get("scene")
+		.and_then(|scene| scene.as_object())
+		.into_iter()
+		.flat_map(|scene| scene.values());
+
+	libraries
+		.chain(scene)
+		.filter_map(|value| value.as_array())
+		.flatten()
+		.filter_map(|asset| asset.get("id")?.as_str())
+		.map(percent_decode)
+		.collect()
+}
+
+fn collect_references(value: &json::Value, context: Option<&str>, result: &mut Vec<Reference>) {
+	match value {
+		json::Value::Object(object) => {
+			let context = object.get("id").and_then(|id| id.as_str()).or(context);
+
+			for (key, value) in object.iter() {
+				match value.as_str() {
+					Some(uri) if REFERENCE_KEYS.contains(&key.as_str()) && !uri.is_empty() => {
+						result.push(Reference {
+							key: key.clone(),
+							context: context.map(str::to_owned),
+							uri: uri.to_owned(),
+						});
+					}
+					_ => collect_references(value, context, result),
+				}
+			}
+		}
+		json::Value::Array(values) => {
+			for value in values.iter() {
+				collect_references(value, context, result);
+			}
+		}
+		_ => {}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Uri;
+
+	#[test]
+	fn parses_uris() {
+		let uri = Uri::parse(
+			"l_thigh:/data/Daz%203D/Genesis%209/Base/Genesis9.dsf#l_thigh?center_point/x",
+		);
+		assert_eq!(uri, Uri {
+			node: Some("l_thigh".into()),
+			path: "/data/Daz 3D/Genesis 9/Base/Genesis9.dsf".into(),
+			id: Some("l_thigh".into()),
+			property: Some("center_point/x".into()),
+		});
+
+		let uri = Uri::parse("#Genesis9-1");
+		assert_eq!(uri, Uri {
+			id: Some("Genesis9-1".into()),
+			..Default::default()
+		});
+
+		let uri = Uri::parse("/Runtime/Textures/Skin%20Base.jpg");
+		assert_eq!(uri.path, "/Runtime/Textures/Skin Base.jpg");
+		assert_eq!(uri.id, None);
+	}
+}
@@ -0,0 +1,359 @@
+//! Conversion of DSON figures and props to glTF 2.0.
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use daz_asset_types::{ChannelsAsVec3, Geometry, Modifier, Node, TriangleMesh, UvSet};
+use daz_gltf::{f32_bytes, push_child, Gltf, GltfBuilder, ARRAY_BUFFER, FLOAT, UNSIGNED_SHORT};
+use glam::{Affine3A, Mat4, Vec3};
+use serde_json::{self as json, json};
+
+use crate::document::{Document, Library, Uri};
+
+/// Matches the default material of `bevy_daz`'s loader (`#AAAAAA` in linear
+/// space), since DSON materials aren't parsed yet.
+const DEFAULT_BASE_COLOR: [f32; 4] = [0.402, 0.402, 0.402, 1.];
+
+/// Converts the nodes, geometries, skins and morphs in `doc` to glTF.
+///
+/// Geometries are converted like `bevy_daz`'s loader does, with
+/// [TriangleMesh], and each one's surfaces (polygon material groups) become
+/// primitives with a placeholder material of the same name. UVs come from the
+/// geometry's default UV set, which is looked up in the `library` if it isn't
+/// defined in `doc`.
+pub fn convert(doc: &Document, library: &Library) -> anyhow::Result<Gltf> {
+	let mut builder = ConvertBuilder::default();
+
+	let nodes = doc.daz.node_library.as_deref().unwrap_or_default();
+	let node_indices = nodes
+		.iter()
+		.enumerate()
+		.map(|(idx, node)| (node.id.as_str(), idx))
+		.collect::<HashMap<_, _>>();
+	let parent_idx = |node: &Node| {
+		let parent = doc.local_id(node.parent.as_deref()?)?;
+		node_indices.get(parent.as_str()).copied()
+	};
+
+	let root_transforms = nodes.iter().map(root_transform).collect::<Vec<_>>();
+	let mut roots = vec![];
+	for (idx, node) in nodes.iter().enumerate() {
+		let transform = match parent_idx(node) {
+			Some(parent) => root_transforms[parent].inverse() * root_transforms[idx],
+			None => root_transforms[idx],
+		};
+		let (_, rotation, translation) = transform.to_scale_rotation_translation();
+		builder.gltf.nodes.push(json!({
+			"name": node.name,
+			"translation": translation.to_array(),
+			"rotation": rotation.to_array(),
+		}));
+	}
+	for (idx, node) in nodes.iter().enumerate() {
+		match parent_idx(node) {
+			Some(parent) => push_child(&mut builder.gltf.nodes[parent], idx),
+			None => roots.push(idx),
+		}
+	}
+
+	let modifiers = doc.daz.modifier_library.as_deref().unwrap_or_default();
+
+	for geometry in doc.daz.geometry_library.as_deref().unwrap_or_default() {
+		let uv_set = geometry.default_uv_set.as_deref().and_then(|uri| {
+			match find_uv_set(doc, library, uri) {
+				Ok(uv_set) => Some(uv_set),
+				Err(err) => {
+					eprintln!(
+						"warning: geometry '{}' will have no UVs: {err}",
+						geometry.id
+					);
+					None
+				}
+			}
+		});
+
+		let skin = modifiers.iter().find(|modifier| {
+			modifier.skin.as_ref().is_some_and(|skin| {
+				doc.local_id(&skin.geometry).as_deref() == Some(geometry.id.as_str())
+			})
+		});
+		let morphs = modifiers
+			.iter()
+			.filter(|modifier| {
+				modifier.morph.is_some()
+					&& modifier
+						.parent
+						.as_deref()
+						.and_then(|uri| doc.local_id(uri))
+						.as_deref() == Some(geometry.id.as_str())
+			})
+			.collect::<Vec<_>>();
+
+		let joints = skin
+			.and_then(|modifier| modifier.skin.as_ref())
+			.map(|skin| {
+				skin.joints
+					.iter()
+					.flatten()
+					.map(|joint| {
+						doc.local_id(&joint.node)
+							.and_then(|id| node_indices.get(id.as_str()).copied())
+							.ok_or_else(|| anyhow!("Joint node '{}' not found", joint.node))
+					})
+					.collect::<anyhow::Result<Vec<_>>>()
+			})
+			.transpose()?;
+
+		let mesh_idx = builder.mesh(geometry, uv_set.as_ref(), skin, &morphs);
+		let skin_idx =
+			joints.map(|joints| builder.skin(&joints, &root_transforms, nodes, &node_indices, doc));
+
+		// Geometries are usually named after the figure node they belong to
+		let node_idx = geometry
+			.name
+			.as_deref()
+			.and_then(|name| node_indices.get(name).copied())
+			.unwrap_or_else(|| {
+				builder.gltf.nodes.push(json!({ "name": geometry.id }));
+				roots.push(builder.gltf.nodes.len() - 1);
+				builder.gltf.nodes.len() - 1
+			});
+		builder.gltf.nodes[node_idx]["mesh"] = json!(mesh_idx);
+		if let Some(skin_idx) = skin_idx {
+			builder.gltf.nodes[node_idx]["skin"] = json!(skin_idx);
+		}
+	}
+
+	Ok(builder
+		.gltf
+		.finish(concat!("daz-tool ", env!("CARGO_PKG_VERSION")), roots))
+}
+
+/// The node's rest transform relative to the figure, in meters.
+fn root_transform(node: &Node) -> Affine3A {
+	Affine3A::from_rotation_translation(node.orientation_quat(), node.center_point.as_vec3() * 0.01)
+}
+
+fn find_uv_set(doc: &Document, library: &Library, uri: &str) -> anyhow::Result<UvSet> {
+	let parsed = Uri::parse(uri);
+	let id = parsed
+		.id
+		.as_deref()
+		.ok_or_else(|| anyhow!("\"{uri}\" has no asset ID"))?;
+
+	let external;
+	let uv_sets = if doc.local_id(uri).is_some() {
+		doc.daz.uv_set_library.as_deref()
+	} else {
+		let path = library
+			.find(&parsed.path)
+			.ok_or_else(|| anyhow!("{} not found in library roots", parsed.path))?;
+		external = Document::load(path)?;
+		external.daz.uv_set_library.as_deref()
+	};
+
+	uv_sets
+		.unwrap_or_default()
+		.iter()
+		.find(|uv_set| uv_set.id == id)
+		.cloned()
+		.ok_or_else(|| anyhow!("no UV set '{id}' in {}", parsed.path))
+}
+
+#[derive(Default)]
+struct ConvertBuilder {
+	gltf: GltfBuilder,
+	material_indices: HashMap<String, usize>,
+}
+
+impl ConvertBuilder {
+	fn f32_accessor(&mut self, values: &[f32], count: usize, type_: &str) -> usize {
+		self.gltf.accessor(
+			&f32_bytes(values),
+			FLOAT,
+			count,
+			type_,
+			Some(ARRAY_BUFFER),
+			None,
+		)
+	}
+
+	fn mesh(
+		&mut self,
+		geometry: &Geometry,
+		uv_set: Option<&UvSet>,
+		skin: Option<&Modifier>,
+		morphs: &[&Modifier],
+	) -> usize {
+		let triangles = TriangleMesh::new(geometry, uv_set);
+		let vertex_count = triangles.positions.len();
+
+		let mut attributes = json::Map::new();
+		let positions = triangles
+			.positions
+			.iter()
+			.map(Vec3::to_array)
+			.collect::<Vec<_>>();
+		attributes.insert(
+			"POSITION".into(),
+			json!(self.gltf.vec3_accessor(&positions, Some(ARRAY_BUFFER))),
+		);
+		let normals = triangles
+			.normals
+			.iter()
+			.flat_map(Vec3::to_array)
+			.collect::<Vec<_>>();
+		attributes.insert(
+			"NORMAL".into(),
+			json!(self.f32_accessor(&normals, vertex_count, "VEC3")),
+		);
+		if let Some(uvs) = triangles.uvs.as_ref() {
+			let uvs = uvs.iter().flat_map(|uv| uv.to_array()).collect::<Vec<_>>();
+			attributes.insert(
+				"TEXCOORD_0".into(),
+				json!(self.f32_accessor(&uvs, vertex_count, "VEC2")),
+			);
+		}
+
+		if let Some(skin) = skin.and_then(|modifier| modifier.skin.as_ref()) {
+			let (joints, weights) = triangles.joint_weights(skin);
+			let joints = joints
+				.as_flattened()
+				.iter()
+				.flat_map(|joint| joint.to_le_bytes())
+				.collect::<Vec<_>>();
+			let accessor = self.gltf.accessor(
+				&joints,
+				UNSIGNED_SHORT,
+				vertex_count,
+				"VEC4",
+				Some(ARRAY_BUFFER),
+				None,
+			);
+			attributes.insert("JOINTS_0".into(), json!(accessor));
+			attributes.insert(
+				"WEIGHTS_0".into(),
+				json!(self.f32_accessor(weights.as_flattened(), vertex_count, "VEC4")),
+			);
+
+			if let Some(blends) = triangles.blend_weights(skin) {
+				attributes.insert(
+					"_DQS_BLEND".into(),
+					json!(self.f32_accessor(&blends, vertex_count, "SCALAR")),
+				);
+			}
+		}
+
+		// Every surface shares the same vertices, so they can share targets too
+		let targets = morphs
+			.iter()
+			.map(|modifier| {
+				let deltas = triangles
+					.morph_deltas(modifier.morph.as_ref().unwrap())
+					.iter()
+					.map(Vec3::to_array)
+					.collect::<Vec<_>>();
+
+				json!({ "POSITION": self.gltf.vec3_accessor(&deltas, Some(ARRAY_BUFFER)) })
+			})
+			.collect::<Vec<_>>();
+
+		let primitives = triangles
+			.surfaces
+			.iter()
+			.enumerate()
+			.filter(|(_, indices)| !indices.is_empty())
+			.map(|(surface_idx, indices)| {
+				let indices = self.gltf.index_accessor(indices);
+				let material = self.material(&geometry.polygon_material_groups.values[surface_idx]);
+
+				let mut primitive = json!({
+					"attributes": attributes,
+					"indices": indices,
+					"material": material,
+				});
+				if !targets.is_empty() {
+					primitive["targets"] = json!(targets);
+				}
+				primitive
+			})
+			.collect::<Vec<_>>();
+
+		let mut mesh = json!({
+			"name": geometry.name.as_ref().unwrap_or(&geometry.id),
+			"primitives": primitives,
+		});
+		if !morphs.is_empty() {
+			mesh["weights"] = json!(vec![0.; morphs.len()]);
+			mesh["extras"] = json!({
+				"targetNames": morphs.iter().map(|modifier| &modifier.id).collect::<Vec<_>>(),
+			});
+		}
+		self.gltf.meshes.push(mesh);
+
+		self.gltf.meshes.len() - 1
+	}
+
+	fn skin(
+		&mut self,
+		joints: &[usize],
+		root_transforms: &[Affine3A],
+		nodes: &[Node],
+		node_indices: &HashMap<&str, usize>,
+		doc: &Document,
+	) -> usize {
+		let matrices = joints
+			.iter()
+			.flat_map(|&idx| Mat4::from(root_transforms[idx].inverse()).to_cols_array())
+			.collect::<Vec<_>>();
+		let accessor = self.gltf.accessor(
+			&f32_bytes(&matrices),
+			FLOAT,
+			joints.len(),
+			"MAT4",
+			None,
+			None,
+		);
+
+		let mut skin = json!({
+			"inverseBindMatrices": accessor,
+			"joints": joints,
+		});
+
+		// The topmost node above the first joint
+		if let Some(&first) = joints.first() {
+			let mut skeleton = first;
+			while let Some(parent) = nodes[skeleton]
+				.parent
+				.as_deref()
+				.and_then(|uri| doc.local_id(uri))
+				.and_then(|id| node_indices.get(id.as_str()).copied())
+			{
+				skeleton = parent;
+			}
+			skin["skeleton"] = json!(skeleton);
+		}
+
+		self.gltf.skins.push(skin);
+		self.gltf.skins.len() - 1
+	}
+
+	fn material(&mut self, name: &str) -> usize {
+		if let Some(&idx) = self.material_indices.get(name) {
+			return idx;
+		}
+
+		self.gltf.materials.push(json!({
+			"name": name,
+			"pbrMetallicRoughness": {
+				"baseColorFactor": DEFAULT_BASE_COLOR,
+				"metallicFactor": 0.,
+				"roughnessFactor": 0.55,
+			},
+		}));
+		self.material_indices
+			.insert(name.to_owned(), self.gltf.materials.len() - 1);
+
+		self.gltf.materials.len() - 1
+	}
+}
@@ -0,0 +1,192 @@
+//! `daz-tool`: inspects, validates and converts Daz Studio (DSON) asset files
+//! without Bevy, for content pipelines and headless machines.
+
+use std::{
+	env,
+	path::{Path, PathBuf},
+	process::ExitCode,
+};
+
+use anyhow::anyhow;
+use daz_asset_types::LIBRARY_PATHS_VAR;
+
+use crate::{
+	document::{Document, Library},
+	validate::Severity,
+};
+
+mod document;
+mod gltf;
+mod summary;
+mod validate;
+
+const USAGE: &str = "\
+Usage: daz-tool <COMMAND> [OPTIONS] <FILE>...
+
+Commands:
+  info       Print a summary of each .dsf/.duf file
+  validate   Check each file for errors, exiting with status 1 if any are found
+  convert    Convert a file to glTF (requires --output)
+
+Options:
+  -r, --root <DIR>      Add a library root for resolving references to other
+                        files (repeatable; also read from DAZ_LIBRARY_PATHS)
+  -o, --output <PATH>   Output path for convert, ending in .gltf or .glb
+  -v, --verbose         List everything in summaries instead of truncating
+  -h, --help            Print this message
+
+Each file's own library root is inferred from its asset ID, so references into
+the same library resolve without --root.
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Command {
+	Info,
+	Validate,
+	Convert,
+}
+
+struct Args {
+	command: Command,
+	files: Vec<PathBuf>,
+	roots: Vec<PathBuf>,
+	output: Option<PathBuf>,
+	verbose: bool,
+}
+
+fn main() -> ExitCode {
+	let args = match parse_args(env::args().skip(1)) {
+		Ok(Some(args)) => args,
+		Ok(None) => {
+			print!("{USAGE}");
+			return ExitCode::SUCCESS;
+		}
+		Err(err) => {
+			eprintln!("error: {err}\n\n{USAGE}");
+			return ExitCode::from(2);
+		}
+	};
+
+	let mut failed = false;
+	for file in args.files.iter() {
+		if let Err(err) = run(&args, file, &mut failed) {
+			eprintln!("{}: error: {err}", file.display());
+			failed = true;
+		}
+	}
+
+	if failed {
+		ExitCode::FAILURE
+	} else {
+		ExitCode::SUCCESS
+	}
+}
+
+fn run(args: &Args, file: &Path, failed: &mut bool) -> anyhow::Result<()> {
+	let doc = Document::load(file)?;
+	let mut library = Library::new(
+		args.roots
+			.iter()
+			.cloned()
+			.chain(doc.library_root())
+			.collect(),
+	);
+
+	match args.command {
+		Command::Info => {
+			print!("{}", summary::summary(&doc, &mut library, args.verbose));
+		}
+		Command::Validate => {
+			let issues = validate::validate(&doc, &mut library);
+			for issue in issues.iter() {
+				println!("{}: {issue}", file.display());
+			}
+
+			let error_count = issues
+				.iter()
+				.filter(|issue| issue.severity == Severity::Error)
+				.count();
+			println!(
+				"{}: {error_count} errors, {} warnings",
+				file.display(),
+				issues.len() - error_count,
+			);
+			*failed |= error_count > 0;
+		}
+		Command::Convert => {
+			let output = args.output.as_deref().unwrap();
+			gltf::convert(&doc, &library)?.write(output)?;
+			println!("{} -> {}", file.display(), output.display());
+		}
+	}
+
+	Ok(())
+}
+
+/// Returns `None` if help was requested.
+fn parse_args(mut args: impl Iterator<Item = String>) -> anyhow::Result<Option<Args>> {
+	let mut command = None;
+	let mut files = vec![];
+	let mut roots = vec![];
+	let mut output = None;
+	let mut verbose = false;
+
+	while let Some(arg) = args.next() {
+		let mut value = |name: &str| {
+			args.next()
+				.map(PathBuf::from)
+				.ok_or_else(|| anyhow!("{name} requires a value"))
+		};
+
+		match arg.as_str() {
+			"-h" | "--help" => return Ok(None),
+			"-v" | "--verbose" => verbose = true,
+			"-r" | "--root" => roots.push(value(&arg)?),
+			"-o" | "--output" => output = Some(value(&arg)?),
+			flag if flag.starts_with('-') => return Err(anyhow!("Unknown option '{flag}'")),
+			"info" if command.is_none() => command = Some(Command::Info),
+			"validate" if command.is_none() => command = Some(Command::Validate),
+			"convert" if command.is_none() => command = Some(Command::Convert),
+			other if command.is_none() => return Err(anyhow!("Unknown command '{other}'")),
+			_ => files.push(PathBuf::from(arg)),
+		}
+	}
+
+	let Some(command) = command else {
+		return Ok(None);
+	};
+	if files.is_empty() {
+		return Err(anyhow!("No input files"));
+	}
+	if command == Command::Convert {
+		let Some(output) = output.as_ref() else {
+			return Err(anyhow!("convert requires --output"));
+		};
+		if !output
+			.extension()
+			.is_some_and(|ext| ext == "gltf" || ext == "glb")
+		{
+			return Err(anyhow!(
+				"Unsupported output format \"{}\"; expected .gltf or .glb",
+				output.display(),
+			));
+		}
+		if files.len() > 1 {
+			return Err(anyhow!("convert takes a single input file"));
+		}
+	}
+
+	roots.extend(
+		env::var_os(LIBRARY_PATHS_VAR)
+			.iter()
+			.flat_map(env::split_paths),
+	);
+
+	Ok(Some(Args {
+		command,
+		files,
+		roots,
+		output,
+		verbose,
+	}))
+}
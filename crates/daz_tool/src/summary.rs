@@ -0,0 +1,387 @@
+//! Human-readable summaries of DSON files.
+
+use std::{collections::HashMap, fmt::Write};
+
+use daz_asset_types::{Formula, FormulaStage, Geometry, Modifier, Node, OperationType};
+use serde_json as json;
+
+use crate::document::{Document, Library, Uri};
+
+/// Lists longer than this are truncated, unless summarizing verbosely.
+const MAX_LIST_LEN: usize = 20;
+
+pub fn summary(doc: &Document, library: &mut Library, verbose: bool) -> String {
+	let mut s = String::new();
+	let daz = &doc.daz;
+	let info = &daz.asset_info;
+
+	writeln!(&mut s, "{}", doc.path.display()).unwrap();
+	writeln!(&mut s, "  file version: {}", daz.file_version).unwrap();
+	writeln!(&mut s, "  asset: {}", doc.library_path()).unwrap();
+	if let Some(type_) = info.r#type.as_ref() {
+		writeln!(&mut s, "  type: {type_}").unwrap();
+	}
+	writeln!(&mut s, "  revision: {}", info.revision).unwrap();
+	writeln!(&mut s, "  author: {}", info.contributor.author).unwrap();
+	if let Some(modified) = info.modified {
+		writeln!(&mut s, "  modified: {modified}").unwrap();
+	}
+
+	let nodes = daz.node_library.as_deref().unwrap_or_default();
+	if !nodes.is_empty() {
+		writeln!(&mut s, "\nnodes ({}):", nodes.len()).unwrap();
+		write_node_tree(&mut s, nodes, verbose);
+	}
+
+	let geometries = daz.geometry_library.as_deref().unwrap_or_default();
+	if !geometries.is_empty() {
+		writeln!(&mut s, "\ngeometries ({}):", geometries.len()).unwrap();
+		for geometry in geometries.iter() {
+			write_geometry(&mut s, geometry);
+		}
+	}
+
+	let uv_sets = daz.uv_set_library.as_deref().unwrap_or_default();
+	if !uv_sets.is_empty() {
+		writeln!(&mut s, "\nuv sets ({}):", uv_sets.len()).unwrap();
+		for uv_set in uv_sets.iter() {
+			writeln!(
+				&mut s,
+				"  {}: {} vertices, {} uvs, {} seam vertices",
+				uv_set.id,
+				uv_set.vertex_count,
+				uv_set.uvs.values.len(),
+				uv_set.polygon_vertex_indices.as_ref().map_or(0, Vec::len),
+			)
+			.unwrap();
+		}
+	}
+
+	let modifiers = daz.modifier_library.as_deref().unwrap_or_default();
+
+	let skins = modifiers
+		.iter()
+		.filter(|modifier| modifier.skin.is_some())
+		.collect::<Vec<_>>();
+	if !skins.is_empty() {
+		writeln!(&mut s, "\nskins ({}):", skins.len()).unwrap();
+		for modifier in skins {
+			write_skin(&mut s, modifier);
+		}
+	}
+
+	let morphs = modifiers
+		.iter()
+		.filter(|modifier| modifier.morph.is_some())
+		.collect::<Vec<_>>();
+	if !morphs.is_empty() {
+		writeln!(&mut s, "\nmorphs ({}):", morphs.len()).unwrap();
+		for modifier in truncated(&morphs, verbose) {
+			let morph = modifier.morph.as_ref().unwrap();
+			writeln!(
+				&mut s,
+				"  {}{}: {} deltas -> {}",
+				modifier.id,
+				modifier
+					.label
+					.as_ref()
+					.map(|label| format!(" \"{label}\""))
+					.unwrap_or_default(),
+				morph.deltas.values.len(),
+				modifier.parent.as_deref().unwrap_or("(no parent)"),
+			)
+			.unwrap();
+		}
+		write_truncation(&mut s, morphs.len(), verbose);
+	}
+
+	let formulas = modifiers
+		.iter()
+		.flat_map(|modifier| {
+			let formulas = modifier.formulas.as_deref().unwrap_or_default();
+			formulas
+				.iter()
+				.map(|formula| (modifier.id.as_str(), formula))
+		})
+		.collect::<Vec<_>>();
+	let node_formula_count = nodes
+		.iter()
+		.map(|node| node.formulas.as_ref().map_or(0, Vec::len))
+		.sum::<usize>();
+	if !formulas.is_empty() || node_formula_count > 0 {
+		writeln!(
+			&mut s,
+			"\nformulas ({} on modifiers, {node_formula_count} on nodes):",
+			formulas.len(),
+		)
+		.unwrap();
+		for (modifier_id, formula) in truncated(&formulas, verbose) {
+			writeln!(&mut s, "  {modifier_id}: {}", format_formula(formula)).unwrap();
+		}
+		write_truncation(&mut s, formulas.len(), verbose);
+	}
+
+	let other_modifier_count = modifiers
+		.iter()
+		.filter(|modifier| modifier.skin.is_none() && modifier.morph.is_none())
+		.count();
+	let scene = daz.scene.as_ref().and_then(json::Value::as_object);
+	let counts = [
+		("other modifiers", other_modifier_count),
+		(
+			"materials",
+			daz.material_library.as_ref().map_or(0, Vec::len),
+		),
+		("images", daz.image_library.as_ref().map_or(0, Vec::len)),
+	]
+	.into_iter()
+	.chain(scene.into_iter().flat_map(|scene| {
+		scene.iter().filter_map(|(key, value)| {
+			let len = value.as_array()?.len();
+			Some((key.as_str(), len))
+		})
+	}))
+	.filter(|(_, count)| *count > 0)
+	.collect::<Vec<_>>();
+	if !counts.is_empty() {
+		writeln!(&mut s).unwrap();
+		for (label, count) in counts {
+			let scope = if scene.is_some_and(|scene| scene.contains_key(label)) {
+				"scene "
+			} else {
+				""
+			};
+			writeln!(&mut s, "{scope}{label}: {count}").unwrap();
+		}
+	}
+
+	let references = doc.references();
+	let external_files = {
+		let mut paths = references
+			.iter()
+			.map(|reference| Uri::parse(&reference.uri).path)
+			.filter(|path| !path.is_empty() && *path != doc.library_path())
+			.collect::<Vec<_>>();
+		paths.sort();
+		paths.dedup();
+		paths
+	};
+	if !external_files.is_empty() {
+		writeln!(&mut s, "\nexternal files ({}):", external_files.len()).unwrap();
+		for path in truncated(&external_files, verbose) {
+			writeln!(&mut s, "  {path}").unwrap();
+		}
+		write_truncation(&mut s, external_files.len(), verbose);
+	}
+
+	let unresolved = library.unresolved_references(doc);
+	if library.roots().is_empty() {
+		writeln!(
+			&mut s,
+			"\nunresolved references ({}, external files unchecked without library roots):",
+			unresolved.len(),
+		)
+		.unwrap();
+	} else {
+		writeln!(&mut s, "\nunresolved references ({}):", unresolved.len()).unwrap();
+	}
+	for (reference, reason) in truncated(&unresolved, verbose) {
+		writeln!(
+			&mut s,
+			"  {} ({} of {}): {reason}",
+			reference.uri,
+			reference.key,
+			reference.context.as_deref().unwrap_or("file"),
+		)
+		.unwrap();
+	}
+	write_truncation(&mut s, unresolved.len(), verbose);
+
+	s
+}
+
+fn write_node_tree(s: &mut String, nodes: &[Node], verbose: bool) {
+	let mut children = HashMap::<&str, Vec<&Node>>::new();
+	let mut roots = vec![];
+	for node in nodes.iter() {
+		match node
+			.parent
+			.as_deref()
+			.and_then(|parent| parent.strip_prefix('#'))
+		{
+			Some(parent) if nodes.iter().any(|node| node.id == parent) => {
+				children.entry(parent).or_default().push(node);
+			}
+			_ => roots.push(node),
+		}
+	}
+
+	let mut stack = roots
+		.into_iter()
+		.rev()
+		.map(|node| (node, 1))
+		.collect::<Vec<_>>();
+	let mut count = 0;
+	while let Some((node, depth)) = stack.pop() {
+		if count == MAX_LIST_LEN * 5 && !verbose {
+			writeln!(s, "  ... {} more", nodes.len() - count).unwrap();
+			break;
+		}
+		count += 1;
+
+		writeln!(
+			s,
+			"{:indent$}{} [{:?}]{}",
+			"",
+			node.id,
+			node.r#type,
+			if node.label != node.id {
+				format!(" \"{}\"", node.label)
+			} else {
+				String::new()
+			},
+			indent = depth * 2,
+		)
+		.unwrap();
+
+		if let Some(children) = children.get(node.id.as_str()) {
+			stack.extend(children.iter().rev().map(|&child| (child, depth + 1)));
+		}
+	}
+}
+
+fn write_geometry(s: &mut String, geometry: &Geometry) {
+	let polygons = &geometry.polylist.values;
+	let quad_count = polygons
+		.iter()
+		.filter(|polygon| polygon.vertex_indices.3.is_some())
+		.count();
+
+	writeln!(
+		s,
+		"  {}{}: {} vertices, {} polygons ({quad_count} quads, {} triangles)",
+		geometry.id,
+		geometry
+			.name
+			.as_ref()
+			.filter(|name| **name != geometry.id)
+			.map(|name| format!(" \"{name}\""))
+			.unwrap_or_default(),
+		geometry.vertices.values.len(),
+		polygons.len(),
+		polygons.len() - quad_count,
+	)
+	.unwrap();
+	if let Some(type_) = geometry.r#type {
+		writeln!(s, "    type: {type_:?}").unwrap();
+	}
+	writeln!(
+		s,
+		"    surfaces ({}): {}",
+		geometry.polygon_material_groups.values.len(),
+		geometry.polygon_material_groups.values.join(", "),
+	)
+	.unwrap();
+	writeln!(
+		s,
+		"    polygon groups: {}",
+		geometry.polygon_groups.values.len()
+	)
+	.unwrap();
+	if let Some(uv_set) = geometry.default_uv_set.as_ref() {
+		writeln!(s, "    default uv set: {uv_set}").unwrap();
+	}
+}
+
+fn write_skin(s: &mut String, modifier: &Modifier) {
+	let skin = modifier.skin.as_ref().unwrap();
+	let joints = skin.joints.as_deref().unwrap_or_default();
+
+	let mut influences = vec![0_usize; skin.vertex_count];
+	for joint in joints.iter() {
+		let weights = joint.node_weights.as_ref();
+		for &(idx, _) in weights
+			.map(|weights| &weights.values[..])
+			.unwrap_or_default()
+		{
+			if let Some(count) = influences.get_mut(idx) {
+				*count += 1;
+			}
+		}
+	}
+	let max_influences = influences.iter().copied().max().unwrap_or_default();
+	let over_limit = influences.iter().filter(|&&count| count > 4).count();
+
+	writeln!(
+		s,
+		"  {}: {} -> {}, {} vertices, {} joints",
+		modifier.id,
+		skin.node,
+		skin.geometry,
+		skin.vertex_count,
+		joints.len(),
+	)
+	.unwrap();
+	writeln!(
+		s,
+		"    max influences per vertex: {max_influences} ({over_limit} vertices over 4)",
+	)
+	.unwrap();
+	writeln!(
+		s,
+		"    dual quaternion blend weights: {}",
+		skin.blend_weights
+			.as_ref()
+			.map_or("no".into(), |weights| weights.values.len().to_string()),
+	)
+	.unwrap();
+}
+
+fn format_formula(formula: &Formula) -> String {
+	let operations = formula
+		.operations
+		.iter()
+		.map(|operation| {
+			let op = match operation.op {
+				OperationType::Push => "push",
+				OperationType::Add => "add",
+				OperationType::Sub => "sub",
+				OperationType::Mult => "mult",
+				OperationType::Div => "div",
+				OperationType::SplineConstant => "spline_constant",
+				OperationType::SplineLinear => "spline_linear",
+				OperationType::SplineTcb => "spline_tcb",
+			};
+			match (&operation.url, &operation.val) {
+				(Some(url), _) => format!("{op} {url}"),
+				(None, Some(val)) => format!("{op} {val}"),
+				(None, None) => op.to_owned(),
+			}
+		})
+		.collect::<Vec<_>>();
+	let stage = match formula.stage {
+		FormulaStage::Sum => "+=",
+		FormulaStage::Mult => "*=",
+	};
+
+	format!("{} {stage} [{}]", formula.output, operations.join(", "))
+}
+
+fn truncated<T>(items: &[T], verbose: bool) -> &[T] {
+	if verbose {
+		items
+	} else {
+		&items[..items.len().min(MAX_LIST_LEN)]
+	}
+}
+
+fn write_truncation(s: &mut String, len: usize, verbose: bool) {
+	if !verbose && len > MAX_LIST_LEN {
+		writeln!(
+			s,
+			"  ... {} more (use --verbose to list all)",
+			len - MAX_LIST_LEN
+		)
+		.unwrap();
+	}
+}
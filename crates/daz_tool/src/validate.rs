@@ -0,0 +1,409 @@
+//! Consistency checks for DSON files, beyond what's needed to parse them.
+
+use std::{collections::HashMap, fmt};
+
+use daz_asset_types::{Array, Formula, Geometry, Modifier, OperationType};
+
+use crate::document::{Document, Library};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	Warning,
+	Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+	pub severity: Severity,
+	pub message: String,
+}
+
+impl fmt::Display for Issue {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.severity {
+			Severity::Warning => write!(f, "warning: {}", self.message),
+			Severity::Error => write!(f, "error: {}", self.message),
+		}
+	}
+}
+
+#[derive(Default)]
+struct Issues(Vec<Issue>);
+
+impl Issues {
+	fn error(&mut self, message: impl Into<String>) {
+		self.0.push(Issue {
+			severity: Severity::Error,
+			message: message.into(),
+		});
+	}
+
+	fn warning(&mut self, message: impl Into<String>) {
+		self.0.push(Issue {
+			severity: Severity::Warning,
+			message: message.into(),
+		});
+	}
+
+	fn check_count<T>(&mut self, context: &str, array: &Array<T>) {
+		if array.count != array.values.len() {
+			self.error(format!(
+				"{context}: count is {}, but there are {} values",
+				array.count,
+				array.values.len(),
+			));
+		}
+	}
+}
+
+/// Checks `doc` for invalid indices, mismatched counts, malformed formulas and
+/// unresolved references.
+pub fn validate(doc: &Document, library: &mut Library) -> Vec<Issue> {
+	let mut issues = Issues::default();
+	let daz = &doc.daz;
+
+	let nodes = daz.node_library.as_deref().unwrap_or_default();
+	let geometries = daz.geometry_library.as_deref().unwrap_or_default();
+	let uv_sets = daz.uv_set_library.as_deref().unwrap_or_default();
+	let modifiers = daz.modifier_library.as_deref().unwrap_or_default();
+
+	for (library_name, ids) in [
+		(
+			"node",
+			nodes.iter().map(|node| &node.id).collect::<Vec<_>>(),
+		),
+		("geometry", geometries.iter().map(|geo| &geo.id).collect()),
+		("uv set", uv_sets.iter().map(|uv_set| &uv_set.id).collect()),
+		(
+			"modifier",
+			modifiers.iter().map(|modifier| &modifier.id).collect(),
+		),
+	] {
+		let mut counts = HashMap::<&str, usize>::new();
+		for id in ids {
+			*counts.entry(id).or_default() += 1;
+		}
+		for (id, count) in counts.into_iter().filter(|(_, count)| *count > 1) {
+			issues.error(format!("{library_name} '{id}' is defined {count} times"));
+		}
+	}
+
+	for geometry in geometries.iter() {
+		validate_geometry(&mut issues, geometry);
+	}
+
+	let vertex_counts = geometries
+		.iter()
+		.map(|geo| (geo.id.as_str(), geo.vertices.values.len()))
+		.collect::<HashMap<_, _>>();
+
+	for uv_set in uv_sets.iter() {
+		let context = format!("uv set '{}'", uv_set.id);
+		issues.check_count(&context, &uv_set.uvs);
+
+		let seams = uv_set.polygon_vertex_indices.as_deref().unwrap_or_default();
+		let invalid = seams
+			.iter()
+			.filter(|[_, vert_idx, uv_idx]| {
+				*vert_idx >= uv_set.vertex_count || *uv_idx >= uv_set.uvs.values.len()
+			})
+			.count();
+		if invalid > 0 {
+			issues.error(format!(
+				"{context}: {invalid} polygon vertex indices are out of range",
+			));
+		}
+
+		let geometries = geometries.iter().filter(|geo| {
+			geo.default_uv_set
+				.as_deref()
+				.is_some_and(|uri| doc.local_id(uri).as_deref() == Some(&uv_set.id))
+		});
+		for geometry in geometries {
+			if geometry.vertices.values.len() != uv_set.vertex_count {
+				issues.error(format!(
+					"{context}: has {} vertices, but geometry '{}' has {}",
+					uv_set.vertex_count,
+					geometry.id,
+					geometry.vertices.values.len(),
+				));
+			}
+		}
+	}
+
+	for modifier in modifiers.iter() {
+		validate_modifier(&mut issues, doc, modifier, &vertex_counts);
+	}
+
+	for (reference, reason) in library.unresolved_references(doc) {
+		issues.error(format!(
+			"unresolved {} reference '{}' in {}: {reason}",
+			reference.key,
+			reference.uri,
+			reference.context.as_deref().unwrap_or("file"),
+		));
+	}
+
+	issues.0
+}
+
+fn validate_geometry(issues: &mut Issues, geometry: &Geometry) {
+	let context = format!("geometry '{}'", geometry.id);
+	issues.check_count(&format!("{context} vertices"), &geometry.vertices);
+	issues.check_count(&format!("{context} polylist"), &geometry.polylist);
+	issues.check_count(
+		&format!("{context} polygon groups"),
+		&geometry.polygon_groups,
+	);
+	issues.check_count(
+		&format!("{context} polygon material groups"),
+		&geometry.polygon_material_groups,
+	);
+
+	let vertex_count = geometry.vertices.values.len() as u32;
+	let group_count = geometry.polygon_groups.values.len();
+	let material_group_count = geometry.polygon_material_groups.values.len();
+
+	let (mut invalid_vertices, mut invalid_groups, mut invalid_material_groups, mut degenerate) =
+		(0, 0, 0, 0);
+	for polygon in geometry.polylist.values.iter() {
+		let (i0, i1, i2, i3) = polygon.vertex_indices;
+		let indices = [Some(i0), Some(i1), Some(i2), i3];
+		let indices = indices.iter().flatten().copied().collect::<Vec<_>>();
+
+		if indices.iter().any(|&idx| idx >= vertex_count) {
+			invalid_vertices += 1;
+		}
+		if (1..indices.len()).any(|idx| indices[..idx].contains(&indices[idx])) {
+			degenerate += 1;
+		}
+		if polygon.groups_index >= group_count {
+			invalid_groups += 1;
+		}
+		if polygon.material_groups_index >= material_group_count {
+			invalid_material_groups += 1;
+		}
+	}
+
+	if invalid_vertices > 0 {
+		issues.error(format!(
+			"{context}: {invalid_vertices} polygons have vertex indices out of range",
+		));
+	}
+	if invalid_groups > 0 {
+		issues.error(format!(
+			"{context}: {invalid_groups} polygons have polygon group indices out of range",
+		));
+	}
+	if invalid_material_groups > 0 {
+		issues.error(format!(
+			"{context}: {invalid_material_groups} polygons have material group indices out of range",
+		));
+	}
+	if degenerate > 0 {
+		issues.warning(format!("{context}: {degenerate} polygons repeat a vertex"));
+	}
+}
+
+fn validate_modifier(
+	issues: &mut Issues,
+	doc: &Document,
+	modifier: &Modifier,
+	vertex_counts: &HashMap<&str, usize>,
+) {
+	let context = format!("modifier '{}'", modifier.id);
+
+	if let Some(skin) = modifier.skin.as_ref() {
+		let geometry_vertex_count = doc
+			.local_id(&skin.geometry)
+			.and_then(|id| vertex_counts.get(id.as_str()).copied());
+		if let Some(count) = geometry_vertex_count.filter(|&count| count != skin.vertex_count) {
+			issues.error(format!(
+				"{context}: skin has {} vertices, but geometry '{}' has {count}",
+				skin.vertex_count, skin.geometry,
+			));
+		}
+
+		let mut weights = vec![(0_usize, 0_f32); skin.vertex_count];
+		let mut invalid = 0;
+		for joint in skin.joints.as_deref().unwrap_or_default() {
+			let Some(node_weights) = joint.node_weights.as_ref() else {
+				continue;
+			};
+			issues.check_count(
+				&format!("{context} joint '{}' weights", joint.id),
+				node_weights,
+			);
+
+			for &(vert_idx, weight) in node_weights.values.iter() {
+				match weights.get_mut(vert_idx) {
+					Some((count, sum)) => {
+						*count += 1;
+						*sum += weight;
+					}
+					None => invalid += 1,
+				}
+			}
+		}
+
+		if invalid > 0 {
+			issues.error(format!(
+				"{context}: {invalid} joint weights have vertex indices out of range",
+			));
+		}
+		let unweighted = weights.iter().filter(|(count, _)| *count == 0).count();
+		if unweighted > 0 {
+			issues.warning(format!(
+				"{context}: {unweighted} vertices have no joint weights"
+			));
+		}
+		let unnormalized = weights
+			.iter()
+			.filter(|(count, sum)| *count > 0 && (sum - 1.).abs() > 1e-3)
+			.count();
+		if unnormalized > 0 {
+			issues.warning(format!(
+				"{context}: {unnormalized} vertices have joint weights that don't sum to 1",
+			));
+		}
+		let over_limit = weights.iter().filter(|(count, _)| *count > 4).count();
+		if over_limit > 0 {
+			issues.warning(format!(
+				"{context}: {over_limit} vertices have more than 4 joint weights; only the 4 \
+				largest are used",
+			));
+		}
+	}
+
+	if let Some(morph) = modifier.morph.as_ref() {
+		issues.check_count(&format!("{context} morph deltas"), &morph.deltas);
+
+		let geometry_vertex_count = modifier
+			.parent
+			.as_deref()
+			.and_then(|uri| doc.local_id(uri))
+			.and_then(|id| vertex_counts.get(id.as_str()).copied());
+		if let Some(count) = geometry_vertex_count {
+			if morph.vertex_count >= 0 && morph.vertex_count as usize != count {
+				issues.error(format!(
+					"{context}: morph has {} vertices, but its geometry has {count}",
+					morph.vertex_count,
+				));
+			}
+		}
+
+		let vertex_count =
+			geometry_vertex_count.or_else(|| usize::try_from(morph.vertex_count).ok());
+		if let Some(vertex_count) = vertex_count {
+			let invalid = morph
+				.deltas
+				.values
+				.iter()
+				.filter(|(idx, ..)| *idx >= vertex_count)
+				.count();
+			if invalid > 0 {
+				issues.error(format!(
+					"{context}: {invalid} morph deltas have vertex indices out of range",
+				));
+			}
+		}
+	}
+
+	for (idx, formula) in modifier.formulas.iter().flatten().enumerate() {
+		if let Err(message) = check_formula(formula) {
+			issues.error(format!(
+				"{context} formula {idx} ({}): {message}",
+				formula.output
+			));
+		}
+	}
+}
+
+/// Checks that a formula's operations leave exactly one value on the stack.
+fn check_formula(formula: &Formula) -> Result<(), String> {
+	let mut depth = 0_usize;
+	for (idx, operation) in formula.operations.iter().enumerate() {
+		let (pops, requires_val) = match operation.op {
+			OperationType::Push => {
+				if operation.url.is_none() && operation.val.is_none() {
+					return Err(format!("operation {idx} pushes neither a url nor a val"));
+				}
+				(0, false)
+			}
+			OperationType::Add | OperationType::Sub | OperationType::Mult | OperationType::Div => {
+				(2, false)
+			}
+			OperationType::SplineConstant
+			| OperationType::SplineLinear
+			| OperationType::SplineTcb => (1, true),
+		};
+
+		if requires_val && operation.val.is_none() {
+			return Err(format!("operation {idx} is missing its spline knots"));
+		}
+		depth = depth
+			.checked_sub(pops)
+			.ok_or_else(|| format!("operation {idx} pops from an empty stack"))?
+			+ 1;
+	}
+
+	match depth {
+		1 => Ok(()),
+		_ => Err(format!("leaves {depth} values on the stack instead of 1")),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::{validate, Severity};
+	use crate::document::{Document, Library};
+
+	#[test]
+	fn reports_out_of_range_indices() {
+		let doc = Document::from_value(
+			"test.dsf",
+			json!({
+				"file_version": "0.6.0.0",
+				"asset_info": {
+					"id": "/data/test.dsf",
+					"contributor": { "author": "test" },
+				},
+				"geometry_library": [{
+					"id": "geo",
+					"vertices": { "count": 3, "values": [[0, 0, 0], [1, 0, 0], [0, 1, 0]] },
+					"polygon_groups": { "count": 1, "values": ["body"] },
+					"polygon_material_groups": { "count": 1, "values": ["skin"] },
+					"polylist": { "count": 1, "values": [[0, 0, 0, 1, 3]] },
+				}],
+				"modifier_library": [{
+					"id": "morph",
+					"parent": "#geo",
+					"morph": {
+						"vertex_count": 3,
+						"deltas": { "count": 1, "values": [[5, 0, 1, 0]] },
+					},
+					"formulas": [{
+						"output": "#geo?value",
+						"operations": [{ "op": "push", "val": 1 }, { "op": "mult" }],
+					}],
+				}],
+			}),
+		)
+		.unwrap();
+
+		let issues = validate(&doc, &mut Library::new(vec![]));
+		let errors = issues
+			.iter()
+			.filter(|issue| issue.severity == Severity::Error)
+			.map(|issue| issue.message.as_str())
+			.collect::<Vec<_>>();
+
+		assert_eq!(errors, [
+			"geometry 'geo': 1 polygons have vertex indices out of range",
+			"modifier 'morph': 1 morph deltas have vertex indices out of range",
+			"modifier 'morph' formula 0 (#geo?value): operation 1 pops from an empty stack",
+		]);
+	}
+}
@@ -2,28 +2,31 @@ use std::collections::VecDeque;
 
 use bevy::{
 	core_pipeline::experimental::taa::{TemporalAntiAliasBundle, TemporalAntiAliasPlugin},
-	ecs::entity::EntityHashMap,
-	math::{vec3, Vec3A},
+	math::vec3,
 	pbr::PointLightShadowMap,
 	prelude::*,
 	render::{
 		camera::Exposure,
-		mesh::{skinning::SkinnedMesh, Indices, VertexAttributeValues},
+		mesh::{skinning::SkinnedMesh, Indices},
 	},
 	utils::smallvec::{smallvec, SmallVec},
 	window::PresentMode,
 };
-use bevy_daz::{DazAsset, DazAssetSourcePlugin, DazBone, DazFigure, DazPlugins, DualQuat};
+use bevy_daz::{
+	CpuSkinning, DazAsset, DazAssetSourcePlugin, DazAssetSpawned, DazBone, DazBones, DazFigure,
+	DazPlugins, DazSpawningSet, DeformedMesh, FitTo, SkinningMethod,
+};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
 
 fn main() {
 	let mut app = App::new();
 	app.add_plugins((
-		// This needs to be loaded before `DefaultPlugins`
-		DazAssetSourcePlugin::with_root_paths(vec![
-			"C:/Users/Public/Documents/My DAZ 3D Library".into()
-		]),
+		// This needs to be loaded before `DefaultPlugins`. The library root
+		// directories are discovered from the `DAZ_LIBRARY_PATHS` environment
+		// variable, a `daz_library.json` config file, or Daz Studio's default
+		// install location.
+		DazAssetSourcePlugin::default(),
 		DefaultPlugins.set(WindowPlugin {
 			primary_window: Some(Window {
 				present_mode: PresentMode::AutoNoVsync,
@@ -41,7 +44,8 @@ fn main() {
 	app.insert_resource(PointLightShadowMap { size: 2048 })
 		.insert_resource(Msaa::Off);
 
-	app.add_systems(Startup, (spawn_environment, spawn_genesis9_figure));
+	app.add_systems(Startup, (spawn_environment, spawn_genesis9_figure))
+		.add_systems(Update, attach_prop.after(DazSpawningSet));
 
 	app.run();
 }
@@ -50,20 +54,26 @@ fn main() {
 fn spawn_genesis9_figure(mut cmd: Commands, r_assets: Res<AssetServer>) {
 	const G9_DIR: &str = "daz://data/Daz 3D/Genesis 9";
 
-	cmd.spawn((
-		Name::new("Figure"),
-		DazFigure,
-		SpatialBundle::default(),
-		r_assets.load::<DazAsset>(format!("{G9_DIR}/Base/Genesis9.dsf")),
-	))
-	.with_children(|builder| {
+	let figure = cmd
+		.spawn((
+			Name::new("Figure"),
+			DazFigure,
+			SpatialBundle::default(),
+			r_assets.load::<DazAsset>(format!("{G9_DIR}/Base/Genesis9.dsf")),
+		))
+		.id();
+
+	// These are bound directly to the figure's skeleton
+	cmd.entity(figure).with_children(|builder| {
 		builder.spawn((
 			Name::new("Eyes"),
+			FitTo(figure),
 			SpatialBundle::default(),
 			r_assets.load::<DazAsset>(format!("{G9_DIR}/Genesis 9 Eyes/Genesis9Eyes.dsf")),
 		));
 		builder.spawn((
 			Name::new("Eyelashes"),
+			FitTo(figure),
 			SpatialBundle::default(),
 			r_assets.load::<DazAsset>(format!(
 				"{G9_DIR}/Genesis 9 Eyelashes/Genesis9Eyelashes.dsf"
@@ -71,11 +81,13 @@ fn spawn_genesis9_figure(mut cmd: Commands, r_assets: Res<AssetServer>) {
 		));
 		builder.spawn((
 			Name::new("Tear"),
+			FitTo(figure),
 			SpatialBundle::default(),
 			r_assets.load::<DazAsset>(format!("{G9_DIR}/Genesis 9 Tear/Genesis9Tear.dsf")),
 		));
 		builder.spawn((
 			Name::new("Mouth"),
+			FitTo(figure),
 			SpatialBundle::default(),
 			r_assets.load::<DazAsset>(format!("{G9_DIR}/Genesis 9 Mouth/Genesis9Mouth.dsf")),
 		));
@@ -83,6 +95,40 @@ fn spawn_genesis9_figure(mut cmd: Commands, r_assets: Res<AssetServer>) {
 }
 
 // The base environment
+/// Puts a small cube in the figure's right hand once it has been spawned.
+fn attach_prop(
+	mut cmd: Commands,
+	mut r_spawned: EventReader<DazAssetSpawned>,
+	mut ra_meshes: ResMut<Assets<Mesh>>,
+	mut ra_std_mats: ResMut<Assets<StandardMaterial>>,
+	q_figures: Query<(), With<DazFigure>>,
+	q_bones: Query<&DazBone>,
+	bones: DazBones,
+) {
+	for event in r_spawned.read() {
+		if !q_figures.contains(event.entity) {
+			continue;
+		}
+		let Some(hand) = bones.bone(event.entity, "r_hand") else {
+			continue;
+		};
+		// Halfway along the bone, in its local space
+		let palm = q_bones
+			.get(hand)
+			.map_or(Vec3::ZERO, |bone| bone.end_point * 0.5);
+
+		let prop = cmd
+			.spawn((Name::new("Prop"), PbrBundle {
+				mesh: ra_meshes.add(Cuboid::from_size(Vec3::splat(0.04))),
+				material: ra_std_mats.add(Color::hex("C04040").unwrap()),
+				transform: Transform::from_translation(palm),
+				..default()
+			}))
+			.id();
+		cmd.entity(hand).add_child(prop);
+	}
+}
+
 fn spawn_environment(
 	mut cmd: Commands,
 	mut ra_meshes: ResMut<Assets<Mesh>>,
@@ -433,192 +479,50 @@ fn visualize_wires_and_normals(
 	mut gizmos: Gizmos<WireframeGizmoGroup>,
 	r_config: Res<OverlayVisualizations>,
 	ra_meshes: Res<Assets<Mesh>>,
-	q_meshes: Query<(Entity, &Handle<Mesh>)>,
-	q_skinned_mesh: Query<&SkinnedMesh>,
-	q_joints: Query<(&Name, &GlobalTransform, &DazBone)>,
+	q_meshes: Query<(Entity, &Handle<Mesh>), With<SkinnedMesh>>,
+	cpu_skinning: CpuSkinning,
 ) {
 	if !r_config.wireframe && !r_config.normals {
 		return;
 	}
 
 	for (entity, mesh_handle) in q_meshes.iter() {
-		let Some(mesh) = ra_meshes.get(mesh_handle) else {
-			continue;
-		};
-		use VertexAttributeValues::*;
-		let Some(Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
-			continue;
-		};
-		let Some(Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL) else {
-			continue;
-		};
-		let Some(Indices::U32(indices)) = mesh.indices() else {
-			continue;
-		};
-		let Some(Uint16x4(joint_indices)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_INDEX) else {
+		let Some(Indices::U32(indices)) =
+			ra_meshes.get(mesh_handle).and_then(|mesh| mesh.indices())
+		else {
 			continue;
 		};
-		let Some(Float32x4(joint_weights)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT) else {
+		let Some(DeformedMesh {
+			positions, normals, ..
+		}) = cpu_skinning.deform(entity, SkinningMethod::Blended)
+		else {
 			continue;
 		};
 
-		let skinned_mesh = q_skinned_mesh.get(entity).unwrap();
-		let joints = &skinned_mesh.joints;
-		let dual_quats = joints
-			.iter()
-			.copied()
-			.map(|entity| {
-				let (_, world_xform, bone) = q_joints.get(entity).unwrap();
-				let joint_xform = DualQuat::from(*world_xform) * bone.inverse_bindpose;
-
-				(entity, joint_xform)
-			})
-			.collect::<EntityHashMap<_>>();
-
 		for face in indices.chunks_exact(6) {
 			let &[i0, i1, i2, _, _, i3] = face else {
 				continue;
 			};
-			let v0 = positions[i0 as usize];
-			let v1 = positions[i1 as usize];
-			let v2 = positions[i2 as usize];
-			let v3 = positions[i3 as usize];
-
-			let n0 = normals[i0 as usize];
-			let n1 = normals[i1 as usize];
-			let n2 = normals[i2 as usize];
-			let n3 = normals[i3 as usize];
-
-			let (v0, n0) = deform_vertex(
-				joint_indices,
-				joint_weights,
-				&skinned_mesh.joints,
-				&dual_quats,
-				i0 as usize,
-				v0.into(),
-				n0.into(),
-			);
-			let (v1, n1) = deform_vertex(
-				joint_indices,
-				joint_weights,
-				&skinned_mesh.joints,
-				&dual_quats,
-				i1 as usize,
-				v1.into(),
-				n1.into(),
-			);
-			let (v2, n2) = deform_vertex(
-				joint_indices,
-				joint_weights,
-				&skinned_mesh.joints,
-				&dual_quats,
-				i2 as usize,
-				v2.into(),
-				n2.into(),
-			);
-			let (v3, n3) = deform_vertex(
-				joint_indices,
-				joint_weights,
-				&skinned_mesh.joints,
-				&dual_quats,
-				i3 as usize,
-				v3.into(),
-				n3.into(),
-			);
+			let quad = [i0, i1, i2, i3].map(|idx| idx as usize);
 
 			if r_config.wireframe {
-				gizmos.line(v0.into(), v1.into(), r_config.wireframe_color);
-				gizmos.line(v1.into(), v2.into(), r_config.wireframe_color);
-				gizmos.line(v2.into(), v3.into(), r_config.wireframe_color);
-				gizmos.line(v3.into(), v0.into(), r_config.wireframe_color);
-			}
-
-			if r_config.normals {
-				gizmos.line(
-					v0.into(),
-					(v0 + n0 * r_config.normals_length).into(),
-					r_config.normals_color,
-				);
-				gizmos.line(
-					v1.into(),
-					(v1 + n1 * r_config.normals_length).into(),
-					r_config.normals_color,
-				);
-				gizmos.line(
-					v2.into(),
-					(v2 + n2 * r_config.normals_length).into(),
-					r_config.normals_color,
-				);
-				gizmos.line(
-					v3.into(),
-					(v3 + n3 * r_config.normals_length).into(),
-					r_config.normals_color,
-				);
-			}
-		}
-	}
-}
-
-fn deform_vertex(
-	joint_indices: &[[u16; 4]],
-	joint_weights: &[[f32; 4]],
-	joints: &[Entity],
-	dual_quats: &EntityHashMap<DualQuat>,
-	vert_idx: usize,
-	pos: Vec3A,
-	norm: Vec3A,
-) -> (Vec3A, Vec3A) {
-	let vert_joint_indices = joint_indices[vert_idx];
-	let vert_joint_weights = joint_weights[vert_idx];
-
-	let weights = vert_joint_indices
-		.iter()
-		.enumerate()
-		.map(|(buffer_idx, joint_idx)| {
-			let weight = vert_joint_weights[buffer_idx];
-			let joint_ent = joints[(*joint_idx) as usize];
-			(joint_ent, weight)
-		})
-		.collect::<Vec<_>>();
-
-	let first_rotation = weights
-		.first()
-		.map(|(joint, _)| dual_quats[joint])
-		.unwrap_or_default()
-		.rotation();
-
-	let xform_sum = weights
-		.into_iter()
-		.fold(None, |accum: Option<DualQuat>, (joint, weight)| {
-			if weight.abs() <= 1.0e-3 {
-				return accum;
+				for (idx, &vert) in quad.iter().enumerate() {
+					let next = quad[(idx + 1) % quad.len()];
+					gizmos.line(positions[vert], positions[next], r_config.wireframe_color);
+				}
 			}
 
-			let xform = dual_quats[&joint];
-
-			if let Some(acc_xform) = accum {
-				if xform.rotation().dot(first_rotation) < 0. {
-					Some(acc_xform + xform * -weight)
-				} else {
-					Some(acc_xform + xform * weight)
+			if r_config.normals && !normals.is_empty() {
+				for vert in quad {
+					gizmos.line(
+						positions[vert],
+						positions[vert] + normals[vert] * r_config.normals_length,
+						r_config.normals_color,
+					);
 				}
-			} else {
-				Some(xform * weight)
 			}
-		});
-
-	let xform_sum = xform_sum.unwrap_or_default();
-
-	if xform_sum.magnitude() <= 1.0e-3 {
-		return (pos, norm);
+		}
 	}
-
-	let joint_xform = xform_sum.normalize();
-
-	(
-		joint_xform.transform_point3a(pos),
-		joint_xform.transform_vector3a(norm),
-	)
 }
 
 fn visualize_bones(
@@ -0,0 +1,209 @@
+//! Spawns a crowd of Genesis 9 figures to benchmark skinning and animation.
+//!
+//! The number of figures can be passed as the first argument (default: 200).
+//! Every figure shares the same meshes, materials and animation clip, and
+//! animates less often the further it is from the camera.
+
+use std::f32::consts::PI;
+
+use bevy::{
+	animation::{Interpolation, Keyframes, VariableCurve},
+	diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
+	math::vec3,
+	prelude::*,
+	window::PresentMode,
+};
+use bevy_daz::{
+	BoneMap, DazAsset, DazAssetSourcePlugin, DazAssetSpawned, DazBone, DazFigure, DazPlugins,
+	DazSpawningSet, FitTo, HumanoidBone, RetargetSkeleton, SkeletonLod,
+};
+use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
+
+const DEFAULT_CROWD_SIZE: usize = 200;
+const SPACING: f32 = 1.2;
+
+fn main() {
+	let crowd_size = std::env::args()
+		.nth(1)
+		.and_then(|arg| arg.parse().ok())
+		.unwrap_or(DEFAULT_CROWD_SIZE);
+
+	let mut app = App::new();
+	app.add_plugins((
+		DazAssetSourcePlugin::default(),
+		DefaultPlugins.set(WindowPlugin {
+			primary_window: Some(Window {
+				present_mode: PresentMode::AutoNoVsync,
+				..default()
+			}),
+			..default()
+		}),
+		DazPlugins,
+		PanOrbitCameraPlugin,
+		FrameTimeDiagnosticsPlugin,
+		LogDiagnosticsPlugin::default(),
+	));
+
+	app.insert_resource(CrowdSize(crowd_size))
+		.add_systems(Startup, (spawn_environment, spawn_crowd))
+		.add_systems(Update, animate_crowd.after(DazSpawningSet));
+
+	app.run();
+}
+
+#[derive(Resource)]
+struct CrowdSize(usize);
+
+fn spawn_crowd(mut cmd: Commands, r_assets: Res<AssetServer>, r_crowd_size: Res<CrowdSize>) {
+	const G9_DIR: &str = "daz://data/Daz 3D/Genesis 9";
+
+	// Loaded once, so every figure shares the same meshes and materials
+	let figure_asset = r_assets.load::<DazAsset>(format!("{G9_DIR}/Base/Genesis9.dsf"));
+	let eyes_asset = r_assets.load::<DazAsset>(format!("{G9_DIR}/Genesis 9 Eyes/Genesis9Eyes.dsf"));
+
+	let columns = (r_crowd_size.0 as f32).sqrt().ceil() as usize;
+	for idx in 0..r_crowd_size.0 {
+		let (row, column) = (idx / columns, idx % columns);
+		let translation = vec3(
+			(column as f32 - (columns - 1) as f32 / 2.) * SPACING,
+			0.,
+			-(row as f32) * SPACING,
+		);
+
+		let figure = cmd
+			.spawn((
+				Name::new(format!("Figure {idx}")),
+				DazFigure,
+				SpatialBundle::from_transform(Transform::from_translation(translation)),
+				figure_asset.clone(),
+			))
+			.id();
+
+		cmd.entity(figure).with_children(|builder| {
+			builder.spawn((
+				Name::new("Eyes"),
+				FitTo(figure),
+				SpatialBundle::default(),
+				eyes_asset.clone(),
+			));
+		});
+	}
+}
+
+/// Starts the same idle animation on every figure as it finishes spawning, each
+/// at a different point in the clip.
+fn animate_crowd(
+	mut cmd: Commands,
+	mut r_spawned: EventReader<DazAssetSpawned>,
+	mut ra_clips: ResMut<Assets<AnimationClip>>,
+	q_figures: Query<(), With<DazFigure>>,
+	q_nodes: Query<(&Name, &Transform, Option<&DazBone>, Option<&Children>)>,
+	mut l_clip: Local<Option<Handle<AnimationClip>>>,
+) {
+	for event in r_spawned.read() {
+		if !q_figures.contains(event.entity) {
+			continue;
+		}
+		// The animation player goes on the root node, which clip paths start at
+		let Some(&root) = event.root_nodes.first() else {
+			continue;
+		};
+
+		let clip = l_clip
+			.get_or_insert_with(|| {
+				let skeleton =
+					RetargetSkeleton::from_hierarchy(root, &BoneMap::genesis9(), &q_nodes);
+				ra_clips.add(idle_clip(&skeleton))
+			})
+			.clone();
+
+		let mut player = AnimationPlayer::default();
+		player
+			.play(clip)
+			.repeat()
+			.seek_to((event.entity.index() % 16) as f32 * 0.25);
+
+		cmd.entity(root).insert((player, SkeletonLod::default()));
+	}
+}
+
+/// A few seconds of swaying through the spine and head.
+fn idle_clip(skeleton: &RetargetSkeleton) -> AnimationClip {
+	const DURATION: f32 = 4.;
+	const KEYFRAMES: usize = 17;
+
+	let mut clip = AnimationClip::default();
+	let timestamps = (0..KEYFRAMES)
+		.map(|idx| idx as f32 * DURATION / (KEYFRAMES - 1) as f32)
+		.collect::<Vec<_>>();
+
+	let sways = [
+		(HumanoidBone::Spine, Vec3::Z, 0.04),
+		(HumanoidBone::Chest, Vec3::Z, 0.04),
+		(HumanoidBone::Neck, Vec3::Y, 0.1),
+		(HumanoidBone::Head, Vec3::Y, 0.15),
+	];
+
+	for (humanoid, axis, amplitude) in sways {
+		let Some(bone) = skeleton.bones.get(&humanoid) else {
+			continue;
+		};
+		let rotations = timestamps
+			.iter()
+			.map(|time| {
+				let angle = (time / DURATION * 2. * PI).sin() * amplitude;
+				// Around the world-space axis, relative to the bind pose
+				let world = Quat::from_axis_angle(axis, angle);
+				bone.parent_bind_rotation.inverse() * world * bone.bind_rotation
+			})
+			.collect();
+
+		clip.add_curve_to_path(bone.path.clone(), VariableCurve {
+			keyframe_timestamps: timestamps.clone(),
+			keyframes: Keyframes::Rotation(rotations),
+			interpolation: Interpolation::Linear,
+		});
+	}
+
+	clip
+}
+
+fn spawn_environment(
+	mut cmd: Commands,
+	mut ra_meshes: ResMut<Assets<Mesh>>,
+	mut ra_mats: ResMut<Assets<StandardMaterial>>,
+	r_crowd_size: Res<CrowdSize>,
+) {
+	let extent = (r_crowd_size.0 as f32).sqrt().ceil() * SPACING;
+
+	cmd.spawn((Name::new("Ground Plane"), MaterialMeshBundle {
+		mesh: ra_meshes.add(Plane3d::new(Vec3::Y)),
+		material: ra_mats.add(Color::hex("CCCCCC").unwrap()),
+		transform: Transform::from_xyz(0., 0., -extent / 2.)
+			.with_scale(Vec3::splat(extent / 2. + 2.)),
+		..default()
+	}));
+
+	let camera_focus = vec3(0., 1., -extent / 2.);
+	cmd.spawn((
+		Name::new("Main Camera"),
+		Camera3dBundle {
+			transform: Transform::from_xyz(0., 2.5, 4.).looking_at(camera_focus, Vec3::Y),
+			..default()
+		},
+		PanOrbitCamera {
+			focus: camera_focus,
+			..default()
+		},
+	));
+
+	cmd.spawn((Name::new("Sun"), DirectionalLightBundle {
+		directional_light: DirectionalLight {
+			illuminance: 5_000.,
+			shadows_enabled: true,
+			..default()
+		},
+		transform: Transform::from_xyz(1., 2., 1.).looking_at(Vec3::ZERO, Vec3::Y),
+		..default()
+	}));
+}
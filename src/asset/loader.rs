@@ -3,31 +3,68 @@ use std::collections::VecDeque;
 use anyhow::anyhow;
 use bevy::{
 	asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+	pbr::ExtendedMaterial,
 	prelude::*,
-	render::mesh::{Mesh, VertexAttributeValues},
+	render::{
+		mesh::{
+			morph::{
+				MeshMorphWeights, MorphAttributes, MorphBuildError, MorphTargetImage, MorphWeights,
+				MAX_MORPH_WEIGHTS,
+			},
+			skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+			Mesh, VertexAttributeValues,
+		},
+		render_asset::RenderAssetUsages,
+	},
 	utils::{
 		hashbrown::{HashMap, HashSet},
 		BoxedFuture,
 	},
 };
-use daz_asset_types::{ChannelsAsVec3, Daz, Geometry, Modifier, Node, NodeType};
-use regex::{Captures, Regex};
+use bevy_dqskinning::{DqsMaterialExt, DqsSkinningMode, DqsStandardMaterial, ATTRIBUTE_DQS_BLEND};
+use daz_asset_types::{
+	percent_decode, ChannelFloat, ChannelType, ChannelsAsVec3, Daz, Formula, FormulaStage,
+	Geometry, Modifier, Node, NodeType, OperationType, TriangleMesh,
+};
+use serde::{Deserialize, Serialize};
 use serde_json as json;
 
-use crate::asset::{DazAsset, DazMesh, DazNode, DazPrimitive, DazUvSet};
+use crate::{
+	asset::{
+		simplify::simplify, DazAsset, DazLod, DazMesh, DazModifier, DazMorph, DazNode,
+		DazPrimitive, DazUvSet, JointAdjustment, SCENE_LABEL,
+	},
+	DazBone, DazSkeleton, FullDetailMesh, MeshLods,
+};
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct DazAssetLoader;
 
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DazAssetLoaderSettings {
+	/// The LODs to generate for each mesh primitive, from most to least
+	/// detailed, e.g. ratios and screen sizes of 0.5, 0.25 and 0.1. Empty by
+	/// default, which skips LOD generation.
+	pub lods: Vec<LodSettings>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct LodSettings {
+	/// The fraction of the full mesh's triangles to keep.
+	pub ratio: f32,
+	/// The screen size below which this LOD is used. See [MeshLods].
+	pub screen_size: f32,
+}
+
 impl AssetLoader for DazAssetLoader {
 	type Asset = DazAsset;
-	type Settings = (); // TODO
+	type Settings = DazAssetLoaderSettings;
 	type Error = anyhow::Error;
 
 	fn load<'a>(
 		&'a self,
 		reader: &'a mut Reader,
-		_: &'a Self::Settings,
+		settings: &'a Self::Settings,
 		cx: &'a mut LoadContext,
 	) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
 		Box::pin(async move {
@@ -51,9 +88,29 @@ impl AssetLoader for DazAssetLoader {
 
 			let mut mods_lib = daz.modifier_library.take().unwrap_or_default();
 			process_skins(&mut meshes, &raw_nodes, &mut mods_lib);
+			let modifiers = process_modifiers(mods_lib, &meshes);
+
+			let meshes = finish_meshes(
+				cx,
+				meshes,
+				&modifiers,
+				&mut nodes,
+				&node_indices,
+				&settings.lods,
+			);
+			let nodes = finish_nodes(nodes, &mut children);
 
-			let meshes = finish_meshes(cx, meshes, &mut nodes, &node_indices);
-			let nodes = finish_nodes(cx, nodes, &mut children);
+			let scene = build_scene(cx, &nodes, &meshes);
+			let scene = cx.add_labeled_asset(SCENE_LABEL.to_owned(), scene);
+
+			let meshes = meshes
+				.into_iter()
+				.map(|(id, mesh)| (id.clone(), cx.add_labeled_asset(id, mesh)))
+				.collect();
+			let nodes = nodes
+				.into_iter()
+				.map(|(id, node)| (id.clone(), cx.add_labeled_asset(id, node)))
+				.collect();
 
 			let uv_sets = daz
 				.uv_set_library
@@ -70,8 +127,10 @@ impl AssetLoader for DazAssetLoader {
 				.collect();
 
 			Ok(DazAsset {
+				scene,
 				meshes,
 				nodes,
+				modifiers,
 				materials: Default::default(), // TODO
 				uv_sets,
 			})
@@ -112,10 +171,8 @@ fn process_nodes(raw_nodes: &[Node]) -> TempNodesData {
 			scale: Vec3::splat(1.), // TODO
 		});
 
-		let end_point = root_transform
-			.affine()
-			.inverse()
-			.transform_point(raw_node.end_point.as_vec3() * 0.01);
+		let inverse_bindpose = root_transform.affine().inverse();
+		let end_point = inverse_bindpose.transform_point(raw_node.end_point.as_vec3() * 0.01);
 
 		let parent_id = raw_node.parent.as_ref().map(|selector| &selector[1..]);
 		let parent_idx = parent_id.and_then(|id| node_indices.get(id).copied());
@@ -132,6 +189,7 @@ fn process_nodes(raw_nodes: &[Node]) -> TempNodesData {
 			type_,
 			mesh: None,
 			root_transform,
+			inverse_bindpose,
 			transform,
 			end_point,
 			parent: parent_id.map(|id| id.to_owned()),
@@ -157,7 +215,10 @@ fn process_nodes(raw_nodes: &[Node]) -> TempNodesData {
 struct TempMeshData {
 	name: Option<String>,
 	mesh: Mesh,
+	/// The number of vertices in the geometry. `triangles` can have more, since
+	/// they're split along UV seams.
 	vertex_count: usize,
+	triangles: TriangleMesh,
 	joints: Vec<String>,
 }
 
@@ -169,52 +230,43 @@ async fn process_geometries(
 
 	for raw_geo in geo_lib {
 		let id = raw_geo.id.clone();
-		let name = raw_geo.name.clone();
-		let vertex_count = raw_geo.vertices.count;
-		let default_uv_set_uri = raw_geo.default_uv_set.as_ref().cloned();
-		let mut mesh = Mesh::from(raw_geo);
-
-		if let Some(uri) = default_uv_set_uri {
-			// TODO: Should probably break this URI parsing out into a
-			//       separate function and be more judicious about caching
-			//       the compiled regular expressions.
-			let rel_path = uri.strip_prefix('/').unwrap_or(&uri);
-			let unicode_re = Regex::new(r"%([0-9]{2})").unwrap();
-			let decoded = unicode_re.replace_all(rel_path, |captures: &Captures| {
-				let code_point = captures.get(1).unwrap().as_str();
-				let code_point = u32::from_str_radix(code_point, 16).unwrap();
-				char::from_u32(code_point).unwrap().to_string()
-			});
-
-			let fragment_re = Regex::new(r"#(.+)").unwrap();
-			let target_id = fragment_re
-				.captures(decoded.as_ref())
-				.unwrap()
-				.get(1)
-				.unwrap()
-				.as_str();
-
-			let untyped = cx
-				.load_direct(format!("daz://{decoded}"))
-				.await
-				.map_err(|err| anyhow!("{err}"))?;
-
-			let daz_asset = untyped.get::<DazAsset>().unwrap();
-			let uv_set = daz_asset.uv_sets.get(target_id).unwrap();
-			// TODO: We're not currently dealing correctly with alternate
-			//       coords for vertices at UV boundaries. I'm a little bit
-			//       confused about the representation of the
-			//       `polygon_vertex_indices` array, so this will require
-			//       some additional research.
-			let uvs = uv_set.uvs[..vertex_count].to_vec();
+		let triangles = match raw_geo.default_uv_set.as_deref() {
+			Some(uri) => {
+				let path = percent_decode(uri.strip_prefix('/').unwrap_or(uri));
+				let (_, target_id) = path
+					.split_once('#')
+					.ok_or_else(|| anyhow!("UV set URI \"{uri}\" has no asset ID"))?;
+
+				let untyped = cx
+					.load_direct(format!("daz://{path}"))
+					.await
+					.map_err(|err| anyhow!("{err}"))?;
+
+				let daz_asset = untyped.get::<DazAsset>().unwrap();
+				let uv_set = daz_asset.uv_sets.get(target_id).unwrap();
+
+				TriangleMesh::with_uvs(
+					&raw_geo,
+					&uv_set.uvs,
+					uv_set.polygon_vertex_indices.as_deref().unwrap_or_default(),
+				)
+			}
+			None => TriangleMesh::new(&raw_geo, None),
+		};
 
-			mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+		let mut mesh = Mesh::from(&triangles);
+		// Required for normal maps
+		if triangles.uvs.is_some() {
+			if let Err(err) = mesh.generate_tangents() {
+				warn!("Failed to generate tangents for geometry `{id}`: {err}");
+			}
 		}
 
 		result.insert(id, TempMeshData {
-			name,
+			name: raw_geo.name,
 			mesh,
-			vertex_count,
+			vertex_count: raw_geo.vertices.count,
+			triangles,
 			joints: vec![],
 		});
 	}
@@ -229,181 +281,440 @@ fn process_skins(
 ) {
 	let joint_ids = raw_nodes
 		.iter()
-		.filter_map(|node| {
-			if node.r#type == NodeType::Bone {
-				Some(&node.id[..])
-			} else {
-				None
-			}
-		})
-		.collect::<Vec<_>>();
+		.filter(|node| node.r#type == NodeType::Bone)
+		.map(|node| node.id.as_str())
+		.collect::<HashSet<_>>();
 
-	let joint_indices = joint_ids
-		.iter()
-		.enumerate()
-		.map(|(idx, id)| (*id, idx))
-		.collect::<HashMap<_, _>>();
-
-	for skin in mods_lib.iter_mut().filter_map(|m| m.skin.take()) {
-		let vert_count = skin.vertex_count;
+	for mut skin in mods_lib.iter_mut().filter_map(|m| m.skin.take()) {
 		let mesh_id = &skin.geometry[1..];
-		if !meshes.contains_key(mesh_id) || vert_count != meshes[mesh_id].vertex_count {
+		let Some(mesh_data) = meshes
+			.get_mut(mesh_id)
+			.filter(|mesh_data| mesh_data.vertex_count == skin.vertex_count)
+		else {
 			error!("Geometry '{mesh_id}' not found for skin!");
 			continue;
-		}
+		};
 
-		let Some(joints) = skin.joints else {
+		let Some(joints) = skin.joints.as_mut() else {
 			warn!("No joints found for '{mesh_id}' skin!");
 			continue;
 		};
+		joints.retain(|joint| {
+			let found = joint_ids.contains(&joint.node[1..]);
+			if !found {
+				error!("Failed to find joint index for node '{}'", joint.node);
+			}
+			found
+		});
+		mesh_data.joints = joints
+			.iter()
+			.map(|joint| joint.node[1..].to_owned())
+			.collect();
 
-		let mut vert_joints: Vec<[Option<usize>; 4]> = vec![[None, None, None, None]; vert_count];
-		let mut vert_weights: Vec<[Option<f32>; 4]> = vec![[None, None, None, None]; vert_count];
+		let (vert_joints, vert_weights) = mesh_data.triangles.joint_weights(&skin);
+		mesh_data.mesh.insert_attribute(
+			Mesh::ATTRIBUTE_JOINT_INDEX,
+			VertexAttributeValues::Uint16x4(vert_joints),
+		);
+		mesh_data
+			.mesh
+			.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, vert_weights);
 
-		let mut excess_weights_by_vert = HashMap::<usize, usize>::default();
+		if let Some(vert_blends) = mesh_data.triangles.blend_weights(&skin) {
+			mesh_data
+				.mesh
+				.insert_attribute(ATTRIBUTE_DQS_BLEND, vert_blends);
+		}
+	}
+}
 
-		for joint in joints {
-			let Some(joint_idx) = joint_indices.get(&joint.node[1..]).copied() else {
-				error!("Failed to find joint index for node '{}'", joint.node);
-				continue;
-			};
-			let Some(node_weights) = joint.node_weights else {
-				error!("No node weights for joint '{}'", joint.node);
-				continue;
-			};
-
-			for (vert_idx, weight) in node_weights.values {
-				let Some((buf_idx, vert_joint)) = (match vert_joints[vert_idx]
-					.iter_mut()
-					.enumerate()
-					.find(|(_, vert_joint)| vert_joint.is_none())
-				{
-					None => {
-						if excess_weights_by_vert.contains_key(&vert_idx) {
-							*excess_weights_by_vert.get_mut(&vert_idx).unwrap() += 1;
-						} else {
-							excess_weights_by_vert.insert_unique_unchecked(vert_idx, 1);
-						}
-
-						let lowest_weight = vert_weights[vert_idx].iter().enumerate().fold(
-							(usize::MAX, f32::MAX),
-							|accum, current| {
-								if current.1.unwrap() < accum.1 {
-									(current.0, current.1.unwrap())
-								} else {
-									accum
-								}
-							},
-						);
+/// Morphs of geometries in the same file are checked against them, and dropped
+/// if they don't fit. Others are checked wherever they're applied.
+fn process_modifiers(
+	mods_lib: Vec<Modifier>,
+	meshes: &HashMap<String, TempMeshData>,
+) -> HashMap<String, DazModifier> {
+	mods_lib
+		.into_iter()
+		.filter_map(|modifier| {
+			let channel = json::from_value::<ChannelFloat>(modifier.channel?).ok()?;
+			if channel.r#type != ChannelType::Float {
+				return None;
+			}
 
-						if lowest_weight.1 < weight {
-							Some((lowest_weight.0, &mut vert_joints[vert_idx][lowest_weight.0]))
-						} else {
-							None
-						}
-					}
-					some => some,
-				}) else {
+			let mut joint_adjustments = Vec::<JointAdjustment>::new();
+			for formula in modifier.formulas.iter().flatten() {
+				let Some((node, channel, axis, scale)) = joint_formula(&modifier.id, formula)
+				else {
 					continue;
 				};
 
-				*vert_joint = Some(joint_idx);
-				vert_weights[vert_idx][buf_idx] = Some(weight);
+				let idx = match joint_adjustments.iter().position(|adj| adj.node == node) {
+					Some(idx) => idx,
+					None => {
+						joint_adjustments.push(JointAdjustment {
+							node: node.to_owned(),
+							..default()
+						});
+						joint_adjustments.len() - 1
+					}
+				};
+				let adjustment = &mut joint_adjustments[idx];
+				let point = match channel {
+					"center_point" => &mut adjustment.center_point,
+					_ => &mut adjustment.end_point,
+				};
+				// Centimeters to meters, like the node's own points
+				point[axis] += scale * 0.01;
 			}
-		}
 
-		// for (vert_idx, excess_weights) in excess_weights_by_vert {
-		// 	warn!(
-		// 		"Attempted to assign {} joint weights to vertex {vert_idx}, \
-		// 		but a maximum of 4 are supported.",
-		// 		excess_weights + 4,
-		// 	)
-		// }
+			let morph = modifier.morph.and_then(|morph| {
+				let (_, geometry) = modifier.parent.as_deref()?.split_once('#')?;
+				let morph = DazMorph {
+					geometry: geometry.to_owned(),
+					vertex_count: usize::try_from(morph.vertex_count).ok(),
+					deltas: morph
+						.deltas
+						.values
+						.into_iter()
+						.map(|(idx, x, y, z)| (idx as u32, Vec3::new(x, y, z) * 0.01))
+						.collect(),
+				};
 
-		let vert_joints = vert_joints
-			.into_iter()
-			.map(|[a, b, c, d]| {
-				let a = a.unwrap_or_default();
-				let b = b.unwrap_or_default();
-				let c = c.unwrap_or_default();
-				let d = d.unwrap_or_default();
+				match meshes.get(geometry) {
+					Some(mesh_data) if !morph.fits(mesh_data.vertex_count) => {
+						let expected = morph
+							.deltas
+							.iter()
+							.map(|&(idx, _)| idx as usize + 1)
+							.chain(morph.vertex_count)
+							.max()
+							.unwrap_or_default();
+						warn!(
+							"Skipping morph '{}': geometry '{geometry}' has {} vertices, but the \
+							 morph expects {expected}",
+							modifier.id, mesh_data.vertex_count,
+						);
+						None
+					}
+					_ => Some(morph),
+				}
+			});
 
-				[a as u16, b as u16, c as u16, d as u16]
-			})
-			.collect::<Vec<_>>();
+			Some((modifier.id.clone(), DazModifier {
+				id: modifier.id,
+				label: modifier.label.or_else(|| channel.label.clone()),
+				channel,
+				joint_adjustments,
+				morph,
+			}))
+		})
+		.collect()
+}
 
-		let vert_weights = vert_weights
-			.into_iter()
-			.map(|[a, b, c, d]| {
-				let a = a.unwrap_or_default();
-				let b = b.unwrap_or_default();
-				let c = c.unwrap_or_default();
-				let d = d.unwrap_or_default();
-
-				let sum = a + b + c + d;
-				if sum.abs() <= f32::EPSILON {
-					Vec4::new(0., 0., 0., 0.)
-				} else {
-					Vec4::new(a, b, c, d) / sum
-				}
-			})
-			.collect::<Vec<_>>();
+/// Parses a formula that moves a joint's center or end point linearly with the
+/// value of modifier `modifier_id`. Returns the node ID, channel, axis, and the
+/// distance per unit of the modifier's value.
+///
+/// Only the simple `value * constant` form used by shaping morphs is supported.
+/// Other forms are skipped with a warning, and formulas driven by other
+/// modifiers with a debug message.
+fn joint_formula<'a>(
+	modifier_id: &str,
+	formula: &'a Formula,
+) -> Option<(&'a str, &'a str, usize, f32)> {
+	let (node, property) = split_channel_uri(&formula.output)?;
+	let (channel, axis) = property.split_once('/')?;
+	if channel != "center_point" && channel != "end_point" {
+		return None;
+	}
 
-		let mesh_data = meshes.get_mut(mesh_id).unwrap();
+	let is_own_value = |url: &str| {
+		split_channel_uri(url)
+			.is_some_and(|(id, property)| id == modifier_id && property == "value")
+	};
+	// Formulas driven by other modifiers are applied by those modifiers
+	let is_driven_by_own_value = formula
+		.operations
+		.iter()
+		.any(|op| op.url.as_deref().is_some_and(is_own_value));
+	if !is_driven_by_own_value {
+		debug!("Skipping {node} {channel} formula of modifier '{modifier_id}' driven by others");
+		return None;
+	}
 
-		mesh_data.joints = joint_ids.iter().copied().map(|id| id.to_owned()).collect();
+	let axis = match axis {
+		"x" => Some(0),
+		"y" => Some(1),
+		"z" => Some(2),
+		_ => None,
+	};
+	let constant = |val: &json::Value| val.as_f64().map(|val| val as f32);
+	let scale = match &formula.operations[..] {
+		_ if formula.stage != FormulaStage::Sum => None,
+		[push] if push.op == OperationType::Push => Some(1.),
+		[a, b, mult]
+			if a.op == OperationType::Push
+				&& b.op == OperationType::Push
+				&& mult.op == OperationType::Mult =>
+		{
+			match (a.url.as_deref(), b.url.as_deref()) {
+				(Some(url), None) if is_own_value(url) => b.val.as_ref().and_then(constant),
+				(None, Some(url)) if is_own_value(url) => a.val.as_ref().and_then(constant),
+				_ => None,
+			}
+		}
+		_ => None,
+	};
 
-		mesh_data.mesh.insert_attribute(
-			Mesh::ATTRIBUTE_JOINT_INDEX,
-			VertexAttributeValues::Uint16x4(vert_joints),
+	let (Some(axis), Some(scale)) = (axis, scale) else {
+		warn!(
+			"Skipping unsupported {node} {channel} formula of modifier '{modifier_id}'; only \
+			`value * constant` is supported"
 		);
+		return None;
+	};
 
-		mesh_data
-			.mesh
-			.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, vert_weights);
-	}
+	Some((node, channel, axis, scale))
+}
+
+/// Splits a channel URI like `node:/path/to/file.dsf#node?channel/x` into the
+/// ID of the object it belongs to and the channel's property path.
+fn split_channel_uri(uri: &str) -> Option<(&str, &str)> {
+	let (object, property) = uri.split_once('?')?;
+	let id = match object.split_once('#') {
+		Some((_, fragment)) => fragment,
+		None => object.split_once(':').map_or(object, |(id, _)| id),
+	};
+
+	Some((id, property))
 }
 
+/// Returns the finished meshes, keyed by ID. They're added as labeled assets
+/// once the scene has been built from them.
 fn finish_meshes(
 	cx: &mut LoadContext<'_>,
 	meshes: impl IntoIterator<Item = (String, TempMeshData)>,
+	modifiers: &HashMap<String, DazModifier>,
 	nodes: &mut [(String, DazNode)],
 	node_indices: &HashMap<String, usize>,
-) -> HashMap<String, Handle<DazMesh>> {
+	lod_settings: &[LodSettings],
+) -> HashMap<String, DazMesh> {
 	let mut result = HashMap::default();
+	// Every skin in a file is bound to the same joints, so they can usually share
+	// a single inverse bindposes asset
+	let mut inverse_bindposes_by_joints =
+		HashMap::<Vec<String>, Handle<SkinnedMeshInverseBindposes>>::default();
 
 	for (idx, (id, mesh_data)) in meshes.into_iter().enumerate() {
 		let mesh_name = mesh_data.name;
 
+		let inverse_bindposes = if mesh_data.joints.is_empty() {
+			None
+		} else if let Some(handle) = inverse_bindposes_by_joints.get(&mesh_data.joints) {
+			Some(handle.clone())
+		} else {
+			let inverse_bindposes = mesh_data
+				.joints
+				.iter()
+				.map(|joint_id| {
+					node_indices.get(joint_id).map_or(Mat4::IDENTITY, |&idx| {
+						Mat4::from(nodes[idx].1.inverse_bindpose)
+					})
+				})
+				.collect::<Vec<_>>();
+
+			let handle = cx.add_labeled_asset(
+				format!("{id}/InverseBindposes"),
+				SkinnedMeshInverseBindposes::from(inverse_bindposes),
+			);
+			inverse_bindposes_by_joints.insert(mesh_data.joints.clone(), handle.clone());
+			Some(handle)
+		};
+
+		let primitive_label = format!("{id}/Primitive{idx}");
+		let mut mesh = mesh_data.mesh;
+		let mut morphs = morph_targets(&id, modifiers);
+		let mut morph_target_names = vec![];
+		let source_vertices = mesh_data.triangles.source_vertices;
+		if !morphs.is_empty() {
+			match add_morph_targets(cx, &primitive_label, &mut mesh, &morphs, &source_vertices) {
+				Ok(names) => morph_target_names = names,
+				Err(err) => {
+					warn!("Failed to build morph targets for geometry '{id}': {err}");
+					morphs.clear();
+				}
+			}
+		}
+		let lods = generate_lods(
+			cx,
+			&primitive_label,
+			&mesh,
+			&source_vertices,
+			&morphs,
+			lod_settings,
+		);
 		let daz_prim = DazPrimitive {
-			mesh: cx.add_labeled_asset(format!("{id}/Primitive{idx}"), mesh_data.mesh),
+			mesh: cx.add_labeled_asset(primitive_label, mesh),
 			material: None,
+			lods,
+			source_vertices,
 		};
 
-		let mesh_handle = cx.add_labeled_asset(id.clone(), DazMesh {
+		if let Some(mesh_name) = mesh_name {
+			if let Some(&parent_idx) = node_indices.get(&mesh_name) {
+				let (_, node) = nodes.get_mut(parent_idx).unwrap();
+				node.mesh = Some(cx.get_label_handle(id.clone()));
+			}
+		}
+
+		result.insert(id, DazMesh {
 			primitives: vec![daz_prim],
 			joints: mesh_data.joints,
+			inverse_bindposes,
+			morph_targets: morph_target_names,
+			vertex_count: mesh_data.vertex_count,
 		});
+	}
 
-		result.insert(id, mesh_handle.clone());
+	result
+}
 
-		if let Some(mesh_name) = mesh_name {
-			if let Some(&parent_idx) = node_indices.get(&mesh_name) {
-				let (_, node) = nodes.get_mut(parent_idx).unwrap();
-				node.mesh = Some(mesh_handle);
+/// The morphs of geometry `id` that become its morph targets, sorted by
+/// modifier ID.
+fn morph_targets<'a>(
+	id: &str,
+	modifiers: &'a HashMap<String, DazModifier>,
+) -> Vec<(&'a str, &'a DazMorph)> {
+	let mut morphs = modifiers
+		.values()
+		.filter_map(|modifier| {
+			let morph = modifier.morph.as_ref()?;
+			(morph.geometry == id).then_some((modifier.id.as_str(), morph))
+		})
+		.collect::<Vec<_>>();
+	morphs.sort_by_key(|(id, _)| *id);
+	if morphs.len() > MAX_MORPH_WEIGHTS {
+		warn!(
+			"Geometry '{id}' has {} morphs; only the first {MAX_MORPH_WEIGHTS} become morph \
+			 targets",
+			morphs.len(),
+		);
+		morphs.truncate(MAX_MORPH_WEIGHTS);
+	}
+
+	morphs
+}
+
+/// Gives `mesh` morph targets for `morphs`, labeled after the mesh, e.g.
+/// `{label}/MorphTargets`, and returns their names. Fails if the mesh has too
+/// many vertices.
+///
+/// `source_vertices` are the geometry vertices that the mesh's are copies of,
+/// since the morphs' deltas are indexed by the former.
+fn add_morph_targets(
+	cx: &mut LoadContext<'_>,
+	label: &str,
+	mesh: &mut Mesh,
+	morphs: &[(&str, &DazMorph)],
+	source_vertices: &[u32],
+) -> Result<Vec<String>, MorphBuildError> {
+	let image = morph_target_image(morphs, source_vertices)?;
+	mesh.set_morph_targets(cx.add_labeled_asset(format!("{label}/MorphTargets"), image));
+
+	let names = morphs
+		.iter()
+		.map(|(id, _)| (*id).to_owned())
+		.collect::<Vec<_>>();
+	mesh.set_morph_target_names(names.clone());
+
+	Ok(names)
+}
+
+fn morph_target_image(
+	morphs: &[(&str, &DazMorph)],
+	source_vertices: &[u32],
+) -> Result<Image, MorphBuildError> {
+	// Morphs were checked against their geometry by `process_modifiers`
+	let targets = morphs.iter().map(|(_, morph)| {
+		morph
+			.vertex_deltas(source_vertices)
+			.into_iter()
+			.map(|position| MorphAttributes {
+				position,
+				..default()
+			})
+	});
+
+	MorphTargetImage::new(targets, source_vertices.len(), RenderAssetUsages::default())
+		.map(|image| image.0)
+}
+
+/// Simplifies `mesh` once for each of `lod_settings`, labeling the results
+/// after the primitive, e.g. `{primitive_label}/Lod1`. Each LOD is simplified
+/// from the one before it, and generation stops at the first one that can't be
+/// simplified any further.
+///
+/// LODs get their own morph targets for `morphs`, which are those of `mesh`,
+/// whose vertices are copies of `geometry_vertices`.
+fn generate_lods(
+	cx: &mut LoadContext<'_>,
+	primitive_label: &str,
+	mesh: &Mesh,
+	geometry_vertices: &[u32],
+	morphs: &[(&str, &DazMorph)],
+	lod_settings: &[LodSettings],
+) -> Vec<DazLod> {
+	let mut lods = Vec::<DazLod>::with_capacity(lod_settings.len());
+	let mut previous = None::<(Mesh, Vec<u32>)>;
+	let mut previous_ratio = 1.;
+
+	for settings in lod_settings {
+		let source = previous.as_ref().map_or(mesh, |(mesh, _)| mesh);
+		let Some((mut lod_mesh, mut source_vertices)) =
+			simplify(source, settings.ratio / previous_ratio)
+		else {
+			break;
+		};
+		// Map back to the full mesh's vertices
+		if let Some((_, previous_sources)) = previous.as_ref() {
+			for idx in source_vertices.iter_mut() {
+				*idx = previous_sources[*idx as usize];
+			}
+		}
+
+		let label = format!("{primitive_label}/Lod{}", lods.len() + 1);
+		// Replaces the morph targets copied from the source mesh, which are
+		// indexed by its vertices
+		if !morphs.is_empty() {
+			let geometry_vertices = source_vertices
+				.iter()
+				.map(|&idx| geometry_vertices[idx as usize])
+				.collect::<Vec<_>>();
+			let result = add_morph_targets(cx, &label, &mut lod_mesh, morphs, &geometry_vertices);
+			if let Err(err) = result {
+				warn!("Failed to build morph targets for {label}: {err}");
+				break;
 			}
 		}
+		lods.push(DazLod {
+			mesh: cx.add_labeled_asset(label, lod_mesh.clone()),
+			screen_size: settings.screen_size,
+			source_vertices: source_vertices.clone(),
+		});
+
+		previous = Some((lod_mesh, source_vertices));
+		previous_ratio = settings.ratio;
 	}
 
-	result
+	lods
 }
 
 fn finish_nodes(
-	cx: &mut LoadContext<'_>,
 	nodes: impl IntoIterator<Item = (String, DazNode)>,
 	children: &mut HashMap<String, Vec<usize>>,
-) -> HashMap<String, Handle<DazNode>> {
-	let resolved_nodes = resolve_node_hierarchy(
+) -> Vec<(String, DazNode)> {
+	resolve_node_hierarchy(
 		nodes
 			.into_iter()
 			.map(|(id, node)| {
@@ -411,12 +722,175 @@ fn finish_nodes(
 				(id, node, children)
 			})
 			.collect(),
-	);
+	)
+}
 
-	resolved_nodes
-		.into_iter()
-		.map(|(id, node)| (id.clone(), cx.add_labeled_asset(id, node)))
-		.collect()
+/// Builds the node hierarchy as a [Scene], with a [DazBone] for each bone and a
+/// [MaterialMeshBundle] for each mesh primitive, plus [MeshLods] if it has any
+/// LODs. Each root node gets a [DazSkeleton] of the bones beneath it, and each
+/// node with morph targets gets [MorphWeights].
+fn build_scene(
+	cx: &mut LoadContext<'_>,
+	nodes: &[(String, DazNode)],
+	meshes: &HashMap<String, DazMesh>,
+) -> Scene {
+	let mut world = World::default();
+
+	let nodes_by_id = nodes
+		.iter()
+		.map(|(id, node)| (id.as_str(), node))
+		.collect::<HashMap<_, _>>();
+	let mut entities = HashMap::<&str, Entity>::with_capacity(nodes.len());
+
+	for (id, node) in nodes.iter() {
+		let name = if node.parent.is_none() {
+			&node.id
+		} else {
+			&node.name
+		};
+		let mut entity =
+			world.spawn((Name::new(name.clone()), SpatialBundle::from(node.transform)));
+
+		if node.type_ == NodeType::Bone {
+			entity.insert(DazBone {
+				end_point: node.end_point,
+				inverse_bindpose: node.inverse_bindpose.into(),
+			});
+		}
+
+		entities.insert(id, entity.id());
+	}
+
+	let mut skeletons = HashMap::<&str, DazSkeleton>::default();
+
+	// Parents aren't guaranteed to come before their children
+	for (id, node) in nodes.iter() {
+		let entity = entities[id.as_str()];
+		if let Some(&parent) = node
+			.parent
+			.as_ref()
+			.and_then(|id| entities.get(id.as_str()))
+		{
+			world.entity_mut(parent).add_child(entity);
+		}
+
+		if node.type_ == NodeType::Bone {
+			let mut root = node;
+			while let Some(parent) = root
+				.parent
+				.as_ref()
+				.and_then(|id| nodes_by_id.get(id.as_str()))
+			{
+				root = parent;
+			}
+			skeletons
+				.entry(&root.id)
+				.or_default()
+				.bones
+				.insert(id.clone(), entity);
+		}
+	}
+
+	for (root_id, skeleton) in skeletons {
+		if let Some(&root) = entities.get(root_id) {
+			world.entity_mut(root).insert(skeleton);
+		}
+	}
+
+	let mut default_material_handle = None::<Handle<DqsStandardMaterial>>;
+
+	for (mesh_id, mesh) in meshes.iter() {
+		let handle = cx.get_label_handle::<DazMesh>(mesh_id.clone());
+		let Some(node_entity) = nodes
+			.iter()
+			.find(|(_, node)| node.mesh.as_ref() == Some(&handle))
+			.map(|(id, _)| entities[id.as_str()])
+		else {
+			continue;
+		};
+
+		let joints = mesh
+			.joints
+			.iter()
+			.map(|id| entities.get(id.as_str()).copied())
+			.collect::<Option<Vec<_>>>();
+
+		let skinned_mesh = match (joints, mesh.inverse_bindposes.as_ref()) {
+			(Some(joints), Some(inverse_bindposes)) => Some(SkinnedMesh {
+				inverse_bindposes: inverse_bindposes.clone(),
+				joints,
+			}),
+			(None, _) => {
+				error!("Missing joints for mesh '{mesh_id}'");
+				None
+			}
+			_ => None,
+		};
+
+		// Set on the node, and inherited by its primitives
+		let morph_weights = (!mesh.morph_targets.is_empty()).then(|| {
+			let first_mesh = mesh
+				.primitives
+				.first()
+				.map(|primitive| primitive.mesh.clone());
+			MorphWeights::new(vec![0.; mesh.morph_targets.len()], first_mesh).unwrap()
+		});
+		if let Some(morph_weights) = morph_weights.as_ref() {
+			world.entity_mut(node_entity).insert(morph_weights.clone());
+		}
+
+		for primitive in mesh.primitives.iter() {
+			let material = primitive.material.clone().unwrap_or_else(|| {
+				default_material_handle
+					.get_or_insert_with(|| {
+						cx.add_labeled_asset("DefaultMaterial".to_owned(), default_material())
+					})
+					.clone()
+			});
+
+			let mut mesh_entity = world.spawn(MaterialMeshBundle {
+				mesh: primitive.mesh.clone(),
+				material,
+				..default()
+			});
+			if let Some(skinned_mesh) = skinned_mesh.as_ref() {
+				mesh_entity.insert(skinned_mesh.clone());
+			}
+			if !primitive.lods.is_empty() {
+				mesh_entity.insert((
+					MeshLods::from(primitive),
+					FullDetailMesh(primitive.mesh.clone()),
+				));
+			}
+			if let Some(morph_weights) = morph_weights.as_ref() {
+				mesh_entity
+					.insert(MeshMorphWeights::new(morph_weights.weights().to_vec()).unwrap());
+			}
+
+			let mesh_entity = mesh_entity.id();
+			world.entity_mut(node_entity).add_child(mesh_entity);
+		}
+	}
+
+	Scene::new(world)
+}
+
+/// The material of primitives that don't have one of their own.
+pub(crate) fn default_material() -> DqsStandardMaterial {
+	ExtendedMaterial {
+		base: StandardMaterial {
+			base_color: Color::hex("AAAAAA").unwrap(),
+			metallic: 0.,
+			perceptual_roughness: 0.55,
+			reflectance: 0.45,
+			..default()
+		},
+		// Falls back to pure DQS for meshes without a blend map
+		extension: DqsMaterialExt {
+			mode: DqsSkinningMode::Blended,
+			..default()
+		},
+	}
 }
 
 fn resolve_node_hierarchy(nodes: Vec<(String, DazNode, Vec<usize>)>) -> Vec<(String, DazNode)> {
@@ -472,3 +946,169 @@ fn resolve_node_hierarchy(nodes: Vec<(String, DazNode, Vec<usize>)>) -> Vec<(Str
 	nodes.sort_by_key(|(i, _)| *i);
 	nodes.into_iter().map(|(_, tuple)| tuple).collect()
 }
+
+#[cfg(test)]
+mod tests {
+	use bevy::{
+		prelude::*,
+		render::{
+			mesh::skinning::SkinnedMesh, render_asset::RenderAssetUsages,
+			render_resource::PrimitiveTopology,
+		},
+		utils::hashbrown::HashMap,
+	};
+	use daz_asset_types::{Formula, Modifier, TriangleMesh};
+	use serde_json as json;
+
+	use super::{joint_formula, morph_target_image, process_modifiers, TempMeshData};
+	use crate::{
+		testing::{load, loader_app, FIGURE_DSF},
+		DazAsset, DazBone, DazMorph, DazSkeleton,
+	};
+
+	#[test]
+	fn parses_linear_joint_center_formulas() {
+		let formula = json::from_value::<Formula>(json::json!({
+			"output": "l_thigh:/data/Daz%203D/Genesis%209/Base/Genesis9.dsf#l_thigh?center_point/y",
+			"operations": [
+				{ "op": "push", "url": "body_bs_Tone:#body_bs_Tone?value" },
+				{ "op": "push", "val": -0.5 },
+				{ "op": "mult" }
+			]
+		}))
+		.unwrap();
+
+		assert_eq!(
+			joint_formula("body_bs_Tone", &formula),
+			Some(("l_thigh", "center_point", 1, -0.5))
+		);
+		// Driven by some other modifier
+		assert_eq!(joint_formula("body_bs_Mass", &formula), None);
+
+		// Not a plain `value * constant`, so skipped with a warning
+		let squared = json::from_value::<Formula>(json::json!({
+			"output": "l_thigh:#l_thigh?end_point/y",
+			"operations": [
+				{ "op": "push", "url": "body_bs_Tone:#body_bs_Tone?value" },
+				{ "op": "push", "url": "body_bs_Tone:#body_bs_Tone?value" },
+				{ "op": "mult" }
+			]
+		}))
+		.unwrap();
+		assert_eq!(joint_formula("body_bs_Tone", &squared), None);
+	}
+
+	#[test]
+	fn drops_morphs_that_dont_fit_their_geometry() {
+		let morph = |id: &str, vertex_count: i64, idx: usize| {
+			json::from_value::<Modifier>(json::json!({
+				"id": id,
+				"parent": "/data/Triangle.dsf#Triangle",
+				"channel": { "id": "value", "type": "float", "name": id },
+				"morph": {
+					"vertex_count": vertex_count,
+					"deltas": { "count": 1, "values": [[idx, 0, 10, 0]] }
+				}
+			}))
+			.unwrap()
+		};
+		let meshes = HashMap::from([("Triangle".to_owned(), TempMeshData {
+			name: None,
+			mesh: Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all()),
+			vertex_count: 3,
+			triangles: TriangleMesh::default(),
+			joints: vec![],
+		})]);
+
+		let modifiers = process_modifiers(
+			vec![
+				morph("Fits", 3, 2),
+				morph("AnyGeometry", -1, 2),
+				morph("WrongCount", 4, 2),
+				morph("OutOfRange", 3, 3),
+			],
+			&meshes,
+		);
+
+		let has_morph = |id: &str| modifiers[id].morph.is_some();
+		assert!(has_morph("Fits"));
+		assert!(has_morph("AnyGeometry"));
+		assert!(!has_morph("WrongCount"));
+		assert!(!has_morph("OutOfRange"));
+	}
+
+	#[test]
+	fn maps_morph_targets_through_source_vertices() {
+		let morph = DazMorph {
+			geometry: "Grid".into(),
+			vertex_count: Some(4),
+			deltas: vec![(1, Vec3::X), (3, Vec3::Y)],
+		};
+		let morphs = [("Morph", &morph)];
+		// E.g. an LOD that kept vertices 0 and 3 of the geometry, with a copy of
+		// vertex 3 on the other side of a UV seam
+		let image = morph_target_image(&morphs, &[0, 3, 3]).unwrap();
+
+		let data = image
+			.data
+			.chunks_exact(4)
+			.map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+			.collect::<Vec<_>>();
+		// Position, normal and tangent deltas for each vertex
+		assert_eq!(data[..3], [0., 0., 0.]);
+		assert_eq!(data[9..12], [0., 1., 0.]);
+		assert_eq!(data[18..21], [0., 1., 0.]);
+	}
+
+	#[test]
+	fn builds_scenes_of_nodes_bones_and_skinned_meshes() {
+		let mut app = loader_app(&[("figure.dsf", FIGURE_DSF)]);
+		let handle = load(&mut app, "figure.dsf");
+
+		let scene = app
+			.world
+			.resource::<Assets<DazAsset>>()
+			.get(&handle)
+			.unwrap()
+			.scene
+			.clone();
+		let labeled = app
+			.world
+			.resource::<AssetServer>()
+			.get_handle::<Scene>("figure.dsf#Scene0");
+		assert_eq!(labeled, Some(scene.clone()));
+
+		let mut scenes = app.world.resource_mut::<Assets<Scene>>();
+		let world = &mut scenes.get_mut(&scene).unwrap().world;
+		let mut named = |name: &str| {
+			world
+				.query::<(Entity, &Name)>()
+				.iter(world)
+				.find(|(_, entity_name)| entity_name.as_str() == name)
+				.map(|(entity, _)| entity)
+				.unwrap()
+		};
+		let (figure, hip, thigh) = (named("Figure"), named("hip"), named("thigh"));
+
+		assert!(world.get::<DazBone>(figure).is_none());
+		assert!(world.get::<DazBone>(hip).is_some());
+		assert_eq!(world.get::<Parent>(hip).map(Parent::get), Some(figure));
+		assert_eq!(world.get::<Parent>(thigh).map(Parent::get), Some(hip));
+		assert_eq!(
+			world.get::<Transform>(thigh).unwrap().translation,
+			Vec3::Y * -0.5
+		);
+
+		// The root node has the skeleton of every bone beneath it
+		let skeleton = world.get::<DazSkeleton>(figure).unwrap();
+		assert_eq!(skeleton.bones.len(), 2);
+		assert_eq!(skeleton.bone("hip"), Some(hip));
+		assert_eq!(skeleton.bone("thigh"), Some(thigh));
+		assert!(world.get::<DazSkeleton>(hip).is_none());
+
+		// The mesh primitive is a child of its node, skinned to the bones
+		let (mesh, skinned_mesh) = world.query::<(Entity, &SkinnedMesh)>().single(world);
+		assert_eq!(world.get::<Parent>(mesh).map(Parent::get), Some(figure));
+		assert_eq!(skinned_mesh.joints, [hip, thigh]);
+	}
+}
@@ -1,10 +1,15 @@
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{
+	math::Affine3A, prelude::*, render::mesh::skinning::SkinnedMeshInverseBindposes, utils::HashMap,
+};
 use bevy_dqskinning::DqsStandardMaterial;
-use daz_asset_types::NodeType;
+use daz_asset_types::{ChannelFloat, NodeType};
 
+pub(crate) use self::loader::default_material;
 use self::loader::DazAssetLoader;
+pub use self::loader::{DazAssetLoaderSettings, LodSettings};
 
 mod loader;
+mod simplify;
 
 pub struct DazAssetTypesPlugin;
 
@@ -20,10 +25,19 @@ impl Plugin for DazAssetTypesPlugin {
 	}
 }
 
+/// Label of a [DazAsset]'s [Scene], e.g. `figure.dsf#Scene0`.
+pub const SCENE_LABEL: &str = "Scene0";
+
 #[derive(Asset, Clone, Debug, TypePath)]
 pub struct DazAsset {
+	/// The asset's node hierarchy, with its bones and mesh primitives, ready to
+	/// be spawned with a [SceneBundle]. Also loadable directly by its
+	/// [SCENE_LABEL].
+	pub scene: Handle<Scene>,
 	pub meshes: HashMap<String, Handle<DazMesh>>,
 	pub nodes: HashMap<String, Handle<DazNode>>,
+	/// The asset's modifiers with float channels, keyed by ID.
+	pub modifiers: HashMap<String, DazModifier>,
 	// TODO
 	pub materials: HashMap<String, Handle<StandardMaterial>>,
 	pub uv_sets: HashMap<String, DazUvSet>,
@@ -36,6 +50,8 @@ pub struct DazNode {
 	pub type_: NodeType,
 	pub mesh: Option<Handle<DazMesh>>,
 	pub root_transform: GlobalTransform,
+	/// Inverse of `root_transform`, i.e. the node's inverse bindpose.
+	pub inverse_bindpose: Affine3A,
 	pub transform: Transform,
 	pub parent: Option<String>,
 	pub children: Vec<DazNode>,
@@ -43,16 +59,153 @@ pub struct DazNode {
 	// TODO: Formulas, rotation limits?
 }
 
+impl DazAsset {
+	/// The morphs that apply to geometry `mesh_id`, with their clamped values,
+	/// from the modifiers of any of `assets`. Morphs are often loaded from
+	/// files of their own, separate from the figure whose geometry they morph.
+	///
+	/// `values` are keyed by modifier ID, name or label, like
+	/// [DazProperties](crate::DazProperties).
+	pub fn weighted_morphs<'a, 'k>(
+		assets: &[&'a DazAsset],
+		mesh_id: &str,
+		values: impl IntoIterator<Item = (&'k str, f32)>,
+	) -> Vec<(&'a DazMorph, f32)> {
+		let modifiers = || assets.iter().flat_map(|asset| asset.modifiers.values());
+
+		values
+			.into_iter()
+			.filter_map(|(key, value)| {
+				let modifier = assets
+					.iter()
+					.find_map(|asset| asset.modifiers.get(key))
+					.or_else(|| modifiers().find(|modifier| modifier.is_keyed_by(key)))?;
+				let morph = modifier.morph.as_ref()?;
+
+				(morph.geometry == mesh_id).then(|| (morph, modifier.clamp(value)))
+			})
+			.collect()
+	}
+}
+
+/// A modifier with a float channel, like a morph's "Body Tone" dial.
+#[derive(Clone, Debug)]
+pub struct DazModifier {
+	pub id: String,
+	pub label: Option<String>,
+	pub channel: ChannelFloat,
+	/// Joint center adjustments made by the modifier's formulas, e.g. for
+	/// shaping morphs.
+	pub joint_adjustments: Vec<JointAdjustment>,
+	pub morph: Option<DazMorph>,
+}
+
+/// Vertex offsets for a geometry, per unit of a modifier's value.
+#[derive(Clone, Debug)]
+pub struct DazMorph {
+	/// The ID of the geometry the morph applies to, i.e. its key in
+	/// [DazAsset::meshes] (possibly of another asset).
+	pub geometry: String,
+	/// The number of vertices the morph expects its geometry to have, if it
+	/// says.
+	pub vertex_count: Option<usize>,
+	/// Offsets in meters, keyed by vertex index.
+	pub deltas: Vec<(u32, Vec3)>,
+}
+
+impl DazMorph {
+	/// Whether the morph can be applied to a geometry with `vertex_count`
+	/// vertices, i.e. it expects that many and its deltas are in range.
+	pub fn fits(&self, vertex_count: usize) -> bool {
+		self.vertex_count.is_none_or(|count| count == vertex_count)
+			&& self
+				.deltas
+				.iter()
+				.all(|&(idx, _)| (idx as usize) < vertex_count)
+	}
+
+	/// The offset of each vertex of a mesh whose vertices are copies of
+	/// `source_vertices` of the geometry, like a [DazPrimitive]'s.
+	pub fn vertex_deltas(&self, source_vertices: &[u32]) -> Vec<Vec3> {
+		let deltas = self.deltas.iter().copied().collect::<HashMap<_, _>>();
+
+		source_vertices
+			.iter()
+			.map(|idx| deltas.get(idx).copied().unwrap_or_default())
+			.collect()
+	}
+}
+
+/// How far a modifier moves a bone's joint center and end point, in root space,
+/// per unit of the modifier's value.
+#[derive(Clone, Debug, Default)]
+pub struct JointAdjustment {
+	/// The bone's node ID.
+	pub node: String,
+	pub center_point: Vec3,
+	pub end_point: Vec3,
+}
+
+impl DazModifier {
+	/// Whether `key` is the modifier's ID, name or label.
+	pub fn is_keyed_by(&self, key: &str) -> bool {
+		self.id == key || self.channel.name == key || self.label.as_deref() == Some(key)
+	}
+
+	/// Clamps `value` to the channel's range, if the channel enforces it.
+	pub fn clamp(&self, value: f32) -> f32 {
+		if self.channel.clamped {
+			value.clamp(self.channel.min, self.channel.max)
+		} else {
+			value
+		}
+	}
+}
+
 #[derive(Asset, Clone, Debug, TypePath)]
 pub struct DazMesh {
 	pub primitives: Vec<DazPrimitive>,
 	pub joints: Vec<String>,
+	/// The inverse bindposes of `joints`, shared by every spawned instance of
+	/// the mesh. `None` if the mesh isn't skinned.
+	pub inverse_bindposes: Option<Handle<SkinnedMeshInverseBindposes>>,
+	/// The IDs of the modifiers whose morphs are the primitives' morph
+	/// targets, in order. Only morphs from the mesh's own file are included,
+	/// up to [MAX_MORPH_WEIGHTS].
+	///
+	/// [MAX_MORPH_WEIGHTS]: bevy::render::mesh::morph::MAX_MORPH_WEIGHTS
+	pub morph_targets: Vec<String>,
+	/// The number of vertices in the geometry, which morphs are checked against
+	/// with [DazMorph::fits]. Its primitives can have more, since they're split
+	/// along UV seams.
+	pub vertex_count: usize,
 }
 
 #[derive(Asset, Clone, Debug, TypePath)]
 pub struct DazPrimitive {
 	pub mesh: Handle<Mesh>,
 	pub material: Option<Handle<DqsStandardMaterial>>,
+	/// Simplified versions of `mesh`, from most to least detailed, as
+	/// configured by [LodSettings].
+	pub lods: Vec<DazLod>,
+	/// For each vertex of `mesh`, the index of the geometry vertex that it's a
+	/// copy of. Vertices along UV seams have a copy for each side. Used to apply
+	/// morphs, whose deltas are indexed by the latter.
+	pub source_vertices: Vec<u32>,
+}
+
+/// A simplified version of a [DazPrimitive]'s mesh.
+#[derive(Clone, Debug)]
+pub struct DazLod {
+	pub mesh: Handle<Mesh>,
+	/// The screen size below which this LOD is used. See [MeshLods].
+	///
+	/// [MeshLods]: crate::MeshLods
+	pub screen_size: f32,
+	/// For each vertex of `mesh`, the index of the vertex in the full mesh that
+	/// it's a copy of. Used to carry over other per-vertex data, like morph
+	/// target deltas.
+	pub source_vertices: Vec<u32>,
 }
 
 #[derive(Asset, Clone, Debug, TypePath)]
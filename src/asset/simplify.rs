@@ -0,0 +1,611 @@
+//! Mesh simplification for generating LODs.
+//!
+//! Triangles are removed by collapsing edges onto one of their existing
+//! vertices, in order of increasing quadric error (Garland & Heckbert). Since
+//! no new vertices are created, every vertex of a simplified mesh is a copy of
+//! one of the original's, along with its UVs, skin weights, DQS blend weight,
+//! etc. The original index of each vertex is returned alongside the mesh, for
+//! carrying over any other per-vertex data, like morph target deltas.
+//!
+//! Vertices on open edges are never moved, which keeps the silhouettes of
+//! clothing and other open surfaces intact, as well as any UV seams where
+//! vertices have been split.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use bevy::{
+	math::DVec3,
+	prelude::*,
+	render::{
+		mesh::{Indices, VertexAttributeValues},
+		render_resource::PrimitiveTopology,
+	},
+	utils::HashMap,
+};
+
+/// Simplifies `mesh` to roughly `ratio` of its triangles. Returns the
+/// simplified mesh and, for each of its vertices, the index of the vertex in
+/// `mesh` that it was copied from.
+///
+/// The result keeps `mesh`'s morph targets, which are indexed by its vertices,
+/// so they need replacing (see `generate_lods`).
+///
+/// Returns `None` for meshes that aren't indexed triangle lists, or that can't
+/// be simplified any further.
+pub(crate) fn simplify(mesh: &Mesh, ratio: f32) -> Option<(Mesh, Vec<u32>)> {
+	if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+		return None;
+	}
+	let positions = mesh
+		.attribute(Mesh::ATTRIBUTE_POSITION)?
+		.as_float3()?
+		.iter()
+		.map(|&p| Vec3::from(p).as_dvec3())
+		.collect::<Vec<_>>();
+	let faces = mesh
+		.indices()?
+		.iter()
+		.map(|idx| idx as u32)
+		.collect::<Vec<_>>()
+		.chunks_exact(3)
+		.map(|tri| [tri[0], tri[1], tri[2]])
+		.collect::<Vec<_>>();
+
+	let weights = skin_weights(mesh);
+	let target = ((faces.len() as f32 * ratio.clamp(0., 1.)).ceil() as usize).max(1);
+
+	let faces = Simplifier::new(positions, faces, weights).run(target)?;
+
+	// Compact the surviving vertices, keeping their original order
+	let mut remap = HashMap::<u32, u32>::default();
+	let mut source_vertices = faces.iter().flatten().copied().collect::<Vec<_>>();
+	source_vertices.sort_unstable();
+	source_vertices.dedup();
+	for (new_idx, &old_idx) in source_vertices.iter().enumerate() {
+		remap.insert(old_idx, new_idx as u32);
+	}
+
+	let mut result = mesh.clone();
+	for (_, values) in result.attributes_mut() {
+		*values = gather(values, &source_vertices);
+	}
+	result.insert_indices(Indices::U32(
+		faces.iter().flatten().map(|idx| remap[idx]).collect(),
+	));
+
+	Some((result, source_vertices))
+}
+
+/// Each vertex's joint weights, keyed by joint index.
+fn skin_weights(mesh: &Mesh) -> Option<Vec<[(u16, f32); 4]>> {
+	let Some(VertexAttributeValues::Uint16x4(joints)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_INDEX)
+	else {
+		return None;
+	};
+	let Some(VertexAttributeValues::Float32x4(weights)) =
+		mesh.attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT)
+	else {
+		return None;
+	};
+
+	Some(
+		joints
+			.iter()
+			.zip(weights)
+			.map(|(joints, weights)| std::array::from_fn(|idx| (joints[idx], weights[idx])))
+			.collect(),
+	)
+}
+
+/// Copies the values of `indices` out of `values`.
+fn gather(values: &VertexAttributeValues, indices: &[u32]) -> VertexAttributeValues {
+	macro_rules! gather {
+		($($variant:ident),* $(,)?) => {
+			match values {
+				$(VertexAttributeValues::$variant(values) => VertexAttributeValues::$variant(
+					indices.iter().map(|&idx| values[idx as usize]).collect(),
+				),)*
+			}
+		};
+	}
+
+	gather!(
+		Float32, Sint32, Uint32, Float32x2, Sint32x2, Uint32x2, Float32x3, Sint32x3, Uint32x3,
+		Float32x4, Sint32x4, Uint32x4, Sint16x2, Snorm16x2, Uint16x2, Unorm16x2, Sint16x4,
+		Snorm16x4, Uint16x4, Unorm16x4, Sint8x2, Snorm8x2, Uint8x2, Unorm8x2, Sint8x4, Snorm8x4,
+		Uint8x4, Unorm8x4,
+	)
+}
+
+/// Minimum cosine of the angle a face's normal may turn by in a collapse.
+const MAX_FLIP_COS: f64 = 0.2;
+
+struct Simplifier {
+	positions: Vec<DVec3>,
+	weights: Option<Vec<[(u16, f32); 4]>>,
+	quadrics: Vec<Quadric>,
+	faces: Vec<[u32; 3]>,
+	live_faces: Vec<bool>,
+	/// Faces around each vertex. May include dead faces.
+	vertex_faces: Vec<Vec<u32>>,
+	locked: Vec<bool>,
+	collapsed: Vec<bool>,
+}
+
+impl Simplifier {
+	fn new(
+		positions: Vec<DVec3>,
+		faces: Vec<[u32; 3]>,
+		weights: Option<Vec<[(u16, f32); 4]>>,
+	) -> Self {
+		let vertex_count = positions.len();
+		let mut quadrics = vec![Quadric::default(); vertex_count];
+		let mut vertex_faces = vec![vec![]; vertex_count];
+		let mut edge_faces = HashMap::<(u32, u32), u32>::default();
+		let mut live_faces = vec![true; faces.len()];
+
+		for (face_idx, face) in faces.iter().enumerate() {
+			if face.iter().any(|&idx| idx as usize >= vertex_count) {
+				live_faces[face_idx] = false;
+				continue;
+			}
+			let [p0, p1, p2] = face.map(|idx| positions[idx as usize]);
+			let normal = (p1 - p0).cross(p2 - p0);
+			let area = normal.length();
+			if area > f64::EPSILON {
+				let normal = normal / area;
+				let quadric = Quadric::from_plane(normal, -normal.dot(p0), area);
+				for &idx in face {
+					quadrics[idx as usize].add(&quadric);
+				}
+			}
+
+			for (idx, &vertex) in face.iter().enumerate() {
+				vertex_faces[vertex as usize].push(face_idx as u32);
+
+				let next = face[(idx + 1) % 3];
+				*edge_faces
+					.entry((vertex.min(next), vertex.max(next)))
+					.or_default() += 1;
+			}
+		}
+
+		// Open and non-manifold edges stay where they are
+		let mut locked = vec![false; vertex_count];
+		for (&(a, b), &count) in edge_faces.iter() {
+			if count != 2 {
+				locked[a as usize] = true;
+				locked[b as usize] = true;
+			}
+		}
+
+		Self {
+			positions,
+			weights,
+			quadrics,
+			faces,
+			live_faces,
+			vertex_faces,
+			locked,
+			collapsed: vec![false; vertex_count],
+		}
+	}
+
+	/// Collapses edges until at most `target` faces remain, or no more edges can
+	/// be collapsed. Returns the remaining faces, or `None` if nothing changed.
+	fn run(mut self, target: usize) -> Option<Vec<[u32; 3]>> {
+		let mut live_count = self.live_faces.iter().filter(|&&live| live).count();
+		let initial_count = live_count;
+
+		let mut heap = BinaryHeap::new();
+		for vertex in 0..self.positions.len() as u32 {
+			self.push_collapses(&mut heap, vertex);
+		}
+
+		while live_count > target {
+			let Some(Collapse { cost, from, to }) = heap.pop() else {
+				break;
+			};
+			if self.collapsed[from as usize] || self.collapsed[to as usize] {
+				continue;
+			}
+			let neighbors = self.neighbors(from);
+			if !neighbors.contains(&to) {
+				continue;
+			}
+			// Costs go stale as quadrics are merged
+			let current_cost = self.cost(from, to);
+			if current_cost > cost + f64::EPSILON {
+				heap.push(Collapse {
+					cost: current_cost,
+					from,
+					to,
+				});
+				continue;
+			}
+			if !self.can_collapse(from, to, &neighbors) {
+				continue;
+			}
+
+			live_count -= self.collapse(from, to);
+			self.push_collapses(&mut heap, to);
+			for vertex in self.neighbors(to) {
+				self.push_collapses(&mut heap, vertex);
+			}
+		}
+
+		if live_count == initial_count {
+			return None;
+		}
+
+		Some(
+			self.faces
+				.iter()
+				.zip(&self.live_faces)
+				.filter(|(_, &live)| live)
+				.map(|(face, _)| *face)
+				.collect(),
+		)
+	}
+
+	fn push_collapses(&self, heap: &mut BinaryHeap<Collapse>, from: u32) {
+		if self.locked[from as usize] || self.collapsed[from as usize] {
+			return;
+		}
+		for to in self.neighbors(from) {
+			heap.push(Collapse {
+				cost: self.cost(from, to),
+				from,
+				to,
+			});
+		}
+	}
+
+	fn live_faces_of(&self, vertex: u32) -> impl Iterator<Item = &[u32; 3]> + '_ {
+		self.vertex_faces[vertex as usize]
+			.iter()
+			.filter(|&&face| self.live_faces[face as usize])
+			.map(|&face| &self.faces[face as usize])
+	}
+
+	fn neighbors(&self, vertex: u32) -> Vec<u32> {
+		let mut result = self
+			.live_faces_of(vertex)
+			.flatten()
+			.copied()
+			.filter(|&other| other != vertex)
+			.collect::<Vec<_>>();
+		result.sort_unstable();
+		result.dedup();
+		result
+	}
+
+	/// The error of moving `from` onto `to`, plus a penalty for merging vertices
+	/// with different skin weights, scaled by the edge's length so it's
+	/// comparable with the geometric error.
+	fn cost(&self, from: u32, to: u32) -> f64 {
+		let (from, to) = (from as usize, to as usize);
+		let mut quadric = self.quadrics[from].clone();
+		quadric.add(&self.quadrics[to]);
+		let target = self.positions[to];
+		let error = quadric.error(target).max(0.);
+
+		let weight_penalty = self.weights.as_ref().map_or(0., |weights| {
+			weight_distance(&weights[from], &weights[to])
+				* self.positions[from].distance_squared(target)
+		});
+
+		error + weight_penalty
+	}
+
+	fn can_collapse(&self, from: u32, to: u32, from_neighbors: &[u32]) -> bool {
+		// Edges shared by more than two faces after the collapse would make the
+		// mesh non-manifold
+		let to_neighbors = self.neighbors(to);
+		let shared = from_neighbors
+			.iter()
+			.filter(|vertex| to_neighbors.binary_search(vertex).is_ok())
+			.count();
+		if shared > 2 {
+			return false;
+		}
+
+		let target = self.positions[to as usize];
+		self.live_faces_of(from)
+			.filter(|face| !face.contains(&to))
+			.all(|face| {
+				let [p0, p1, p2] = face.map(|idx| self.positions[idx as usize]);
+				let [q0, q1, q2] = face.map(|idx| {
+					if idx == from {
+						target
+					} else {
+						self.positions[idx as usize]
+					}
+				});
+				let before = (p1 - p0).cross(p2 - p0);
+				let after = (q1 - q0).cross(q2 - q0);
+				let after_len = after.length();
+
+				after_len > f64::EPSILON
+					&& before.dot(after) >= MAX_FLIP_COS * before.length() * after_len
+			})
+	}
+
+	/// Moves `from` onto `to`, returning the number of faces removed.
+	fn collapse(&mut self, from: u32, to: u32) -> usize {
+		let mut removed = 0;
+		let from_faces = std::mem::take(&mut self.vertex_faces[from as usize]);
+
+		for face_idx in from_faces {
+			if !self.live_faces[face_idx as usize] {
+				continue;
+			}
+			let face = &mut self.faces[face_idx as usize];
+			if face.contains(&to) {
+				self.live_faces[face_idx as usize] = false;
+				removed += 1;
+			} else {
+				for vertex in face.iter_mut().filter(|vertex| **vertex == from) {
+					*vertex = to;
+				}
+				self.vertex_faces[to as usize].push(face_idx);
+			}
+		}
+
+		let quadric = self.quadrics[from as usize].clone();
+		self.quadrics[to as usize].add(&quadric);
+		self.collapsed[from as usize] = true;
+
+		removed
+	}
+}
+
+/// Half the L1 distance between two sets of joint weights, from 0 (identical)
+/// to 1 (no joints in common).
+fn weight_distance(a: &[(u16, f32); 4], b: &[(u16, f32); 4]) -> f64 {
+	let weight_of = |weights: &[(u16, f32); 4], joint: u16| -> f32 {
+		weights
+			.iter()
+			.filter(|(other, _)| *other == joint)
+			.map(|(_, weight)| weight)
+			.sum()
+	};
+
+	let mut distance = 0.;
+	for &(joint, weight) in a.iter().filter(|(_, weight)| *weight > 0.) {
+		distance += (weight - weight_of(b, joint)).abs();
+	}
+	for &(joint, weight) in b.iter().filter(|(_, weight)| *weight > 0.) {
+		if weight_of(a, joint) == 0. {
+			distance += weight;
+		}
+	}
+
+	distance as f64 * 0.5
+}
+
+#[derive(PartialEq)]
+struct Collapse {
+	cost: f64,
+	from: u32,
+	to: u32,
+}
+
+impl Eq for Collapse {}
+
+impl Ord for Collapse {
+	fn cmp(&self, other: &Self) -> Ordering {
+		// Reversed, so the heap pops the cheapest collapse first
+		other.cost.total_cmp(&self.cost)
+	}
+}
+
+impl PartialOrd for Collapse {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+/// A symmetric 4x4 matrix measuring squared distance to a set of planes.
+#[derive(Clone, Debug, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+	fn from_plane(normal: DVec3, d: f64, weight: f64) -> Self {
+		let DVec3 { x: a, y: b, z: c } = normal;
+		Self(
+			[
+				a * a,
+				a * b,
+				a * c,
+				a * d,
+				b * b,
+				b * c,
+				b * d,
+				c * c,
+				c * d,
+				d * d,
+			]
+			.map(|value| value * weight),
+		)
+	}
+
+	fn add(&mut self, other: &Self) {
+		for (value, other) in self.0.iter_mut().zip(other.0) {
+			*value += other;
+		}
+	}
+
+	fn error(&self, p: DVec3) -> f64 {
+		let [a2, ab, ac, ad, b2, bc, bd, c2, cd, d2] = self.0;
+		let DVec3 { x, y, z } = p;
+
+		a2 * x * x
+			+ 2. * ab * x * y
+			+ 2. * ac * x * z
+			+ 2. * ad * x
+			+ b2 * y * y
+			+ 2. * bc * y * z
+			+ 2. * bd * y
+			+ c2 * z * z
+			+ 2. * cd * z
+			+ d2
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bevy::render::{
+		mesh::{Indices, Mesh, VertexAttributeValues},
+		render_asset::RenderAssetUsages,
+		render_resource::PrimitiveTopology,
+	};
+
+	use super::simplify;
+
+	#[test]
+	fn simplifies_interior_and_keeps_vertex_data() {
+		// A flat 9x9-vertex grid, so every interior vertex can be collapsed
+		const SIZE: u32 = 9;
+		let positions = (0..SIZE * SIZE)
+			.map(|idx| [(idx % SIZE) as f32, 0., (idx / SIZE) as f32])
+			.collect::<Vec<_>>();
+		let uvs = positions
+			.iter()
+			.map(|p| [p[0] / SIZE as f32, p[2] / SIZE as f32])
+			.collect::<Vec<_>>();
+		let mut indices = vec![];
+		for row in 0..SIZE - 1 {
+			for column in 0..SIZE - 1 {
+				let i0 = row * SIZE + column;
+				let (i1, i2, i3) = (i0 + SIZE, i0 + SIZE + 1, i0 + 1);
+				indices.extend([i0, i1, i2, i0, i2, i3]);
+			}
+		}
+		let face_count = indices.len() / 3;
+
+		let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
+		mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions.clone());
+		mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs.clone());
+		mesh.insert_indices(Indices::U32(indices));
+
+		let (lod, source_vertices) = simplify(&mesh, 0.25).unwrap();
+
+		assert!(lod.indices().unwrap().len() / 3 < face_count);
+		assert_eq!(lod.count_vertices(), source_vertices.len());
+
+		// Every vertex is an untouched copy of one of the original's
+		let lod_positions = lod
+			.attribute(Mesh::ATTRIBUTE_POSITION)
+			.unwrap()
+			.as_float3()
+			.unwrap();
+		for (lod_idx, &src_idx) in source_vertices.iter().enumerate() {
+			assert_eq!(lod_positions[lod_idx], positions[src_idx as usize]);
+		}
+
+		// The grid's border is left intact
+		for idx in 0..SIZE * SIZE {
+			let (row, column) = (idx / SIZE, idx % SIZE);
+			if row == 0 || column == 0 || row == SIZE - 1 || column == SIZE - 1 {
+				assert!(source_vertices.contains(&idx));
+			}
+		}
+	}
+
+	#[test]
+	fn keeps_uv_seams_and_skin_weights() {
+		// A 9x9-vertex grid whose middle column is split into two copies with
+		// different UVs, with each half bound to its own joint
+		const SIZE: u32 = 9;
+		const SEAM: u32 = SIZE / 2;
+		let seam_copy = |row: u32| SIZE * SIZE + row;
+
+		let mut positions = vec![];
+		let mut uvs = vec![];
+		let mut joints = vec![];
+		for idx in 0..SIZE * SIZE {
+			let (row, column) = (idx / SIZE, idx % SIZE);
+			let right = column > SEAM;
+			positions.push([column as f32, 0., row as f32]);
+			uvs.push([
+				column as f32 / SIZE as f32 + right as u8 as f32,
+				row as f32 / SIZE as f32,
+			]);
+			joints.push([right as u16, 0, 0, 0]);
+		}
+		for row in 0..SIZE {
+			positions.push([SEAM as f32, 0., row as f32]);
+			uvs.push([SEAM as f32 / SIZE as f32 + 1., row as f32 / SIZE as f32]);
+			joints.push([1, 0, 0, 0]);
+		}
+		let vertex = |row: u32, column: u32, right: bool| {
+			if column == SEAM && right {
+				seam_copy(row)
+			} else {
+				row * SIZE + column
+			}
+		};
+		let mut indices = vec![];
+		for row in 0..SIZE - 1 {
+			for column in 0..SIZE - 1 {
+				let right = column >= SEAM;
+				let [i0, i1, i2, i3] = [(0, 0), (1, 0), (1, 1), (0, 1)]
+					.map(|(dr, dc)| vertex(row + dr, column + dc, right));
+				indices.extend([i0, i1, i2, i0, i2, i3]);
+			}
+		}
+
+		let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
+		mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions.clone());
+		mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs.clone());
+		mesh.insert_attribute(
+			Mesh::ATTRIBUTE_JOINT_INDEX,
+			VertexAttributeValues::Uint16x4(joints.clone()),
+		);
+		mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, vec![
+			[1., 0., 0., 0.];
+			positions.len()
+		]);
+		mesh.insert_indices(Indices::U32(indices.clone()));
+
+		let (lod, source_vertices) = simplify(&mesh, 0.25).unwrap();
+		assert!(lod.indices().unwrap().len() < indices.len());
+
+		// Both copies of every seam vertex survive
+		for row in 0..SIZE {
+			assert!(source_vertices.contains(&(row * SIZE + SEAM)));
+			assert!(source_vertices.contains(&seam_copy(row)));
+		}
+
+		// Vertices keep their own UVs and joints
+		let Some(VertexAttributeValues::Float32x2(lod_uvs)) = lod.attribute(Mesh::ATTRIBUTE_UV_0)
+		else {
+			panic!("UVs changed format");
+		};
+		let Some(VertexAttributeValues::Uint16x4(lod_joints)) =
+			lod.attribute(Mesh::ATTRIBUTE_JOINT_INDEX)
+		else {
+			panic!("Joint indices changed format");
+		};
+		for (lod_idx, &src_idx) in source_vertices.iter().enumerate() {
+			assert_eq!(lod_uvs[lod_idx], uvs[src_idx as usize]);
+			assert_eq!(lod_joints[lod_idx], joints[src_idx as usize]);
+		}
+
+		// No triangle was collapsed across the seam onto the other joint
+		for tri in lod
+			.indices()
+			.unwrap()
+			.iter()
+			.collect::<Vec<_>>()
+			.chunks_exact(3)
+		{
+			let tri_joints = tri
+				.iter()
+				.map(|&idx| lod_joints[idx][0])
+				.collect::<Vec<_>>();
+			assert!(tri_joints.iter().all(|&joint| joint == tri_joints[0]));
+		}
+	}
+}
@@ -0,0 +1,260 @@
+//! Baking morphs and poses into static meshes, e.g. for background characters
+//! that don't need to be skinned or morphed at runtime.
+
+use bevy::{
+	prelude::*,
+	render::mesh::{MeshVertexAttribute, VertexAttributeValues},
+};
+use bevy_dqskinning::ATTRIBUTE_DQS_BLEND;
+
+use crate::{CpuSkin, DazMorph, SkinningMethod};
+
+/// Attributes carried over from the source mesh.
+const BAKED_ATTRIBUTES: [MeshVertexAttribute; 4] = [
+	Mesh::ATTRIBUTE_UV_0,
+	Mesh::ATTRIBUTE_UV_1,
+	Mesh::ATTRIBUTE_COLOR,
+	Mesh::ATTRIBUTE_TANGENT,
+];
+
+/// Attributes only carried over by unposed bakes, which stay skinned.
+const SKINNING_ATTRIBUTES: [MeshVertexAttribute; 3] = [
+	Mesh::ATTRIBUTE_JOINT_INDEX,
+	Mesh::ATTRIBUTE_JOINT_WEIGHT,
+	ATTRIBUTE_DQS_BLEND,
+];
+
+/// Bakes weighted morphs, and optionally a pose, into a copy of `mesh`.
+///
+/// Morphs are applied before skinning, like they are on the GPU, and normals
+/// and tangents are regenerated if any of them move a vertex. Collect the
+/// morphs with [DazAsset::weighted_morphs]. Their deltas are indexed by the
+/// vertices of the geometry, so `source_vertices` are the [DazPrimitive]'s
+/// and `vertex_count` the [DazMesh]'s, and morphs that don't fit the latter are
+/// skipped with a warning.
+///
+/// Without a `pose`, the result is still bound to the same joints, with the
+/// morphs applied to its bind pose. With one, the result is deformed by the
+/// [CpuSkin] and has no skinning attributes. [CpuSkin]s created from the joints'
+/// [GlobalTransform]s produce world-space positions; to keep the result
+/// relative to the figure, multiply each joint matrix by the inverse of the
+/// figure's transform first.
+///
+/// Returns `None` if `mesh` has no positions, `source_vertices` don't match
+/// them, or it can't be skinned by `pose`.
+///
+/// [DazAsset::weighted_morphs]: crate::DazAsset::weighted_morphs
+/// [DazPrimitive]: crate::DazPrimitive
+/// [DazMesh]: crate::DazMesh
+pub fn bake_mesh(
+	mesh: &Mesh,
+	source_vertices: &[u32],
+	vertex_count: usize,
+	morphs: &[(&DazMorph, f32)],
+	pose: Option<(&CpuSkin, SkinningMethod)>,
+) -> Option<Mesh> {
+	let Some(VertexAttributeValues::Float32x3(positions)) =
+		mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+	else {
+		return None;
+	};
+	if source_vertices.len() != positions.len() {
+		return None;
+	}
+
+	let mut positions = positions
+		.iter()
+		.copied()
+		.map(Vec3::from)
+		.collect::<Vec<_>>();
+	let mut morphed = false;
+	for &(morph, weight) in morphs.iter().filter(|(_, weight)| *weight != 0.) {
+		if !morph.fits(vertex_count) {
+			warn!(
+				"Skipping morph of geometry '{}', which doesn't fit a geometry with \
+				 {vertex_count} vertices",
+				morph.geometry,
+			);
+			continue;
+		}
+		for (position, delta) in positions
+			.iter_mut()
+			.zip(morph.vertex_deltas(source_vertices))
+		{
+			*position += delta * weight;
+		}
+		morphed |= !morph.deltas.is_empty();
+	}
+
+	let mut result = Mesh::new(mesh.primitive_topology(), mesh.asset_usage);
+	if let Some(indices) = mesh.indices() {
+		result.insert_indices(indices.clone());
+	}
+	for attribute in BAKED_ATTRIBUTES
+		.into_iter()
+		.chain(SKINNING_ATTRIBUTES)
+		.filter(|attribute| attribute.id != Mesh::ATTRIBUTE_TANGENT.id || !morphed)
+	{
+		if let Some(values) = mesh.attribute(attribute.id) {
+			result.insert_attribute(attribute, values.clone());
+		}
+	}
+
+	match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+		Some(normals) if !morphed => {
+			result.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals.clone());
+		}
+		_ => {
+			if let Some(indices) = mesh.indices() {
+				let normals = smooth_normals(&positions, indices.iter(), source_vertices);
+				result.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+			}
+		}
+	}
+	result.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+
+	if let Some((skin, method)) = pose {
+		let deformed = skin.deform_mesh(&result, method)?;
+		result.insert_attribute(Mesh::ATTRIBUTE_POSITION, deformed.positions);
+		if !deformed.normals.is_empty() {
+			result.insert_attribute(Mesh::ATTRIBUTE_NORMAL, deformed.normals);
+		}
+		if !deformed.tangents.is_empty() {
+			result.insert_attribute(Mesh::ATTRIBUTE_TANGENT, deformed.tangents);
+		}
+		for attribute in SKINNING_ATTRIBUTES {
+			result.remove_attribute(attribute);
+		}
+	}
+
+	if morphed && result.contains_attribute(Mesh::ATTRIBUTE_UV_0) {
+		if let Err(err) = result.generate_tangents() {
+			warn!("Failed to generate tangents for baked mesh: {err}");
+		}
+	}
+
+	Some(result)
+}
+
+/// Area-weighted vertex normals of an indexed triangle list. They're summed
+/// per geometry vertex, so that the copies of a vertex along a UV seam share a
+/// normal and the seam doesn't crease, like the loader's.
+fn smooth_normals(
+	positions: &[Vec3],
+	indices: impl Iterator<Item = usize>,
+	source_vertices: &[u32],
+) -> Vec<Vec3> {
+	let geometry_vertex_count = source_vertices
+		.iter()
+		.map(|&idx| idx as usize + 1)
+		.max()
+		.unwrap_or_default();
+	let mut normals = vec![Vec3::ZERO; geometry_vertex_count];
+	let indices = indices.collect::<Vec<_>>();
+
+	for tri in indices.chunks_exact(3) {
+		let [i0, i1, i2] = [tri[0], tri[1], tri[2]];
+		let (Some(&p0), Some(&p1), Some(&p2)) =
+			(positions.get(i0), positions.get(i1), positions.get(i2))
+		else {
+			continue;
+		};
+		let normal = (p1 - p0).cross(p2 - p0);
+		for idx in [i0, i1, i2] {
+			normals[source_vertices[idx] as usize] += normal;
+		}
+	}
+
+	source_vertices
+		.iter()
+		.map(|&idx| normals[idx as usize].normalize_or_zero())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use bevy::{
+		prelude::*,
+		render::{
+			mesh::{Indices, VertexAttributeValues},
+			render_asset::RenderAssetUsages,
+			render_resource::PrimitiveTopology,
+		},
+	};
+
+	use super::bake_mesh;
+	use crate::{CpuSkin, DazMorph, SkinningMethod};
+
+	#[test]
+	fn bakes_morphs_before_pose() {
+		let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
+		mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![
+			[0., 0., 0.],
+			[1., 0., 0.],
+			[0., 1., 0.],
+		]);
+		mesh.insert_attribute(
+			Mesh::ATTRIBUTE_JOINT_INDEX,
+			VertexAttributeValues::Uint16x4(vec![[0; 4]; 3]),
+		);
+		mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, vec![[1., 0., 0., 0.]; 3]);
+		mesh.insert_indices(Indices::U32(vec![0, 1, 2]));
+
+		let morph = DazMorph {
+			geometry: "Triangle".into(),
+			vertex_count: Some(3),
+			deltas: vec![(2, Vec3::Y)],
+		};
+		let skin = CpuSkin::new([Mat4::from_translation(Vec3::X)]);
+
+		let unposed = bake_mesh(&mesh, &[0, 1, 2], 3, &[(&morph, 0.5)], None).unwrap();
+		assert!(unposed.contains_attribute(Mesh::ATTRIBUTE_JOINT_INDEX));
+
+		let posed = bake_mesh(
+			&mesh,
+			&[0, 1, 2],
+			3,
+			&[(&morph, 0.5)],
+			Some((&skin, SkinningMethod::DualQuaternion)),
+		)
+		.unwrap();
+		assert!(!posed.contains_attribute(Mesh::ATTRIBUTE_JOINT_INDEX));
+
+		let positions = posed
+			.attribute(Mesh::ATTRIBUTE_POSITION)
+			.unwrap()
+			.as_float3()
+			.unwrap();
+		assert!(Vec3::from(positions[2]).abs_diff_eq(Vec3::new(1., 1.5, 0.), 1e-5));
+	}
+
+	#[test]
+	fn shares_regenerated_normals_between_seam_copies() {
+		let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
+		// Vertex 4 is a copy of vertex 0, on the other side of a UV seam
+		mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![
+			[0., 0., 0.],
+			[1., 0., 0.],
+			[0., 1., 0.],
+			[0., 0., 1.],
+			[0., 0., 0.],
+		]);
+		mesh.insert_indices(Indices::U32(vec![0, 1, 2, 4, 3, 1]));
+
+		let morph = DazMorph {
+			geometry: "Fold".into(),
+			vertex_count: Some(4),
+			deltas: vec![(2, Vec3::Y)],
+		};
+		let baked = bake_mesh(&mesh, &[0, 1, 2, 3, 0], 4, &[(&morph, 0.5)], None).unwrap();
+
+		let normals = baked
+			.attribute(Mesh::ATTRIBUTE_NORMAL)
+			.unwrap()
+			.as_float3()
+			.unwrap();
+		let expected = Vec3::new(0., 1., 1.5).normalize();
+		assert!(Vec3::from(normals[0]).abs_diff_eq(expected, 1e-5));
+		assert!(Vec3::from(normals[4]).abs_diff_eq(expected, 1e-5));
+	}
+}
@@ -0,0 +1,416 @@
+//! Cheaper animation for crowds of figures.
+//!
+//! Figures loaded from the same [DazAsset](crate::DazAsset) already share their
+//! meshes and materials. [SkeletonLod] animates distant figures less often, and
+//! [SharedSkeleton] poses a figure with another one's skeleton, so both are
+//! drawn from a single joint palette on the GPU.
+
+use bevy::{
+	animation::animation_player,
+	core::FrameCount,
+	ecs::{
+		entity::{EntityMapper, MapEntities},
+		reflect::ReflectMapEntities,
+	},
+	prelude::*,
+	render::mesh::skinning::SkinnedMesh,
+	utils::HashMap,
+};
+use bevy_dqskinning::DqsSharedPalette;
+
+use crate::{DazAssetSpawned, DazReady, DazSpawningSet};
+
+pub struct DazCrowdPlugin;
+
+impl Plugin for DazCrowdPlugin {
+	fn build(&self, app: &mut App) {
+		app.register_type::<SkeletonLod>()
+			.register_type::<SkeletonLodSettings>()
+			.register_type::<SharedSkeleton>()
+			.init_resource::<SkeletonLodSettings>()
+			.add_event::<DazAssetSpawned>();
+
+		app.add_systems(Update, share_skeletons.after(DazSpawningSet));
+		app.add_systems(
+			PostUpdate,
+			(
+				(update_skeleton_lods, throttle_skeleton_animations)
+					.chain()
+					.before(animation_player),
+				restore_skeleton_animation_speeds.after(animation_player),
+			),
+		);
+	}
+}
+
+/// Distance bands for [SkeletonLod].
+#[derive(Resource, Clone, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct SkeletonLodSettings {
+	/// `(distance, interval)` pairs, sorted by distance. Figures at least
+	/// `distance` away from the nearest active camera are animated once every
+	/// `interval` frames. Closer figures are animated every frame.
+	pub levels: Vec<(f32, u32)>,
+}
+
+impl Default for SkeletonLodSettings {
+	fn default() -> Self {
+		Self {
+			levels: vec![(10., 2), (25., 4), (50., 8)],
+		}
+	}
+}
+
+/// Animates a figure's skeleton less often the further it is from the camera,
+/// for large crowds. Insert this alongside the figure's [AnimationPlayer].
+///
+/// Bevy evaluates every [AnimationPlayer] each frame, even paused ones, so on
+/// skipped frames the player is parked here and swapped for an empty one. On
+/// the next update, it's put back and catches up on the skipped time. Paused
+/// players are left alone.
+///
+/// While a player is parked, change it with
+/// [SkeletonLod::throttled_player_mut]. Playing a clip on the empty player
+/// replaces the parked one, and removing the [SkeletonLod] from a throttled
+/// figure drops it.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct SkeletonLod {
+	/// The current update interval in frames, from [SkeletonLodSettings].
+	pub interval: u32,
+	/// Time skipped since the last update.
+	#[reflect(ignore)]
+	skipped: f32,
+	/// The player's speed before it was raised to catch up.
+	#[reflect(ignore)]
+	restore_speed: Option<f32>,
+	/// The figure's player, on the frames it isn't animated.
+	#[reflect(ignore)]
+	parked: Option<AnimationPlayer>,
+}
+
+impl SkeletonLod {
+	/// Whether the figure isn't animated this frame.
+	pub fn is_throttled(&self) -> bool {
+		self.parked.is_some()
+	}
+
+	/// The figure's [AnimationPlayer] while it isn't animated. Changes made to
+	/// it are kept when it's put back.
+	pub fn throttled_player_mut(&mut self) -> Option<&mut AnimationPlayer> {
+		self.parked.as_mut()
+	}
+}
+
+impl std::fmt::Debug for SkeletonLod {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("SkeletonLod")
+			.field("interval", &self.interval)
+			.field("skipped", &self.skipped)
+			.field("restore_speed", &self.restore_speed)
+			.field("throttled", &self.is_throttled())
+			.finish()
+	}
+}
+
+fn update_skeleton_lods(
+	r_settings: Res<SkeletonLodSettings>,
+	q_cameras: Query<(&Camera, &GlobalTransform)>,
+	mut q_lods: Query<(&GlobalTransform, &mut SkeletonLod)>,
+) {
+	q_lods.par_iter_mut().for_each(|(xform, mut lod)| {
+		let distance = q_cameras
+			.iter()
+			.filter(|(camera, _)| camera.is_active)
+			.map(|(_, camera_xform)| camera_xform.translation().distance(xform.translation()))
+			.min_by(f32::total_cmp)
+			.unwrap_or_default();
+
+		let interval = r_settings
+			.levels
+			.iter()
+			.rev()
+			.find(|&&(min_distance, _)| distance >= min_distance)
+			.map_or(1, |&(_, interval)| interval.max(1));
+
+		if lod.interval != interval {
+			lod.interval = interval;
+		}
+	});
+}
+
+fn throttle_skeleton_animations(
+	r_time: Res<Time>,
+	r_frame: Res<FrameCount>,
+	mut q_players: Query<(Entity, &mut SkeletonLod, &mut AnimationPlayer)>,
+) {
+	let dt = r_time.delta_seconds();
+
+	for (entity, mut lod, mut player) in q_players.iter_mut() {
+		// Staggered, so figures with the same interval don't all update at once
+		let is_due =
+			lod.interval <= 1 || r_frame.0.wrapping_add(entity.index()) % lod.interval == 0;
+		let lod = lod.bypass_change_detection();
+
+		let Some(parked) = lod.parked.take() else {
+			if !is_due && !player.is_paused() {
+				lod.skipped = dt;
+				lod.parked = Some(std::mem::take(player.bypass_change_detection()));
+			}
+			continue;
+		};
+
+		// Replaced by other code in the meantime
+		if *player.animation_clip() != Handle::default() {
+			lod.skipped = 0.;
+			continue;
+		}
+
+		if !is_due {
+			lod.skipped += dt;
+			lod.parked = Some(parked);
+			continue;
+		}
+
+		let player = player.bypass_change_detection();
+		*player = parked;
+		if dt > 0. && lod.skipped > 0. && !player.is_paused() {
+			let speed = player.speed();
+			player.set_speed(speed * (lod.skipped + dt) / dt);
+			lod.restore_speed = Some(speed);
+		}
+		lod.skipped = 0.;
+	}
+}
+
+fn restore_skeleton_animation_speeds(
+	mut q_players: Query<(&mut SkeletonLod, &mut AnimationPlayer)>,
+) {
+	for (mut lod, mut player) in q_players.iter_mut() {
+		if let Some(speed) = lod.bypass_change_detection().restore_speed.take() {
+			player.bypass_change_detection().set_speed(speed);
+		}
+	}
+}
+
+/// Poses a spawned [DazAsset](crate::DazAsset) with the skeleton of another
+/// instance of the same asset, on the GPU. Insert this on the
+/// `Handle<DazAsset>` entity, with the other instance's `Handle<DazAsset>`
+/// entity.
+///
+/// Once both are [DazReady], each skinned mesh of this instance is bound to
+/// the joints of the other instance's mesh with the same `Handle<Mesh>`, and
+/// both are given a [DqsSharedPalette] rooted at the other instance's mesh. The
+/// meshes are then drawn where their own transforms place them, posed by the
+/// other instance's joints, and those without morphs are drawn instanced. This
+/// only works for meshes using a [DqsMaterial](crate::DqsMaterial); others are
+/// drawn on top of the other instance.
+///
+/// This instance's own bones are left in place, but no longer move its meshes,
+/// so it doesn't need an [AnimationPlayer]. Removing this component doesn't
+/// bind the meshes back to them.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct SharedSkeleton(pub Entity);
+
+impl FromWorld for SharedSkeleton {
+	fn from_world(_: &mut World) -> Self {
+		Self(Entity::PLACEHOLDER)
+	}
+}
+
+impl MapEntities for SharedSkeleton {
+	fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+		self.0 = entity_mapper.map_entity(self.0);
+	}
+}
+
+/// Binds the meshes of [SharedSkeleton] instances to the other instance's
+/// joints. Rechecked whenever an asset is spawned, so fitted assets spawned
+/// later are bound too.
+fn share_skeletons(
+	mut cmd: Commands,
+	mut r_spawned: EventReader<DazAssetSpawned>,
+	q_shared: Query<(Entity, Ref<SharedSkeleton>)>,
+	q_ready: Query<(), With<DazReady>>,
+	q_children: Query<&Children>,
+	q_meshes: Query<(&Handle<Mesh>, &SkinnedMesh, Option<&DqsSharedPalette>)>,
+) {
+	let is_any_spawned = r_spawned.read().count() > 0;
+
+	for (entity, shared) in q_shared.iter() {
+		let SharedSkeleton(source) = *shared;
+		if !is_any_spawned && !shared.is_changed() {
+			continue;
+		}
+		if !q_ready.contains(entity) || !q_ready.contains(source) {
+			continue;
+		}
+
+		let mut source_meshes = HashMap::new();
+		for desc in q_children.iter_descendants(source) {
+			if let Ok((mesh, skinned_mesh, _)) = q_meshes.get(desc) {
+				source_meshes
+					.entry(mesh.id())
+					.or_insert((desc, skinned_mesh));
+			}
+		}
+
+		for desc in q_children.iter_descendants(entity) {
+			let Ok((mesh, _, current)) = q_meshes.get(desc) else {
+				continue;
+			};
+			let Some(&(root, skinned_mesh)) = source_meshes.get(&mesh.id()) else {
+				continue;
+			};
+			let palette = DqsSharedPalette { root };
+			if desc == root || current == Some(&palette) {
+				continue;
+			}
+
+			cmd.entity(desc).insert((
+				SkinnedMesh {
+					inverse_bindposes: skinned_mesh.inverse_bindposes.clone(),
+					joints: skinned_mesh.joints.clone(),
+				},
+				palette,
+			));
+			cmd.entity(root).insert(palette);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use bevy::{
+		animation::{Interpolation, Keyframes, VariableCurve},
+		asset::AssetPlugin,
+		prelude::*,
+		render::mesh::skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+		time::TimeUpdateStrategy,
+	};
+	use bevy_dqskinning::DqsSharedPalette;
+
+	use super::{DazCrowdPlugin, SharedSkeleton, SkeletonLod, SkeletonLodSettings};
+	use crate::DazReady;
+
+	#[test]
+	fn throttled_animations_catch_up() {
+		let mut app = App::new();
+		app.add_plugins((
+			MinimalPlugins,
+			AssetPlugin::default(),
+			AnimationPlugin,
+			DazCrowdPlugin,
+		))
+		.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+			100,
+		)))
+		.insert_resource(SkeletonLodSettings {
+			levels: vec![(0., 4)],
+		});
+
+		// Moves along X at one unit per second
+		let name = Name::new("Root");
+		let mut clip = AnimationClip::default();
+		clip.add_curve_to_path(
+			EntityPath {
+				parts: vec![name.clone()],
+			},
+			VariableCurve {
+				keyframe_timestamps: vec![0., 100.],
+				keyframes: Keyframes::Translation(vec![Vec3::ZERO, Vec3::X * 100.]),
+				interpolation: Interpolation::Linear,
+			},
+		);
+		let clip = app.world.resource_mut::<Assets<AnimationClip>>().add(clip);
+
+		let mut player = AnimationPlayer::default();
+		player.play(clip);
+		let figure = app
+			.world
+			.spawn((
+				name,
+				SpatialBundle::default(),
+				player,
+				SkeletonLod::default(),
+			))
+			.id();
+
+		let mut updates = 0;
+		let mut skips = 0;
+		let mut last_x = 0.;
+		for _ in 0..16 {
+			app.update();
+
+			let x = app.world.get::<Transform>(figure).unwrap().translation.x;
+			if app.world.get::<SkeletonLod>(figure).unwrap().is_throttled() {
+				assert_eq!(x, last_x, "skipped frames aren't evaluated");
+				skips += 1;
+			} else {
+				let player = app.world.get::<AnimationPlayer>(figure).unwrap();
+				assert_eq!(player.speed(), 1., "speed is restored after catching up");
+			}
+			if x != last_x {
+				// Caught up on the skipped time
+				let elapsed = app.world.resource::<Time>().elapsed_seconds();
+				assert!((x - elapsed).abs() < 1e-3, "{x} != {elapsed}");
+				updates += 1;
+			}
+			last_x = x;
+		}
+
+		assert!(updates >= 3, "animated {updates} times");
+		assert!(skips >= 9, "skipped {skips} frames");
+	}
+
+	#[test]
+	fn shared_skeletons_bind_meshes_to_one_palette() {
+		let mut app = App::new();
+		app.add_plugins((MinimalPlugins, AssetPlugin::default(), DazCrowdPlugin));
+
+		let body = Handle::<Mesh>::weak_from_u128(1);
+		let hair = Handle::<Mesh>::weak_from_u128(2);
+		let bindposes = Handle::<SkinnedMeshInverseBindposes>::weak_from_u128(3);
+		let mut spawn_figure = |meshes: &[&Handle<Mesh>]| {
+			let joint = app.world.spawn(TransformBundle::default()).id();
+			let meshes = meshes
+				.iter()
+				.map(|&mesh| {
+					app.world
+						.spawn((mesh.clone(), SkinnedMesh {
+							inverse_bindposes: bindposes.clone(),
+							joints: vec![joint],
+						}))
+						.id()
+				})
+				.collect::<Vec<_>>();
+			let figure = app.world.spawn(DazReady).push_children(&meshes).id();
+			(figure, joint, meshes)
+		};
+
+		let (source, source_joint, source_meshes) = spawn_figure(&[&body, &hair]);
+		let (figure, figure_joint, figure_meshes) = spawn_figure(&[&body]);
+		app.world.entity_mut(figure).insert(SharedSkeleton(source));
+		app.update();
+
+		let skinned_mesh = app.world.get::<SkinnedMesh>(figure_meshes[0]).unwrap();
+		assert_eq!(skinned_mesh.joints, vec![source_joint]);
+		assert_ne!(source_joint, figure_joint);
+		let palette = DqsSharedPalette {
+			root: source_meshes[0],
+		};
+		assert_eq!(
+			app.world.get::<DqsSharedPalette>(figure_meshes[0]),
+			Some(&palette)
+		);
+		assert_eq!(
+			app.world.get::<DqsSharedPalette>(source_meshes[0]),
+			Some(&palette)
+		);
+		// Meshes the figure doesn't have are left alone
+		assert_eq!(app.world.get::<DqsSharedPalette>(source_meshes[1]), None);
+	}
+}
@@ -0,0 +1,691 @@
+//! Export of loaded [DazAsset]s to glTF 2.0, for use in other tools and engines.
+
+use std::{io::Cursor, path::Path};
+
+use anyhow::anyhow;
+use bevy::{
+	ecs::system::SystemParam,
+	prelude::*,
+	render::{
+		mesh::{skinning::SkinnedMeshInverseBindposes, Indices, VertexAttributeValues},
+		render_resource::{Face, PrimitiveTopology},
+	},
+	utils::HashMap,
+};
+use bevy_dqskinning::ATTRIBUTE_DQS_BLEND;
+use daz_gltf::{f32_bytes, push_child, Gltf, GltfBuilder, ARRAY_BUFFER, FLOAT, UNSIGNED_SHORT};
+use serde_json::{self as json, json};
+
+use crate::{asset::default_material, DazAsset, DazMesh, DazMorph, DazNode, DqsStandardMaterial};
+
+/// Exports loaded [DazAsset]s to glTF.
+///
+/// The asset's nodes become the glTF node hierarchy, with a skin for each
+/// skinned mesh and its modifiers' morphs as morph targets, named in the mesh's
+/// `extras.targetNames`. Each primitive's DQS blend weights are kept in a
+/// custom `_DQS_BLEND` attribute.
+///
+/// Materials are exported as their PBR factors. Textures are embedded as PNGs,
+/// except for those whose pixels aren't available in the main world or can't
+/// be converted (e.g. compressed formats), which are referenced by their asset
+/// paths and need to be copied alongside the exported file.
+#[derive(SystemParam)]
+pub struct DazGltfExporter<'w> {
+	ra_daz_assets: Res<'w, Assets<DazAsset>>,
+	ra_nodes: Res<'w, Assets<DazNode>>,
+	ra_daz_meshes: Res<'w, Assets<DazMesh>>,
+	ra_meshes: Res<'w, Assets<Mesh>>,
+	ra_inverse_bindposes: Res<'w, Assets<SkinnedMeshInverseBindposes>>,
+	ra_materials: Res<'w, Assets<DqsStandardMaterial>>,
+	ra_images: Res<'w, Assets<Image>>,
+}
+
+impl DazGltfExporter<'_> {
+	pub fn export(&self, handle: &Handle<DazAsset>) -> anyhow::Result<Gltf> {
+		self.export_with_morphs(handle, &[])
+	}
+
+	/// Like [DazGltfExporter::export], but also exports the morphs of the
+	/// asset's geometries found in `morph_assets`, which are often loaded from
+	/// files of their own. Morphs with the same ID in more than one asset are
+	/// taken from the first, starting with the exported asset.
+	pub fn export_with_morphs(
+		&self,
+		handle: &Handle<DazAsset>,
+		morph_assets: &[Handle<DazAsset>],
+	) -> anyhow::Result<Gltf> {
+		let assets = std::iter::once(handle)
+			.chain(morph_assets)
+			.map(|handle| {
+				self.ra_daz_assets
+					.get(handle)
+					.ok_or_else(|| anyhow!("DazAsset {handle:?} isn't loaded"))
+			})
+			.collect::<anyhow::Result<Vec<_>>>()?;
+		let asset = assets[0];
+
+		let mut nodes = asset
+			.nodes
+			.iter()
+			.filter_map(|(id, handle)| Some((id.as_str(), self.ra_nodes.get(handle)?)))
+			.collect::<Vec<_>>();
+		nodes.sort_by_key(|(id, _)| *id);
+		let node_indices = nodes
+			.iter()
+			.enumerate()
+			.map(|(idx, (id, _))| (*id, idx))
+			.collect::<HashMap<_, _>>();
+
+		let mut builder = ExportBuilder::default();
+
+		for (_, node) in nodes.iter() {
+			let Transform {
+				translation,
+				rotation,
+				scale,
+			} = node.transform;
+			builder.gltf.nodes.push(json!({
+				"name": node.name,
+				"translation": translation.to_array(),
+				"rotation": rotation.to_array(),
+				"scale": scale.to_array(),
+			}));
+		}
+		let mut roots = vec![];
+		for (idx, (_, node)) in nodes.iter().enumerate() {
+			match node
+				.parent
+				.as_ref()
+				.and_then(|id| node_indices.get(id.as_str()))
+			{
+				Some(&parent) => push_child(&mut builder.gltf.nodes[parent], idx),
+				None => roots.push(idx),
+			}
+		}
+
+		let mut mesh_ids = asset.meshes.keys().collect::<Vec<_>>();
+		mesh_ids.sort();
+
+		for mesh_id in mesh_ids {
+			let mesh_handle = &asset.meshes[mesh_id];
+			let Some(daz_mesh) = self.ra_daz_meshes.get(mesh_handle) else {
+				continue;
+			};
+
+			let mut morphs = HashMap::<&str, &DazMorph>::new();
+			for modifier in assets.iter().flat_map(|asset| asset.modifiers.values()) {
+				let Some(morph) = modifier.morph.as_ref() else {
+					continue;
+				};
+				if morph.geometry != *mesh_id || morphs.contains_key(modifier.id.as_str()) {
+					continue;
+				}
+				if !morph.fits(daz_mesh.vertex_count) {
+					warn!(
+						"Skipping morph '{}', which doesn't fit geometry '{mesh_id}' with {} vertices",
+						modifier.id, daz_mesh.vertex_count,
+					);
+					continue;
+				}
+				morphs.insert(&modifier.id, morph);
+			}
+			let mut morphs = morphs.into_iter().collect::<Vec<_>>();
+			morphs.sort_by_key(|(id, _)| *id);
+
+			let mut primitives = vec![];
+			for primitive in daz_mesh.primitives.iter() {
+				let Some(mesh) = self.ra_meshes.get(&primitive.mesh) else {
+					continue;
+				};
+				let material = match &primitive.material {
+					Some(handle) => self.ra_materials.get(handle).map(|material| {
+						builder.material(Some(handle.id()), material, &self.ra_images)
+					}),
+					None => Some(builder.material(None, &default_material(), &self.ra_images)),
+				};
+
+				primitives.push(builder.primitive(
+					mesh,
+					&primitive.source_vertices,
+					material,
+					&morphs,
+				)?);
+			}
+			if primitives.is_empty() {
+				continue;
+			}
+
+			let mesh_idx = builder.gltf.meshes.len();
+			builder.gltf.meshes.push(json!({
+				"name": mesh_id,
+				"primitives": primitives,
+				"weights": vec![0.; morphs.len()],
+				"extras": {
+					"targetNames": morphs.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+				},
+			}));
+
+			let skin = self.skin(&mut builder.gltf, daz_mesh, &nodes, &node_indices)?;
+
+			// Skinned meshes are usually parented to their figure's root node;
+			// others get a node of their own
+			let node_idx = nodes
+				.iter()
+				.position(|(_, node)| {
+					node.mesh.as_ref().map(|handle| handle.id()) == Some(mesh_handle.id())
+				})
+				.unwrap_or_else(|| {
+					builder.gltf.nodes.push(json!({ "name": mesh_id }));
+					roots.push(builder.gltf.nodes.len() - 1);
+					builder.gltf.nodes.len() - 1
+				});
+			builder.gltf.nodes[node_idx]["mesh"] = json!(mesh_idx);
+			if let Some(skin) = skin {
+				builder.gltf.nodes[node_idx]["skin"] = json!(skin);
+			}
+		}
+
+		Ok(builder
+			.gltf
+			.finish(concat!("bevy_daz ", env!("CARGO_PKG_VERSION")), roots))
+	}
+
+	fn skin(
+		&self,
+		builder: &mut GltfBuilder,
+		daz_mesh: &DazMesh,
+		nodes: &[(&str, &DazNode)],
+		node_indices: &HashMap<&str, usize>,
+	) -> anyhow::Result<Option<usize>> {
+		if daz_mesh.joints.is_empty() {
+			return Ok(None);
+		}
+
+		let joints = daz_mesh
+			.joints
+			.iter()
+			.map(|id| {
+				node_indices
+					.get(id.as_str())
+					.copied()
+					.ok_or_else(|| anyhow!("Missing joint node '{id}'"))
+			})
+			.collect::<anyhow::Result<Vec<_>>>()?;
+
+		let inverse_bindposes = match daz_mesh
+			.inverse_bindposes
+			.as_ref()
+			.and_then(|handle| self.ra_inverse_bindposes.get(handle))
+		{
+			Some(inverse_bindposes) => inverse_bindposes.to_vec(),
+			None => joints
+				.iter()
+				.map(|&idx| Mat4::from(nodes[idx].1.inverse_bindpose))
+				.collect(),
+		};
+		let matrices = inverse_bindposes
+			.iter()
+			.flat_map(|matrix| matrix.to_cols_array())
+			.collect::<Vec<_>>();
+		let accessor = builder.accessor(
+			&f32_bytes(&matrices),
+			FLOAT,
+			inverse_bindposes.len(),
+			"MAT4",
+			None,
+			None,
+		);
+
+		// The topmost node above the first joint
+		let mut skeleton = joints[0];
+		while let Some(&parent) = nodes[skeleton]
+			.1
+			.parent
+			.as_ref()
+			.and_then(|id| node_indices.get(id.as_str()))
+		{
+			skeleton = parent;
+		}
+
+		builder.skins.push(json!({
+			"inverseBindMatrices": accessor,
+			"joints": joints,
+			"skeleton": skeleton,
+		}));
+
+		Ok(Some(builder.skins.len() - 1))
+	}
+}
+
+/// Encodes an image's pixels as a PNG, if they're in a format that can be.
+fn png_bytes(image: &Image) -> Option<Vec<u8>> {
+	let image = image.clone().try_into_dynamic().ok()?;
+	let mut bytes = vec![];
+	image
+		.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+		.ok()?;
+
+	Some(bytes)
+}
+
+/// Percent-encodes a relative path for use as a URI, keeping its separators.
+fn path_uri(path: &Path) -> String {
+	path.components()
+		.map(|component| {
+			let component = component.as_os_str().to_string_lossy();
+			let mut encoded = String::with_capacity(component.len());
+			for byte in component.bytes() {
+				match byte {
+					b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+						encoded.push(byte as char);
+					}
+					_ => encoded.push_str(&format!("%{byte:02X}")),
+				}
+			}
+			encoded
+		})
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+/// A [GltfBuilder] that shares materials and textures between primitives.
+#[derive(Default)]
+struct ExportBuilder {
+	gltf: GltfBuilder,
+	material_indices: HashMap<Option<AssetId<DqsStandardMaterial>>, usize>,
+	texture_indices: HashMap<AssetId<Image>, usize>,
+}
+
+impl ExportBuilder {
+	fn attribute_accessor(&mut self, values: &VertexAttributeValues) -> Option<usize> {
+		use VertexAttributeValues::*;
+
+		let (bytes, component_type, type_) = match values {
+			Float32(values) => (f32_bytes(values), FLOAT, "SCALAR"),
+			Float32x2(values) => (f32_bytes(values.as_flattened()), FLOAT, "VEC2"),
+			Float32x3(values) => return Some(self.gltf.vec3_accessor(values, Some(ARRAY_BUFFER))),
+			Float32x4(values) => (f32_bytes(values.as_flattened()), FLOAT, "VEC4"),
+			Uint16x4(values) => (
+				values
+					.as_flattened()
+					.iter()
+					.flat_map(|value| value.to_le_bytes())
+					.collect(),
+				UNSIGNED_SHORT,
+				"VEC4",
+			),
+			_ => return None,
+		};
+
+		Some(self.gltf.accessor(
+			&bytes,
+			component_type,
+			values.len(),
+			type_,
+			Some(ARRAY_BUFFER),
+			None,
+		))
+	}
+
+	/// `source_vertices` are the geometry vertices that the mesh's are copies
+	/// of, which the morphs' deltas are indexed by.
+	fn primitive(
+		&mut self,
+		mesh: &Mesh,
+		source_vertices: &[u32],
+		material: Option<usize>,
+		morphs: &[(&str, &DazMorph)],
+	) -> anyhow::Result<json::Value> {
+		if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+			return Err(anyhow!("Only triangle lists can be exported"));
+		}
+
+		let mut attributes = json::Map::new();
+		for (name, attribute) in [
+			("POSITION", Mesh::ATTRIBUTE_POSITION),
+			("NORMAL", Mesh::ATTRIBUTE_NORMAL),
+			("TANGENT", Mesh::ATTRIBUTE_TANGENT),
+			("TEXCOORD_0", Mesh::ATTRIBUTE_UV_0),
+			("TEXCOORD_1", Mesh::ATTRIBUTE_UV_1),
+			("COLOR_0", Mesh::ATTRIBUTE_COLOR),
+			("JOINTS_0", Mesh::ATTRIBUTE_JOINT_INDEX),
+			("WEIGHTS_0", Mesh::ATTRIBUTE_JOINT_WEIGHT),
+			("_DQS_BLEND", ATTRIBUTE_DQS_BLEND),
+		] {
+			let Some(values) = mesh.attribute(attribute) else {
+				continue;
+			};
+			if let Some(accessor) = self.attribute_accessor(values) {
+				attributes.insert(name.to_owned(), json!(accessor));
+			}
+		}
+
+		let mut primitive = json!({ "attributes": attributes });
+
+		if let Some(indices) = mesh.indices() {
+			let indices = match indices {
+				Indices::U16(indices) => indices.iter().map(|&idx| idx as u32).collect(),
+				Indices::U32(indices) => indices.clone(),
+			};
+			primitive["indices"] = json!(self.gltf.index_accessor(&indices));
+		}
+		if let Some(material) = material {
+			primitive["material"] = json!(material);
+		}
+
+		if !morphs.is_empty() {
+			if source_vertices.len() != mesh.count_vertices() {
+				return Err(anyhow!(
+					"Expected {} source vertices, found {}",
+					mesh.count_vertices(),
+					source_vertices.len(),
+				));
+			}
+			let targets = morphs
+				.iter()
+				.map(|(_, morph)| {
+					let deltas = morph
+						.vertex_deltas(source_vertices)
+						.iter()
+						.map(Vec3::to_array)
+						.collect::<Vec<_>>();
+					json!({ "POSITION": self.gltf.vec3_accessor(&deltas, Some(ARRAY_BUFFER)) })
+				})
+				.collect::<Vec<_>>();
+			primitive["targets"] = json!(targets);
+		}
+
+		Ok(primitive)
+	}
+
+	fn material(
+		&mut self,
+		id: Option<AssetId<DqsStandardMaterial>>,
+		material: &DqsStandardMaterial,
+		images: &Assets<Image>,
+	) -> usize {
+		if let Some(&idx) = self.material_indices.get(&id) {
+			return idx;
+		}
+
+		let material = &material.base;
+		let mut pbr = json!({
+			"baseColorFactor": material.base_color.as_linear_rgba_f32(),
+			"metallicFactor": material.metallic,
+			"roughnessFactor": material.perceptual_roughness,
+		});
+		if let Some(texture) = self.texture(material.base_color_texture.as_ref(), images) {
+			pbr["baseColorTexture"] = json!({ "index": texture });
+		}
+		if let Some(texture) = self.texture(material.metallic_roughness_texture.as_ref(), images) {
+			pbr["metallicRoughnessTexture"] = json!({ "index": texture });
+		}
+
+		let emissive = material.emissive.as_linear_rgba_f32();
+		let mut result = json!({
+			"pbrMetallicRoughness": pbr,
+			"emissiveFactor": [emissive[0], emissive[1], emissive[2]],
+			"doubleSided": material.cull_mode != Some(Face::Back),
+		});
+		if let Some(texture) = self.texture(material.normal_map_texture.as_ref(), images) {
+			result["normalTexture"] = json!({ "index": texture });
+		}
+		if let Some(texture) = self.texture(material.emissive_texture.as_ref(), images) {
+			result["emissiveTexture"] = json!({ "index": texture });
+		}
+		match material.alpha_mode {
+			AlphaMode::Opaque => {}
+			AlphaMode::Mask(cutoff) => {
+				result["alphaMode"] = json!("MASK");
+				result["alphaCutoff"] = json!(cutoff);
+			}
+			_ => result["alphaMode"] = json!("BLEND"),
+		}
+
+		self.gltf.materials.push(result);
+		self.material_indices
+			.insert(id, self.gltf.materials.len() - 1);
+
+		self.gltf.materials.len() - 1
+	}
+
+	/// Textures that can't be embedded and have no asset path to reference are
+	/// skipped.
+	fn texture(&mut self, handle: Option<&Handle<Image>>, images: &Assets<Image>) -> Option<usize> {
+		let handle = handle?;
+		if let Some(&idx) = self.texture_indices.get(&handle.id()) {
+			return Some(idx);
+		}
+
+		let image = match images.get(handle).and_then(png_bytes) {
+			Some(png) => json!({
+				"bufferView": self.gltf.buffer_view(&png, None),
+				"mimeType": "image/png",
+			}),
+			None => json!({ "uri": path_uri(handle.path()?.path()) }),
+		};
+		self.gltf.images.push(image);
+		self.gltf
+			.textures
+			.push(json!({ "source": self.gltf.images.len() - 1 }));
+		self.texture_indices
+			.insert(handle.id(), self.gltf.textures.len() - 1);
+
+		Some(self.gltf.textures.len() - 1)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bevy::{
+		ecs::system::RunSystemOnce,
+		prelude::*,
+		render::{
+			mesh::{Indices, VertexAttributeValues},
+			render_asset::RenderAssetUsages,
+			render_resource::{Extent3d, PrimitiveTopology, TextureDimension, TextureFormat},
+		},
+	};
+	use daz_asset_types::{ChannelFloat, NodeType};
+	use serde_json::{self as json, json};
+
+	use super::DazGltfExporter;
+	use crate::{
+		testing::{app, asset, node},
+		DazAsset, DazMesh, DazModifier, DazMorph, DazPrimitive, DqsStandardMaterial,
+	};
+
+	#[test]
+	fn round_trips_skinned_morphed_mesh() {
+		let mut app = app();
+
+		let positions = vec![[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]];
+		let mut mesh = Mesh::new(
+			PrimitiveTopology::TriangleList,
+			RenderAssetUsages::default(),
+		);
+		mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions.clone());
+		mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 0., 1.]; 3]);
+		mesh.insert_attribute(
+			Mesh::ATTRIBUTE_JOINT_INDEX,
+			VertexAttributeValues::Uint16x4(vec![[0, 0, 0, 0], [0, 1, 0, 0], [1, 0, 0, 0]]),
+		);
+		mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, vec![
+			[1., 0., 0., 0.],
+			[0.5, 0.5, 0., 0.],
+			[1., 0., 0., 0.],
+		]);
+		mesh.insert_indices(Indices::U32(vec![0, 1, 2]));
+		let mesh = app.world.resource_mut::<Assets<Mesh>>().add(mesh);
+
+		let texture = app
+			.world
+			.resource_mut::<Assets<Image>>()
+			.add(Image::new_fill(
+				Extent3d {
+					width: 2,
+					height: 2,
+					depth_or_array_layers: 1,
+				},
+				TextureDimension::D2,
+				&[255, 0, 0, 255],
+				TextureFormat::Rgba8UnormSrgb,
+				RenderAssetUsages::default(),
+			));
+		let mut material = crate::asset::default_material();
+		material.base.base_color_texture = Some(texture);
+		let material = app
+			.world
+			.resource_mut::<Assets<DqsStandardMaterial>>()
+			.add(material);
+
+		let daz_mesh = app.world.resource_mut::<Assets<DazMesh>>().add(DazMesh {
+			primitives: vec![DazPrimitive {
+				mesh,
+				material: Some(material),
+				lods: vec![],
+				source_vertices: vec![0, 1, 2],
+			}],
+			joints: vec!["hip".into(), "thigh".into()],
+			inverse_bindposes: None,
+			morph_targets: vec![],
+			vertex_count: 3,
+		});
+
+		let mut figure = node("Figure", NodeType::Figure, None, Vec3::ZERO);
+		figure.mesh = Some(daz_mesh.clone());
+		let figure = asset(
+			&mut app,
+			vec![
+				figure,
+				node("hip", NodeType::Bone, Some("Figure"), Vec3::Y),
+				node("thigh", NodeType::Bone, Some("hip"), Vec3::X),
+			],
+			vec![],
+		);
+		app.world
+			.resource_mut::<Assets<DazAsset>>()
+			.get_mut(&figure)
+			.unwrap()
+			.meshes
+			.insert("geometry".into(), daz_mesh);
+
+		// Loaded separately from the figure, along with one that doesn't fit
+		let morph = |id: &str, vertex_count, deltas| DazModifier {
+			id: id.into(),
+			label: None,
+			channel: ChannelFloat::new("value", id, 0.),
+			joint_adjustments: vec![],
+			morph: Some(DazMorph {
+				geometry: "geometry".into(),
+				vertex_count: Some(vertex_count),
+				deltas,
+			}),
+		};
+		let morphs = asset(&mut app, vec![], vec![
+			morph("Smile", 3, vec![(2, Vec3::new(0., 0.5, 0.))]),
+			morph("Mismatched", 4, vec![(3, Vec3::X)]),
+		]);
+
+		let export = app
+			.world
+			.run_system_once(move |exporter: DazGltfExporter| {
+				exporter.export_with_morphs(&figure, std::slice::from_ref(&morphs))
+			})
+			.unwrap();
+		let glb = export.to_glb();
+		let gltf = gltf::Gltf::from_slice(&glb).unwrap();
+		let blob = gltf.blob.as_deref();
+		let buffers = |_| blob;
+
+		let exported_mesh = gltf.meshes().next().unwrap();
+		let extras = exported_mesh.extras().as_ref().unwrap().get();
+		assert_eq!(
+			json::from_str::<json::Value>(extras).unwrap(),
+			json!({ "targetNames": ["Smile"] })
+		);
+
+		let primitive = exported_mesh.primitives().next().unwrap();
+		let reader = primitive.reader(buffers);
+		assert_eq!(
+			reader.read_positions().unwrap().collect::<Vec<_>>(),
+			positions
+		);
+		assert_eq!(
+			reader
+				.read_joints(0)
+				.unwrap()
+				.into_u16()
+				.collect::<Vec<_>>(),
+			[[0, 0, 0, 0], [0, 1, 0, 0], [1, 0, 0, 0]]
+		);
+		assert_eq!(
+			reader
+				.read_weights(0)
+				.unwrap()
+				.into_f32()
+				.collect::<Vec<_>>(),
+			[[1., 0., 0., 0.], [0.5, 0.5, 0., 0.], [1., 0., 0., 0.]]
+		);
+		let targets = reader.read_morph_targets().collect::<Vec<_>>();
+		assert_eq!(targets.len(), 1);
+		let (deltas, ..) = targets.into_iter().next().unwrap();
+		assert_eq!(deltas.unwrap().collect::<Vec<_>>(), [
+			[0., 0., 0.],
+			[0., 0., 0.],
+			[0., 0.5, 0.]
+		]);
+
+		let node = gltf.nodes().find(|node| node.mesh().is_some()).unwrap();
+		assert_eq!(node.name(), Some("Figure"));
+		let skin = node.skin().unwrap();
+		let joint_names = skin
+			.joints()
+			.map(|joint| joint.name().unwrap().to_owned())
+			.collect::<Vec<_>>();
+		assert_eq!(joint_names, ["hip", "thigh"]);
+		let inverse_bindposes = skin
+			.reader(buffers)
+			.read_inverse_bind_matrices()
+			.unwrap()
+			.map(|matrix| Mat4::from_cols_array_2d(&matrix))
+			.collect::<Vec<_>>();
+		assert_eq!(inverse_bindposes, [
+			Mat4::from_translation(-Vec3::Y),
+			Mat4::from_translation(-Vec3::X),
+		]);
+
+		// The texture is embedded
+		let texture = primitive
+			.material()
+			.pbr_metallic_roughness()
+			.base_color_texture()
+			.unwrap()
+			.texture();
+		let gltf::image::Source::View { view, mime_type } = texture.source().source() else {
+			panic!("texture isn't embedded");
+		};
+		assert_eq!(mime_type, "image/png");
+		let png = &blob.unwrap()[view.offset()..][..view.length()];
+		assert_eq!(&png[1..4], b"PNG");
+	}
+
+	#[test]
+	fn leaves_out_empty_buffers() {
+		let mut app = app();
+		let handle = asset(&mut app, vec![], vec![]);
+
+		let export = app
+			.world
+			.run_system_once(move |exporter: DazGltfExporter| exporter.export(&handle))
+			.unwrap();
+		assert!(export.json.get("buffers").is_none());
+
+		let glb = export.to_glb();
+		assert_eq!(
+			u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+			glb.len()
+		);
+		gltf::Gltf::from_slice(&glb).unwrap();
+	}
+}
@@ -0,0 +1,276 @@
+use std::{
+	env,
+	path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use bevy::prelude::*;
+pub use daz_asset_types::LIBRARY_PATHS_VAR;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json as json;
+
+/// Environment variable containing the path to a [DazLibraryConfig] file. If
+/// unset, [DEFAULT_CONFIG_FILE] is used instead.
+pub const LIBRARY_CONFIG_VAR: &str = "DAZ_LIBRARY_CONFIG";
+
+/// Default location of the [DazLibraryConfig] file, relative to the working
+/// directory.
+pub const DEFAULT_CONFIG_FILE: &str = "daz_library.json";
+
+/// Configuration file for locating Daz library root directories.
+///
+/// ## Example
+///
+/// ```json
+/// {
+///   "root_paths": ["/mnt/content/My DAZ 3D Library"],
+///   "content_directories_xml": "/mnt/content/ContentDirectories.xml"
+/// }
+/// ```
+///
+/// Relative paths are resolved against the directory containing the config
+/// file.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct DazLibraryConfig {
+	/// Library root directories, in order of priority.
+	#[serde(default)]
+	pub root_paths: Vec<PathBuf>,
+
+	/// An XML export of Daz Studio's Content Directory Manager. Its directories
+	/// are appended after `root_paths`.
+	pub content_directories_xml: Option<PathBuf>,
+}
+
+impl DazLibraryConfig {
+	pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+		let path = path.as_ref();
+		let contents = std::fs::read_to_string(path)
+			.map_err(|err| anyhow!("Failed to read \"{}\": {err}", path.to_string_lossy()))?;
+
+		let mut config = json::from_str::<Self>(&contents)
+			.map_err(|err| anyhow!("Failed to parse \"{}\": {err}", path.to_string_lossy()))?;
+
+		if let Some(base_dir) = path.parent() {
+			for root_path in config.root_paths.iter_mut() {
+				*root_path = base_dir.join(&*root_path);
+			}
+			if let Some(xml_path) = config.content_directories_xml.as_mut() {
+				*xml_path = base_dir.join(&*xml_path);
+			}
+		}
+
+		Ok(config)
+	}
+
+	/// Resolves the full list of root directories described by this config.
+	pub fn root_paths(&self) -> anyhow::Result<Vec<PathBuf>> {
+		let mut result = self.root_paths.clone();
+		if let Some(xml_path) = self.content_directories_xml.as_ref() {
+			result.extend(roots_from_content_directories_xml(xml_path)?);
+		}
+
+		Ok(result)
+	}
+}
+
+/// Discovers Daz library root directories, combining those from each of these
+/// sources in search order:
+///
+/// 1. The [LIBRARY_PATHS_VAR] environment variable
+/// 2. The [DazLibraryConfig] file named by [LIBRARY_CONFIG_VAR], or
+///    [DEFAULT_CONFIG_FILE] if it exists
+///
+/// The platform's default Daz Studio library locations are only used, if they
+/// exist, when neither source lists any roots. Duplicate paths are removed,
+/// keeping the first occurrence.
+pub fn discover_library_roots() -> Vec<PathBuf> {
+	let mut result = roots_from_env();
+
+	let config_path = env::var_os(LIBRARY_CONFIG_VAR)
+		.map(PathBuf::from)
+		.or_else(|| Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.is_file()));
+
+	if let Some(config_path) = config_path {
+		match DazLibraryConfig::load(&config_path).and_then(|config| config.root_paths()) {
+			Ok(root_paths) => result.extend(root_paths),
+			Err(err) => error!("{err}"),
+		}
+	}
+
+	if result.is_empty() {
+		result.extend(default_roots().into_iter().filter(|path| path.is_dir()));
+	}
+
+	if result.is_empty() {
+		warn!(
+			"No Daz library root directories found. Set the `{LIBRARY_PATHS_VAR}` environment \
+			variable or create a `{DEFAULT_CONFIG_FILE}` file."
+		);
+	}
+
+	dedup_paths(result)
+}
+
+/// Reads library root directories from the [LIBRARY_PATHS_VAR] environment
+/// variable.
+pub fn roots_from_env() -> Vec<PathBuf> {
+	env::var_os(LIBRARY_PATHS_VAR)
+		.map(|paths| {
+			env::split_paths(&paths)
+				.filter(|path| !path.as_os_str().is_empty())
+				.collect()
+		})
+		.unwrap_or_default()
+}
+
+/// Reads library root directories from an XML export of Daz Studio's Content
+/// Directory Manager.
+pub fn roots_from_content_directories_xml(path: impl AsRef<Path>) -> anyhow::Result<Vec<PathBuf>> {
+	let path = path.as_ref();
+	let contents = std::fs::read_to_string(path)
+		.map_err(|err| anyhow!("Failed to read \"{}\": {err}", path.to_string_lossy()))?;
+
+	Ok(parse_content_directories_xml(&contents))
+}
+
+/// Extracts the text content of each `<ContentDir>` or `<Directory>` element.
+/// This is deliberately lenient: other elements and attributes are ignored.
+fn parse_content_directories_xml(xml: &str) -> Vec<PathBuf> {
+	let element_re = Regex::new(r"<(?:ContentDir|Directory)(?:\s[^>]*)?>([^<]*)</").unwrap();
+
+	element_re
+		.captures_iter(xml)
+		.filter_map(|captures| {
+			let text = decode_xml_entities(captures.get(1)?.as_str().trim());
+			if text.is_empty() {
+				None
+			} else {
+				Some(PathBuf::from(text))
+			}
+		})
+		.collect()
+}
+
+fn decode_xml_entities(text: &str) -> String {
+	text.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&")
+}
+
+/// The default library locations used by Daz Studio and the Daz Install
+/// Manager.
+fn default_roots() -> Vec<PathBuf> {
+	let mut result = vec![];
+
+	if cfg!(target_os = "windows") {
+		result.push(PathBuf::from("C:/Users/Public/Documents/My DAZ 3D Library"));
+	} else if cfg!(target_os = "macos") {
+		result.push(PathBuf::from("/Users/Shared/My DAZ 3D Library"));
+	}
+
+	if let Some(home) = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+		let home = PathBuf::from(home);
+		result.push(home.join("Documents/DAZ 3D/Studio/My Library"));
+		result.push(home.join("Documents/My DAZ 3D Library"));
+	}
+
+	result
+}
+
+fn dedup_paths(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+	let mut result = Vec::<PathBuf>::with_capacity(paths.len());
+	for path in paths {
+		if !result.contains(&path) {
+			result.push(path);
+		}
+	}
+
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{env, fs, path::PathBuf};
+
+	use serde_json as json;
+
+	use super::{parse_content_directories_xml, DazLibraryConfig};
+
+	#[test]
+	fn content_directories_xml() {
+		let xml = r#"
+			<?xml version="1.0" encoding="UTF-8"?>
+			<ContentDirectoryManager>
+				<DazStudio>
+					<ContentDir>C:/Users/Public/Documents/My DAZ 3D Library</ContentDir>
+					<ContentDir type="native"> D:/Daz &amp; Friends </ContentDir>
+					<ContentDir></ContentDir>
+				</DazStudio>
+				<Poser>
+					<Directory>/mnt/poser</Directory>
+				</Poser>
+			</ContentDirectoryManager>
+		"#;
+
+		assert_eq!(parse_content_directories_xml(xml), vec![
+			PathBuf::from("C:/Users/Public/Documents/My DAZ 3D Library"),
+			PathBuf::from("D:/Daz & Friends"),
+			PathBuf::from("/mnt/poser"),
+		]);
+	}
+
+	#[test]
+	fn decodes_escaped_xml_paths() {
+		let xml = r#"
+			<ContentDir>/mnt/Bob&apos;s &quot;Library&quot;</ContentDir>
+			<ContentDir>/mnt/&lt;old&gt;/&amp;lt;escaped&amp;gt;</ContentDir>
+			<ContentDir>C:\Users\Public\Documents\My DAZ 3D Library</ContentDir>
+		"#;
+
+		assert_eq!(parse_content_directories_xml(xml), vec![
+			PathBuf::from("/mnt/Bob's \"Library\""),
+			PathBuf::from("/mnt/<old>/&lt;escaped&gt;"),
+			PathBuf::from(r"C:\Users\Public\Documents\My DAZ 3D Library"),
+		]);
+	}
+
+	#[test]
+	fn resolves_config_paths_against_its_directory() {
+		let dir = env::temp_dir().join(format!("bevy_daz_library_config_{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let absolute = env::temp_dir().join("Absolute Library");
+		fs::write(
+			dir.join("daz_library.json"),
+			json::json!({
+				"root_paths": ["My Library", absolute],
+				"content_directories_xml": "ContentDirectories.xml",
+			})
+			.to_string(),
+		)
+		.unwrap();
+		fs::write(
+			dir.join("ContentDirectories.xml"),
+			"<ContentDir>/mnt/Daz &amp; Friends</ContentDir>",
+		)
+		.unwrap();
+
+		let config = DazLibraryConfig::load(dir.join("daz_library.json")).unwrap();
+		let root_paths = config.root_paths();
+		let missing = DazLibraryConfig::load(dir.join("missing.json"));
+		fs::remove_dir_all(&dir).unwrap();
+
+		assert_eq!(config.root_paths, [
+			dir.join("My Library"),
+			absolute.clone()
+		]);
+		assert_eq!(root_paths.unwrap(), [
+			dir.join("My Library"),
+			absolute,
+			PathBuf::from("/mnt/Daz & Friends"),
+		]);
+		assert!(missing.unwrap_err().to_string().contains("missing.json"));
+	}
+}
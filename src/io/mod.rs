@@ -0,0 +1,268 @@
+use std::{
+	fmt::Write,
+	path::{Path, PathBuf},
+	sync::{Arc, OnceLock},
+};
+
+use async_fs::File;
+#[cfg(feature = "file_watcher")]
+use bevy::asset::io::{file::FileWatcher, AssetSourceEvent, AssetWatcher};
+#[cfg(feature = "file_watcher")]
+use bevy::utils::Duration;
+use bevy::{
+	asset::io::{AssetReader, AssetReaderError, AssetSource, AssetSourceId, PathStream, Reader},
+	prelude::*,
+	utils::{hashbrown::HashMap, BoxedFuture},
+};
+#[cfg(feature = "file_watcher")]
+use crossbeam_channel::Sender;
+use futures_lite::StreamExt;
+use merge_streams::MergeStreams;
+
+/// Matches the debounce time used by Bevy's default file watcher.
+#[cfg(feature = "file_watcher")]
+const WATCHER_DEBOUNCE_WAIT_TIME: Duration = Duration::from_millis(300);
+
+pub use self::library_roots::{
+	discover_library_roots, roots_from_content_directories_xml, roots_from_env, DazLibraryConfig,
+	DEFAULT_CONFIG_FILE, LIBRARY_CONFIG_VAR, LIBRARY_PATHS_VAR,
+};
+
+mod library_roots;
+
+#[derive(Default)]
+pub struct DazAssetSourcePlugin {
+	/// The library root directories to read from, or `None` to find them with
+	/// [discover_library_roots].
+	pub root_paths: Option<Vec<PathBuf>>,
+}
+
+impl DazAssetSourcePlugin {
+	pub fn with_root_paths(root_paths: Vec<PathBuf>) -> Self {
+		Self {
+			root_paths: Some(root_paths),
+		}
+	}
+}
+
+impl Plugin for DazAssetSourcePlugin {
+	fn build(&self, app: &mut App) {
+		// This plugin has to be added before `AssetPlugin`, and so before
+		// `LogPlugin` too. Discovery is deferred until `AssetPlugin` builds the
+		// source, so that its warnings are logged.
+		let root_paths = Arc::new(OnceLock::new());
+		if let Some(paths) = self.root_paths.clone() {
+			root_paths.set(paths).unwrap();
+		}
+
+		let reader_root_paths = root_paths.clone();
+		let source = AssetSource::build().with_reader(move || {
+			Box::new(DazAssetReader {
+				root_paths: reader_root_paths
+					.get_or_init(discover_library_roots)
+					.clone(),
+			})
+		});
+
+		#[cfg(feature = "file_watcher")]
+		let source = source.with_watcher(move |sender| {
+			let root_paths = root_paths.get_or_init(discover_library_roots);
+			DazAssetWatcher::new(root_paths, sender, WATCHER_DEBOUNCE_WAIT_TIME)
+				.map(|watcher| Box::new(watcher) as Box<dyn AssetWatcher>)
+		});
+
+		#[cfg(not(feature = "file_watcher"))]
+		let source = source.with_watch_warning(
+			"Hot reloading of the daz:// asset source requires the `file_watcher` feature of \
+			`bevy_daz`.",
+		);
+
+		app.register_asset_source(AssetSourceId::Name("daz".into()), source);
+	}
+}
+
+/// An [AssetWatcher] for the multi-root `daz://` asset source.
+///
+/// Each configured root directory gets its own filesystem watcher, so change
+/// events are always emitted relative to the root that contains the changed
+/// file -- i.e., with the same path that was used to load the asset.
+#[cfg(feature = "file_watcher")]
+pub struct DazAssetWatcher {
+	_watchers: Vec<FileWatcher>,
+}
+
+#[cfg(feature = "file_watcher")]
+impl DazAssetWatcher {
+	/// Returns `None` if none of the `root_paths` could be watched.
+	pub fn new(
+		root_paths: &[PathBuf],
+		sender: Sender<AssetSourceEvent>,
+		debounce_wait_time: Duration,
+	) -> Option<Self> {
+		let watchers = root_paths
+			.iter()
+			.filter_map(|root_path| {
+				// `FileWatcher` resolves relative paths against the Bevy asset
+				// root, but `DazAssetReader` resolves them against the working
+				// directory.
+				let root_path = std::env::current_dir()
+					.map(|cwd| cwd.join(root_path))
+					.unwrap_or_else(|_| root_path.clone());
+
+				match FileWatcher::new(root_path.clone(), sender.clone(), debounce_wait_time) {
+					Ok(watcher) => Some(watcher),
+					Err(err) => {
+						warn!(
+							"Failed to watch Daz library root \"{}\": {err}",
+							root_path.to_string_lossy(),
+						);
+						None
+					}
+				}
+			})
+			.collect::<Vec<_>>();
+
+		if watchers.is_empty() {
+			None
+		} else {
+			Some(Self {
+				_watchers: watchers,
+			})
+		}
+	}
+}
+
+#[cfg(feature = "file_watcher")]
+impl AssetWatcher for DazAssetWatcher {}
+
+#[derive(Clone, Debug)]
+pub struct DazAssetReader {
+	pub root_paths: Vec<PathBuf>,
+}
+
+impl AssetReader for DazAssetReader {
+	fn read<'a>(
+		&'a self,
+		path: &'a Path,
+	) -> BoxedFuture<'a, Result<Box<Reader<'a>>, AssetReaderError>> {
+		Box::pin(async move {
+			let mut errors = HashMap::<PathBuf, AssetReaderError>::default();
+
+			for root_path in self.root_paths.iter() {
+				let full_path = root_path.join(path);
+				match File::open(&full_path).await {
+					Ok(file) => {
+						let reader: Box<Reader> = Box::new(file);
+						return Ok(reader);
+					}
+					Err(err) => {
+						errors.insert(full_path, AssetReaderError::Io(Arc::new(err)));
+					}
+				}
+			}
+
+			let base_message = format!(
+				"Failed to read path \"{}\" from any of the configured root directories.",
+				path.to_string_lossy(),
+			);
+			let path_messages = errors
+				.iter()
+				.fold(String::new(), |mut s, (full_path, error)| {
+					writeln!(&mut s, "    - \"{}\": {error}", full_path.to_string_lossy()).unwrap();
+					s
+				});
+
+			Err(AssetReaderError::Io(Arc::new(std::io::Error::other(
+				format!("{base_message}\n{path_messages}"),
+			))))
+		})
+	}
+
+	fn read_meta<'a>(
+		&'a self,
+		path: &'a Path,
+	) -> BoxedFuture<'a, Result<Box<Reader<'a>>, AssetReaderError>> {
+		Box::pin(async move {
+			let meta_path = get_meta_path(path);
+
+			for root_path in self.root_paths.iter() {
+				match File::open(root_path.join(&meta_path)).await {
+					Ok(file) => {
+						let reader: Box<Reader> = Box::new(file);
+						return Ok(reader);
+					}
+					Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+					Err(err) => return Err(AssetReaderError::Io(Arc::new(err))),
+				}
+			}
+
+			Err(AssetReaderError::NotFound(meta_path))
+		})
+	}
+
+	fn read_directory<'a>(
+		&'a self,
+		path: &'a Path,
+	) -> BoxedFuture<'a, Result<Box<PathStream>, AssetReaderError>> {
+		Box::pin(async move {
+			let mut streams: Vec<Box<PathStream>> = vec![];
+
+			for root_path in self.root_paths.iter() {
+				let full_path = root_path.join(path);
+				if let Ok(entries) = async_fs::read_dir(&full_path).await {
+					let root_path = root_path.clone();
+					let mapped_stream = entries.filter_map(move |f| {
+						f.ok().and_then(|dirent| {
+							let path = dirent.path();
+							let rel_path = path.strip_prefix(&root_path).ok()?;
+							Some(rel_path.to_owned())
+						})
+					});
+
+					streams.push(Box::new(mapped_stream));
+				}
+			}
+
+			let merged: Box<PathStream> = Box::new(streams.merge());
+			Ok(merged)
+		})
+	}
+
+	fn is_directory<'a>(
+		&'a self,
+		path: &'a Path,
+	) -> BoxedFuture<'a, Result<bool, AssetReaderError>> {
+		Box::pin(async move {
+			let results = self
+				.root_paths
+				.iter()
+				.map(|root_path| {
+					root_path
+						.join(path)
+						.metadata()
+						.map_err(|err| {
+							AssetReaderError::Io(Arc::new(std::io::Error::other(format!("{err}"))))
+						})
+						.map(|meta| meta.file_type().is_dir())
+				})
+				.collect::<Vec<_>>();
+
+			if results.iter().any(|result| matches!(result, Ok(true))) {
+				Ok(true)
+			} else if results.iter().any(|result| matches!(result, Ok(false))) {
+				Ok(false)
+			} else {
+				results.first().cloned().unwrap_or(Ok(false))
+			}
+		})
+	}
+}
+
+/// Mirrors Bevy's convention of appending `.meta` to the asset's full file name.
+fn get_meta_path(path: &Path) -> PathBuf {
+	let mut meta_path = path.to_path_buf();
+	let mut extension = path.extension().unwrap_or_default().to_os_string();
+	extension.push(".meta");
+	meta_path.set_extension(extension);
+	meta_path
+}
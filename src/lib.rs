@@ -1,18 +1,50 @@
 use bevy::app::{PluginGroup, PluginGroupBuilder};
 
 mod asset;
+mod bake;
+mod crowd;
+mod export;
 mod io;
+mod lod;
+mod projection;
+mod properties;
+mod retarget;
 mod runtime;
 mod spawning;
+#[cfg(test)]
+mod testing;
 
+#[cfg(feature = "file_watcher")]
+pub use crate::io::DazAssetWatcher;
 pub use crate::{
-	asset::{DazAsset, DazAssetTypesPlugin, DazMesh, DazNode, DazPrimitive, DazUvSet},
-	io::{DazAssetReader, DazAssetSourcePlugin},
-	runtime::DazRuntimePlugin,
-	spawning::{DazBone, DazFigure, DazSpawningPlugin},
+	asset::{
+		DazAsset, DazAssetLoaderSettings, DazAssetTypesPlugin, DazLod, DazMesh, DazModifier,
+		DazMorph, DazNode, DazPrimitive, DazUvSet, JointAdjustment, LodSettings, SCENE_LABEL,
+	},
+	bake::bake_mesh,
+	crowd::{DazCrowdPlugin, SharedSkeleton, SkeletonLod, SkeletonLodSettings},
+	export::DazGltfExporter,
+	io::{
+		discover_library_roots, roots_from_content_directories_xml, roots_from_env, DazAssetReader,
+		DazAssetSourcePlugin, DazLibraryConfig, DEFAULT_CONFIG_FILE, LIBRARY_CONFIG_VAR,
+		LIBRARY_PATHS_VAR,
+	},
+	lod::{DazLodPlugin, MeshLods},
+	properties::{DazProperties, DazPropertiesPlugin},
+	retarget::{retarget_clip, BoneMap, HumanoidBone, RetargetBone, RetargetSkeleton},
+	runtime::{DazRuntimePlugin, FollowBone},
+	spawning::{
+		DazAssetSpawned, DazBone, DazBones, DazFigure, DazReady, DazSkeleton, DazSpawningPlugin,
+		DazSpawningSet, FitTo,
+	},
+};
+pub use bevy_dqskinning::{
+	CpuSkin, CpuSkinning, DeformedMesh, DqsMaterial, DqsMaterialExt, DqsMaterialPlugin,
+	DqsSharedPalette, DqsSkinningMode, DqsStandardMaterial, DualQuat, FullDetailMesh,
+	SkinningMethod,
 };
-pub use bevy_dqskinning::{DqsMaterialExt, DqsStandardMaterial, DualQuat};
 pub use daz_asset_types::NodeType;
+pub use daz_gltf::Gltf;
 
 pub struct DazPlugins;
 
@@ -22,5 +54,8 @@ impl PluginGroup for DazPlugins {
 			.add(DazAssetTypesPlugin)
 			.add(DazSpawningPlugin)
 			.add(DazRuntimePlugin)
+			.add(DazCrowdPlugin)
+			.add(DazLodPlugin)
+			.add(DazPropertiesPlugin)
 	}
 }
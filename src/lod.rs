@@ -0,0 +1,224 @@
+use bevy::{
+	prelude::*,
+	render::{primitives::Aabb, view::VisibilitySystems},
+	transform::TransformSystem,
+};
+
+use crate::DazPrimitive;
+
+/// How much larger than a level's screen size a mesh has to get before it
+/// switches back to a more detailed LOD, so meshes near a threshold don't
+/// flicker between LODs.
+const LOD_HYSTERESIS: f32 = 1.1;
+
+pub struct DazLodPlugin;
+
+impl Plugin for DazLodPlugin {
+	fn build(&self, app: &mut App) {
+		app.register_type::<MeshLods>();
+
+		app.add_systems(
+			PostUpdate,
+			select_mesh_lods
+				.after(TransformSystem::TransformPropagate)
+				.after(VisibilitySystems::CalculateBounds),
+		);
+	}
+}
+
+/// Swaps an entity's [`Handle<Mesh>`] for a simplified version as it gets
+/// smaller on screen. Added to each spawned mesh primitive that has LODs.
+///
+/// Screen size is the height of the mesh's bounding sphere as a fraction of the
+/// viewport's height, for whichever active camera it's largest in.
+///
+/// The full mesh is also kept in a [FullDetailMesh](crate::FullDetailMesh) on
+/// spawned primitives, so [CpuSkinning](crate::CpuSkinning) deforms it rather
+/// than the current LOD. Likewise, pass `base` to [bake_mesh](crate::bake_mesh)
+/// along with the [DazPrimitive]'s `source_vertices`.
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct MeshLods {
+	/// The full mesh.
+	pub base: Handle<Mesh>,
+	/// `(screen_size, mesh)` pairs, sorted by decreasing screen size. Each mesh
+	/// is used below its screen size, until the screen size is 10% above it
+	/// again.
+	pub levels: Vec<(f32, Handle<Mesh>)>,
+	/// Index of the current LOD: 0 for the full mesh, `1..` for `levels`.
+	pub current: usize,
+}
+
+impl From<&DazPrimitive> for MeshLods {
+	fn from(primitive: &DazPrimitive) -> Self {
+		Self {
+			base: primitive.mesh.clone(),
+			levels: primitive
+				.lods
+				.iter()
+				.map(|lod| (lod.screen_size, lod.mesh.clone()))
+				.collect(),
+			current: 0,
+		}
+	}
+}
+
+impl MeshLods {
+	/// The LOD to switch to at `screen_size`, from the current one.
+	pub fn select(&self, screen_size: f32) -> usize {
+		let lod = self.count_below(screen_size, 1.);
+		if lod >= self.current {
+			return lod;
+		}
+
+		self.count_below(screen_size, LOD_HYSTERESIS)
+			.min(self.current)
+	}
+
+	/// The number of levels whose screen size, scaled by `factor`, is above
+	/// `screen_size`.
+	fn count_below(&self, screen_size: f32, factor: f32) -> usize {
+		self.levels
+			.iter()
+			.take_while(|(max_size, _)| screen_size < *max_size * factor)
+			.count()
+	}
+
+	pub fn mesh(&self, lod: usize) -> &Handle<Mesh> {
+		match lod {
+			0 => &self.base,
+			_ => &self.levels[lod - 1].1,
+		}
+	}
+}
+
+fn select_mesh_lods(
+	q_cameras: Query<(&Camera, &Projection, &GlobalTransform)>,
+	mut q_meshes: Query<(
+		&mut MeshLods,
+		&mut Handle<Mesh>,
+		&GlobalTransform,
+		Option<&Aabb>,
+	)>,
+) {
+	let cameras = q_cameras
+		.iter()
+		.filter(|(camera, ..)| camera.is_active)
+		.collect::<Vec<_>>();
+	if cameras.is_empty() {
+		return;
+	}
+
+	for (mut lods, mut mesh, xform, aabb) in q_meshes.iter_mut() {
+		let (center, radius) = match aabb {
+			Some(aabb) => {
+				let (scale, _, _) = xform.to_scale_rotation_translation();
+				(
+					xform.transform_point(aabb.center.into()),
+					aabb.half_extents.length() * scale.abs().max_element(),
+				)
+			}
+			None => (xform.translation(), 1.),
+		};
+
+		let screen_size = cameras
+			.iter()
+			.map(|(_, projection, camera_xform)| {
+				screen_size(projection, camera_xform, center, radius)
+			})
+			.fold(0., f32::max);
+
+		let lod = lods.select(screen_size);
+		if lod != lods.current {
+			*mesh = lods.mesh(lod).clone();
+			lods.current = lod;
+		}
+	}
+}
+
+fn screen_size(
+	projection: &Projection,
+	camera_xform: &GlobalTransform,
+	center: Vec3,
+	radius: f32,
+) -> f32 {
+	match projection {
+		Projection::Perspective(perspective) => {
+			let distance = camera_xform.translation().distance(center);
+			if distance <= radius {
+				return f32::INFINITY;
+			}
+			radius / (distance * (perspective.fov / 2.).tan())
+		}
+		Projection::Orthographic(ortho) => 2. * radius / ortho.area.height(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bevy::{
+		prelude::*,
+		render::camera::{
+			CameraProjection, OrthographicProjection, PerspectiveProjection, ScalingMode,
+		},
+	};
+
+	use super::{screen_size, MeshLods};
+
+	fn lods(current: usize) -> MeshLods {
+		MeshLods {
+			base: Handle::weak_from_u128(1),
+			levels: vec![
+				(0.5, Handle::weak_from_u128(2)),
+				(0.25, Handle::weak_from_u128(3)),
+			],
+			current,
+		}
+	}
+
+	#[test]
+	fn selects_less_detail_below_each_level() {
+		assert_eq!(lods(0).select(1.), 0);
+		assert_eq!(lods(0).select(0.49), 1);
+		assert_eq!(lods(0).select(0.1), 2);
+		assert_eq!(lods(1).select(0.24), 2);
+	}
+
+	#[test]
+	fn selects_more_detail_past_the_hysteresis() {
+		// Between a level's screen size and 10% above it, the current LOD stays
+		assert_eq!(lods(1).select(0.52), 1);
+		assert_eq!(lods(1).select(0.56), 0);
+		assert_eq!(lods(2).select(0.26), 2);
+		assert_eq!(lods(2).select(0.3), 1);
+		assert_eq!(lods(2).select(1.), 0);
+		assert_eq!(lods(0).select(0.52), 0);
+	}
+
+	#[test]
+	fn measures_screen_size() {
+		let camera = GlobalTransform::from_translation(Vec3::Z * 10.);
+		let perspective = Projection::Perspective(PerspectiveProjection {
+			fov: std::f32::consts::FRAC_PI_2,
+			..default()
+		});
+		// tan(45°) = 1, so a sphere 10 units away fills the viewport at radius 10
+		let size = screen_size(&perspective, &camera, Vec3::ZERO, 1.);
+		assert!((size - 0.1).abs() < 1e-5, "{size}");
+		assert_eq!(
+			screen_size(&perspective, &camera, Vec3::Z * 9.5, 1.),
+			f32::INFINITY
+		);
+
+		let mut ortho = OrthographicProjection {
+			scaling_mode: ScalingMode::Fixed {
+				width: 20.,
+				height: 20.,
+			},
+			..default()
+		};
+		ortho.update(1., 1.);
+		let size = screen_size(&Projection::Orthographic(ortho), &camera, Vec3::ZERO, 1.);
+		assert!((size - 0.1).abs() < 1e-5, "{size}");
+	}
+}
@@ -0,0 +1,754 @@
+//! Projection of a figure's morphs onto the assets fitted to it, for the morphs
+//! that the assets don't have their own version of.
+//!
+//! Each vertex of a fitted mesh is bound to the nearest point on the surface of
+//! the figure's geometry, searched for among the triangles around the nearest
+//! figure vertex. A morph's deltas are interpolated at that point, so the
+//! vertex moves with the skin beneath it.
+
+use bevy::{
+	prelude::*,
+	render::{
+		mesh::morph::{MeshMorphWeights, MorphAttributes, MorphTargetImage, MorphWeights},
+		render_asset::RenderAssetUsages,
+	},
+	utils::HashMap,
+};
+use bevy_dqskinning::FullDetailMesh;
+
+use crate::{DazAsset, DazFigure, DazMesh, DazMorph, DazProperties, DazReady, FitTo, MeshLods};
+
+/// Figure vertices and triangles, for finding the nearest point on the figure's
+/// surface.
+pub(crate) struct Surface {
+	positions: Vec<Vec3>,
+	triangles: Vec<[u32; 3]>,
+	/// The triangles around each vertex.
+	vertex_triangles: Vec<Vec<u32>>,
+	/// Vertices bucketed by position.
+	grid: HashMap<IVec3, Vec<u32>>,
+	cell_size: f32,
+	min_cell: IVec3,
+	max_cell: IVec3,
+}
+
+/// A point on a [Surface], as weights of the vertices of its triangle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SurfacePoint {
+	vertices: [u32; 3],
+	weights: [f32; 3],
+}
+
+impl Surface {
+	pub fn new(positions: Vec<Vec3>, triangles: Vec<[u32; 3]>) -> Self {
+		let mut vertex_triangles = vec![vec![]; positions.len()];
+		for (idx, triangle) in triangles.iter().enumerate() {
+			for &vertex in triangle {
+				vertex_triangles[vertex as usize].push(idx as u32);
+			}
+		}
+
+		// Roughly one vertex per cell, if they filled their bounds
+		let (min, max) = positions.iter().fold(
+			(Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+			|(min, max), &position| (min.min(position), max.max(position)),
+		);
+		let extent = (max - min).max_element();
+		let cell_size = if extent.is_finite() && extent > 0. {
+			extent / (positions.len() as f32).cbrt().max(1.)
+		} else {
+			1.
+		};
+
+		let mut surface = Self {
+			positions,
+			triangles,
+			vertex_triangles,
+			grid: HashMap::default(),
+			cell_size,
+			min_cell: IVec3::MAX,
+			max_cell: IVec3::MIN,
+		};
+		for (idx, &position) in surface.positions.iter().enumerate() {
+			let cell = surface.cell(position);
+			surface.min_cell = surface.min_cell.min(cell);
+			surface.max_cell = surface.max_cell.max(cell);
+			surface.grid.entry(cell).or_default().push(idx as u32);
+		}
+
+		surface
+	}
+
+	/// The surface of a Daz geometry, indexed by geometry vertex like its
+	/// morphs, from the meshes of its primitives. Returns `None` if any of them
+	/// aren't loaded.
+	pub fn from_daz_mesh(daz_mesh: &DazMesh, meshes: &Assets<Mesh>) -> Option<Self> {
+		let mut positions = vec![Vec3::ZERO; daz_mesh.vertex_count];
+		let mut triangles = vec![];
+
+		for primitive in daz_mesh.primitives.iter() {
+			let mesh = meshes.get(&primitive.mesh)?;
+			let source = &primitive.source_vertices;
+			let primitive_positions = mesh.attribute(Mesh::ATTRIBUTE_POSITION)?.as_float3()?;
+
+			for (&position, &vertex) in primitive_positions.iter().zip(source) {
+				if let Some(dst) = positions.get_mut(vertex as usize) {
+					*dst = position.into();
+				}
+			}
+			if let Some(indices) = mesh.indices() {
+				let indices = indices.iter().collect::<Vec<_>>();
+				triangles.extend(
+					indices
+						.chunks_exact(3)
+						.map(|tri| [source[tri[0]], source[tri[1]], source[tri[2]]]),
+				);
+			}
+		}
+
+		Some(Self::new(positions, triangles))
+	}
+
+	/// The nearest point on the surface to `point`, or `None` if the surface has
+	/// no vertices.
+	pub fn nearest(&self, point: Vec3) -> Option<SurfacePoint> {
+		let vertex = self.nearest_vertex(point)?;
+
+		let nearest = self.vertex_triangles[vertex as usize]
+			.iter()
+			.map(|&triangle| {
+				let vertices = self.triangles[triangle as usize];
+				let [a, b, c] = vertices.map(|idx| self.positions[idx as usize]);
+				let weights = closest_point_on_triangle(point, a, b, c);
+				let closest = a * weights[0] + b * weights[1] + c * weights[2];
+				(closest.distance_squared(point), SurfacePoint {
+					vertices,
+					weights,
+				})
+			})
+			.min_by(|(lhs, _), (rhs, _)| lhs.total_cmp(rhs))
+			.map(|(_, point)| point);
+
+		Some(nearest.unwrap_or(SurfacePoint {
+			vertices: [vertex; 3],
+			weights: [1., 0., 0.],
+		}))
+	}
+
+	fn cell(&self, position: Vec3) -> IVec3 {
+		(position / self.cell_size).floor().as_ivec3()
+	}
+
+	/// Searches the grid in rings of cells around `point`'s, until the rest are
+	/// further away than the nearest vertex found so far.
+	fn nearest_vertex(&self, point: Vec3) -> Option<u32> {
+		let center = self.cell(point);
+		let max_ring = (center - self.min_cell)
+			.abs()
+			.max((self.max_cell - center).abs())
+			.max_element();
+
+		let mut nearest = None::<(f32, u32)>;
+		for ring in 0..=max_ring.max(0) {
+			// Vertices in this ring are at least `ring - 1` cells away
+			let min_distance = (ring - 1).max(0) as f32 * self.cell_size;
+			if nearest.is_some_and(|(distance, _)| distance <= min_distance * min_distance) {
+				break;
+			}
+
+			let min = (center - ring).max(self.min_cell);
+			let max = (center + ring).min(self.max_cell);
+			for x in min.x..=max.x {
+				for y in min.y..=max.y {
+					for z in min.z..=max.z {
+						let cell = IVec3::new(x, y, z);
+						if (cell - center).abs().max_element() != ring {
+							continue;
+						}
+						for &vertex in self.grid.get(&cell).into_iter().flatten() {
+							let distance = self.positions[vertex as usize].distance_squared(point);
+							if nearest.is_none_or(|(nearest, _)| distance < nearest) {
+								nearest = Some((distance, vertex));
+							}
+						}
+					}
+				}
+			}
+		}
+
+		nearest.map(|(_, vertex)| vertex)
+	}
+}
+
+impl SurfacePoint {
+	/// The delta at this point, from a morph's `deltas` keyed by vertex.
+	pub fn interpolate(&self, deltas: &HashMap<u32, Vec3>) -> Vec3 {
+		self.vertices
+			.iter()
+			.zip(self.weights)
+			.map(|(vertex, weight)| deltas.get(vertex).copied().unwrap_or_default() * weight)
+			.sum()
+	}
+}
+
+/// Barycentric weights of the point on triangle `abc` closest to `p`.
+///
+/// * [Reference](https://realtimecollisiondetection.net/) (Ericson,
+///   "Real-Time Collision Detection", section 5.1.5)
+fn closest_point_on_triangle(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> [f32; 3] {
+	let ab = b - a;
+	let ac = c - a;
+	let ap = p - a;
+	let d1 = ab.dot(ap);
+	let d2 = ac.dot(ap);
+	if d1 <= 0. && d2 <= 0. {
+		return [1., 0., 0.];
+	}
+
+	let bp = p - b;
+	let d3 = ab.dot(bp);
+	let d4 = ac.dot(bp);
+	if d3 >= 0. && d4 <= d3 {
+		return [0., 1., 0.];
+	}
+
+	let vc = d1 * d4 - d3 * d2;
+	if vc <= 0. && d1 >= 0. && d3 <= 0. {
+		let v = d1 / (d1 - d3);
+		return [1. - v, v, 0.];
+	}
+
+	let cp = p - c;
+	let d5 = ab.dot(cp);
+	let d6 = ac.dot(cp);
+	if d6 >= 0. && d5 <= d6 {
+		return [0., 0., 1.];
+	}
+
+	let vb = d5 * d2 - d1 * d6;
+	if vb <= 0. && d2 >= 0. && d6 <= 0. {
+		let w = d2 / (d2 - d6);
+		return [1. - w, 0., w];
+	}
+
+	let va = d3 * d6 - d5 * d4;
+	if va <= 0. && (d4 - d3) >= 0. && (d5 - d6) >= 0. {
+		let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+		return [0., 1. - w, w];
+	}
+
+	let denom = va + vb + vc;
+	if denom.abs() <= f32::EPSILON {
+		// Degenerate triangle
+		return [1., 0., 0.];
+	}
+	let v = vb / denom;
+	let w = vc / denom;
+	[1. - v - w, v, w]
+}
+
+/// The figure morphs projected onto a fitted asset, on its `Handle<DazAsset>`
+/// entity.
+#[derive(Component, Default)]
+pub(crate) struct ProjectedMorphs {
+	/// Modifier IDs of the projected morphs of each of the asset's meshes,
+	/// after its own morph targets.
+	targets: HashMap<AssetId<DazMesh>, Vec<String>>,
+	/// The mesh with the projected morph targets for each of the asset's
+	/// primitives.
+	meshes: HashMap<AssetId<Mesh>, Handle<Mesh>>,
+}
+
+/// Gives the meshes of [FitTo] assets morph targets projected from the
+/// figure's, for each of the figure's [DazProperties] that the asset doesn't
+/// have its own morph for.
+///
+/// Each affected primitive gets a copy of its mesh with the projected targets
+/// after its own, and morph weights to match. LODs keep the asset's own morph
+/// targets only.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn project_fitted_morphs(
+	mut cmd: Commands,
+	ra_daz_assets: Res<Assets<DazAsset>>,
+	ra_daz_meshes: Res<Assets<DazMesh>>,
+	mut ra_meshes: ResMut<Assets<Mesh>>,
+	mut ra_images: ResMut<Assets<Image>>,
+	q_figures: Query<(Ref<DazProperties>, &Handle<DazAsset>), (With<DazFigure>, With<DazReady>)>,
+	mut q_fitted: Query<
+		(
+			Entity,
+			&FitTo,
+			&Handle<DazAsset>,
+			Option<&mut ProjectedMorphs>,
+		),
+		With<DazReady>,
+	>,
+	q_ready: Query<(), Added<DazReady>>,
+	q_children: Query<&Children>,
+	q_parents: Query<&Parent>,
+	mut q_meshes: Query<(
+		&mut Handle<Mesh>,
+		Option<&mut MeshLods>,
+		Option<&mut FullDetailMesh>,
+	)>,
+	q_weights: Query<&MorphWeights>,
+	mut l_bindings: Local<HashMap<(AssetId<DazMesh>, AssetId<Mesh>), Vec<Option<SurfacePoint>>>>,
+) {
+	for (root, FitTo(figure), asset_handle, projected) in q_fitted.iter_mut() {
+		let Ok((properties, figure_handle)) = q_figures.get(*figure) else {
+			continue;
+		};
+		if !properties.is_changed() && !q_ready.contains(root) && !q_ready.contains(*figure) {
+			continue;
+		}
+		let (Some(figure_asset), Some(asset)) = (
+			ra_daz_assets.get(figure_handle),
+			ra_daz_assets.get(asset_handle),
+		) else {
+			continue;
+		};
+
+		// The figure's morphs that the properties use
+		let figure_morphs = properties
+			.iter()
+			.filter_map(|(key, _)| {
+				let modifier = figure_asset.modifiers.get(key).or_else(|| {
+					figure_asset
+						.modifiers
+						.values()
+						.find(|modifier| modifier.is_keyed_by(key))
+				})?;
+				let morph = modifier.morph.as_ref()?;
+				let geometry = figure_asset.meshes.get(&morph.geometry)?;
+				let figure_mesh = ra_daz_meshes.get(geometry)?;
+				morph
+					.fits(figure_mesh.vertex_count)
+					.then_some((modifier.id.as_str(), (geometry.id(), morph)))
+			})
+			.collect::<HashMap<_, _>>();
+
+		let mut inserted = None;
+		let projected = match projected {
+			Some(projected) => projected.into_inner(),
+			None => inserted.insert(ProjectedMorphs::default()),
+		};
+
+		// Rebuilt primitive meshes and their morph target names, by the IDs of
+		// the meshes they replace
+		let mut replaced = HashMap::<AssetId<Mesh>, (Handle<Mesh>, Vec<String>)>::default();
+		for (mesh_id, daz_mesh_handle) in asset.meshes.iter() {
+			let Some(daz_mesh) = ra_daz_meshes.get(daz_mesh_handle) else {
+				continue;
+			};
+			let previous = projected
+				.targets
+				.get(&daz_mesh_handle.id())
+				.cloned()
+				.unwrap_or_default();
+
+			// Previously projected morphs are kept, so their weights don't need
+			// carrying over to yet another mesh
+			let mut targets = previous.clone();
+			let mut new_morphs = figure_morphs
+				.keys()
+				.filter(|id| {
+					!daz_mesh.morph_targets.iter().any(|own| own == *id)
+						&& !previous.iter().any(|previous| previous == *id)
+				})
+				.map(|id| (*id).to_owned())
+				.collect::<Vec<_>>();
+			new_morphs.sort();
+			targets.extend(new_morphs);
+			targets.truncate(
+				bevy::render::mesh::morph::MAX_MORPH_WEIGHTS
+					.saturating_sub(daz_mesh.morph_targets.len()),
+			);
+			if targets == previous {
+				continue;
+			}
+
+			let own_morphs = daz_mesh
+				.morph_targets
+				.iter()
+				.map(|id| {
+					asset
+						.modifiers
+						.get(id)
+						.and_then(|modifier| modifier.morph.as_ref())
+				})
+				.collect::<Option<Vec<&DazMorph>>>();
+			let Some(own_morphs) = own_morphs else {
+				warn!("Can't project morphs onto '{mesh_id}', whose own morphs are missing");
+				continue;
+			};
+
+			let mut surfaces = HashMap::<AssetId<DazMesh>, Option<Surface>>::default();
+			let mut names = daz_mesh.morph_targets.clone();
+			names.extend(targets.iter().cloned());
+
+			for primitive in daz_mesh.primitives.iter() {
+				let Some(mut mesh) = ra_meshes.get(&primitive.mesh).cloned() else {
+					continue;
+				};
+				let Some(positions) = mesh
+					.attribute(Mesh::ATTRIBUTE_POSITION)
+					.and_then(|positions| positions.as_float3())
+					.map(|positions| {
+						positions
+							.iter()
+							.copied()
+							.map(Vec3::from)
+							.collect::<Vec<_>>()
+					})
+				else {
+					continue;
+				};
+
+				let mut deltas = own_morphs
+					.iter()
+					.map(|morph| morph.vertex_deltas(&primitive.source_vertices))
+					.collect::<Vec<_>>();
+				for id in targets.iter() {
+					let Some(&(geometry, morph)) = figure_morphs.get(id.as_str()) else {
+						// No longer used by the figure, so it stays at rest
+						deltas.push(vec![Vec3::ZERO; positions.len()]);
+						continue;
+					};
+
+					let binding = l_bindings
+						.entry((geometry, primitive.mesh.id()))
+						.or_insert_with(|| {
+							let surface = surfaces.entry(geometry).or_insert_with(|| {
+								let figure_mesh = ra_daz_meshes.get(geometry)?;
+								Surface::from_daz_mesh(figure_mesh, &ra_meshes)
+							});
+							match surface {
+								Some(surface) => positions
+									.iter()
+									.map(|&position| surface.nearest(position))
+									.collect(),
+								None => vec![None; positions.len()],
+							}
+						});
+
+					let morph_deltas = morph.deltas.iter().copied().collect::<HashMap<_, _>>();
+					deltas.push(
+						binding
+							.iter()
+							.map(|point| {
+								point.map_or(Vec3::ZERO, |point| point.interpolate(&morph_deltas))
+							})
+							.collect(),
+					);
+				}
+
+				let targets = deltas.into_iter().map(|deltas| {
+					deltas.into_iter().map(|position| MorphAttributes {
+						position,
+						..default()
+					})
+				});
+				let image = match MorphTargetImage::new(
+					targets,
+					positions.len(),
+					RenderAssetUsages::default(),
+				) {
+					Ok(image) => image.0,
+					Err(err) => {
+						warn!("Can't project morphs onto '{mesh_id}': {err}");
+						continue;
+					}
+				};
+
+				mesh.set_morph_targets(ra_images.add(image));
+				mesh.set_morph_target_names(names.clone());
+				let handle = ra_meshes.add(mesh);
+
+				let rebuilt = (handle.clone(), names.clone());
+				let previous = projected.meshes.insert(primitive.mesh.id(), handle);
+				if let Some(previous) = previous {
+					replaced.insert(previous.id(), rebuilt.clone());
+				}
+				replaced.insert(primitive.mesh.id(), rebuilt);
+			}
+
+			projected.targets.insert(daz_mesh_handle.id(), targets);
+		}
+		if let Some(inserted) = inserted {
+			cmd.entity(root).insert(inserted);
+		}
+
+		for entity in q_children.iter_descendants(root) {
+			let Ok((mut mesh, lods, full_detail)) = q_meshes.get_mut(entity) else {
+				continue;
+			};
+			let base = match (&lods, &full_detail) {
+				(Some(lods), _) => lods.base.id(),
+				(_, Some(full_detail)) => full_detail.0.id(),
+				_ => mesh.id(),
+			};
+			let Some((new_mesh, names)) = replaced.get(&base) else {
+				continue;
+			};
+
+			if mesh.id() == base {
+				*mesh = new_mesh.clone();
+			}
+			if let Some(mut lods) = lods {
+				lods.base = new_mesh.clone();
+			}
+			if let Some(mut full_detail) = full_detail {
+				full_detail.0 = new_mesh.clone();
+			}
+
+			// Morph weights are set on the node, and inherited by its primitives
+			let node = q_parents.get(entity).map_or(entity, Parent::get);
+			let old_weights = q_weights
+				.get(node)
+				.ok()
+				.and_then(|weights| {
+					let names = ra_meshes.get(weights.first_mesh()?)?.morph_target_names()?;
+					Some(
+						names
+							.iter()
+							.cloned()
+							.zip(weights.weights().iter().copied())
+							.collect::<HashMap<_, _>>(),
+					)
+				})
+				.unwrap_or_default();
+			let weights = names
+				.iter()
+				.map(|name| old_weights.get(name).copied().unwrap_or_default())
+				.collect::<Vec<_>>();
+
+			if let Ok(morph_weights) = MorphWeights::new(weights.clone(), Some(new_mesh.clone())) {
+				cmd.entity(node).insert(morph_weights);
+			}
+			if let Ok(mesh_weights) = MeshMorphWeights::new(weights) {
+				cmd.entity(entity).insert(mesh_weights);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bevy::{
+		math::vec3,
+		prelude::*,
+		render::{
+			mesh::{
+				morph::{MeshMorphWeights, MorphWeights},
+				Indices,
+			},
+			render_asset::RenderAssetUsages,
+			render_resource::PrimitiveTopology,
+		},
+		utils::HashMap,
+	};
+	use daz_asset_types::ChannelFloat;
+
+	use super::{closest_point_on_triangle, Surface};
+	use crate::{
+		testing::app, DazAsset, DazFigure, DazMesh, DazModifier, DazMorph, DazPrimitive,
+		DazProperties, DazPropertiesPlugin, DazReady, FitTo,
+	};
+
+	/// A 4x4 grid of vertices on the XZ plane, one unit apart.
+	fn plane() -> Surface {
+		let positions = (0..16)
+			.map(|idx| vec3((idx % 4) as f32, 0., (idx / 4) as f32))
+			.collect::<Vec<_>>();
+		let mut triangles = vec![];
+		for z in 0..3 {
+			for x in 0..3 {
+				let idx = z * 4 + x;
+				triangles.push([idx, idx + 4, idx + 1]);
+				triangles.push([idx + 1, idx + 4, idx + 5]);
+			}
+		}
+
+		Surface::new(positions, triangles)
+	}
+
+	#[test]
+	fn finds_closest_points_on_triangles() {
+		let [a, b, c] = [Vec3::ZERO, Vec3::X, Vec3::Z];
+		let point = |weights: [f32; 3]| a * weights[0] + b * weights[1] + c * weights[2];
+
+		let inside = closest_point_on_triangle(vec3(0.25, 1., 0.25), a, b, c);
+		assert!(point(inside).abs_diff_eq(vec3(0.25, 0., 0.25), 1e-5));
+		let corner = closest_point_on_triangle(vec3(2., 0., -1.), a, b, c);
+		assert_eq!(corner, [0., 1., 0.]);
+		let edge = closest_point_on_triangle(vec3(1., 0., 1.), a, b, c);
+		assert!(point(edge).abs_diff_eq(vec3(0.5, 0., 0.5), 1e-5));
+	}
+
+	#[test]
+	fn interpolates_deltas_at_the_nearest_surface_point() {
+		let surface = plane();
+		// Raises the vertex at (1, 0, 1)
+		let deltas = HashMap::from_iter([(5, Vec3::Y)]);
+
+		let above_vertex = surface.nearest(vec3(1., 0.2, 1.)).unwrap();
+		assert!(above_vertex.interpolate(&deltas).abs_diff_eq(Vec3::Y, 1e-5));
+
+		// Halfway along an edge from the raised vertex
+		let above_edge = surface.nearest(vec3(1.5, 0.3, 1.)).unwrap();
+		assert!(above_edge
+			.interpolate(&deltas)
+			.abs_diff_eq(Vec3::Y * 0.5, 1e-5));
+
+		// Far from the raised vertex
+		let far = surface.nearest(vec3(3., -0.5, 3.)).unwrap();
+		assert_eq!(far.interpolate(&deltas), Vec3::ZERO);
+
+		// Outside the surface's bounds
+		let outside = surface.nearest(vec3(-5., 0., 1.)).unwrap();
+		assert!(outside.interpolate(&deltas).length() < 1e-5);
+	}
+
+	fn triangle_mesh(positions: Vec<Vec3>, triangles: &[[u32; 3]]) -> Mesh {
+		Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all())
+			.with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+			.with_inserted_indices(Indices::U32(triangles.concat()))
+	}
+
+	/// A [DazAsset] with a single geometry, of a single primitive.
+	fn asset(
+		app: &mut App,
+		geometry: &str,
+		mesh: Mesh,
+		morph_targets: Vec<String>,
+		modifiers: Vec<DazModifier>,
+	) -> (Handle<DazAsset>, Handle<Mesh>) {
+		let vertex_count = mesh.count_vertices();
+		let mesh = app.world.resource_mut::<Assets<Mesh>>().add(mesh);
+		let daz_mesh = app.world.resource_mut::<Assets<DazMesh>>().add(DazMesh {
+			primitives: vec![DazPrimitive {
+				mesh: mesh.clone(),
+				material: None,
+				lods: vec![],
+				source_vertices: (0..vertex_count as u32).collect(),
+			}],
+			joints: vec![],
+			inverse_bindposes: None,
+			morph_targets,
+			vertex_count,
+		});
+
+		let asset = app.world.resource_mut::<Assets<DazAsset>>().add(DazAsset {
+			scene: Handle::default(),
+			meshes: [(geometry.to_owned(), daz_mesh)].into_iter().collect(),
+			nodes: HashMap::default(),
+			modifiers: modifiers
+				.into_iter()
+				.map(|modifier| (modifier.id.clone(), modifier))
+				.collect(),
+			materials: HashMap::default(),
+			uv_sets: HashMap::default(),
+		});
+		(asset, mesh)
+	}
+
+	fn modifier(id: &str, geometry: &str, deltas: Vec<(u32, Vec3)>) -> DazModifier {
+		DazModifier {
+			id: id.into(),
+			label: None,
+			channel: ChannelFloat::new(id, id, 0.),
+			joint_adjustments: vec![],
+			morph: Some(DazMorph {
+				geometry: geometry.into(),
+				vertex_count: None,
+				deltas,
+			}),
+		}
+	}
+
+	#[test]
+	fn projects_figure_morphs_onto_fitted_meshes() {
+		let mut app = app();
+		app.add_plugins(DazPropertiesPlugin);
+
+		let plane = plane();
+		let (figure_asset, _) = asset(
+			&mut app,
+			"Body",
+			triangle_mesh(plane.positions.clone(), &plane.triangles),
+			vec![],
+			vec![
+				modifier("Tall", "Body", vec![(5, Vec3::Y)]),
+				modifier("Wide", "Body", vec![(6, Vec3::X)]),
+			],
+		);
+		// A shirt with its own version of "Wide", but not of "Tall"
+		let (shirt_asset, shirt_mesh) = asset(
+			&mut app,
+			"Shirt",
+			triangle_mesh(
+				vec![vec3(1., 0.2, 1.), vec3(1.5, 0.3, 1.), vec3(3., 0.1, 3.)],
+				&[[0, 1, 2]],
+			),
+			vec!["Wide".into()],
+			vec![modifier("Wide", "Shirt", vec![(2, Vec3::X)])],
+		);
+
+		let figure = app
+			.world
+			.spawn((
+				figure_asset,
+				DazFigure,
+				DazReady,
+				DazProperties::default().with("Tall", 1.).with("Wide", 0.5),
+			))
+			.id();
+		let primitive = app
+			.world
+			.spawn((shirt_mesh.clone(), MeshMorphWeights::new(vec![0.]).unwrap()))
+			.id();
+		let node = app
+			.world
+			.spawn(MorphWeights::new(vec![0.], Some(shirt_mesh.clone())).unwrap())
+			.add_child(primitive)
+			.id();
+		let shirt = app
+			.world
+			.spawn((shirt_asset, FitTo(figure), DazReady))
+			.add_child(node)
+			.id();
+
+		app.update();
+		let projected = app.world.get::<Handle<Mesh>>(primitive).unwrap().clone();
+		assert_ne!(projected, shirt_mesh);
+		let meshes = app.world.resource::<Assets<Mesh>>();
+		let names = meshes
+			.get(&projected)
+			.unwrap()
+			.morph_target_names()
+			.unwrap();
+		assert_eq!(names, ["Wide", "Tall"]);
+		let weights = app.world.get::<MorphWeights>(node).unwrap();
+		assert_eq!(weights.weights(), [0.5, 1.]);
+		assert_eq!(weights.first_mesh(), Some(&projected));
+		assert_eq!(
+			app.world
+				.get::<MeshMorphWeights>(primitive)
+				.unwrap()
+				.weights()
+				.len(),
+			2
+		);
+
+		// Values of morphs that are already there leave the meshes alone
+		app.world
+			.get_mut::<DazProperties>(figure)
+			.unwrap()
+			.set("Wide", 0.25);
+		app.update();
+		assert_eq!(app.world.get::<Handle<Mesh>>(primitive), Some(&projected));
+		let weights = app.world.get::<MorphWeights>(node).unwrap();
+		assert_eq!(weights.weights(), [0.25, 1.]);
+		assert!(app.world.get::<super::ProjectedMorphs>(shirt).is_some());
+	}
+}
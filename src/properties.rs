@@ -0,0 +1,611 @@
+use bevy::{
+	ecs::entity::{EntityHashMap, EntityHashSet},
+	math::Affine3A,
+	prelude::*,
+	render::mesh::{
+		morph::{inherit_weights, MorphWeights},
+		skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+	},
+	transform::TransformSystem,
+	utils::{HashMap, HashSet},
+};
+
+use crate::{
+	projection::project_fitted_morphs, DazAsset, DazBone, DazFigure, DazModifier, DazNode,
+	DazReady, DazSkeleton, DualQuat, FitTo,
+};
+
+pub struct DazPropertiesPlugin;
+
+impl Plugin for DazPropertiesPlugin {
+	fn build(&self, app: &mut App) {
+		app.register_type::<DazProperties>();
+
+		app.add_systems(
+			PostUpdate,
+			(
+				apply_daz_properties.before(inherit_weights),
+				project_fitted_morphs.before(apply_daz_properties),
+				// Before the changed values are cleared
+				apply_daz_shaping
+					.before(apply_daz_properties)
+					.before(TransformSystem::TransformPropagate),
+			),
+		);
+	}
+}
+
+/// Property values of a [DazFigure], e.g. `"Body Tone" = 0.6`, applied to the
+/// morph targets of every mesh in the figure and in the assets fitted to it.
+///
+/// Properties are keyed by modifier ID, name or label, and morph targets are
+/// matched by their modifier's ID. Values are clamped to the modifier's channel
+/// range if it enforces one. Fitted assets without their own version of one of
+/// the figure's morphs get one projected from the figure's surface.
+///
+/// Modifiers whose formulas move joint centers, like most shaping morphs, also
+/// move the figure's bones and recompute its inverse bindposes. See
+/// [JointAdjustment](crate::JointAdjustment).
+///
+/// Values changed with [DazProperties::set] are re-applied on their own.
+/// Changing the component any other way (e.g. through reflection) re-applies
+/// every value.
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct DazProperties {
+	values: HashMap<String, f32>,
+	#[reflect(ignore)]
+	dirty: HashSet<String>,
+}
+
+impl DazProperties {
+	pub fn with(mut self, key: impl Into<String>, value: f32) -> Self {
+		self.set(key, value);
+		self
+	}
+
+	pub fn get(&self, key: &str) -> Option<f32> {
+		self.values.get(key).copied()
+	}
+
+	pub fn set(&mut self, key: impl Into<String>, value: f32) {
+		let key = key.into();
+		if self.values.get(&key) != Some(&value) {
+			self.dirty.insert(key.clone());
+			self.values.insert(key, value);
+		}
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&str, f32)> {
+		self.values
+			.iter()
+			.map(|(key, &value)| (key.as_str(), value))
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_daz_properties(
+	ra_daz_assets: Res<Assets<DazAsset>>,
+	ra_meshes: Res<Assets<Mesh>>,
+	mut q_figures: Query<(Entity, &mut DazProperties), With<DazFigure>>,
+	q_fitted: Query<(Entity, &FitTo)>,
+	q_ready: Query<(), Added<DazReady>>,
+	q_asset_handles: Query<&Handle<DazAsset>>,
+	q_children: Query<&Children>,
+	mut q_morphs: Query<&mut MorphWeights>,
+	mut l_entities: Local<EntityHashSet>,
+) {
+	for (figure, mut properties) in q_figures.iter_mut() {
+		let roots = std::iter::once(figure)
+			.chain(
+				q_fitted
+					.iter()
+					.filter(|(_, fit_to)| fit_to.0 == figure)
+					.map(|(entity, _)| entity),
+			)
+			.collect::<Vec<_>>();
+
+		// Newly spawned meshes need every value
+		let apply_all = roots.iter().any(|&root| q_ready.contains(root))
+			|| (properties.is_changed() && properties.dirty.is_empty());
+		if !apply_all && properties.dirty.is_empty() {
+			continue;
+		}
+
+		let properties = properties.bypass_change_detection();
+		let dirty = std::mem::take(&mut properties.dirty);
+
+		let modifiers = roots
+			.iter()
+			.filter_map(|&root| ra_daz_assets.get(q_asset_handles.get(root).ok()?))
+			.flat_map(|asset| asset.modifiers.values())
+			.collect::<Vec<_>>();
+
+		// Morph target name -> clamped value
+		let values = properties
+			.values
+			.iter()
+			.filter(|(key, _)| apply_all || dirty.contains(key.as_str()))
+			.map(|(key, &value)| match find_modifier(&modifiers, key) {
+				Some(modifier) => (modifier.id.as_str(), modifier.clamp(value)),
+				None => (key.as_str(), value),
+			})
+			.collect::<HashMap<_, _>>();
+
+		l_entities.clear();
+		for &root in roots.iter() {
+			l_entities.insert(root);
+			l_entities.extend(q_children.iter_descendants(root));
+		}
+
+		for &entity in l_entities.iter() {
+			let Ok(mut weights) = q_morphs.get_mut(entity) else {
+				continue;
+			};
+			let Some(names) = weights
+				.first_mesh()
+				.and_then(|mesh| ra_meshes.get(mesh))
+				.and_then(|mesh| mesh.morph_target_names())
+			else {
+				continue;
+			};
+
+			for (idx, name) in names.iter().enumerate() {
+				let Some(&value) = values.get(name.as_str()) else {
+					continue;
+				};
+				if weights.weights()[idx] != value {
+					weights.weights_mut()[idx] = value;
+				}
+			}
+		}
+	}
+}
+
+/// Moves the figure's bones to their shaped rest positions, and gives the
+/// figure's meshes (and those of its fitted assets) their own inverse
+/// bindposes to match. Unshaped figures keep sharing their asset's bindposes.
+///
+/// Bones of fitted assets that the figure doesn't have follow the figure bone
+/// they were moved under, so their rest poses are offset by that bone's.
+///
+/// Only the bones moved by modifiers whose values changed are updated, along
+/// with the meshes bound to them.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn apply_daz_shaping(
+	ra_daz_assets: Res<Assets<DazAsset>>,
+	ra_nodes: Res<Assets<DazNode>>,
+	mut ra_bindposes: ResMut<Assets<SkinnedMeshInverseBindposes>>,
+	q_figures: Query<
+		(Entity, Ref<DazProperties>, &Handle<DazAsset>, &DazSkeleton),
+		(With<DazFigure>, With<DazReady>),
+	>,
+	q_fitted: Query<(Entity, &FitTo)>,
+	q_ready: Query<(), Added<DazReady>>,
+	q_asset_handles: Query<&Handle<DazAsset>>,
+	q_skeletons: Query<&DazSkeleton>,
+	q_children: Query<&Children>,
+	mut q_bones: Query<(&mut DazBone, &mut Transform)>,
+	mut q_skinned_meshes: Query<&mut SkinnedMesh>,
+	mut l_shaped: Local<EntityHashSet>,
+	mut l_moved: Local<EntityHashSet>,
+	mut l_owned_bindposes: Local<HashSet<AssetId<SkinnedMeshInverseBindposes>>>,
+) {
+	for (figure, properties, asset_handle, skeleton) in q_figures.iter() {
+		let roots = std::iter::once(figure)
+			.chain(
+				q_fitted
+					.iter()
+					.filter(|(_, fit_to)| fit_to.0 == figure)
+					.map(|(entity, _)| entity),
+			)
+			.collect::<Vec<_>>();
+
+		let is_ready = roots.iter().any(|&root| q_ready.contains(root));
+		if !properties.is_changed() && !is_ready {
+			continue;
+		}
+		let Some(asset) = ra_daz_assets.get(asset_handle) else {
+			continue;
+		};
+
+		let modifiers = roots
+			.iter()
+			.filter_map(|&root| ra_daz_assets.get(q_asset_handles.get(root).ok()?))
+			.flat_map(|asset| asset.modifiers.values())
+			.collect::<Vec<_>>();
+
+		// IDs of the nodes moved by the changed values, or `None` if every value
+		// needs applying, like in `apply_daz_properties`
+		let touched = (!is_ready && !properties.dirty.is_empty()).then(|| {
+			properties
+				.dirty
+				.iter()
+				.filter_map(|key| find_modifier(&modifiers, key))
+				.flat_map(|modifier| modifier.joint_adjustments.iter())
+				.map(|adjustment| adjustment.node.as_str())
+				.collect::<HashSet<_>>()
+		});
+		if touched.as_ref().is_some_and(HashSet::is_empty) {
+			continue;
+		}
+		let is_touched = |id: &str| touched.as_ref().is_none_or(|touched| touched.contains(id));
+		l_moved.clear();
+
+		// Node ID -> (center point offset, end point offset)
+		let mut offsets = HashMap::<&str, (Vec3, Vec3)>::default();
+		for (key, value) in properties.iter() {
+			let Some(modifier) = find_modifier(&modifiers, key) else {
+				continue;
+			};
+			let value = modifier.clamp(value);
+			for adjustment in modifier.joint_adjustments.iter() {
+				let offset = offsets.entry(adjustment.node.as_str()).or_default();
+				offset.0 += adjustment.center_point * value;
+				offset.1 += adjustment.end_point * value;
+			}
+		}
+
+		// Nothing to do for figures that were never shaped
+		if offsets.is_empty() && !l_shaped.remove(&figure) {
+			continue;
+		}
+		if !offsets.is_empty() {
+			l_shaped.insert(figure);
+		}
+
+		let shaped_root_transform = |node: &DazNode| {
+			let (center_offset, _) = offsets.get(node.id.as_str()).copied().unwrap_or_default();
+			let mut xform = node.root_transform.compute_transform();
+			xform.translation += center_offset;
+			GlobalTransform::from(xform)
+		};
+
+		for (id, &bone) in skeleton.bones.iter() {
+			let Some(node) = asset.nodes.get(id).and_then(|handle| ra_nodes.get(handle)) else {
+				continue;
+			};
+			// Bones are positioned relative to their parent
+			if !is_touched(id) && !node.parent.as_deref().is_some_and(is_touched) {
+				continue;
+			}
+			let Ok((mut daz_bone, mut xform)) = q_bones.get_mut(bone) else {
+				continue;
+			};
+			l_moved.insert(bone);
+
+			let root_xform = shaped_root_transform(node);
+			let parent_root_xform = node
+				.parent
+				.as_ref()
+				.and_then(|id| asset.nodes.get(id))
+				.and_then(|handle| ra_nodes.get(handle))
+				.map(shaped_root_transform)
+				.unwrap_or_default();
+
+			let (_, end_offset) = offsets.get(id.as_str()).copied().unwrap_or_default();
+			let end_point = node.root_transform.transform_point(node.end_point) + end_offset;
+			let inverse_bindpose = root_xform.affine().inverse();
+
+			xform.translation = root_xform.reparented_to(&parent_root_xform).translation;
+			daz_bone.end_point = inverse_bindpose.transform_point3(end_point);
+			daz_bone.inverse_bindpose = DualQuat::from(inverse_bindpose);
+		}
+
+		// Figure bone -> node ID
+		let figure_ids = skeleton
+			.bones
+			.iter()
+			.map(|(id, &bone)| (bone, id.as_str()))
+			.collect::<EntityHashMap<_>>();
+		for &root in roots.iter().skip(1) {
+			let Ok(fitted_skeleton) = q_skeletons.get(root) else {
+				continue;
+			};
+			let Some(fitted_asset) = q_asset_handles
+				.get(root)
+				.ok()
+				.and_then(|handle| ra_daz_assets.get(handle))
+			else {
+				continue;
+			};
+			let fitted_node = |id: &str| {
+				let handle = fitted_asset.nodes.get(id)?;
+				ra_nodes.get(handle)
+			};
+
+			for (id, &bone) in fitted_skeleton.bones.iter() {
+				if figure_ids.contains_key(&bone) {
+					continue;
+				}
+				let Some(node) = fitted_node(id) else {
+					continue;
+				};
+
+				// The closest ancestor that was bound to a figure bone
+				let mut parent = node.parent.as_deref();
+				let leader = loop {
+					let Some(id) = parent else {
+						break None;
+					};
+					let figure_id = fitted_skeleton
+						.bone(id)
+						.and_then(|bone| figure_ids.get(&bone));
+					if let Some(&figure_id) = figure_id {
+						break Some(figure_id);
+					}
+					parent = fitted_node(id).and_then(|node| node.parent.as_deref());
+				};
+				if leader.is_some_and(|id| !is_touched(id)) {
+					continue;
+				}
+				let leader_offset = leader
+					.and_then(|id| asset.nodes.get(id))
+					.and_then(|handle| ra_nodes.get(handle))
+					.map_or(Affine3A::IDENTITY, |leader| {
+						shaped_root_transform(leader).affine() * leader.inverse_bindpose
+					});
+
+				if let Ok((mut daz_bone, _)) = q_bones.get_mut(bone) {
+					let root_xform = leader_offset * node.root_transform.affine();
+					daz_bone.inverse_bindpose = DualQuat::from(root_xform.inverse());
+					l_moved.insert(bone);
+				}
+			}
+		}
+
+		for &root in roots.iter() {
+			for entity in std::iter::once(root).chain(q_children.iter_descendants(root)) {
+				let Ok(mut skinned_mesh) = q_skinned_meshes.get_mut(entity) else {
+					continue;
+				};
+				if !skinned_mesh
+					.joints
+					.iter()
+					.any(|joint| l_moved.contains(joint))
+				{
+					continue;
+				}
+				let inverse_bindposes = skinned_mesh
+					.joints
+					.iter()
+					.map(|&joint| {
+						q_bones.get(joint).map_or(Mat4::IDENTITY, |(daz_bone, _)| {
+							Mat4::from(daz_bone.inverse_bindpose)
+						})
+					})
+					.collect::<Vec<_>>();
+				let inverse_bindposes = SkinnedMeshInverseBindposes::from(inverse_bindposes);
+
+				// Shaped figures can't share their bindposes with other instances
+				let id = skinned_mesh.inverse_bindposes.id();
+				if l_owned_bindposes.contains(&id) {
+					ra_bindposes.insert(id, inverse_bindposes);
+				} else {
+					let handle = ra_bindposes.add(inverse_bindposes);
+					l_owned_bindposes.insert(handle.id());
+					skinned_mesh.inverse_bindposes = handle;
+				}
+			}
+		}
+	}
+}
+
+fn find_modifier<'a>(modifiers: &[&'a DazModifier], key: &str) -> Option<&'a DazModifier> {
+	modifiers
+		.iter()
+		.find(|modifier| modifier.id == key)
+		.or_else(|| modifiers.iter().find(|modifier| modifier.is_keyed_by(key)))
+		.copied()
+}
+
+#[cfg(test)]
+mod tests {
+	use bevy::{
+		math::Affine3A,
+		prelude::*,
+		render::{
+			mesh::{
+				morph::MorphWeights,
+				skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+			},
+			render_asset::RenderAssetUsages,
+			render_resource::PrimitiveTopology,
+		},
+	};
+	use daz_asset_types::{ChannelFloat, NodeType};
+
+	use super::{apply_daz_shaping, DazProperties, DazPropertiesPlugin};
+	use crate::{
+		testing::{app, asset, node},
+		DazBone, DazFigure, DazModifier, DazMorph, DazReady, DazSkeleton, FitTo, JointAdjustment,
+	};
+
+	/// Moves the chest up by 0.2 per unit.
+	fn tall() -> DazModifier {
+		DazModifier {
+			id: "Tall".into(),
+			label: None,
+			channel: ChannelFloat::new("Tall", "Tall", 0.),
+			joint_adjustments: vec![JointAdjustment {
+				node: "chest".into(),
+				center_point: Vec3::Y * 0.2,
+				end_point: Vec3::Y * 0.2,
+			}],
+			morph: None,
+		}
+	}
+
+	#[test]
+	fn applies_clamped_values_to_morph_weights() {
+		let mut app = app();
+		app.add_plugins(DazPropertiesPlugin);
+
+		let mut smile_channel = ChannelFloat::new("value", "Smile", 0.);
+		smile_channel.clamped = true;
+		let figure_asset = asset(
+			&mut app,
+			vec![
+				node("hip", NodeType::Bone, None, Vec3::Y),
+				node("chest", NodeType::Bone, Some("hip"), Vec3::Y * 1.5),
+			],
+			vec![tall(), DazModifier {
+				id: "Smile".into(),
+				label: Some("Big Smile".into()),
+				channel: smile_channel,
+				joint_adjustments: vec![],
+				morph: Some(DazMorph {
+					geometry: "Face".into(),
+					vertex_count: None,
+					deltas: vec![],
+				}),
+			}],
+		);
+		let mesh = app.world.resource_mut::<Assets<Mesh>>().add(
+			Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all())
+				.with_morph_target_names(vec!["Tall".into(), "Smile".into()]),
+		);
+
+		let chest = app
+			.world
+			.spawn((
+				DazBone::default(),
+				Transform::from_translation(Vec3::Y * 0.5),
+			))
+			.id();
+		let face = app
+			.world
+			.spawn(MorphWeights::new(vec![0.; 2], Some(mesh)).unwrap())
+			.id();
+		let figure = app
+			.world
+			.spawn((
+				figure_asset,
+				DazFigure,
+				DazReady,
+				DazProperties::default().with("Smile", 2.),
+				DazSkeleton {
+					bones: [("chest".into(), chest)].into(),
+				},
+			))
+			.add_child(face)
+			.id();
+
+		app.update();
+		let weights = |app: &App| {
+			app.world
+				.get::<MorphWeights>(face)
+				.unwrap()
+				.weights()
+				.to_vec()
+		};
+		assert_eq!(weights(&app), [0., 1.]);
+
+		// Values that don't shape the figure leave its bones alone
+		let moved = Transform::from_translation(Vec3::X);
+		*app.world.get_mut::<Transform>(chest).unwrap() = moved;
+		let mut properties = app.world.get_mut::<DazProperties>(figure).unwrap();
+		properties.set("Big Smile", 0.25);
+		app.update();
+		assert_eq!(weights(&app), [0., 0.25]);
+		assert_eq!(*app.world.get::<Transform>(chest).unwrap(), moved);
+
+		// Unclamped channels keep any value
+		let mut properties = app.world.get_mut::<DazProperties>(figure).unwrap();
+		properties.set("Tall", 1.5);
+		app.update();
+		assert_eq!(weights(&app), [1.5, 0.25]);
+		let chest_translation = app.world.get::<Transform>(chest).unwrap().translation;
+		assert!(chest_translation.abs_diff_eq(Vec3::Y * 0.8, 1e-5));
+	}
+
+	#[test]
+	fn shapes_figure_and_fitted_bones() {
+		let mut app = app();
+		app.add_systems(Update, apply_daz_shaping);
+
+		let figure_asset = asset(
+			&mut app,
+			vec![
+				node("hip", NodeType::Bone, None, Vec3::Y),
+				node("chest", NodeType::Bone, Some("hip"), Vec3::Y * 1.5),
+			],
+			vec![tall()],
+		);
+		let cape_rest = Vec3::new(0., 1.4, -0.1);
+		let fitted_asset = asset(
+			&mut app,
+			vec![
+				node("chest", NodeType::Bone, None, Vec3::Y * 1.5),
+				node("cape", NodeType::Bone, Some("chest"), cape_rest),
+			],
+			vec![],
+		);
+
+		let bone =
+			|translation: Vec3| (DazBone::default(), Transform::from_translation(translation));
+		let hip = app.world.spawn(bone(Vec3::Y)).id();
+		let chest = app.world.spawn(bone(Vec3::Y * 0.5)).id();
+		let cape = app.world.spawn(bone(cape_rest - Vec3::Y * 1.5)).id();
+
+		let figure = app
+			.world
+			.spawn((
+				figure_asset,
+				DazFigure,
+				DazReady,
+				DazProperties::default().with("Tall", 1.),
+				DazSkeleton {
+					bones: [("hip".into(), hip), ("chest".into(), chest)].into(),
+				},
+			))
+			.id();
+		let original_bindposes = app
+			.world
+			.resource_mut::<Assets<SkinnedMeshInverseBindposes>>()
+			.add(SkinnedMeshInverseBindposes::from(vec![Mat4::IDENTITY; 2]));
+		let mesh = app
+			.world
+			.spawn(SkinnedMesh {
+				inverse_bindposes: original_bindposes.clone(),
+				joints: vec![chest, cape],
+			})
+			.id();
+		app.world
+			.spawn((fitted_asset, FitTo(figure), DazReady, DazSkeleton {
+				bones: [("chest".into(), chest), ("cape".into(), cape)].into(),
+			}))
+			.add_child(mesh);
+
+		app.update();
+
+		let bone_rest = |bone: Entity| {
+			let daz_bone = app.world.get::<DazBone>(bone).unwrap();
+			Affine3A::from(daz_bone.inverse_bindpose)
+				.inverse()
+				.translation
+		};
+		let shaped_chest = Vec3::Y * 1.7;
+		assert!(bone_rest(chest).abs_diff_eq(shaped_chest.into(), 1e-5));
+		assert!(app
+			.world
+			.get::<Transform>(chest)
+			.unwrap()
+			.translation
+			.abs_diff_eq(Vec3::Y * 0.7, 1e-5));
+		// The cape bone follows the chest, instead of keeping its unshaped rest pose
+		let shaped_cape = cape_rest + Vec3::Y * 0.2;
+		assert!(bone_rest(cape).abs_diff_eq(shaped_cape.into(), 1e-5));
+
+		let skinned_mesh = app.world.get::<SkinnedMesh>(mesh).unwrap();
+		assert_ne!(skinned_mesh.inverse_bindposes, original_bindposes);
+		let bindposes = app.world.resource::<Assets<SkinnedMeshInverseBindposes>>();
+		let bindposes = bindposes.get(&skinned_mesh.inverse_bindposes).unwrap();
+		assert!(bindposes[0]
+			.transform_point3(shaped_chest)
+			.abs_diff_eq(Vec3::ZERO, 1e-5));
+		assert!(bindposes[1]
+			.transform_point3(shaped_cape)
+			.abs_diff_eq(Vec3::ZERO, 1e-5));
+	}
+}
@@ -0,0 +1,460 @@
+//! Retargeting of [AnimationClip]s between skeletons with different bone names
+//! and bind orientations -- e.g. between Genesis 8 and Genesis 9 figures, or
+//! from a Mixamo rig onto a Daz figure.
+//!
+//! Each skeleton's bones are mapped onto a canonical [HumanoidBone] with a
+//! [BoneMap]. Rotations are then transferred so that every mapped bone rotates
+//! by the same amount in world space, relative to its bind pose, as its
+//! counterpart in the source skeleton.
+
+use bevy::{
+	animation::{EntityPath, Interpolation, Keyframes, VariableCurve},
+	prelude::*,
+	utils::HashMap,
+};
+
+use crate::{DazAsset, DazBone, DazNode, NodeType};
+
+/// The bones of a canonical humanoid skeleton.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum HumanoidBone {
+	Hips,
+	Spine,
+	Chest,
+	UpperChest,
+	Neck,
+	Head,
+	Jaw,
+	LeftEye,
+	RightEye,
+	LeftShoulder,
+	LeftUpperArm,
+	LeftLowerArm,
+	LeftHand,
+	RightShoulder,
+	RightUpperArm,
+	RightLowerArm,
+	RightHand,
+	LeftUpperLeg,
+	LeftLowerLeg,
+	LeftFoot,
+	LeftToes,
+	RightUpperLeg,
+	RightLowerLeg,
+	RightFoot,
+	RightToes,
+}
+
+/// Maps a skeleton's bone names to [HumanoidBone]s.
+#[derive(Clone, Debug, Default)]
+pub struct BoneMap {
+	bones: HashMap<String, HumanoidBone>,
+}
+
+impl BoneMap {
+	pub fn new(bones: impl IntoIterator<Item = (impl Into<String>, HumanoidBone)>) -> Self {
+		Self {
+			bones: bones
+				.into_iter()
+				.map(|(name, bone)| (name.into(), bone))
+				.collect(),
+		}
+	}
+
+	pub fn genesis9() -> Self {
+		use HumanoidBone::*;
+
+		Self::new([
+			("hip", Hips),
+			("spine1", Spine),
+			("spine2", Chest),
+			("spine4", UpperChest),
+			("neck1", Neck),
+			("head", Head),
+			("lowerjaw", Jaw),
+			("l_eye", LeftEye),
+			("r_eye", RightEye),
+			("l_shoulder", LeftShoulder),
+			("l_upperarm", LeftUpperArm),
+			("l_forearm", LeftLowerArm),
+			("l_hand", LeftHand),
+			("r_shoulder", RightShoulder),
+			("r_upperarm", RightUpperArm),
+			("r_forearm", RightLowerArm),
+			("r_hand", RightHand),
+			("l_thigh", LeftUpperLeg),
+			("l_shin", LeftLowerLeg),
+			("l_foot", LeftFoot),
+			("l_toes", LeftToes),
+			("r_thigh", RightUpperLeg),
+			("r_shin", RightLowerLeg),
+			("r_foot", RightFoot),
+			("r_toes", RightToes),
+		])
+	}
+
+	pub fn genesis8() -> Self {
+		use HumanoidBone::*;
+
+		Self::new([
+			("hip", Hips),
+			("abdomenLower", Spine),
+			("chestLower", Chest),
+			("chestUpper", UpperChest),
+			("neckLower", Neck),
+			("head", Head),
+			("lowerJaw", Jaw),
+			("lEye", LeftEye),
+			("rEye", RightEye),
+			("lCollar", LeftShoulder),
+			("lShldrBend", LeftUpperArm),
+			("lForearmBend", LeftLowerArm),
+			("lHand", LeftHand),
+			("rCollar", RightShoulder),
+			("rShldrBend", RightUpperArm),
+			("rForearmBend", RightLowerArm),
+			("rHand", RightHand),
+			("lThighBend", LeftUpperLeg),
+			("lShin", LeftLowerLeg),
+			("lFoot", LeftFoot),
+			("lToe", LeftToes),
+			("rThighBend", RightUpperLeg),
+			("rShin", RightLowerLeg),
+			("rFoot", RightFoot),
+			("rToe", RightToes),
+		])
+	}
+
+	/// Mixamo bone names, with or without the `mixamorig:` prefix.
+	pub fn mixamo() -> Self {
+		use HumanoidBone::*;
+
+		Self::new([
+			("Hips", Hips),
+			("Spine", Spine),
+			("Spine1", Chest),
+			("Spine2", UpperChest),
+			("Neck", Neck),
+			("Head", Head),
+			("LeftEye", LeftEye),
+			("RightEye", RightEye),
+			("LeftShoulder", LeftShoulder),
+			("LeftArm", LeftUpperArm),
+			("LeftForeArm", LeftLowerArm),
+			("LeftHand", LeftHand),
+			("RightShoulder", RightShoulder),
+			("RightArm", RightUpperArm),
+			("RightForeArm", RightLowerArm),
+			("RightHand", RightHand),
+			("LeftUpLeg", LeftUpperLeg),
+			("LeftLeg", LeftLowerLeg),
+			("LeftFoot", LeftFoot),
+			("LeftToeBase", LeftToes),
+			("RightUpLeg", RightUpperLeg),
+			("RightLeg", RightLowerLeg),
+			("RightFoot", RightFoot),
+			("RightToeBase", RightToes),
+		])
+	}
+
+	/// Looks up a bone by name, ignoring any namespace prefix (e.g.
+	/// `mixamorig:`).
+	pub fn get(&self, name: &str) -> Option<HumanoidBone> {
+		self.bones.get(name).copied().or_else(|| {
+			let (_, unprefixed) = name.rsplit_once(':')?;
+			self.bones.get(unprefixed).copied()
+		})
+	}
+}
+
+/// The bind pose of a single mapped bone, as needed for retargeting.
+#[derive(Clone, Debug)]
+pub struct RetargetBone {
+	/// Path to the bone entity, relative to the entity with the
+	/// [AnimationPlayer].
+	pub path: EntityPath,
+	/// World-space bind rotation of the bone.
+	pub bind_rotation: Quat,
+	/// World-space bind rotation of the bone's parent.
+	pub parent_bind_rotation: Quat,
+	/// World-space bind translation of the bone.
+	pub bind_translation: Vec3,
+	/// Local rest translation of the bone.
+	pub rest_translation: Vec3,
+}
+
+/// The mapped bones of a skeleton in its bind pose.
+#[derive(Clone, Debug, Default)]
+pub struct RetargetSkeleton {
+	pub bones: HashMap<HumanoidBone, RetargetBone>,
+}
+
+impl RetargetSkeleton {
+	/// Builds a skeleton from a loaded [DazAsset], with entity paths relative to
+	/// the asset's root node (i.e. as spawned by the `DazSpawningPlugin`).
+	pub fn from_daz_asset(
+		asset: &DazAsset,
+		ra_nodes: &Assets<DazNode>,
+		bone_map: &BoneMap,
+	) -> Self {
+		let nodes = asset
+			.nodes
+			.iter()
+			.filter_map(|(id, handle)| Some((id.as_str(), ra_nodes.get(handle)?)))
+			.collect::<HashMap<_, _>>();
+
+		let bones = nodes
+			.values()
+			.filter(|node| node.type_ == NodeType::Bone)
+			.filter_map(|node| {
+				let humanoid = bone_map
+					.get(&node.id)
+					.or_else(|| bone_map.get(&node.name))?;
+
+				// The spawner names the root entity after the node's ID, and every
+				// other entity after the node's name
+				let mut parts = vec![];
+				let mut current = Some(*node);
+				while let Some(node) = current {
+					current = node
+						.parent
+						.as_ref()
+						.and_then(|id| nodes.get(id.as_str()).copied());
+					parts.push(Name::new(if current.is_some() {
+						node.name.clone()
+					} else {
+						node.id.clone()
+					}));
+				}
+				parts.reverse();
+
+				let parent_bind_rotation = node
+					.parent
+					.as_ref()
+					.and_then(|id| nodes.get(id.as_str()))
+					.map(|parent| parent.root_transform.compute_transform().rotation)
+					.unwrap_or_default();
+
+				let bind = node.root_transform.compute_transform();
+
+				Some((humanoid, RetargetBone {
+					path: EntityPath { parts },
+					bind_rotation: bind.rotation,
+					parent_bind_rotation,
+					bind_translation: bind.translation,
+					rest_translation: node.transform.translation,
+				}))
+			})
+			.collect();
+
+		Self { bones }
+	}
+
+	/// Builds a skeleton from a spawned hierarchy, with entity paths relative to
+	/// `root`.
+	///
+	/// Bind poses are taken from each [DazBone]'s inverse bindpose where present.
+	/// Otherwise, the hierarchy's current local transforms are assumed to be its
+	/// rest pose.
+	pub fn from_hierarchy(
+		root: Entity,
+		bone_map: &BoneMap,
+		q_nodes: &Query<(&Name, &Transform, Option<&DazBone>, Option<&Children>)>,
+	) -> Self {
+		let mut bones = HashMap::default();
+		let mut stack = vec![(root, vec![], GlobalTransform::IDENTITY)];
+
+		while let Some((entity, mut parts, parent_bind)) = stack.pop() {
+			let Ok((name, xform, daz_bone, children)) = q_nodes.get(entity) else {
+				continue;
+			};
+
+			let bind = match daz_bone {
+				Some(bone) => GlobalTransform::from(bone.inverse_bindpose.conjugate()),
+				None => parent_bind.mul_transform(*xform),
+			};
+			parts.push(name.clone());
+
+			if let Some(humanoid) = bone_map.get(name.as_str()) {
+				let (_, bind_rotation, bind_translation) = bind.to_scale_rotation_translation();
+				let (_, parent_bind_rotation, _) = parent_bind.to_scale_rotation_translation();
+
+				bones.insert(humanoid, RetargetBone {
+					path: EntityPath {
+						parts: parts.clone(),
+					},
+					bind_rotation,
+					parent_bind_rotation,
+					bind_translation,
+					rest_translation: xform.translation,
+				});
+			}
+
+			if let Some(children) = children {
+				stack.extend(children.iter().map(|&child| (child, parts.clone(), bind)));
+			}
+		}
+
+		Self { bones }
+	}
+
+	/// The bind height of the [HumanoidBone::Hips], used to scale root motion.
+	pub fn hip_height(&self) -> Option<f32> {
+		self.bones
+			.get(&HumanoidBone::Hips)
+			.map(|hips| hips.bind_translation.y)
+	}
+}
+
+/// Retargets `clip` from the `source` skeleton onto the `target` skeleton.
+///
+/// Rotation curves are transferred for every bone mapped in both skeletons.
+/// Translation curves are only transferred for the [HumanoidBone::Hips], scaled
+/// by the ratio of the two skeletons' hip heights. Everything else is dropped.
+///
+/// For a source bone with local rotation `L`, the target bone's local rotation
+/// is `Pt⁻¹ · Ps · L · Bs⁻¹ · Bt`, where `B` and `P` are the world-space bind
+/// rotations of the bone and its parent, respectively.
+pub fn retarget_clip(
+	clip: &AnimationClip,
+	source: &RetargetSkeleton,
+	target: &RetargetSkeleton,
+) -> AnimationClip {
+	let mut result = AnimationClip::default();
+
+	let hip_scale = match (source.hip_height(), target.hip_height()) {
+		(Some(src), Some(dst)) if src.abs() > f32::EPSILON => dst / src,
+		_ => 1.,
+	};
+
+	for (humanoid, src_bone) in source.bones.iter() {
+		let Some(dst_bone) = target.bones.get(humanoid) else {
+			continue;
+		};
+		let Some(curves) = clip.get_curves_by_path(&src_bone.path) else {
+			continue;
+		};
+
+		let pre = dst_bone.parent_bind_rotation.inverse() * src_bone.parent_bind_rotation;
+		let post = src_bone.bind_rotation.inverse() * dst_bone.bind_rotation;
+
+		for curve in curves {
+			// Cubic spline keyframes are stored as (in-tangent, value, out-tangent)
+			// triplets. The tangents are derivatives, so they're only transformed
+			// linearly.
+			let is_value = |idx: usize| match curve.interpolation {
+				Interpolation::CubicSpline => idx % 3 == 1,
+				_ => true,
+			};
+
+			let keyframes = match &curve.keyframes {
+				Keyframes::Rotation(rotations) => Keyframes::Rotation(
+					rotations
+						.iter()
+						.enumerate()
+						.map(|(idx, &rotation)| {
+							let result = pre * rotation * post;
+							if is_value(idx) {
+								result.normalize()
+							} else {
+								result
+							}
+						})
+						.collect(),
+				),
+				Keyframes::Translation(translations) if *humanoid == HumanoidBone::Hips => {
+					Keyframes::Translation(
+						translations
+							.iter()
+							.enumerate()
+							.map(|(idx, &translation)| {
+								if is_value(idx) {
+									dst_bone.rest_translation
+										+ pre
+											* (translation - src_bone.rest_translation)
+											* hip_scale
+								} else {
+									pre * translation * hip_scale
+								}
+							})
+							.collect(),
+					)
+				}
+				_ => continue,
+			};
+
+			result.add_curve_to_path(dst_bone.path.clone(), VariableCurve {
+				keyframe_timestamps: curve.keyframe_timestamps.clone(),
+				keyframes,
+				interpolation: curve.interpolation.clone(),
+			});
+		}
+	}
+
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use bevy::{
+		animation::{EntityPath, Interpolation, Keyframes, VariableCurve},
+		prelude::*,
+	};
+
+	use super::{retarget_clip, HumanoidBone, RetargetBone, RetargetSkeleton};
+
+	fn skeleton(name: &str, parent_bind: Quat, bind: Quat) -> RetargetSkeleton {
+		let mut result = RetargetSkeleton::default();
+		result
+			.bones
+			.insert(HumanoidBone::LeftUpperArm, RetargetBone {
+				path: EntityPath {
+					parts: vec![Name::new("root"), Name::new(name.to_owned())],
+				},
+				bind_rotation: bind,
+				parent_bind_rotation: parent_bind,
+				bind_translation: Vec3::ZERO,
+				rest_translation: Vec3::ZERO,
+			});
+		result
+	}
+
+	#[test]
+	fn preserves_world_space_rotation_delta() {
+		// Same world-space bind pose, but different local bone orientations
+		let src_parent = Quat::from_rotation_y(0.3);
+		let src_bind = src_parent * Quat::from_rotation_z(0.5);
+		let dst_parent = Quat::from_rotation_x(-0.7);
+		let dst_bind = Quat::from_rotation_x(1.1);
+
+		let source = skeleton("lShldrBend", src_parent, src_bind);
+		let target = skeleton("l_upperarm", dst_parent, dst_bind);
+
+		let src_local = Quat::from_euler(EulerRot::XYZ, 0.4, -0.2, 0.9);
+		let mut clip = AnimationClip::default();
+		clip.add_curve_to_path(
+			source.bones[&HumanoidBone::LeftUpperArm].path.clone(),
+			VariableCurve {
+				keyframe_timestamps: vec![0.],
+				keyframes: Keyframes::Rotation(vec![src_local]),
+				interpolation: Interpolation::Linear,
+			},
+		);
+
+		let result = retarget_clip(&clip, &source, &target);
+		let curves = result
+			.get_curves_by_path(&target.bones[&HumanoidBone::LeftUpperArm].path)
+			.unwrap();
+		let Keyframes::Rotation(rotations) = &curves[0].keyframes else {
+			panic!("Expected rotation keyframes");
+		};
+
+		// Parents are at rest, so the world-space deltas should match
+		let src_delta = (src_parent * src_local) * src_bind.inverse();
+		let dst_delta = (dst_parent * rotations[0]) * dst_bind.inverse();
+
+		assert!(
+			src_delta.abs_diff_eq(dst_delta, 1.0e-5) || src_delta.abs_diff_eq(-dst_delta, 1.0e-5)
+		);
+	}
+}
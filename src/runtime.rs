@@ -1,109 +1,248 @@
-use bevy::{ecs::entity::EntityHashMap, prelude::*, utils::HashMap};
+use bevy::{
+	ecs::entity::EntityHashSet,
+	prelude::*,
+	render::mesh::morph::{inherit_weights, MorphWeights},
+	transform::TransformSystem,
+	utils::HashMap,
+};
 
-use crate::{DazAsset, DazBone, DazFigure};
+use crate::{DazBone, DazFigure, DazSkeleton, FitTo};
 
 pub struct DazRuntimePlugin;
 
 impl Plugin for DazRuntimePlugin {
 	fn build(&self, app: &mut App) {
-		app.add_systems(PostUpdate, auto_follow_parent_skeletons);
+		app.register_type::<FollowBone>();
+
+		app.add_systems(
+			PostUpdate,
+			(
+				(
+					bind_follower_skeletons,
+					apply_deferred,
+					auto_follow_parent_skeletons,
+				)
+					.chain()
+					.before(TransformSystem::TransformPropagate),
+				auto_follow_figure_morphs.before(inherit_weights),
+			),
+		);
 	}
 }
 
+/// Makes a [DazBone] follow the local transform of another bone.
+///
 /// Daz "figures" are commonly split into separate skinned meshes -- one for the
 /// main body, one for the eyes, one for the mouth, etc. Each of these pieces
 /// have their own skeletons, which are at least partially identical to the
-/// "main" figure skeleton.
+/// "main" figure skeleton. When one of these assets is spawned as a direct child
+/// of a [DazFigure], each of its bones that has a counterpart in the figure's
+/// skeleton gets a `FollowBone` pointing to that counterpart.
 ///
-/// This system updates the transforms of the "child" skeleton joints to match
-/// the corresponding joints of the "parent" skeleton.
+/// The mapping is only recomputed when the follower's parent or either of the
+/// two [DazSkeleton]s change.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component)]
+pub struct FollowBone(pub Entity);
+
+impl FromWorld for FollowBone {
+	fn from_world(_: &mut World) -> Self {
+		Self(Entity::PLACEHOLDER)
+	}
+}
+
+#[allow(clippy::type_complexity)]
+fn bind_follower_skeletons(
+	mut cmd: Commands,
+	q_figures: Query<&DazSkeleton, With<DazFigure>>,
+	q_changed_figures: Query<&Children, (With<DazFigure>, Changed<DazSkeleton>)>,
+	q_changed_followers: Query<
+		Entity,
+		(
+			With<DazSkeleton>,
+			Or<(Changed<Parent>, Changed<DazSkeleton>)>,
+		),
+	>,
+	q_followers: Query<(Option<&Parent>, &DazSkeleton), (Without<DazFigure>, Without<FitTo>)>,
+	mut r_removed_parents: RemovedComponents<Parent>,
+	mut l_dirty: Local<EntityHashSet>,
+) {
+	l_dirty.clear();
+	l_dirty.extend(q_changed_followers.iter());
+	l_dirty.extend(q_changed_figures.iter().flatten().copied());
+	l_dirty.extend(r_removed_parents.read());
+
+	for follower in l_dirty.iter().copied() {
+		let Ok((parent, skeleton)) = q_followers.get(follower) else {
+			continue;
+		};
+		let leader = parent.and_then(|parent| q_figures.get(parent.get()).ok());
+
+		for (id, &bone) in skeleton.bones.iter() {
+			let Some(mut bone_cmd) = cmd.get_entity(bone) else {
+				continue;
+			};
+
+			match leader.and_then(|leader| leader.bone(id)) {
+				Some(leader_bone) if leader_bone != bone => {
+					bone_cmd.insert(FollowBone(leader_bone));
+				}
+				_ => {
+					bone_cmd.remove::<FollowBone>();
+				}
+			}
+		}
+	}
+}
+
+/// Copies the local transforms of [FollowBone] targets to their followers.
 fn auto_follow_parent_skeletons(
-	q_figures: Query<Entity, With<DazFigure>>,
+	mut q_followers: Query<(&FollowBone, &mut Transform)>,
+	q_leaders: Query<&Transform, (With<DazBone>, Without<FollowBone>)>,
+) {
+	q_followers
+		.par_iter_mut()
+		.for_each(|(&FollowBone(leader), mut xform)| {
+			if let Ok(leader_xform) = q_leaders.get(leader) {
+				xform.set_if_neq(*leader_xform);
+			}
+		});
+}
+
+/// Assets spawned with [FitTo] share their figure's skeleton, but their morphs
+/// are separate. This system copies the figure's morph weights to any
+/// identically-named morph targets of the fitted assets' meshes.
+///
+/// Only names are matched. Morphs of the figure's
+/// [DazProperties](crate::DazProperties) that a fitted asset has no version of
+/// are projected onto its meshes as morph targets of the same name, so they
+/// follow along too.
+fn auto_follow_figure_morphs(
+	ra_meshes: Res<Assets<Mesh>>,
+	q_fitted: Query<(Entity, &FitTo)>,
 	q_children: Query<&Children>,
-	q_other_asset_roots: Query<Entity, (With<Handle<DazAsset>>, Without<DazFigure>)>,
-	mut q_joints: Query<(&Name, &mut Transform), With<DazBone>>,
+	q_is_fitted: Query<(), With<FitTo>>,
+	mut q_morphs: Query<&mut MorphWeights>,
+	mut l_figure_weights: Local<HashMap<String, f32>>,
+	mut l_stack: Local<Vec<Entity>>,
 ) {
-	let follower_joint_sets = q_other_asset_roots
-		.iter()
-		.map(|root| {
-			(
-				root,
-				q_children
-					.iter_descendants(root)
-					.filter_map(|desc| {
-						q_joints
-							.get(desc)
-							.ok()
-							.map(|(name, _)| (name.to_string(), desc))
-					})
-					.collect::<HashMap<_, _>>(),
-			)
-		})
-		.collect::<EntityHashMap<_>>();
-
-	let parent_joint_sets = q_figures
-		.iter()
-		.filter_map(|root| {
-			let Ok(children) = q_children.get(root) else {
-				return None;
-			};
+	for (fitted, &FitTo(figure)) in q_fitted.iter() {
+		// Collect the figure's own morph weights, skipping over any fitted
+		// assets parented to it
+		l_figure_weights.clear();
+		l_stack.clear();
+		l_stack.push(figure);
 
-			let grandchildren = children
-				.iter()
-				.filter_map(|&child| q_children.get(child).ok())
-				.flatten();
-
-			grandchildren.copied().find_map(|grandchild| {
-				if q_joints.contains(grandchild) {
-					Some((root, grandchild))
-				} else {
-					None
+		while let Some(entity) = l_stack.pop() {
+			if let Ok(weights) = q_morphs.get(entity) {
+				if let Some(names) = morph_target_names(&ra_meshes, weights) {
+					for (name, &weight) in names.iter().zip(weights.weights()) {
+						l_figure_weights.insert(name.clone(), weight);
+					}
 				}
-			})
-		})
-		.map(|(root, root_joint)| {
-			(
-				root,
-				std::iter::once(root_joint)
-					.chain(
-						q_children
-							.iter_descendants(root_joint)
-							.filter(|&ent| q_joints.contains(ent)),
-					)
-					.collect::<Vec<_>>(),
-			)
-		})
-		.collect::<EntityHashMap<_>>();
+			}
 
-	for parent in q_figures.iter() {
-		let Some(parent_joints) = parent_joint_sets.get(&parent) else {
-			continue;
-		};
-		let Ok(children) = q_children.get(parent) else {
+			if let Ok(children) = q_children.get(entity) {
+				l_stack.extend(
+					children
+						.iter()
+						.filter(|&&child| !q_is_fitted.contains(child)),
+				);
+			}
+		}
+
+		if l_figure_weights.is_empty() {
 			continue;
-		};
-		let parent_joints = parent_joints
-			.iter()
-			.copied()
-			.map(|joint_ent| {
-				let (name, xform) = q_joints.get(joint_ent).unwrap();
-				(name.to_string(), *xform)
-			})
-			.collect::<Vec<_>>();
+		}
 
-		for child in children.iter() {
-			let Some(follower_joints) = follower_joint_sets.get(child) else {
+		for desc in std::iter::once(fitted).chain(q_children.iter_descendants(fitted)) {
+			let Ok(mut weights) = q_morphs.get_mut(desc) else {
+				continue;
+			};
+			let Some(names) = morph_target_names(&ra_meshes, &weights) else {
 				continue;
 			};
 
-			for (name, leader_xform) in parent_joints.iter() {
-				let Some(follower_joint) = follower_joints.get(name) else {
+			for (idx, name) in names.iter().enumerate() {
+				let Some(&leader_weight) = l_figure_weights.get(name) else {
 					continue;
 				};
-
-				let (_, mut follower_xform) = q_joints.get_mut(*follower_joint).unwrap();
-				*follower_xform = *leader_xform;
+				if weights.weights()[idx] != leader_weight {
+					weights.weights_mut()[idx] = leader_weight;
+				}
 			}
 		}
 	}
 }
+
+fn morph_target_names<'a>(
+	ra_meshes: &'a Assets<Mesh>,
+	weights: &MorphWeights,
+) -> Option<&'a [String]> {
+	ra_meshes.get(weights.first_mesh()?)?.morph_target_names()
+}
+
+#[cfg(test)]
+mod tests {
+	use bevy::prelude::*;
+
+	use super::{auto_follow_parent_skeletons, bind_follower_skeletons, FollowBone};
+	use crate::{DazBone, DazFigure, DazSkeleton};
+
+	fn skeleton(world: &mut World, ids: &[&str]) -> (Entity, Vec<Entity>) {
+		let bones = ids
+			.iter()
+			.map(|_| world.spawn((DazBone::default(), Transform::default())).id())
+			.collect::<Vec<_>>();
+		let skeleton = DazSkeleton {
+			bones: ids
+				.iter()
+				.map(|&id| id.to_owned())
+				.zip(bones.iter().copied())
+				.collect(),
+		};
+		let root = world.spawn(skeleton).push_children(&bones).id();
+
+		(root, bones)
+	}
+
+	#[test]
+	fn follows_the_figure_bones_of_child_skeletons() {
+		let mut app = App::new();
+		app.add_plugins(MinimalPlugins).add_systems(
+			Update,
+			(
+				bind_follower_skeletons,
+				apply_deferred,
+				auto_follow_parent_skeletons,
+			)
+				.chain(),
+		);
+
+		let (figure, figure_bones) = skeleton(&mut app.world, &["hip"]);
+		app.world.entity_mut(figure).insert(DazFigure);
+		let (eyes, eye_bones) = skeleton(&mut app.world, &["hip", "eye"]);
+		app.world.entity_mut(figure).add_child(eyes);
+
+		app.update();
+
+		let following = |world: &World, bone| world.get::<FollowBone>(bone).map(|follow| follow.0);
+		assert_eq!(following(&app.world, eye_bones[0]), Some(figure_bones[0]));
+		assert_eq!(following(&app.world, eye_bones[1]), None);
+
+		// Copied every frame through the cached mapping
+		let pose = Transform::from_xyz(1., 2., 3.);
+		*app.world.get_mut::<Transform>(figure_bones[0]).unwrap() = pose;
+		app.update();
+		assert_eq!(*app.world.get::<Transform>(eye_bones[0]).unwrap(), pose);
+
+		// Rebuilt when the follower's parent changes
+		app.world.entity_mut(eyes).remove_parent();
+		app.update();
+		assert_eq!(following(&app.world, eye_bones[0]), None);
+
+		app.world.entity_mut(figure).add_child(eyes);
+		app.update();
+		assert_eq!(following(&app.world, eye_bones[0]), Some(figure_bones[0]));
+	}
+}
@@ -1,15 +1,17 @@
-use std::collections::VecDeque;
-
 use bevy::{
-	ecs::entity::EntityHashSet,
-	pbr::ExtendedMaterial,
+	ecs::{
+		entity::{EntityHashMap, EntityMapper, MapEntities},
+		reflect::ReflectMapEntities,
+		system::SystemParam,
+	},
 	prelude::*,
-	render::mesh::skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+	render::mesh::skinning::SkinnedMesh,
+	scene::{SceneInstance, SceneInstanceReady},
 	utils::HashMap,
 };
-use bevy_dqskinning::{DqSkinningPlugin, DqsMaterialExt, DqsStandardMaterial, DualQuat};
+use bevy_dqskinning::{DqSkinningPlugin, DualQuat};
 
-use crate::{DazAsset, DazMesh, DazNode, NodeType};
+use crate::DazAsset;
 
 pub struct DazSpawningPlugin;
 
@@ -17,20 +19,24 @@ impl Plugin for DazSpawningPlugin {
 	fn build(&self, app: &mut App) {
 		app.add_plugins(DqSkinningPlugin);
 
-		app.insert_resource(DazSpawner::default());
-
 		app.register_type::<DazFigure>();
 		app.register_type::<DazBone>();
-
-		app.add_systems(Update, (queue_asset_spawns, spawn_daz_assets));
+		app.register_type::<DazSkeleton>();
+		app.register_type::<DazReady>();
+		app.register_type::<FitTo>();
+
+		app.add_event::<DazAssetSpawned>();
+
+		app.add_systems(
+			Update,
+			(
+				spawn_daz_assets,
+				finish_daz_asset_spawns.in_set(DazSpawningSet),
+			),
+		);
 	}
 }
 
-#[derive(Resource, Clone, Debug, Default)]
-struct DazSpawner {
-	pending: EntityHashSet,
-}
-
 #[derive(Component, Clone, Copy, Debug, Default, Reflect)]
 #[reflect(Component)]
 pub struct DazFigure;
@@ -42,168 +48,477 @@ pub struct DazBone {
 	pub inverse_bindpose: DualQuat,
 }
 
-fn queue_asset_spawns(
-	mut r_spawner: ResMut<DazSpawner>,
-	q_added_daz_assets: Query<Entity, Added<Handle<DazAsset>>>,
-) {
-	for ent in q_added_daz_assets.iter() {
-		r_spawner.pending.insert(ent);
+/// The bone entities that the meshes of a spawned [DazAsset] are bound to,
+/// keyed by Daz node ID. Inserted on the `Handle<DazAsset>` entity once it has
+/// been spawned.
+///
+/// In the asset's [Scene], each root node has the skeleton of the bones
+/// beneath it instead.
+///
+/// For assets spawned with [FitTo], this includes the bones shared with the
+/// target figure.
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct DazSkeleton {
+	pub bones: HashMap<String, Entity>,
+}
+
+impl DazSkeleton {
+	pub fn bone(&self, id: &str) -> Option<Entity> {
+		self.bones.get(id).copied()
 	}
 }
 
-#[allow(clippy::too_many_arguments)]
+impl MapEntities for DazSkeleton {
+	fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+		for bone in self.bones.values_mut() {
+			*bone = entity_mapper.map_entity(*bone);
+		}
+	}
+}
+
+/// Marks a `Handle<DazAsset>` entity whose scene has been spawned, once its
+/// [DazSkeleton] is in place and any [FitTo] binding is done.
+#[derive(Component, Clone, Copy, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct DazReady;
+
+/// Sent when a `Handle<DazAsset>` entity becomes [DazReady].
+#[derive(Event, Clone, Debug)]
+pub struct DazAssetSpawned {
+	/// The `Handle<DazAsset>` entity.
+	pub entity: Entity,
+	/// The entities spawned for the asset's root nodes, which are children of
+	/// `entity`.
+	pub root_nodes: Vec<Entity>,
+}
+
+/// The system that sends [DazAssetSpawned] events. Systems ordered after this
+/// set see the [DazReady] and [DazSkeleton] components of the spawned assets.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DazSpawningSet;
+
+/// Looks up the bones of spawned Daz assets by Daz node ID.
+#[derive(SystemParam)]
+pub struct DazBones<'w, 's> {
+	q_skeletons: Query<'w, 's, &'static DazSkeleton, With<DazReady>>,
+}
+
+impl DazBones<'_, '_> {
+	/// The bone entity for node `id` of the asset spawned on the `asset` entity,
+	/// or `None` if the asset isn't [DazReady] yet. For [FitTo] assets, bones
+	/// shared with the figure resolve to the figure's bones.
+	pub fn bone(&self, asset: Entity, id: &str) -> Option<Entity> {
+		self.q_skeletons.get(asset).ok()?.bone(id)
+	}
+}
+
+/// Fits a conforming asset (clothing, hair, etc.) to the [DazFigure] on the
+/// given entity.
+///
+/// Add this alongside the asset's `Handle<DazAsset>`. Once its scene has been
+/// spawned, the asset's skinned meshes are rebound directly to the figure's
+/// [DazBone] entities and its own copies of those bones are despawned. Any
+/// bones the figure doesn't have (e.g. skirt or hair bones) are moved under the
+/// corresponding figure bones.
+///
+/// The figure's morph weights are copied to the asset's morph targets by name.
+/// Morphs of the figure's that the asset doesn't have its own version of are
+/// projected onto it, by moving each of its vertices like the nearest point on
+/// the figure's surface. See [DazProperties](crate::DazProperties).
+///
+/// Spawning is deferred until the target figure itself has been spawned.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component)]
+pub struct FitTo(pub Entity);
+
+impl FromWorld for FitTo {
+	fn from_world(_: &mut World) -> Self {
+		Self(Entity::PLACEHOLDER)
+	}
+}
+
+/// Spawns the [Scene] of each loaded `Handle<DazAsset>` entity as its
+/// children, once any [FitTo] target has been spawned.
+#[allow(clippy::type_complexity)]
 fn spawn_daz_assets(
 	mut cmd: Commands,
 	r_assets: Res<AssetServer>,
-	mut r_spawner: ResMut<DazSpawner>,
 	ra_daz_assets: Res<Assets<DazAsset>>,
-	ra_daz_nodes: Res<Assets<DazNode>>,
-	ra_daz_meshes: Res<Assets<DazMesh>>,
-	mut ra_inverse_bindposes: ResMut<Assets<SkinnedMeshInverseBindposes>>,
-	mut ra_dqstandard_mats: ResMut<Assets<DqsStandardMaterial>>,
-	q_daz_assets: Query<&Handle<DazAsset>>,
-	mut l_assets_to_spawn: Local<Vec<Entity>>,
+	q_unspawned: Query<(Entity, &Handle<DazAsset>, Option<&FitTo>), Without<Handle<Scene>>>,
+	q_skeletons: Query<&DazSkeleton>,
 ) {
-	l_assets_to_spawn.clear();
-
-	let pending = r_spawner.pending.iter().copied().collect::<Vec<_>>();
-	for entity in pending {
-		if let Ok(handle) = q_daz_assets.get(entity) {
-			if r_assets.is_loaded_with_dependencies(handle) {
-				l_assets_to_spawn.push(entity);
-				r_spawner.pending.remove(&entity);
-			}
-		} else {
-			r_spawner.pending.remove(&entity);
+	for (entity, handle, fit_to) in q_unspawned.iter() {
+		let is_fit_target_ready = fit_to.is_none_or(|FitTo(figure)| q_skeletons.contains(*figure));
+		if !is_fit_target_ready || !r_assets.is_loaded_with_dependencies(handle) {
+			continue;
+		}
+
+		if let Some(asset) = ra_daz_assets.get(handle) {
+			cmd.entity(entity).insert(asset.scene.clone());
 		}
 	}
+}
 
-	for asset_entity in l_assets_to_spawn.iter().copied() {
-		let handle = q_daz_assets.get(asset_entity).unwrap();
-		let asset = ra_daz_assets.get(handle).unwrap();
-
-		let root_nodes = asset
-			.nodes
-			.iter()
-			.filter_map(|(_, handle)| {
-				ra_daz_nodes.get(handle).and_then(|node| {
-					if node.parent.is_none() {
-						Some(node)
-					} else {
-						None
-					}
-				})
+/// Collects the [DazSkeleton]s of a spawned [DazAsset]'s root nodes into one on
+/// the `Handle<DazAsset>` entity, and binds [FitTo] assets to their figure.
+#[allow(clippy::too_many_arguments)]
+fn finish_daz_asset_spawns(
+	mut cmd: Commands,
+	r_scene_spawner: Res<SceneSpawner>,
+	mut r_ready: EventReader<SceneInstanceReady>,
+	mut w_spawned: EventWriter<DazAssetSpawned>,
+	q_daz_assets: Query<(Option<&FitTo>, &SceneInstance), With<Handle<DazAsset>>>,
+	q_skeletons: Query<&DazSkeleton>,
+	q_children: Query<&Children>,
+	q_parents: Query<&Parent>,
+	q_names: Query<&Name>,
+	mut q_skinned_meshes: Query<&mut SkinnedMesh>,
+) {
+	for &SceneInstanceReady {
+		parent: asset_entity,
+	} in r_ready.read()
+	{
+		let Ok((fit_to, instance)) = q_daz_assets.get(asset_entity) else {
+			continue;
+		};
+
+		// The asset entity may have other children, e.g. fitted assets
+		let root_nodes = r_scene_spawner
+			.iter_instance_entities(**instance)
+			.filter(|&entity| {
+				q_parents
+					.get(entity)
+					.is_ok_and(|parent| parent.get() == asset_entity)
 			})
 			.collect::<Vec<_>>();
 
-		for root_node in root_nodes {
-			let mut spawned_entities = HashMap::<String, Entity>::default();
-			let mut spawned_nodes = HashMap::<String, &DazNode>::default();
-
-			let root_entity = cmd
-				.spawn((
-					Name::new(root_node.id.clone()),
-					SpatialBundle::from(root_node.transform),
-				))
-				.id();
-
-			cmd.entity(asset_entity).add_child(root_entity);
-			spawned_entities.insert(root_node.id.clone(), root_entity);
-			spawned_nodes.insert(root_node.id.clone(), root_node);
-
-			let mut nodes_to_spawn = VecDeque::new();
-			nodes_to_spawn.extend(root_node.children.iter().map(|node| node.id.clone()));
-
-			while let Some(next) = nodes_to_spawn.pop_front() {
-				let handle = asset.nodes.get(&next).unwrap();
-				let node = ra_daz_nodes.get(handle.clone()).unwrap();
-
-				let entity = cmd
-					.spawn((
-						Name::new(node.name.clone()),
-						SpatialBundle::from(node.transform),
-					))
-					.id();
-
-				if node.type_ == NodeType::Bone {
-					cmd.entity(entity).insert(DazBone {
-						end_point: node.end_point,
-						// TODO: Avoid doing this computation twice for each joint
-						inverse_bindpose: node.root_transform.affine().inverse().into(),
-					});
-				}
-
-				spawned_entities.insert(node.id.clone(), entity);
-				spawned_nodes.insert(node.id.clone(), node);
+		let mut skeleton = DazSkeleton::default();
+		for &root in root_nodes.iter() {
+			if let Ok(root_skeleton) = q_skeletons.get(root) {
+				skeleton.bones.extend(
+					root_skeleton
+						.bones
+						.iter()
+						.map(|(id, &bone)| (id.clone(), bone)),
+				);
+				cmd.entity(root).remove::<DazSkeleton>();
+			}
+		}
 
-				if let Some(parent_id) = node.parent.as_ref() {
-					let parent_ent = spawned_entities[parent_id];
-					cmd.entity(parent_ent).add_child(entity);
+		let figure_skeleton = fit_to.and_then(|FitTo(figure)| q_skeletons.get(*figure).ok());
+		if let Some(figure_skeleton) = figure_skeleton {
+			// Bind to the fit target's bones instead of the spawned duplicates
+			let mut figure_bones = EntityHashMap::default();
+			for (id, bone) in skeleton.bones.iter_mut() {
+				let figure_bone = figure_skeleton.bone(id).or_else(|| {
+					let name = q_names.get(*bone).ok()?;
+					figure_skeleton.bone(name.as_str())
+				});
+				if let Some(figure_bone) = figure_bone {
+					figure_bones.insert(*bone, figure_bone);
+					*bone = figure_bone;
 				}
-
-				nodes_to_spawn.extend(node.children.iter().map(|node| node.id.clone()));
 			}
 
-			for (id, &node) in spawned_nodes.iter() {
-				let Some(daz_mesh_handle) = node.mesh.as_ref() else {
-					continue;
-				};
-				let node_entity = spawned_entities[id];
-
-				let daz_mesh = ra_daz_meshes.get(daz_mesh_handle).unwrap();
-				let joints = daz_mesh
-					.joints
-					.iter()
-					.map(|id| spawned_entities[id])
-					.collect::<Vec<_>>();
-
-				let skinned_mesh = if !joints.is_empty() {
-					let inverse_bindposes = daz_mesh
-						.joints
-						.iter()
-						.map(|id| {
-							// TODO: Avoid doing this computation twice for each joint
-							Mat4::from(spawned_nodes[id].root_transform.affine().inverse())
-						})
-						.collect::<Vec<_>>();
-
-					let handle = ra_inverse_bindposes.add(inverse_bindposes);
-
-					Some(SkinnedMesh {
-						inverse_bindposes: handle,
-						joints,
-					})
-				} else {
-					None
-				};
-
-				for primitive in daz_mesh.primitives.iter() {
-					let mesh_entity = cmd
-						.spawn(MaterialMeshBundle {
-							mesh: primitive.mesh.clone(),
-							material: primitive.material.clone().unwrap_or_else(|| {
-								ra_dqstandard_mats.add(ExtendedMaterial {
-									base: StandardMaterial {
-										base_color: Color::hex("AAAAAA").unwrap(),
-										metallic: 0.,
-										perceptual_roughness: 0.55,
-										reflectance: 0.45,
-										..default()
-									},
-									extension: DqsMaterialExt::default(),
-								})
-							}),
-							..default()
-						})
-						.id();
-
-					if let Some(skinned_mesh) = skinned_mesh.as_ref() {
-						cmd.entity(mesh_entity).insert(skinned_mesh.clone());
+			for desc in q_children.iter_descendants(asset_entity) {
+				if let Ok(mut skinned_mesh) = q_skinned_meshes.get_mut(desc) {
+					for joint in skinned_mesh.joints.iter_mut() {
+						if let Some(&figure_bone) = figure_bones.get(joint) {
+							*joint = figure_bone;
+						}
 					}
+				}
+			}
 
-					cmd.entity(node_entity).add_child(mesh_entity);
+			// Bones the figure doesn't have are moved under the corresponding
+			// figure bones, before the duplicates are despawned
+			for (&bone, &figure_bone) in figure_bones.iter() {
+				for &child in q_children.get(bone).into_iter().flatten() {
+					if !figure_bones.contains_key(&child) {
+						cmd.entity(figure_bone).add_child(child);
+					}
 				}
 			}
+			for &bone in figure_bones.keys() {
+				let is_topmost = !q_parents
+					.get(bone)
+					.is_ok_and(|parent| figure_bones.contains_key(&parent.get()));
+				if is_topmost {
+					cmd.entity(bone).despawn_recursive();
+				}
+			}
+		}
+
+		cmd.entity(asset_entity).insert((skeleton, DazReady));
+		w_spawned.send(DazAssetSpawned {
+			entity: asset_entity,
+			root_nodes,
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bevy::{
+		prelude::*,
+		render::mesh::skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+		scene::ScenePlugin,
+		utils::HashMap,
+	};
+
+	use super::{finish_daz_asset_spawns, spawn_daz_assets, DazAssetSpawned, DazSpawningSet};
+	use crate::{
+		testing::{load, loader_app, FIGURE_DSF},
+		DazAsset, DazBone, DazReady, DazSkeleton, FitTo,
+	};
+
+	fn app() -> App {
+		let mut app = App::new();
+		app.add_plugins((
+			MinimalPlugins,
+			AssetPlugin::default(),
+			ScenePlugin,
+			HierarchyPlugin,
+			TransformPlugin,
+		))
+		.init_asset::<DazAsset>()
+		.init_asset::<SkinnedMeshInverseBindposes>()
+		.register_type::<DazBone>()
+		.register_type::<DazSkeleton>()
+		.register_type::<SkinnedMesh>()
+		.add_event::<DazAssetSpawned>()
+		.add_systems(Update, finish_daz_asset_spawns);
+
+		app
+	}
+
+	/// An app that loads [FIGURE_DSF] and spawns it with the spawning systems.
+	fn figure_app() -> App {
+		let mut app = loader_app(&[("figure.dsf", FIGURE_DSF)]);
+		app.add_event::<DazAssetSpawned>().add_systems(
+			Update,
+			(
+				spawn_daz_assets,
+				finish_daz_asset_spawns.in_set(DazSpawningSet),
+			),
+		);
+
+		app
+	}
+
+	fn bone(world: &mut World, name: &str) -> Entity {
+		world
+			.spawn((
+				Name::new(name.to_owned()),
+				DazBone::default(),
+				TransformBundle::default(),
+			))
+			.id()
+	}
+
+	/// A [DazAsset] whose scene is a hip bone with a skirt bone beneath it, and
+	/// a mesh skinned to both.
+	fn skirt_asset(app: &mut App) -> Handle<DazAsset> {
+		let mut world = World::new();
+		let hip = bone(&mut world, "hip");
+		let skirt = bone(&mut world, "skirt");
+		let mesh = world
+			.spawn((TransformBundle::default(), SkinnedMesh {
+				inverse_bindposes: Handle::default(),
+				joints: vec![hip, skirt],
+			}))
+			.id();
+		world
+			.entity_mut(hip)
+			.insert(DazSkeleton {
+				bones: [("hip".into(), hip), ("skirt".into(), skirt)].into(),
+			})
+			.push_children(&[skirt, mesh]);
+
+		let scene = app
+			.world
+			.resource_mut::<Assets<Scene>>()
+			.add(Scene::new(world));
+		app.world.resource_mut::<Assets<DazAsset>>().add(DazAsset {
+			scene,
+			meshes: HashMap::default(),
+			nodes: HashMap::default(),
+			modifiers: HashMap::default(),
+			materials: HashMap::default(),
+			uv_sets: HashMap::default(),
+		})
+	}
+
+	#[test]
+	fn binds_fitted_assets_to_figure_bones() {
+		let mut app = app();
+
+		let figure_hip = bone(&mut app.world, "hip");
+		let figure = app
+			.world
+			.spawn((DazReady, DazSkeleton {
+				bones: [("hip".into(), figure_hip)].into(),
+			}))
+			.add_child(figure_hip)
+			.id();
+
+		let asset = skirt_asset(&mut app);
+		let scene = app
+			.world
+			.resource::<Assets<DazAsset>>()
+			.get(&asset)
+			.unwrap()
+			.scene
+			.clone();
+		let fitted = app
+			.world
+			.spawn((asset, scene, FitTo(figure), TransformBundle::default()))
+			.id();
+
+		// Spawned after `Update`, so bound on the next one
+		app.update();
+		app.update();
+
+		let skeleton = app.world.get::<DazSkeleton>(fitted).unwrap();
+		assert_eq!(skeleton.bone("hip"), Some(figure_hip));
+		let skirt = skeleton.bone("skirt").unwrap();
+		assert_ne!(skirt, figure_hip);
+		assert!(app.world.get::<DazReady>(fitted).is_some());
+
+		// Bones the figure doesn't have move under its bones
+		assert_eq!(
+			app.world.get::<Parent>(skirt).map(Parent::get),
+			Some(figure_hip)
+		);
+		let mesh = app
+			.world
+			.query::<(Entity, &SkinnedMesh)>()
+			.iter(&app.world)
+			.map(|(entity, skinned_mesh)| (entity, skinned_mesh.joints.clone()))
+			.next()
+			.unwrap();
+		assert_eq!(mesh.1, [figure_hip, skirt]);
+
+		// The spawned hip is despawned, along with the skeleton on it
+		let hips = app
+			.world
+			.query::<&Name>()
+			.iter(&app.world)
+			.filter(|name| name.as_str() == "hip")
+			.count();
+		assert_eq!(hips, 1);
+		let skeletons = app.world.query::<&DazSkeleton>().iter(&app.world).count();
+		assert_eq!(skeletons, 2);
+
+		let spawned = app.world.resource::<Events<DazAssetSpawned>>();
+		let spawned = spawned
+			.get_reader()
+			.read(spawned)
+			.map(|event| event.entity)
+			.collect::<Vec<_>>();
+		assert_eq!(spawned, [fitted]);
+	}
+
+	#[test]
+	fn maps_skeletons_to_spawned_bones() {
+		let mut app = figure_app();
+		let handle = load(&mut app, "figure.dsf");
+		let figure = app.world.spawn((handle, TransformBundle::default())).id();
+
+		app.update();
+		app.update();
+
+		let skeleton = app.world.get::<DazSkeleton>(figure).unwrap().clone();
+		assert_eq!(skeleton.bones.len(), 2);
+		for (id, &bone) in skeleton.bones.iter() {
+			assert_eq!(app.world.get::<Name>(bone).unwrap().as_str(), id);
+			assert!(app.world.get::<DazBone>(bone).is_some());
+			let is_descendant = std::iter::successors(Some(bone), |&entity| {
+				app.world.get::<Parent>(entity).map(Parent::get)
+			})
+			.any(|entity| entity == figure);
+			assert!(is_descendant);
+		}
+
+		// Moved from the root node to the asset entity
+		let skeletons = app.world.query::<&DazSkeleton>().iter(&app.world).count();
+		assert_eq!(skeletons, 1);
+	}
+
+	#[test]
+	fn reports_fitted_assets_once_after_their_figure() {
+		#[derive(Resource, Default)]
+		struct Spawned(Vec<(Entity, usize)>);
+
+		let mut app = figure_app();
+		app.init_resource::<Spawned>().add_systems(
+			Update,
+			(|mut r_spawned: EventReader<DazAssetSpawned>, mut spawned: ResMut<Spawned>| {
+				for event in r_spawned.read() {
+					spawned.0.push((event.entity, event.root_nodes.len()));
+				}
+			})
+			.after(DazSpawningSet),
+		);
+		let handle = load(&mut app, "figure.dsf");
+
+		// Given its asset further down
+		let figure = app.world.spawn(TransformBundle::default()).id();
+		let fitted = app
+			.world
+			.spawn((handle.clone(), FitTo(figure), TransformBundle::default()))
+			.id();
+
+		for _ in 0..4 {
+			app.update();
+		}
+		assert!(app.world.get::<Handle<Scene>>(fitted).is_none());
+		assert!(app.world.get::<DazReady>(fitted).is_none());
+		assert!(app.world.resource::<Spawned>().0.is_empty());
+
+		app.world.entity_mut(figure).insert(handle);
+		for _ in 0..8 {
+			app.update();
+		}
+
+		assert!(app.world.get::<DazReady>(figure).is_some());
+		assert!(app.world.get::<DazReady>(fitted).is_some());
+		assert_eq!(app.world.resource::<Spawned>().0, [
+			(figure, 1),
+			(fitted, 1)
+		]);
+	}
+
+	#[test]
+	fn shares_inverse_bindposes_between_instances() {
+		let mut app = figure_app();
+		let handle = load(&mut app, "figure.dsf");
+		for _ in 0..2 {
+			app.world
+				.spawn((handle.clone(), TransformBundle::default()));
 		}
+
+		app.update();
+		app.update();
+
+		let inverse_bindposes = app
+			.world
+			.query::<&SkinnedMesh>()
+			.iter(&app.world)
+			.map(|skinned_mesh| skinned_mesh.inverse_bindposes.clone())
+			.collect::<Vec<_>>();
+		let labeled = app
+			.world
+			.resource::<AssetServer>()
+			.get_handle::<SkinnedMeshInverseBindposes>("figure.dsf#Triangle/InverseBindposes")
+			.unwrap();
+		assert_eq!(inverse_bindposes, [labeled.clone(), labeled.clone()]);
+		assert_eq!(
+			app.world
+				.resource::<Assets<SkinnedMeshInverseBindposes>>()
+				.len(),
+			1
+		);
 	}
 }
@@ -0,0 +1,195 @@
+//! Helpers shared by the unit tests.
+
+use std::{path::Path, time::Duration};
+
+use bevy::{
+	asset::{
+		io::{
+			memory::{Dir, MemoryAssetReader},
+			AssetSource, AssetSourceId,
+		},
+		LoadState,
+	},
+	math::Affine3A,
+	prelude::*,
+	render::mesh::skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+	scene::ScenePlugin,
+	utils::HashMap,
+};
+use daz_asset_types::NodeType;
+
+use crate::{
+	DazAsset, DazAssetTypesPlugin, DazBone, DazMesh, DazModifier, DazNode, DazSkeleton,
+	DqsStandardMaterial,
+};
+
+/// A figure with a hip bone and a thigh bone beneath it, and a triangle skinned
+/// to both.
+pub const FIGURE_DSF: &str = r##"{
+	"file_version": "0.6.0.0",
+	"asset_info": { "id": "/figure.dsf", "contributor": { "author": "Test" } },
+	"geometry_library": [{
+		"id": "Triangle",
+		"name": "Figure",
+		"vertices": { "count": 3, "values": [[0, 100, 0], [10, 100, 0], [0, 50, 0]] },
+		"polygon_groups": { "count": 1, "values": ["Default"] },
+		"polygon_material_groups": { "count": 1, "values": ["Skin"] },
+		"polylist": { "count": 1, "values": [[0, 0, 0, 1, 2]] }
+	}],
+	"node_library": [
+		{ "id": "Figure", "name": "Figure", "label": "Figure", "type": "figure" },
+		{
+			"id": "hip",
+			"name": "hip",
+			"label": "Hip",
+			"type": "bone",
+			"parent": "#Figure",
+			"center_point": [
+				{ "id": "x", "type": "float", "name": "xOrigin", "value": 0 },
+				{ "id": "y", "type": "float", "name": "yOrigin", "value": 100 },
+				{ "id": "z", "type": "float", "name": "zOrigin", "value": 0 }
+			]
+		},
+		{
+			"id": "thigh",
+			"name": "thigh",
+			"label": "Thigh",
+			"type": "bone",
+			"parent": "#hip",
+			"center_point": [
+				{ "id": "x", "type": "float", "name": "xOrigin", "value": 0 },
+				{ "id": "y", "type": "float", "name": "yOrigin", "value": 50 },
+				{ "id": "z", "type": "float", "name": "zOrigin", "value": 0 }
+			]
+		}
+	],
+	"modifier_library": [{
+		"id": "SkinBinding",
+		"parent": "#Figure",
+		"skin": {
+			"node": "#Figure",
+			"geometry": "#Triangle",
+			"vertex_count": 3,
+			"joints": [
+				{ "id": "hip", "node": "#hip", "node_weights": { "count": 2, "values": [[0, 1], [1, 1]] } },
+				{ "id": "thigh", "node": "#thigh", "node_weights": { "count": 1, "values": [[2, 1]] } }
+			]
+		}
+	}]
+}"##;
+
+/// An app with the asset collections the DAZ systems read, but no plugins.
+pub fn app() -> App {
+	let mut app = App::new();
+	app.init_resource::<Assets<DazAsset>>()
+		.init_resource::<Assets<DazNode>>()
+		.init_resource::<Assets<DazMesh>>()
+		.init_resource::<Assets<Mesh>>()
+		.init_resource::<Assets<SkinnedMeshInverseBindposes>>()
+		.init_resource::<Assets<DqsStandardMaterial>>()
+		.init_resource::<Assets<Image>>();
+
+	app
+}
+
+/// A node at rest at `translation`, in root space.
+pub fn node(id: &str, type_: NodeType, parent: Option<&str>, translation: Vec3) -> DazNode {
+	DazNode {
+		id: id.into(),
+		name: id.into(),
+		type_,
+		mesh: None,
+		root_transform: GlobalTransform::from_translation(translation),
+		inverse_bindpose: Affine3A::from_translation(-translation),
+		transform: Transform::from_translation(translation),
+		parent: parent.map(str::to_owned),
+		children: vec![],
+		end_point: Vec3::ZERO,
+	}
+}
+
+/// Adds an asset with `nodes` and `modifiers` and no scene.
+pub fn asset(app: &mut App, nodes: Vec<DazNode>, modifiers: Vec<DazModifier>) -> Handle<DazAsset> {
+	let nodes = nodes
+		.into_iter()
+		.map(|node| {
+			let id = node.id.clone();
+			(id, app.world.resource_mut::<Assets<DazNode>>().add(node))
+		})
+		.collect();
+
+	app.world.resource_mut::<Assets<DazAsset>>().add(DazAsset {
+		scene: Handle::default(),
+		meshes: HashMap::default(),
+		nodes,
+		modifiers: modifiers
+			.into_iter()
+			.map(|modifier| (modifier.id.clone(), modifier))
+			.collect(),
+		materials: HashMap::default(),
+		uv_sets: HashMap::default(),
+	})
+}
+
+/// An app that loads Daz assets from `files`, given as paths and contents, and
+/// can spawn their scenes.
+pub fn loader_app(files: &[(&str, &str)]) -> App {
+	let dir = Dir::default();
+	for (path, contents) in files {
+		dir.insert_asset_text(Path::new(path), contents);
+	}
+
+	let mut app = App::new();
+	app.register_asset_source(
+		AssetSourceId::Default,
+		AssetSource::build().with_reader(move || Box::new(MemoryAssetReader { root: dir.clone() })),
+	)
+	.add_plugins((
+		MinimalPlugins,
+		AssetPlugin::default(),
+		ScenePlugin,
+		HierarchyPlugin,
+		TransformPlugin,
+		DazAssetTypesPlugin,
+	))
+	.init_asset::<Mesh>()
+	.init_asset::<Image>()
+	.init_asset::<SkinnedMeshInverseBindposes>()
+	.init_asset::<DqsStandardMaterial>()
+	// Everything in the scenes built by the loader
+	.register_type::<Visibility>()
+	.register_type::<InheritedVisibility>()
+	.register_type::<ViewVisibility>()
+	.register_type::<Handle<Mesh>>()
+	.register_type::<Handle<DqsStandardMaterial>>()
+	.register_type::<SkinnedMesh>()
+	.register_type::<DazBone>()
+	.register_type::<DazSkeleton>();
+
+	app
+}
+
+/// Loads the [DazAsset] at `path`, updating `app` until it and its
+/// dependencies have loaded.
+pub fn load(app: &mut App, path: &str) -> Handle<DazAsset> {
+	let handle = app
+		.world
+		.resource::<AssetServer>()
+		.load::<DazAsset>(path.to_owned());
+
+	for _ in 0..1000 {
+		app.update();
+		let asset_server = app.world.resource::<AssetServer>();
+		if asset_server.is_loaded_with_dependencies(&handle) {
+			return handle;
+		}
+		assert_ne!(
+			asset_server.get_load_state(&handle),
+			Some(LoadState::Failed),
+			"Failed to load {path}"
+		);
+		std::thread::sleep(Duration::from_millis(1));
+	}
+
+	panic!("Timed out loading {path}");
+}